@@ -0,0 +1,179 @@
+use crate::summary::summary;
+use crate::{OutputFormat, print_statement_error};
+use anyhow::{Context, bail};
+use clio::Output;
+use itertools::Itertools;
+use locksmith::{InspectedStatement, LintWarning, QueryOracle, TableOperation};
+use serde::Serialize;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use tracing::{error, info, warn};
+
+/// A condition that fails the `check` subcommand when any statement of the migration meets it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailOn {
+    /// The statement rewrites a table
+    Rewrite,
+    /// The statement has a lint warning
+    Lint,
+    /// The statement takes a lock that blocks reads from an existing table
+    BlocksReads,
+    /// The statement takes a lock that blocks writes to an existing table
+    BlocksWrites,
+}
+
+impl FailOn {
+    fn is_met_by(&self, inspected: &InspectedStatement, warnings: &[LintWarning]) -> bool {
+        match self {
+            FailOn::Rewrite => !inspected.rewrites.is_empty(),
+            FailOn::Lint => !warnings.is_empty(),
+            FailOn::BlocksReads => inspected
+                .table_operations()
+                .iter()
+                .any(|operation| operation.blocks_reads()),
+            FailOn::BlocksWrites => inspected
+                .table_operations()
+                .iter()
+                .any(|operation| operation.blocks_writes()),
+        }
+    }
+}
+
+/// The result of inspecting a single statement of the migration, written as one line of the JSON
+/// lines report printed by the `check` subcommand.
+#[derive(Serialize)]
+struct CheckEntry<'a> {
+    /// The position of the statement in the migration, starting from 1
+    statement: usize,
+    /// The line of the migration file that the statement starts at
+    line: usize,
+    sql: &'a str,
+    inspected: &'a InspectedStatement,
+    /// The strongest lock on each locked table and how long it is held for, see
+    /// [InspectedStatement::table_operations]
+    operations: Vec<TableOperation>,
+    warnings: &'a [LintWarning],
+    /// The `--fail-on` conditions that the statement meets
+    failed: &'a [FailOn],
+}
+
+/// The line of `contents` that each of `statements` starts at, which are found in order as each
+/// statement is a trimmed slice of the migration.
+fn statement_lines<'a>(
+    contents: &str,
+    statements: impl Iterator<Item = &'a str>,
+) -> impl Iterator<Item = usize> {
+    let mut offset = 0;
+    statements.map(move |statement| {
+        let start = offset + contents[offset..].find(statement).unwrap_or_default();
+        offset = start + statement.len();
+        contents[..start].matches('\n').count() + 1
+    })
+}
+
+/// Inspect each statement of the migration file at `path` with `oracle` via
+/// [QueryOracle::inspect_migration], writing the result and lint warnings of each to `output` as a
+/// line of JSON, or the position and [summary] of each with [OutputFormat::Summary].
+///
+/// The command fails if any statement cannot be inspected, after reporting the position of the
+/// statement and the error, or once every statement has been reported if any of them meet one of
+/// the `fail_on` conditions.
+pub async fn check_migration(
+    oracle: &mut QueryOracle,
+    path: &Path,
+    fail_on: &[FailOn],
+    format: OutputFormat,
+    mut output: Output,
+) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading migration file {path:?}"))?;
+    let results = oracle.inspect_migration(&contents).await;
+    if results.is_empty() {
+        bail!("No statements found in migration file {path:?}");
+    }
+    let lines: Vec<_> =
+        statement_lines(&contents, results.iter().map(|(sql, _)| sql.as_str())).collect();
+
+    let mut failures = vec![];
+    for (index, ((sql, result), line)) in results.iter().zip(lines).enumerate() {
+        let statement = index + 1;
+        let inspected = match result {
+            Ok(inspected) => inspected,
+            Err(e) => {
+                let reason =
+                    std::iter::successors(Some(e as &dyn Error), |e| (*e).source()).join(": ");
+                error!(statement, line, "Inspection failed: {reason}");
+                if matches!(format, OutputFormat::Summary) {
+                    writeln!(output, "{statement} (line {line}): failed: {reason}")?;
+                }
+                print_statement_error(e, sql);
+                bail!("Statement {statement} at line {line} of {path:?} failed to be inspected");
+            }
+        };
+        info!(statement, line, "Inspected statement");
+        let warnings = inspected.lint();
+        for warning in &warnings {
+            warn!(statement, line, rule = %warning.rule, "{}", warning.message);
+        }
+        let failed: Vec<_> = fail_on
+            .iter()
+            .copied()
+            .filter(|condition| condition.is_met_by(inspected, &warnings))
+            .collect();
+        if !failed.is_empty() {
+            failures.push(statement);
+        }
+
+        match format {
+            OutputFormat::Summary => {
+                writeln!(output, "{statement} (line {line}): {}", summary(inspected))?;
+                for warning in &warnings {
+                    writeln!(output, "  warning [{}]: {}", warning.rule, warning.message)?;
+                }
+            }
+            _ => {
+                let entry = CheckEntry {
+                    statement,
+                    line,
+                    sql,
+                    inspected,
+                    operations: inspected.table_operations(),
+                    warnings: &warnings,
+                    failed: &failed,
+                };
+                serde_json::to_writer(&mut output, &entry)?;
+                writeln!(output)?;
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        bail!(
+            "Statements meet the `--fail-on` conditions: {}",
+            failures.iter().join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::statement_lines;
+
+    #[test]
+    fn test_statement_lines() {
+        let contents = "-- Add the email column\nalter table customers add column email text;\n\n\
+            select 1; select 1;\n";
+        let statements = [
+            "-- Add the email column\nalter table customers add column email text;",
+            "select 1;",
+            "select 1;",
+        ];
+        assert_eq!(
+            statement_lines(contents, statements.into_iter()).collect::<Vec<_>>(),
+            vec![1, 4, 4]
+        );
+    }
+}
@@ -1,69 +1,401 @@
-use anyhow::Context;
+use crate::check::{FailOn, check_migration};
+use crate::migrations::{MigrationBaseline, inspect_migrations};
+use crate::summary::summary;
+use anyhow::{Context, bail};
 use askama::Template;
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use clio::Output;
 use itertools::Itertools;
-use locksmith::{DBObject, QueryOracle, TableLock};
+use locksmith::{
+    ChangeReason, DBObject, InspectedStatement, Introspector, LintWarning, LocksmithError,
+    ObjectChange, QueryOracle, QueryOracleBuilder, RenamedObject, SchemaSnapshot, TableLock,
+    TableLockCost, TableObject, TableOperation,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
 use std::io::stderr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use testcontainers_modules::postgres::Postgres;
 use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt, runners::AsyncRunner};
-use tracing::info;
 use tracing::level_filters::LevelFilter;
+use tracing::{error, info, warn};
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
+mod check;
+mod migrations;
+mod summary;
+
 pub const DEFAULT_POSTGRES_TAG: &str = "15-alpine";
 
 #[derive(Debug, clap::Parser)]
-#[clap(version)]
+#[clap(
+    version,
+    override_usage = "locksmith-cli [OPTIONS] <SCHEMA_FILE> <QUERY>\n       locksmith-cli [OPTIONS] --dsn <DSN> <QUERY>\n       locksmith-cli [OPTIONS] introspect <SCHEMA_FILE>\n       locksmith-cli [OPTIONS] --dsn <DSN> introspect\n       locksmith-cli [OPTIONS] compare-versions --tags <TAGS> <SCHEMA_FILE> <QUERY>\n       locksmith-cli [OPTIONS] migrations <SCHEMA_FILE> <DIRECTORY>\n       locksmith-cli [OPTIONS] --dsn <DSN> migrations <DIRECTORY>\n       locksmith-cli [OPTIONS] check <SCHEMA_FILE> <FILE>\n       locksmith-cli [OPTIONS] --dsn <DSN> check <FILE>"
+)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// The path to a file containing the initial database schema for the test. This can be
-    /// in a plaintext SQL format or a binary format generated by `pg_dump`.
-    schema_file: PathBuf,
+    /// in a plaintext SQL format or a binary format generated by `pg_dump`. This is omitted
+    /// when `--dsn` is given.
+    schema_file: Option<PathBuf>,
     /// The SQL query to inspect
-    query: String,
+    query: Option<String>,
+
+    /// Inspect the query against an existing database, rather than starting a Postgres container
+    /// with the schema file. WARNING: this locks every table in the target database during the
+    /// inspection and commits the statement if it succeeds, so it must never be a production
+    /// database.
+    #[clap(long, env = "LOCKSMITH_DSN", global = true)]
+    dsn: Option<String>,
+
+    /// A comma-separated list of schemas to set as the `search_path` of every connection, such as
+    /// `app` when the schema is not in the default `public` schema. Objects are listed from the
+    /// first of these schemas that exists. Defaults to the search path of the connecting role
+    #[clap(long, value_delimiter = ',', global = true)]
+    search_path: Vec<String>,
 
     /// The tag of the Postgres container to start
-    #[clap(short, long, env="POSTGRES_TAG", default_value=DEFAULT_POSTGRES_TAG)]
+    #[clap(short, long, env="POSTGRES_TAG", default_value=DEFAULT_POSTGRES_TAG, global = true)]
     tag: String,
 
     /// The output file to write the inspection results to. If not provided, the results will be
     /// written to stdout.
-    #[clap(long, short, value_parser, default_value = "-")]
+    #[clap(long, short, value_parser, default_value = "-", global = true)]
     output: Output,
 
     /// The output format
-    #[clap(long, short, value_enum, default_value_t = OutputFormat::Json)]
+    #[clap(long, short, value_enum, default_value_t = OutputFormat::Json, global = true)]
     format: OutputFormat,
+
+    /// The path to a JSON file containing a previously approved inspection result, or just its
+    /// list of locks. If given, the command fails if the statement takes any locks or causes any
+    /// rewrites that are not present in the baseline
+    #[clap(long)]
+    baseline: Option<PathBuf>,
+
+    /// The path to a JSON snapshot of a database, such as one written by `introspect --snapshot`
+    /// at the last release. If given, only the objects added or removed relative to the snapshot
+    /// are reported
+    #[clap(long)]
+    since: Option<PathBuf>,
+
+    /// Seed every table with this many dummy rows before inspecting the query, so that statements
+    /// which fail on populated tables, such as adding a `NOT NULL` column without a default,
+    /// fail as they would in production
+    #[clap(long, default_value_t = 0)]
+    seed_rows: u64,
+
+    /// Execute the query a second time once it has been inspected, and report whether it can be
+    /// re-run without failing or changing any objects
+    #[clap(long)]
+    check_idempotency: bool,
+
+    /// Increase logging verbosity. Can be given twice for trace logging
+    #[clap(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet", global = true)]
+    verbose: u8,
+
+    /// Only log warnings and errors
+    #[clap(short, long, global = true)]
+    quiet: bool,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Print every object in the database, such as tables, columns and indexes, without
+    /// inspecting a statement
+    Introspect {
+        /// The path to a file containing the database schema. This is omitted when `--dsn` is
+        /// given.
+        schema_file: Option<PathBuf>,
+        /// Print a JSON snapshot of the database, which can be given to `--since`, rather than
+        /// the list of objects
+        #[clap(long)]
+        snapshot: bool,
+    },
+    /// Inspect the query against a new Postgres container for each of several tags, printing the
+    /// result for each version along with the locks and rewrites that differ between them
+    CompareVersions {
+        /// The path to a file containing the initial database schema
+        schema_file: PathBuf,
+        /// The SQL query to inspect
+        query: String,
+        /// A comma separated list of the tags of the Postgres containers to start, such as
+        /// `13-alpine,17-alpine`
+        #[clap(long, required = true, value_delimiter = ',')]
+        tags: Vec<String>,
+    },
+    /// Inspect every `.sql` file in a directory of migrations, in order of their file names,
+    /// committing each before inspecting the next. One line of JSON is printed for each
+    /// migration, containing its file name, the SHA-256 hash of its contents and its result, or
+    /// its file name and summary with `--format summary`
+    Migrations {
+        /// The path to a file containing the initial database schema. This is omitted when `--dsn`
+        /// is given.
+        schema_file: Option<PathBuf>,
+        /// The directory containing the migration files
+        directory: Option<PathBuf>,
+        /// The path to a report previously printed by `migrations`. If given, migrations that are
+        /// unchanged since the report was printed are applied without being inspected, and the
+        /// command fails if any other migration takes locks or causes rewrites that are not
+        /// present in the report for the same file. Migrations that are not in the report must
+        /// not take any locks or cause any rewrites
+        #[clap(long)]
+        baseline: Option<PathBuf>,
+    },
+    /// Inspect each statement of a migration file in turn, committing each before inspecting the
+    /// next, and check them against the lint rules. One line of JSON is printed for each
+    /// statement, containing its position, its result and its lint warnings, or its position,
+    /// summary and lint warnings with `--format summary`
+    Check {
+        /// The path to a file containing the initial database schema. This is omitted when `--dsn`
+        /// is given.
+        schema_file: Option<PathBuf>,
+        /// The migration file to check
+        file: Option<PathBuf>,
+        /// A comma separated list of conditions that fail the command if any statement meets
+        /// them, once every statement has been inspected
+        #[clap(long, value_enum, value_delimiter = ',')]
+        fail_on: Vec<FailOn>,
+    },
+}
+
+/// The database that a query is inspected against
+enum Target {
+    /// A new Postgres container, initialized with the given schema file
+    SchemaFile(PathBuf),
+    /// An existing database
+    Dsn(String),
+}
+
+fn usage_error(message: &str) -> ! {
+    Args::command()
+        .error(clap::error::ErrorKind::WrongNumberOfValues, message)
+        .exit()
+}
+
+impl Args {
+    /// Resolve the positional arguments into a [Target] and a query, exiting with a usage error if
+    /// they are invalid. When `--dsn` is given the schema file is omitted, so the only positional
+    /// argument is the query.
+    fn target_and_query(&mut self) -> (Target, String) {
+        match (self.dsn.take(), self.schema_file.take(), self.query.take()) {
+            (Some(dsn), Some(query), None) => {
+                let query = query
+                    .into_os_string()
+                    .into_string()
+                    .unwrap_or_else(|_| usage_error("The query must be valid UTF-8"));
+                (Target::Dsn(dsn), query)
+            }
+            (None, Some(schema_file), Some(query)) => (Target::SchemaFile(schema_file), query),
+            (Some(_), _, _) => usage_error("Only a query should be given when `--dsn` is used"),
+            (None, _, _) => usage_error("A schema file and a query are required"),
+        }
+    }
+
+    /// Resolve the target of the `introspect` subcommand, exiting with a usage error if both or
+    /// neither of `--dsn` and a schema file are given.
+    fn introspect_target(&mut self, schema_file: Option<PathBuf>) -> Target {
+        if self.schema_file.is_some() || self.query.is_some() {
+            usage_error("Unexpected arguments before `introspect`");
+        }
+        match (self.dsn.take(), schema_file) {
+            (Some(dsn), None) => Target::Dsn(dsn),
+            (None, Some(schema_file)) => Target::SchemaFile(schema_file),
+            (Some(_), Some(_)) => {
+                usage_error("A schema file should not be given when `--dsn` is used")
+            }
+            (None, None) => usage_error("A schema file or `--dsn` is required"),
+        }
+    }
+
+    /// Resolve the target and migrations directory of the `migrations` subcommand, exiting with a
+    /// usage error if they are invalid. When `--dsn` is given the schema file is omitted, so the
+    /// only positional argument is the directory.
+    fn migrations_target(
+        &mut self,
+        schema_file: Option<PathBuf>,
+        directory: Option<PathBuf>,
+    ) -> (Target, PathBuf) {
+        if self.schema_file.is_some() || self.query.is_some() {
+            usage_error("Unexpected arguments before `migrations`");
+        }
+        match (self.dsn.take(), schema_file, directory) {
+            (Some(dsn), Some(directory), None) => (Target::Dsn(dsn), directory),
+            (None, Some(schema_file), Some(directory)) => {
+                (Target::SchemaFile(schema_file), directory)
+            }
+            (Some(_), _, _) => {
+                usage_error("Only a migrations directory should be given when `--dsn` is used")
+            }
+            (None, _, _) => usage_error("A schema file and a migrations directory are required"),
+        }
+    }
+
+    /// Resolve the target and migration file of the `check` subcommand, exiting with a usage
+    /// error if they are invalid. When `--dsn` is given the schema file is omitted, so the only
+    /// positional argument is the migration file.
+    fn check_target(
+        &mut self,
+        schema_file: Option<PathBuf>,
+        file: Option<PathBuf>,
+    ) -> (Target, PathBuf) {
+        if self.schema_file.is_some() || self.query.is_some() {
+            usage_error("Unexpected arguments before `check`");
+        }
+        match (self.dsn.take(), schema_file, file) {
+            (Some(dsn), Some(file), None) => (Target::Dsn(dsn), file),
+            (None, Some(schema_file), Some(file)) => (Target::SchemaFile(schema_file), file),
+            (Some(_), _, _) => {
+                usage_error("Only a migration file should be given when `--dsn` is used")
+            }
+            (None, _, _) => usage_error("A schema file and a migration file are required"),
+        }
+    }
+
+    /// The default log level, which can be overridden with the `RUST_LOG` environment variable.
+    fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::WARN,
+            (false, 0) => LevelFilter::INFO,
+            (false, 1) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, clap::ValueEnum)]
 enum OutputFormat {
     Json,
     Markdown,
+    #[value(alias = "human")]
+    Summary,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let mut args = Args::parse();
     tracing_subscriber::registry()
         .with(fmt::layer().with_writer(stderr))
         .with(
             EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
+                .with_default_directive(args.log_level().into())
                 .from_env_lossy(),
         )
         .init();
+    let mut snapshot = false;
+    let (target, query) = match args.command.take() {
+        Some(Command::Introspect {
+            schema_file,
+            snapshot: take_snapshot,
+        }) => {
+            snapshot = take_snapshot;
+            if matches!(args.format, OutputFormat::Summary) {
+                usage_error("`introspect` does not support the summary format");
+            }
+            (args.introspect_target(schema_file), None)
+        }
+        Some(Command::CompareVersions {
+            schema_file,
+            query,
+            tags,
+        }) => {
+            if args.dsn.is_some() || args.schema_file.is_some() || args.query.is_some() {
+                usage_error("Only `compare-versions` arguments should be given");
+            }
+            let schema = std::fs::read(&schema_file)
+                .with_context(|| format!("Reading schema file from {schema_file:?}"))?;
+            return compare_versions(
+                schema,
+                &query,
+                tags,
+                args.seed_rows,
+                &args.search_path,
+                args.format,
+                args.output,
+            )
+            .await;
+        }
+        Some(Command::Migrations {
+            schema_file,
+            directory,
+            baseline,
+        }) => {
+            let (target, directory) = args.migrations_target(schema_file, directory);
+            if matches!(args.format, OutputFormat::Markdown) {
+                usage_error("`migrations` only prints JSON lines or a summary");
+            }
+            let baseline = baseline
+                .as_deref()
+                .map(MigrationBaseline::read)
+                .transpose()?;
+            let (_container, dsn) = connect(target, args.tag).await?;
+            check_search_path(&dsn, &args.search_path).await?;
+            let mut oracle = query_oracle(dsn, &args.search_path)
+                .seed_rows(args.seed_rows)
+                .idempotency_check(args.check_idempotency)
+                .build();
+            return inspect_migrations(&mut oracle, &directory, baseline, args.format, args.output)
+                .await;
+        }
+        Some(Command::Check {
+            schema_file,
+            file,
+            fail_on,
+        }) => {
+            let (target, file) = args.check_target(schema_file, file);
+            if matches!(args.format, OutputFormat::Markdown) {
+                usage_error("`check` only prints JSON lines or a summary");
+            }
+            let (_container, dsn) = connect(target, args.tag).await?;
+            check_search_path(&dsn, &args.search_path).await?;
+            let mut oracle = query_oracle(dsn, &args.search_path)
+                .seed_rows(args.seed_rows)
+                .idempotency_check(args.check_idempotency)
+                .build();
+            return check_migration(&mut oracle, &file, &fail_on, args.format, args.output).await;
+        }
+        None => {
+            let (target, query) = args.target_and_query();
+            (target, Some(query))
+        }
+    };
+    let baseline = args.baseline.as_deref().map(read_baseline).transpose()?;
+    let since = args.since.as_deref().map(read_snapshot).transpose()?;
 
-    let args = Args::parse();
-    let schema = std::fs::read(&args.schema_file)
-        .with_context(|| format!("Reading schema file from {:?}", args.schema_file))?;
-    let (_container, dsn) = start_postgres(schema, args.tag)
-        .await
-        .context("Creating Postgres container")?;
+    // The container must be kept alive until the inspection is complete.
+    let (_container, dsn) = connect(target, args.tag).await?;
 
-    let mut oracle = QueryOracle::new(dsn);
-    let inspected_statement = oracle.inspect_statement(&args.query).await?;
+    let Some(query) = query else {
+        if snapshot {
+            return print_snapshot(&dsn, &args.search_path, args.output).await;
+        }
+        return introspect(&dsn, &args.search_path, args.format, args.output).await;
+    };
+
+    check_search_path(&dsn, &args.search_path).await?;
+    let mut oracle = query_oracle(dsn, &args.search_path)
+        .seed_rows(args.seed_rows)
+        .idempotency_check(args.check_idempotency)
+        .build();
+    let mut inspected_statement = match oracle.inspect_statement(&query).await {
+        Ok(inspected_statement) => inspected_statement,
+        Err(e) => {
+            if let Some(partial) = e.partial() {
+                error!(locks = ?partial.locks, "Locks detected before inspection failed");
+            }
+            print_statement_error(&e, &query);
+            return Err(e.into());
+        }
+    };
+
+    if let Some(snapshot) = since {
+        let diff = inspected_statement.object_diff().since(&snapshot);
+        inspected_statement.added_objects = diff.added_objects;
+        inspected_statement.removed_objects = diff.removed_objects;
+    }
 
     info!(
         added = inspected_statement.added_objects.len(),
@@ -72,34 +404,340 @@ async fn main() -> anyhow::Result<()> {
         rewrites = inspected_statement.rewrites.len(),
         "Inspected statement"
     );
+    let comparison = baseline.map(|b| inspected_statement.compare_to_baseline(&b));
+    let warnings = inspected_statement.lint();
+    for warning in &warnings {
+        warn!(rule = %warning.rule, "{}", warning.message);
+    }
+
     let mut output = args.output;
     match args.format {
         OutputFormat::Json => {
             serde_json::to_writer_pretty(output, &inspected_statement)?;
         }
         OutputFormat::Markdown => {
+            let changes = inspected_statement.object_diff().changes();
             let comment = MarkdownComment {
-                statement: args.query,
-                added_objects: inspected_statement
-                    .added_objects
+                statement: query,
+                warnings,
+                operations: inspected_statement.table_operations(),
+                added_objects: fold_table_changes(changes.added),
+                removed_objects: fold_table_changes(changes.removed),
+                renamed: inspected_statement.renamed.into_iter().sorted().collect(),
+                locks: inspected_statement.locks.into_iter().sorted().collect(),
+                commit_locks: inspected_statement.commit_locks,
+                lock_costs: inspected_statement
+                    .lock_costs
                     .into_iter()
                     .sorted()
                     .collect(),
-                removed_objects: inspected_statement
-                    .removed_objects
+                rewrites: inspected_statement.rewrites.into_iter().sorted().collect(),
+                rewrite_estimates: inspected_statement
+                    .rewrite_estimates
+                    .into_iter()
+                    .map(|estimate| {
+                        (
+                            DBObject::Table(estimate.table),
+                            estimate.rewrite_bytes_estimate,
+                        )
+                    })
+                    .collect(),
+                rewrite_columns: inspected_statement
+                    .rewrite_columns
+                    .into_iter()
+                    .map(|columns| (DBObject::Table(columns.table), columns.columns.join(", ")))
+                    .collect(),
+                dependents: inspected_statement
+                    .dependents
                     .into_iter()
                     .sorted()
                     .collect(),
-                locks: inspected_statement.locks.into_iter().sorted().collect(),
-                rewrites: inspected_statement.rewrites.into_iter().sorted().collect(),
+                idempotent: inspected_statement.idempotent,
+                affected_rows: inspected_statement.affected_rows,
             };
             let rendered = comment.render()?;
             writeln!(output, "{}", rendered)?;
         }
+        OutputFormat::Summary => {
+            writeln!(output, "{}", summary(&inspected_statement))?;
+        }
+    }
+
+    if let Some(comparison) = comparison
+        && !comparison.is_empty()
+    {
+        error!(
+            new_locks = ?comparison.new_locks,
+            new_rewrites = ?comparison.new_rewrites,
+            "Statement exceeds the baseline"
+        );
+        bail!("Statement takes locks or causes rewrites that are not present in the baseline");
+    }
+    Ok(())
+}
+
+/// Group the objects that were added or removed along with their table under the change to the
+/// table, so that dropping a table is listed once rather than once for each of its columns.
+fn fold_table_changes(changes: Vec<ObjectChange>) -> Vec<(DBObject, Vec<DBObject>)> {
+    let (direct, with_table): (Vec<_>, Vec<_>) = changes
+        .into_iter()
+        .partition(|change| change.reason == ChangeReason::Direct);
+    direct
+        .into_iter()
+        .map(|change| {
+            let table = match &change.object {
+                DBObject::Table(table) => Some(table.clone()),
+                DBObject::ForeignTable(table) => Some(TableObject::from(table.name.as_str())),
+                _ => None,
+            };
+            let members = with_table
+                .iter()
+                .filter(|member| {
+                    matches!(&member.reason, ChangeReason::WithTable(with)
+                        if Some(with) == table.as_ref())
+                })
+                .map(|member| member.object.clone())
+                .collect();
+            (change.object, members)
+        })
+        .collect()
+}
+
+/// If the inspection of `statement` failed because Postgres rejected it, such as because of a
+/// syntax error, print the error to stderr along with the line of the statement it occurred at.
+fn print_statement_error(error: &LocksmithError, statement: &str) {
+    if let Some(statement_error) = error.statement_error() {
+        eprintln!("{}", statement_error.describe(statement));
+    }
+}
+
+/// Connect to `target`, returning its DSN along with the Postgres container it was started in, if
+/// any, which must be kept alive until the database is no longer needed.
+async fn connect(
+    target: Target,
+    tag: String,
+) -> anyhow::Result<(Option<ContainerAsync<Postgres>>, String)> {
+    match target {
+        Target::Dsn(dsn) => Ok((None, dsn)),
+        Target::SchemaFile(schema_file) => {
+            let schema = std::fs::read(&schema_file)
+                .with_context(|| format!("Reading schema file from {schema_file:?}"))?;
+            let (container, dsn) = start_postgres(schema, tag)
+                .await
+                .context("Creating Postgres container")?;
+            Ok((Some(container), dsn))
+        }
+    }
+}
+
+/// Create a builder of an oracle for the database at `dsn`, using `search_path` if one was given
+/// with `--search-path`.
+fn query_oracle(dsn: String, search_path: &[String]) -> QueryOracleBuilder {
+    let oracle = QueryOracle::builder(dsn);
+    if search_path.is_empty() {
+        return oracle;
+    }
+    let schemas: Vec<_> = search_path.iter().map(String::as_str).collect();
+    oracle.search_path(&schemas)
+}
+
+/// Create an introspector for the database at `dsn`, using `search_path` if one was given with
+/// `--search-path`. This exits with a usage error if none of the schemas in `search_path` exist,
+/// as every object would be resolved against an empty search path.
+async fn create_introspector(dsn: &str, search_path: &[String]) -> anyhow::Result<Introspector> {
+    let mut introspector = Introspector::new(dsn)
+        .await
+        .context("Creating introspector")?;
+    if !search_path.is_empty() {
+        introspector.set_search_path(search_path).await?;
+        if introspector.current_schema().await?.is_none() {
+            usage_error(&format!(
+                "No schema in `--search-path` exists: {}",
+                search_path.join(", ")
+            ));
+        }
+    }
+    Ok(introspector)
+}
+
+/// Check that a schema in `search_path` exists in the database at `dsn` before inspecting
+/// statements against it, exiting with a usage error if none do.
+async fn check_search_path(dsn: &str, search_path: &[String]) -> anyhow::Result<()> {
+    if !search_path.is_empty() {
+        create_introspector(dsn, search_path).await?;
+    }
+    Ok(())
+}
+
+/// Print every object in the database at `dsn`, sorted, in the given format.
+async fn introspect(
+    dsn: &str,
+    search_path: &[String],
+    format: OutputFormat,
+    mut output: Output,
+) -> anyhow::Result<()> {
+    let mut introspector = create_introspector(dsn, search_path).await?;
+    let objects: Vec<_> = introspector
+        .list_objects()
+        .await?
+        .into_iter()
+        .sorted()
+        .collect();
+    info!(objects = objects.len(), "Introspected schema");
+
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(output, &objects)?;
+        }
+        OutputFormat::Markdown => {
+            let rendered = SchemaListing { objects }.render()?;
+            writeln!(output, "{}", rendered)?;
+        }
+        OutputFormat::Summary => unreachable!("`introspect` does not support the summary format"),
+    }
+    Ok(())
+}
+
+async fn print_snapshot(dsn: &str, search_path: &[String], output: Output) -> anyhow::Result<()> {
+    let mut introspector = create_introspector(dsn, search_path).await?;
+    let snapshot = introspector.snapshot().await?;
+    info!(objects = snapshot.objects.len(), "Took schema snapshot");
+    serde_json::to_writer_pretty(output, &snapshot)?;
+    Ok(())
+}
+
+/// Inspect `query` against a new Postgres container for each of `tags`, initialized with `schema`,
+/// and print the results in the given format.
+///
+/// Inspection failing on one version does not stop the others from being inspected, as a
+/// statement that only fails on some versions is itself a difference between them. The command
+/// fails once the results have been printed if any inspection failed.
+async fn compare_versions(
+    schema: Vec<u8>,
+    query: &str,
+    tags: Vec<String>,
+    seed_rows: u64,
+    search_path: &[String],
+    format: OutputFormat,
+    mut output: Output,
+) -> anyhow::Result<()> {
+    let mut comparison = VersionComparison::default();
+    for tag in tags {
+        let (_container, dsn) = start_postgres(schema.clone(), &tag)
+            .await
+            .context("Creating Postgres container")?;
+        check_search_path(&dsn, search_path).await?;
+        let mut oracle = query_oracle(dsn, search_path).seed_rows(seed_rows).build();
+        match oracle.inspect_statement(query).await {
+            Ok(inspected_statement) => {
+                comparison.versions.insert(tag, inspected_statement);
+            }
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                error!(tag, "Inspection failed: {e:#}");
+                comparison.errors.insert(tag, format!("{e:#}"));
+            }
+        }
+    }
+    comparison.find_divergences();
+    if !comparison.divergent_locks.is_empty() || !comparison.divergent_rewrites.is_empty() {
+        warn!(
+            divergent_locks = ?comparison.divergent_locks,
+            divergent_rewrites = ?comparison.divergent_rewrites,
+            "Results differ between versions"
+        );
+    }
+
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &comparison)?;
+        }
+        OutputFormat::Markdown => {
+            let rendered = VersionComparisonComment {
+                statement: query,
+                comparison: &comparison,
+            }
+            .render()?;
+            writeln!(output, "{}", rendered)?;
+        }
+        OutputFormat::Summary => {
+            for (tag, inspected) in &comparison.versions {
+                writeln!(output, "{tag}: {}", summary(inspected))?;
+            }
+            for (tag, error) in &comparison.errors {
+                writeln!(output, "{tag}: failed: {error}")?;
+            }
+        }
+    }
+
+    if !comparison.errors.is_empty() {
+        bail!(
+            "Inspection failed with tags: {}",
+            comparison.errors.keys().join(", ")
+        );
     }
     Ok(())
 }
 
+/// The results of inspecting a statement against several Postgres versions, keyed by the tag of
+/// the container.
+#[derive(Default, Serialize)]
+struct VersionComparison {
+    versions: BTreeMap<String, InspectedStatement>,
+    /// The error for each tag that the statement could not be inspected with
+    errors: BTreeMap<String, String>,
+    /// Locks that are only taken with some of the tags
+    divergent_locks: Vec<TableLock>,
+    /// Rewrites that only happen with some of the tags
+    divergent_rewrites: Vec<DBObject>,
+}
+
+impl VersionComparison {
+    /// Populate the divergent locks and rewrites, which are those that are not present in the
+    /// result for every version.
+    fn find_divergences(&mut self) {
+        fn divergent<T: Clone + Ord + std::hash::Hash>(
+            sets: impl Iterator<Item = HashSet<T>> + Clone,
+        ) -> Vec<T> {
+            let all: HashSet<T> = sets.clone().flatten().collect();
+            all.into_iter()
+                .filter(|item| !sets.clone().all(|set| set.contains(item)))
+                .sorted()
+                .collect()
+        }
+        self.divergent_locks = divergent(self.versions.values().map(|v| v.locks.clone()));
+        self.divergent_rewrites = divergent(self.versions.values().map(|v| v.rewrites.clone()));
+    }
+}
+
+/// A baseline file, which can contain a full inspection result or just a list of locks.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Baseline {
+    Statement(Box<InspectedStatement>),
+    Locks(HashSet<TableLock>),
+}
+
+fn read_baseline(path: &Path) -> anyhow::Result<InspectedStatement> {
+    let contents =
+        std::fs::read(path).with_context(|| format!("Reading baseline file from {path:?}"))?;
+    let baseline = serde_json::from_slice(&contents)
+        .with_context(|| format!("Parsing baseline file {path:?}"))?;
+    Ok(match baseline {
+        Baseline::Statement(statement) => *statement,
+        Baseline::Locks(locks) => InspectedStatement {
+            locks,
+            ..Default::default()
+        },
+    })
+}
+
+fn read_snapshot(path: &Path) -> anyhow::Result<SchemaSnapshot> {
+    let contents =
+        std::fs::read(path).with_context(|| format!("Reading snapshot file from {path:?}"))?;
+    serde_json::from_slice(&contents).with_context(|| format!("Parsing snapshot file {path:?}"))
+}
+
 async fn start_postgres(
     schema: Vec<u8>,
     tag: impl AsRef<str>,
@@ -134,8 +772,33 @@ async fn start_postgres(
 #[template(path = "comment.md.jinja2")]
 struct MarkdownComment {
     statement: String,
-    added_objects: Vec<DBObject>,
-    removed_objects: Vec<DBObject>,
+    warnings: Vec<LintWarning>,
+    operations: Vec<TableOperation>,
+    /// Each added object, with the objects that were added along with it if it is a table
+    added_objects: Vec<(DBObject, Vec<DBObject>)>,
+    /// Each removed object, with the objects that were removed along with it if it is a table
+    removed_objects: Vec<(DBObject, Vec<DBObject>)>,
+    renamed: Vec<RenamedObject>,
     locks: Vec<TableLock>,
+    commit_locks: HashSet<TableLock>,
+    lock_costs: Vec<TableLockCost>,
     rewrites: Vec<DBObject>,
+    rewrite_estimates: HashMap<DBObject, i64>,
+    rewrite_columns: HashMap<DBObject, String>,
+    dependents: Vec<DBObject>,
+    idempotent: Option<bool>,
+    affected_rows: Option<u64>,
+}
+
+#[derive(Template)]
+#[template(path = "versions.md.jinja2")]
+struct VersionComparisonComment<'a> {
+    statement: &'a str,
+    comparison: &'a VersionComparison,
+}
+
+#[derive(Template)]
+#[template(path = "schema.md.jinja2")]
+struct SchemaListing {
+    objects: Vec<DBObject>,
 }
@@ -0,0 +1,189 @@
+use crate::summary::summary;
+use crate::{OutputFormat, print_statement_error};
+use anyhow::{Context, bail};
+use clio::Output;
+use locksmith::{InspectedStatement, QueryOracle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use tracing::{error, info};
+
+/// The result of inspecting a single migration file, written as one line of the JSON lines report
+/// printed by the `migrations` subcommand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationEntry {
+    /// The name of the migration file, relative to the migrations directory
+    pub file: String,
+    /// The hex-encoded SHA-256 hash of the contents of the migration file
+    pub sha256: String,
+    pub inspected: InspectedStatement,
+}
+
+/// A migration file, read from the migrations directory.
+struct Migration {
+    file: String,
+    contents: String,
+    sha256: String,
+}
+
+/// A report previously printed by the `migrations` subcommand, keyed by file name.
+pub struct MigrationBaseline {
+    entries: HashMap<String, MigrationEntry>,
+}
+
+impl MigrationBaseline {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading baseline report from {path:?}"))?;
+        let mut entries = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: MigrationEntry = serde_json::from_str(line).with_context(|| {
+                format!("Parsing line {} of baseline report {path:?}", index + 1)
+            })?;
+            if let Some(duplicate) = entries.insert(entry.file.clone(), entry) {
+                bail!(
+                    "Baseline report {path:?} contains {} more than once",
+                    duplicate.file
+                );
+            }
+        }
+        Ok(MigrationBaseline { entries })
+    }
+
+    /// The entry for `migration`, if its contents are unchanged since the baseline was recorded.
+    fn unchanged_entry(&self, migration: &Migration) -> Option<&MigrationEntry> {
+        self.entries
+            .get(&migration.file)
+            .filter(|entry| entry.sha256 == migration.sha256)
+    }
+
+    /// The result that `migration` is compared against. Migrations that are not in the baseline
+    /// are compared against an empty result, so any locks or rewrites they introduce must be
+    /// approved by recording a new baseline.
+    fn baseline_for(&self, migration: &Migration) -> InspectedStatement {
+        self.entries
+            .get(&migration.file)
+            .map(|entry| entry.inspected.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// List the `.sql` files in `directory`, in the order they are applied, which is the lexical
+/// order of their file names.
+fn read_migrations(directory: &Path) -> anyhow::Result<Vec<Migration>> {
+    let mut paths = vec![];
+    for entry in std::fs::read_dir(directory)
+        .with_context(|| format!("Reading migrations directory {directory:?}"))?
+    {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "sql") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Reading migration file {path:?}"))?;
+            let file = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            Ok(Migration {
+                file,
+                sha256: format!("{:x}", Sha256::digest(&contents)),
+                contents,
+            })
+        })
+        .collect()
+}
+
+/// Inspect every migration in `directory` with `oracle`, in order, writing a [MigrationEntry] for
+/// each to `output` as a line of JSON, or the file name and [summary] of each with
+/// [OutputFormat::Summary].
+///
+/// Each migration is committed once it has been inspected, so every migration is inspected against
+/// the schema left by the ones before it. If a `baseline` is given, migrations whose contents are
+/// unchanged since it was recorded are applied without being inspected and their baseline entry
+/// is written instead. The command then fails once every migration has been processed if any of
+/// the others take locks or cause rewrites that are not present in their baseline entry.
+pub async fn inspect_migrations(
+    oracle: &mut QueryOracle,
+    directory: &Path,
+    baseline: Option<MigrationBaseline>,
+    format: OutputFormat,
+    mut output: Output,
+) -> anyhow::Result<()> {
+    let migrations = read_migrations(directory)?;
+    if migrations.is_empty() {
+        bail!("No `.sql` files found in migrations directory {directory:?}");
+    }
+
+    let mut exceeded = vec![];
+    for migration in migrations {
+        let file = migration.file.as_str();
+        let entry = match baseline
+            .as_ref()
+            .and_then(|baseline| baseline.unchanged_entry(&migration))
+        {
+            Some(entry) => {
+                info!(file, "Applying unchanged migration without inspecting it");
+                oracle
+                    .apply_statement(&migration.contents)
+                    .await
+                    .with_context(|| format!("Applying migration {file}"))?;
+                entry.clone()
+            }
+            None => {
+                info!(file, "Inspecting migration");
+                let inspected = oracle
+                    .inspect_statement(&migration.contents)
+                    .await
+                    .inspect_err(|e| print_statement_error(e, &migration.contents))
+                    .with_context(|| format!("Inspecting migration {file}"))?;
+                if let Some(baseline) = &baseline {
+                    let comparison =
+                        inspected.compare_to_baseline(&baseline.baseline_for(&migration));
+                    if !comparison.is_empty() {
+                        error!(
+                            file,
+                            new_locks = ?comparison.new_locks,
+                            new_rewrites = ?comparison.new_rewrites,
+                            "Migration exceeds the baseline"
+                        );
+                        exceeded.push(migration.file.clone());
+                    }
+                }
+                MigrationEntry {
+                    file: migration.file,
+                    sha256: migration.sha256,
+                    inspected,
+                }
+            }
+        };
+        match format {
+            OutputFormat::Summary => {
+                writeln!(output, "{}: {}", entry.file, summary(&entry.inspected))?;
+            }
+            _ => {
+                serde_json::to_writer(&mut output, &entry)?;
+                writeln!(output)?;
+            }
+        }
+    }
+
+    if !exceeded.is_empty() {
+        bail!(
+            "Migrations take locks or cause rewrites that are not present in the baseline: {}",
+            exceeded.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use itertools::Itertools;
+use locksmith::{InspectedStatement, LockCost};
+
+/// Summarize `inspected` on a single line, for the `summary` output format. Each locked table is
+/// grouped under the strongest lock taken on it, from the strongest lock to the weakest, followed
+/// by the number of objects added and removed, the number renamed if there are any, and the number
+/// of tables rewritten. Tables that are scanned or rewritten while locked are marked with their
+/// [LockCost], as the lock is held for time proportional to their size. For example:
+///
+/// ```text
+/// AccessExclusiveLock on customers (rewrite), orders; ShareLock on products; 1 added, 1 removed, 1 rewrite
+/// ```
+pub fn summary(inspected: &InspectedStatement) -> String {
+    let locks = inspected
+        .table_operations()
+        .into_iter()
+        .map(|operation| (operation.lock.clone(), operation))
+        .into_group_map()
+        .into_iter()
+        .sorted_by(|(a, _), (b, _)| b.strength().cmp(&a.strength()).then_with(|| a.cmp(b)))
+        .map(|(lock, operations)| {
+            let tables = operations
+                .iter()
+                .sorted_by(|a, b| a.table.cmp(&b.table))
+                .map(|operation| match operation.cost {
+                    LockCost::Instant => operation.table.to_string(),
+                    LockCost::Scan => format!("{} (scan)", operation.table),
+                    LockCost::Rewrite => format!("{} (rewrite)", operation.table),
+                });
+            format!("{lock} on {}", tables.format(", "))
+        })
+        .join("; ");
+    let rewrites = match inspected.rewrites.len() {
+        1 => "1 rewrite".to_string(),
+        count => format!("{count} rewrites"),
+    };
+    let renamed = match inspected.renamed.len() {
+        0 => String::new(),
+        count => format!(", {count} renamed"),
+    };
+    format!(
+        "{}; {} added, {} removed{renamed}, {rewrites}",
+        if locks.is_empty() { "no locks" } else { &locks },
+        inspected.added_objects.len(),
+        inspected.removed_objects.len(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::summary;
+    use locksmith::{
+        DBObject, IndexObject, InspectedStatement, Lock, LockCost, RenamedObject, TableLock,
+        TableLockCost,
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_summary() {
+        let lock = |table: &str, lock: Lock| TableLock {
+            table: table.into(),
+            lock,
+        };
+        let cost = |table: &str, cost: LockCost| TableLockCost {
+            table: table.into(),
+            cost,
+            total_bytes: 8192,
+            estimated_rows: None,
+        };
+        let inspected = InspectedStatement {
+            added_objects: HashSet::from([DBObject::Table("products".into())]),
+            locks: HashSet::from([
+                lock("orders", Lock::AccessExclusiveLock),
+                lock("orders", Lock::AccessShareLock),
+                lock("products", Lock::ShareLock),
+                lock("customers", Lock::AccessExclusiveLock),
+            ]),
+            rewrites: HashSet::from([DBObject::Table("orders".into())]),
+            lock_costs: HashSet::from([
+                cost("orders", LockCost::Rewrite),
+                cost("products", LockCost::Scan),
+                cost("customers", LockCost::Instant),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            summary(&inspected),
+            "AccessExclusiveLock on customers, orders (rewrite); ShareLock on products (scan); \
+             1 added, 0 removed, 1 rewrite"
+        );
+        assert_eq!(
+            summary(&InspectedStatement::default()),
+            "no locks; 0 added, 0 removed, 0 rewrites"
+        );
+        let index = |name: &str| {
+            DBObject::Index(IndexObject {
+                table: "orders".into(),
+                name: name.to_string(),
+                columns: vec!["price".to_string()],
+                is_primary: false,
+                is_unique: false,
+            })
+        };
+        let rename = InspectedStatement {
+            renamed: HashSet::from([RenamedObject {
+                from: index("orders_price_idx"),
+                to: index("orders_price_index"),
+            }]),
+            ..Default::default()
+        };
+        assert_eq!(
+            summary(&rename),
+            "no locks; 0 added, 0 removed, 1 renamed, 0 rewrites"
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use assert_cmd::cargo;
-use locksmith::{InspectedStatement, Lock, TableLock};
+use locksmith::{DBObject, InspectedStatement, Lock, LockCost, TableLock};
 use std::collections::HashSet;
 
 const TEST_SCHEMA_PATH: &str = concat!(
@@ -24,5 +24,240 @@ fn test_basic_cli() {
         ..Default::default()
     };
     let output: InspectedStatement = serde_json::from_slice(&output.stdout).unwrap();
-    assert_eq!(output, expected)
+    let costs: Vec<_> = output
+        .lock_costs
+        .iter()
+        .map(|cost| (cost.table.name.as_str(), cost.cost))
+        .collect();
+    assert_eq!(costs, vec![("customers", LockCost::Scan)]);
+    assert_eq!(
+        InspectedStatement {
+            lock_costs: HashSet::new(),
+            ..output
+        },
+        expected
+    )
+}
+
+#[test]
+fn test_summary_format() {
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    cmd.arg(TEST_SCHEMA_PATH)
+        .arg("alter table customers alter column id type bigint")
+        .arg("--format")
+        .arg("summary")
+        .assert()
+        .success()
+        .stdout(
+            "AccessExclusiveLock on customers (rewrite), orders (scan); 1 added, 1 removed, \
+             1 rewrite\n",
+        );
+}
+
+#[test]
+fn test_dsn_rejects_schema_file() {
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    cmd.arg("--dsn")
+        .arg("postgresql://localhost/postgres")
+        .arg(TEST_SCHEMA_PATH)
+        .arg("select * from customers")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_baseline() {
+    let baseline_path = std::env::temp_dir().join("locksmith-test-baseline.json");
+    let locks = HashSet::from([TableLock {
+        table: "customers".into(),
+        lock: Lock::AccessShareLock,
+    }]);
+    std::fs::write(&baseline_path, serde_json::to_vec(&locks).unwrap()).unwrap();
+
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    cmd.arg(TEST_SCHEMA_PATH)
+        .arg("select * from customers")
+        .arg("--baseline")
+        .arg(&baseline_path)
+        .assert()
+        .success();
+
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    cmd.arg(TEST_SCHEMA_PATH)
+        .arg("select * from customers join orders on orders.customer_id = customers.id")
+        .arg("--baseline")
+        .arg(&baseline_path)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_introspect() {
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    let assert = cmd
+        .arg("introspect")
+        .arg(TEST_SCHEMA_PATH)
+        .assert()
+        .success();
+    let objects: Vec<DBObject> = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert!(objects.contains(&DBObject::Table("customers".into())));
+    assert!(objects.contains(&DBObject::Table("orders".into())));
+}
+
+#[test]
+fn test_search_path() {
+    let schema_path = std::env::temp_dir().join("locksmith-test-search-path.sql");
+    std::fs::write(
+        &schema_path,
+        "create schema app; create table app.accounts (id int primary key);",
+    )
+    .unwrap();
+
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    cmd.arg(&schema_path)
+        .arg("select * from accounts")
+        .arg("--search-path")
+        .arg("app")
+        .arg("--format")
+        .arg("summary")
+        .assert()
+        .success()
+        .stdout("AccessShareLock on accounts (scan); 0 added, 0 removed, 0 rewrites\n");
+
+    // A search path without any existing schema is a usage error, rather than resolving every
+    // object against an empty search path.
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    let assert = cmd
+        .arg(&schema_path)
+        .arg("select * from accounts")
+        .arg("--search-path")
+        .arg("missing")
+        .assert()
+        .code(2);
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("No schema in `--search-path` exists: missing"));
+}
+
+#[test]
+fn test_compare_versions() {
+    let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+    let assert = cmd
+        .arg("compare-versions")
+        .arg("--tags")
+        .arg("14-alpine,15-alpine")
+        .arg(TEST_SCHEMA_PATH)
+        .arg("select * from customers")
+        .assert()
+        .success();
+    let output: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    for tag in ["14-alpine", "15-alpine"] {
+        let inspected: InspectedStatement =
+            serde_json::from_value(output["versions"][tag].clone()).unwrap();
+        assert!(inspected.locks.contains(&TableLock {
+            table: "customers".into(),
+            lock: Lock::AccessShareLock,
+        }));
+    }
+    assert_eq!(output["divergent_locks"], serde_json::json!([]));
+}
+
+#[test]
+fn test_migrations_baseline() {
+    let directory = std::env::temp_dir().join("locksmith-test-migrations");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(
+        directory.join("001_products.sql"),
+        "create table products (id integer);",
+    )
+    .unwrap();
+    std::fs::write(
+        directory.join("002_price.sql"),
+        "alter table products add column price integer;",
+    )
+    .unwrap();
+    let migrations = |baseline: Option<&std::path::Path>| {
+        let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+        cmd.arg("migrations").arg(TEST_SCHEMA_PATH).arg(&directory);
+        if let Some(baseline) = baseline {
+            cmd.arg("--baseline").arg(baseline);
+        }
+        cmd.assert()
+    };
+
+    let report = migrations(None).success().get_output().stdout.clone();
+    let entries: Vec<serde_json::Value> = report
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["file"], "001_products.sql");
+    assert_eq!(entries[1]["file"], "002_price.sql");
+
+    // Unchanged migrations pass, and a new migration that takes locks fails.
+    let baseline_path = directory.join("baseline.jsonl");
+    std::fs::write(&baseline_path, &report).unwrap();
+    migrations(Some(&baseline_path)).success();
+    std::fs::write(
+        directory.join("003_customers.sql"),
+        "alter table customers add column email text;",
+    )
+    .unwrap();
+    migrations(Some(&baseline_path)).failure();
+}
+
+#[test]
+fn test_check() {
+    let directory = std::env::temp_dir().join("locksmith-test-check");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let migration = directory.join("migration.sql");
+    std::fs::write(
+        &migration,
+        "alter table customers add column email text;\n\
+         create index customers_email_idx on customers (email);\n",
+    )
+    .unwrap();
+    let check = |migration: &std::path::Path, fail_on: Option<&str>| {
+        let mut cmd = cargo::cargo_bin_cmd!("locksmith-cli");
+        cmd.arg("check").arg(TEST_SCHEMA_PATH).arg(migration);
+        if let Some(fail_on) = fail_on {
+            cmd.arg("--fail-on").arg(fail_on);
+        }
+        cmd.assert()
+    };
+
+    let report = check(&migration, Some("rewrite"))
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let entries: Vec<serde_json::Value> = report
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1]["line"], 2);
+    assert_eq!(
+        entries[1]["warnings"][0]["rule"],
+        "prefer-create-index-concurrently"
+    );
+    assert_eq!(
+        entries[1]["operations"],
+        serde_json::json!([{"table": {"name": "customers"}, "lock": "ShareLock", "cost": "Scan"}])
+    );
+    check(&migration, Some("lint")).failure();
+
+    // A statement that fails to be inspected is reported along with its position.
+    std::fs::write(
+        &migration,
+        "alter table customers add column email text;\n\
+         alter table orders add colum x int;\n",
+    )
+    .unwrap();
+    let assert = check(&migration, None).failure();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr);
+    assert!(stderr.contains("Statement 2 at line 2"));
 }
@@ -0,0 +1,556 @@
+use crate::{
+    DBObject, IndexObject, InspectedStatement, LockCost, PredicateLock, RenamedObject,
+    RewriteColumns, RewriteEstimate, TableLock, TableLockCost, TableObject, TableSize,
+    ViewDependency,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Everything the [QueryOracle](crate::QueryOracle) observes while inspecting a statement: the
+/// state of the database before and after the statement was executed, and the locks it was seen
+/// taking.
+///
+/// The oracle only uses the database to collect an observation, and turns it into an
+/// [InspectedStatement] with [StatementObservation::analyze], which does not use the database.
+/// This allows the analysis to be tested against observations constructed by hand, without
+/// starting Postgres.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct StatementObservation {
+    /// The objects in the database before the statement was executed, see
+    /// [Introspector::list_objects](crate::Introspector::list_objects).
+    pub objects_before: HashSet<DBObject>,
+    /// The objects in the database after the statement was executed.
+    pub objects_after: HashSet<DBObject>,
+    /// The file nodes of the tables and indexes in the database before the statement was
+    /// executed, see
+    /// [Introspector::list_object_file_nodes](crate::Introspector::list_object_file_nodes).
+    pub file_nodes_before: HashMap<DBObject, i32>,
+    /// The file nodes of the tables and indexes in the database after the statement was
+    /// executed.
+    pub file_nodes_after: HashMap<DBObject, i32>,
+    /// The sizes of the tables before the statement was executed, see
+    /// [Introspector::list_table_sizes](crate::Introspector::list_table_sizes).
+    pub table_sizes: Vec<TableSize>,
+    /// The views that depended on tables before the statement was executed, see
+    /// [Introspector::list_view_dependencies](crate::Introspector::list_view_dependencies).
+    pub view_dependencies: Vec<ViewDependency>,
+    /// See [InspectedStatement::locks].
+    pub locks: HashSet<TableLock>,
+    /// See [InspectedStatement::commit_locks].
+    pub commit_locks: HashSet<TableLock>,
+    /// See [InspectedStatement::unverified_locks].
+    pub unverified_locks: HashSet<TableLock>,
+    /// The tables that were read in full by the statement.
+    pub scanned_tables: HashSet<TableObject>,
+    /// See [InspectedStatement::affected_rows].
+    pub affected_rows: Option<u64>,
+    /// See [InspectedStatement::predicate_locks].
+    pub predicate_locks: HashSet<PredicateLock>,
+}
+
+impl StatementObservation {
+    /// Work out the side effects of the statement from the observation.
+    ///
+    /// [InspectedStatement::idempotent] requires executing the statement a second time, so it is
+    /// always `None`.
+    pub fn analyze(self) -> InspectedStatement {
+        let mut added_objects: HashSet<_> = self
+            .objects_after
+            .difference(&self.objects_before)
+            .cloned()
+            .collect();
+        let mut removed_objects: HashSet<_> = self
+            .objects_before
+            .difference(&self.objects_after)
+            .cloned()
+            .collect();
+        let renamed = renamed_indexes(
+            &self.file_nodes_before,
+            &self.file_nodes_after,
+            &mut added_objects,
+            &mut removed_objects,
+        );
+        let rewrites = rewritten_tables(&self.file_nodes_before, self.file_nodes_after);
+        let rewrite_estimates = rewrite_estimates(&self.table_sizes, &rewrites);
+        let rewrite_columns = rewrite_columns(&rewrites, &added_objects);
+        let lock_costs = lock_costs(
+            self.table_sizes,
+            &self.locks,
+            &rewrites,
+            &self.scanned_tables,
+        );
+        let dependents = dependent_views(&self.view_dependencies, &removed_objects);
+
+        InspectedStatement {
+            added_objects,
+            removed_objects,
+            renamed,
+            locks: self.locks,
+            commit_locks: self.commit_locks,
+            rewrites,
+            rewrite_estimates,
+            rewrite_columns,
+            lock_costs,
+            dependents,
+            unverified_locks: self.unverified_locks,
+            idempotent: None,
+            affected_rows: self.affected_rows,
+            predicate_locks: self.predicate_locks,
+        }
+    }
+}
+
+/// Return the tables in `objects` that the locker should lock, leaving out views and
+/// `excluded_tables`. Locking a view also locks the tables it reads from, so a lock on the view
+/// could never be told apart from a lock on one of its tables.
+pub(crate) fn lockable_tables<'a>(
+    objects: &'a HashSet<DBObject>,
+    view_dependencies: &[ViewDependency],
+    excluded_tables: &HashSet<TableObject>,
+) -> HashSet<&'a TableObject> {
+    let views: HashSet<_> = view_dependencies
+        .iter()
+        .filter_map(|dependency| match &dependency.view {
+            DBObject::Table(view) => Some(view),
+            _ => None,
+        })
+        .collect();
+    objects
+        .iter()
+        .filter_map(|obj| match obj {
+            DBObject::Table(table)
+                if !views.contains(table) && !excluded_tables.contains(table) =>
+            {
+                Some(table)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return the foreign tables in `objects`. Foreign tables cannot be locked by the locker, so
+/// locks on them never block the executor.
+pub(crate) fn foreign_tables(objects: &HashSet<DBObject>) -> HashSet<TableObject> {
+    objects
+        .iter()
+        .filter_map(|obj| match obj {
+            DBObject::ForeignTable(table) => Some(table.name.as_str().into()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Return the tables that have been rewritten. A rewritten table will always have a different
+/// file node than the original table. Indexes are rebuilt along with their table, so they are
+/// left out.
+fn rewritten_tables(
+    file_nodes_before: &HashMap<DBObject, i32>,
+    file_nodes_after: HashMap<DBObject, i32>,
+) -> HashSet<DBObject> {
+    file_nodes_after
+        .into_iter()
+        .filter_map(|(table, node)| match file_nodes_before.get(&table) {
+            Some(initial_node) if initial_node != &node && matches!(table, DBObject::Table(_)) => {
+                Some(table)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Find the indexes in `removed_objects` that were renamed to an index in `added_objects`, which
+/// has the same file node and only differs by its name, and take both out of the added and
+/// removed objects. An index that is also changed, such as by becoming the index of a primary key
+/// via `ADD CONSTRAINT ... USING INDEX`, is still reported as removed and added.
+fn renamed_indexes(
+    file_nodes_before: &HashMap<DBObject, i32>,
+    file_nodes_after: &HashMap<DBObject, i32>,
+    added_objects: &mut HashSet<DBObject>,
+    removed_objects: &mut HashSet<DBObject>,
+) -> HashSet<RenamedObject> {
+    let renamed: HashSet<_> = removed_objects
+        .iter()
+        .filter_map(|from| {
+            let DBObject::Index(removed) = from else {
+                return None;
+            };
+            let node = file_nodes_before.get(from)?;
+            let to = added_objects.iter().find(|to| {
+                matches!(to, DBObject::Index(added)
+                    if added.name != removed.name
+                        && IndexObject { name: removed.name.clone(), ..added.clone() } == *removed)
+                    && file_nodes_after.get(to) == Some(node)
+            })?;
+            Some(RenamedObject {
+                from: from.clone(),
+                to: to.clone(),
+            })
+        })
+        .collect();
+    for rename in &renamed {
+        removed_objects.remove(&rename.from);
+        added_objects.remove(&rename.to);
+    }
+    renamed
+}
+
+/// Estimate the amount of data written by each rewrite as the size of the table before it was
+/// rewritten. Rewritten tables without a known size are left out.
+fn rewrite_estimates(
+    table_sizes: &[TableSize],
+    rewrites: &HashSet<DBObject>,
+) -> HashSet<RewriteEstimate> {
+    table_sizes
+        .iter()
+        .filter(|size| rewrites.contains(&DBObject::Table(size.table.clone())))
+        .map(|size| RewriteEstimate {
+            table: size.table.clone(),
+            rewrite_bytes_estimate: size.total_bytes,
+        })
+        .collect()
+}
+
+/// Find the columns of each table in `rewrites` that are in `added_objects`, either because they
+/// are new or because they were changed and so were removed and added again.
+fn rewrite_columns(
+    rewrites: &HashSet<DBObject>,
+    added_objects: &HashSet<DBObject>,
+) -> HashSet<RewriteColumns> {
+    let mut columns: HashMap<&TableObject, Vec<String>> = HashMap::new();
+    for object in added_objects {
+        if let DBObject::Column(column) = object
+            && rewrites.contains(&DBObject::Table(column.table.clone()))
+        {
+            columns
+                .entry(&column.table)
+                .or_default()
+                .push(column.name.clone());
+        }
+    }
+    columns
+        .into_iter()
+        .map(|(table, mut columns)| {
+            columns.sort();
+            RewriteColumns {
+                table: table.clone(),
+                columns,
+            }
+        })
+        .collect()
+}
+
+/// Estimate the cost of the locks taken on each table from whether the statement rewrote or
+/// scanned it, alongside the size of the table before the statement was executed. Tables without
+/// any of `locks` are left out.
+fn lock_costs(
+    table_sizes: Vec<TableSize>,
+    locks: &HashSet<TableLock>,
+    rewrites: &HashSet<DBObject>,
+    scanned_tables: &HashSet<TableObject>,
+) -> HashSet<TableLockCost> {
+    table_sizes
+        .into_iter()
+        .filter(|size| locks.iter().any(|lock| lock.table == size.table))
+        .map(|size| {
+            let cost = if rewrites.contains(&DBObject::Table(size.table.clone())) {
+                LockCost::Rewrite
+            } else if scanned_tables.contains(&size.table) {
+                LockCost::Scan
+            } else {
+                LockCost::Instant
+            };
+            TableLockCost {
+                table: size.table,
+                cost,
+                total_bytes: size.total_bytes,
+                estimated_rows: size.estimated_rows,
+            }
+        })
+        .collect()
+}
+
+/// Return the views in `dependencies` that depend on a table or column in `removed_objects`. An
+/// altered column is removed and added again, so this also includes views that depend on altered
+/// columns. Views that depend on a dependent view are included in turn.
+fn dependent_views(
+    dependencies: &[ViewDependency],
+    removed_objects: &HashSet<DBObject>,
+) -> HashSet<DBObject> {
+    let mut affected_tables: HashSet<TableObject> = HashSet::new();
+    let mut affected_columns: HashSet<(&TableObject, &str)> = HashSet::new();
+    for object in removed_objects {
+        match object {
+            DBObject::Table(table) => {
+                affected_tables.insert(table.clone());
+            }
+            DBObject::Column(column) => {
+                affected_columns.insert((&column.table, &column.name));
+            }
+            _ => {}
+        }
+    }
+
+    let mut dependents = HashSet::new();
+    loop {
+        let mut changed = false;
+        for dependency in dependencies {
+            let is_affected = affected_tables.contains(&dependency.table)
+                || dependency
+                    .column
+                    .as_deref()
+                    .is_some_and(|column| affected_columns.contains(&(&dependency.table, column)));
+            if is_affected && dependents.insert(dependency.view.clone()) {
+                changed = true;
+                match &dependency.view {
+                    DBObject::Table(view) => affected_tables.insert(view.clone()),
+                    DBObject::MaterializedView(view) => {
+                        affected_tables.insert(view.name.as_str().into())
+                    }
+                    _ => false,
+                };
+            }
+        }
+        if !changed {
+            return dependents;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{foreign_tables, lockable_tables};
+    use crate::{
+        ColumnObject, DBObject, ForeignTableObject, IndexObject, InspectedStatement, Lock,
+        LockCost, MaterializedViewObject, RenamedObject, RewriteColumns, RewriteEstimate,
+        StatementObservation, TableLock, TableLockCost, TableObject, TableSize, ViewDependency,
+    };
+    use std::collections::{HashMap, HashSet};
+
+    fn column(table: &str, name: &str, data_type: &str) -> DBObject {
+        DBObject::Column(ColumnObject {
+            table: table.into(),
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            generated: None,
+        })
+    }
+
+    fn table_size(table: &str) -> TableSize {
+        TableSize {
+            table: table.into(),
+            total_bytes: 8192,
+            estimated_rows: Some(10),
+        }
+    }
+
+    #[test]
+    fn test_analyze_alter_column_type() {
+        // `alter table orders alter column id type bigint`, which rewrites orders while holding
+        // an ACCESS EXCLUSIVE lock, and drops the view that reads orders.id.
+        let orders = DBObject::Table("orders".into());
+        let observation = StatementObservation {
+            objects_before: HashSet::from([
+                orders.clone(),
+                DBObject::Table("customers".into()),
+                column("orders", "id", "integer"),
+            ]),
+            objects_after: HashSet::from([
+                orders.clone(),
+                DBObject::Table("customers".into()),
+                column("orders", "id", "bigint"),
+            ]),
+            file_nodes_before: HashMap::from([
+                (orders.clone(), 1),
+                (DBObject::Table("customers".into()), 2),
+            ]),
+            file_nodes_after: HashMap::from([
+                (orders.clone(), 3),
+                (DBObject::Table("customers".into()), 2),
+            ]),
+            table_sizes: vec![table_size("orders"), table_size("customers")],
+            view_dependencies: vec![ViewDependency {
+                view: DBObject::Table("order_ids".into()),
+                table: "orders".into(),
+                column: Some("id".to_string()),
+            }],
+            locks: HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            observation.analyze(),
+            InspectedStatement {
+                added_objects: HashSet::from([column("orders", "id", "bigint")]),
+                removed_objects: HashSet::from([column("orders", "id", "integer")]),
+                locks: HashSet::from([TableLock {
+                    table: "orders".into(),
+                    lock: Lock::AccessExclusiveLock,
+                }]),
+                rewrites: HashSet::from([orders]),
+                rewrite_estimates: HashSet::from([RewriteEstimate {
+                    table: "orders".into(),
+                    rewrite_bytes_estimate: 8192,
+                }]),
+                rewrite_columns: HashSet::from([RewriteColumns {
+                    table: "orders".into(),
+                    columns: vec!["id".to_string()],
+                }]),
+                lock_costs: HashSet::from([TableLockCost {
+                    table: "orders".into(),
+                    cost: LockCost::Rewrite,
+                    total_bytes: 8192,
+                    estimated_rows: Some(10),
+                }]),
+                dependents: HashSet::from([DBObject::Table("order_ids".into())]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_analyze_lock_costs() {
+        let lock = |table: &str| TableLock {
+            table: table.into(),
+            lock: Lock::ShareRowExclusiveLock,
+        };
+        let observation = StatementObservation {
+            table_sizes: vec![table_size("orders"), table_size("customers")],
+            locks: HashSet::from([lock("orders"), lock("customers")]),
+            scanned_tables: HashSet::from(["customers".into()]),
+            ..Default::default()
+        };
+        let mut costs: Vec<_> = observation
+            .analyze()
+            .lock_costs
+            .into_iter()
+            .map(|cost| (cost.table, cost.cost))
+            .collect();
+        costs.sort();
+        assert_eq!(
+            costs,
+            vec![
+                ("customers".into(), LockCost::Scan),
+                ("orders".into(), LockCost::Instant),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_dependent_views() {
+        // order_totals reads orders, and the materialized view order_report reads order_totals.
+        let dependency = |view: DBObject, table: &str, column: Option<&str>| ViewDependency {
+            view,
+            table: table.into(),
+            column: column.map(str::to_string),
+        };
+        let order_report = DBObject::MaterializedView(MaterializedViewObject {
+            name: "order_report".to_string(),
+        });
+        let view_dependencies = vec![
+            dependency(
+                DBObject::Table("order_totals".into()),
+                "orders",
+                Some("price"),
+            ),
+            dependency(order_report.clone(), "order_totals", None),
+        ];
+        let dependents = |removed: DBObject| {
+            StatementObservation {
+                objects_before: HashSet::from([removed]),
+                view_dependencies: view_dependencies.clone(),
+                ..Default::default()
+            }
+            .analyze()
+            .dependents
+        };
+
+        assert_eq!(
+            dependents(column("orders", "price", "integer")),
+            HashSet::from([DBObject::Table("order_totals".into()), order_report])
+        );
+        assert!(dependents(column("orders", "notes", "text")).is_empty());
+    }
+
+    #[test]
+    fn test_analyze_renamed_index() {
+        let index = |name: &str| {
+            DBObject::Index(IndexObject {
+                table: "orders".into(),
+                name: name.to_string(),
+                columns: vec!["price".to_string()],
+                is_primary: false,
+                is_unique: false,
+            })
+        };
+        let orders = DBObject::Table("orders".into());
+        // `alter index orders_price_idx rename to orders_price_index`, which keeps the file node
+        // of the index and of its table.
+        let observation = StatementObservation {
+            objects_before: HashSet::from([orders.clone(), index("orders_price_idx")]),
+            objects_after: HashSet::from([orders.clone(), index("orders_price_index")]),
+            file_nodes_before: HashMap::from([(orders.clone(), 1), (index("orders_price_idx"), 2)]),
+            file_nodes_after: HashMap::from([
+                (orders.clone(), 1),
+                (index("orders_price_index"), 2),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            observation.clone().analyze(),
+            InspectedStatement {
+                renamed: HashSet::from([RenamedObject {
+                    from: index("orders_price_idx"),
+                    to: index("orders_price_index"),
+                }]),
+                ..Default::default()
+            }
+        );
+
+        // Dropping the index and creating it again with another name builds a new file node.
+        let recreated = StatementObservation {
+            file_nodes_after: HashMap::from([(orders, 1), (index("orders_price_index"), 3)]),
+            ..observation
+        }
+        .analyze();
+        assert!(recreated.renamed.is_empty());
+        assert_eq!(
+            recreated.added_objects,
+            HashSet::from([index("orders_price_index")])
+        );
+        assert_eq!(
+            recreated.removed_objects,
+            HashSet::from([index("orders_price_idx")])
+        );
+        assert!(recreated.rewrites.is_empty());
+    }
+
+    #[test]
+    fn test_lockable_tables() {
+        let objects = HashSet::from([
+            DBObject::Table("orders".into()),
+            DBObject::Table("customers".into()),
+            DBObject::Table("order_totals".into()),
+            DBObject::ForeignTable(ForeignTableObject {
+                name: "remote_orders".to_string(),
+                server: "remote".to_string(),
+            }),
+        ]);
+        let view_dependencies = [ViewDependency {
+            view: DBObject::Table("order_totals".into()),
+            table: "orders".into(),
+            column: None,
+        }];
+        let excluded = HashSet::from([TableObject::from("customers")]);
+
+        assert_eq!(
+            lockable_tables(&objects, &view_dependencies, &excluded),
+            HashSet::from([&TableObject::from("orders")])
+        );
+        assert_eq!(
+            foreign_tables(&objects),
+            HashSet::from([TableObject::from("remote_orders")])
+        );
+    }
+}
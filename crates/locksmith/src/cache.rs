@@ -0,0 +1,172 @@
+use crate::{InspectedStatement, SchemaSnapshot};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// A cache of inspection results, set via
+/// [QueryOracleBuilder::cache](crate::QueryOracleBuilder::cache).
+///
+/// Results are keyed by a [CacheKey], which changes whenever the statement, the schema it is
+/// inspected against, the major version of the server or the oracle's settings change. Keys are
+/// stable across runs, so implementations can persist results to disk or a shared store such as
+/// Redis to avoid re-inspecting unchanged statements in CI. Keys may change when locksmith or Rust
+/// is upgraded, which only causes statements to be inspected again. [MemoryCache] keeps results
+/// in memory.
+pub trait InspectionCache: Send + Sync {
+    /// Get the result stored for `key`, if any.
+    fn get(&self, key: CacheKey) -> Option<InspectedStatement>;
+
+    /// Store the result of inspecting a statement under `key`.
+    fn insert(&self, key: CacheKey, inspected: &InspectedStatement);
+}
+
+/// The key of a cached inspection result, see [InspectionCache].
+///
+/// Keys are displayed as 16 hexadecimal digits, which can be used as a file name.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct CacheKey(pub u64);
+
+impl CacheKey {
+    /// Create the key for inspecting `statement` against a database matching `snapshot`, see
+    /// [SchemaSnapshot::schema_hash].
+    pub fn new(statement: &str, snapshot: &SchemaSnapshot) -> Self {
+        let mut hasher = StableHasher::default();
+        hasher.write_u64(statement.len() as u64);
+        hasher.write(statement.as_bytes());
+        hasher.write_u64(snapshot.schema_hash());
+        CacheKey(hasher.finish())
+    }
+
+    /// Combine the key with `value`, such as the settings that the result depends on.
+    pub(crate) fn with(self, value: impl Hash) -> Self {
+        let mut hasher = StableHasher::default();
+        self.0.hash(&mut hasher);
+        value.hash(&mut hasher);
+        CacheKey(hasher.finish())
+    }
+}
+
+impl Display for CacheKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// An [InspectionCache] that keeps results in memory, for the lifetime of the cache.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    results: Mutex<HashMap<CacheKey, InspectedStatement>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of results in the cache.
+    pub fn len(&self) -> usize {
+        self.results.lock().unwrap().len()
+    }
+
+    /// Returns `true` if the cache does not contain any results.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl InspectionCache for MemoryCache {
+    fn get(&self, key: CacheKey) -> Option<InspectedStatement> {
+        self.results.lock().unwrap().get(&key).cloned()
+    }
+
+    fn insert(&self, key: CacheKey, inspected: &InspectedStatement) {
+        self.results.lock().unwrap().insert(key, inspected.clone());
+    }
+}
+
+/// A [Hasher] implementing 64-bit FNV-1a, which unlike [std::hash::DefaultHasher] gives the same
+/// result for the same bytes across runs, Rust versions and platforms. Integers are always hashed
+/// as little-endian bytes, with `usize` and `isize` widened to 64 bits.
+///
+/// The bytes that a [Hash] implementation writes are not guaranteed to stay the same between Rust
+/// versions, such as the terminator written after a `str`, so values that must hash the same
+/// forever are written as explicit bytes instead.
+#[derive(Debug)]
+pub(crate) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::StableHasher;
+    use crate::{CacheKey, InspectedStatement, InspectionCache, MemoryCache};
+    use std::hash::{Hash, Hasher};
+
+    #[test]
+    fn test_stable_hasher() {
+        // Published FNV-1a test vectors.
+        let hash = |bytes: &[u8]| {
+            let mut hasher = StableHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+
+        let mut hasher = StableHasher::default();
+        1usize.hash(&mut hasher);
+        assert_eq!(hasher.finish(), hash(&1u64.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_memory_cache() {
+        let cache = MemoryCache::new();
+        let key = CacheKey(1);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(key), None);
+        cache.insert(key, &InspectedStatement::default());
+        assert_eq!(cache.get(key), Some(InspectedStatement::default()));
+        assert_eq!(cache.get(CacheKey(2)), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(key.to_string(), "0000000000000001");
+    }
+}
@@ -0,0 +1,29 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+
+/// The source of time used to confirm blocks in the [StatementExecutor](crate::StatementExecutor)
+/// and to time the lock timeline of the [QueryOracle](crate::QueryOracle).
+///
+/// Time is only read through this trait so that tests of timing-sensitive behaviour can replace
+/// the system clock with one whose time they control.
+pub(crate) trait Clock: Send + Sync {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// Wait until `deadline` has been reached.
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+/// A [Clock] that uses the system time, via the timer of the [runtime](crate::runtime).
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        crate::runtime::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(crate::runtime::sleep_until(deadline))
+    }
+}
@@ -0,0 +1,425 @@
+use crate::{InspectedStatement, TableObject};
+use sqlx::postgres::{PgDatabaseError, PgErrorPosition};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use tokio_postgres::error::{DbError, ErrorPosition};
+
+/// The error returned by [QueryOracle::inspect_statement](crate::QueryOracle::inspect_statement),
+/// and by the methods of the [StatementExecutor](crate::StatementExecutor), the
+/// [Introspector](crate::Introspector) and the [Locker](crate::Locker).
+#[derive(Debug)]
+pub enum LocksmithError {
+    /// Inspection failed after the statement started being executed, before all of its side
+    /// effects were resolved. `partial` contains everything that was detected up to that point,
+    /// such as the locks discovered in earlier iterations.
+    Unresolvable {
+        partial: Box<InspectedStatement>,
+        source: anyhow::Error,
+    },
+    /// The statement referenced a table that does not exist, and the database does not contain
+    /// any tables at all. This usually means that the schema failed to load, for example because
+    /// the wrong schema file was given.
+    EmptySchema { source: anyhow::Error },
+    /// The statement drops an object that other objects depend on without `CASCADE`, so it fails
+    /// under the default `RESTRICT` behaviour. `dependents` describes each dependent object, as
+    /// reported by Postgres, such as `view order_prices depends on table orders`.
+    DependentObjects {
+        partial: Box<InspectedStatement>,
+        dependents: Vec<String>,
+        source: anyhow::Error,
+    },
+    /// The statement is blocked by a lock that the locker does not hold, such as a lock on a
+    /// table held by another session or a row locked by another transaction, so executing it
+    /// again would block in the same way. `table` is the table whose lock the statement is
+    /// waiting for, if it is waiting for a lock on a table in the current schema, and `partial`
+    /// contains the locks detected before the statement was blocked on it.
+    UnobservableLock {
+        partial: Box<InspectedStatement>,
+        table: Option<TableObject>,
+        detail: String,
+    },
+    /// The statement was still blocked after the maximum number of iterations set by
+    /// [QueryOracleBuilder::max_iterations](crate::QueryOracleBuilder::max_iterations). `partial`
+    /// contains the locks detected in those iterations.
+    IterationLimit {
+        partial: Box<InspectedStatement>,
+        iterations: u32,
+    },
+    /// Inspecting the statement took longer than the timeout set by
+    /// [QueryOracleBuilder::timeout](crate::QueryOracleBuilder::timeout). `partial` contains the
+    /// locks detected before the timeout.
+    Timeout {
+        partial: Box<InspectedStatement>,
+        elapsed: Duration,
+        timeout: Duration,
+    },
+    /// The connection to the database could not be established, or was lost whilst it was in
+    /// use, such as when the server is unreachable or the backend was terminated.
+    Connection { source: anyhow::Error },
+    /// Postgres rejected a statement, such as one executed by the
+    /// [StatementExecutor](crate::StatementExecutor) or a `LOCK TABLE` run by the
+    /// [Locker](crate::Locker). `sqlstate` is the SQLSTATE code of the error, such as `42601`
+    /// for a syntax error, and `message` is its primary message.
+    StatementFailed {
+        sqlstate: String,
+        message: String,
+        source: anyhow::Error,
+    },
+    /// A query that reads the system catalogs failed, such as when the
+    /// [Introspector](crate::Introspector) lists the objects in the database or the
+    /// [Locker](crate::Locker) lists the locks held by a connection.
+    Introspection { source: anyhow::Error },
+    /// Any other error, such as a DSN that cannot be parsed.
+    ///
+    /// This, [LocksmithError::Connection], [LocksmithError::StatementFailed] and
+    /// [LocksmithError::Introspection] are displayed as the error they wrap.
+    Other(anyhow::Error),
+}
+
+impl LocksmithError {
+    /// The partial [InspectedStatement] carried by the error, if any.
+    pub fn partial(&self) -> Option<&InspectedStatement> {
+        match self {
+            LocksmithError::Unresolvable { partial, .. }
+            | LocksmithError::DependentObjects { partial, .. }
+            | LocksmithError::UnobservableLock { partial, .. }
+            | LocksmithError::IterationLimit { partial, .. }
+            | LocksmithError::Timeout { partial, .. } => Some(partial),
+            LocksmithError::EmptySchema { .. }
+            | LocksmithError::Connection { .. }
+            | LocksmithError::StatementFailed { .. }
+            | LocksmithError::Introspection { .. }
+            | LocksmithError::Other(_) => None,
+        }
+    }
+
+    /// The error returned by Postgres when executing the statement, if it was rejected by the
+    /// server, such as because of a syntax error or a reference to a column that does not exist.
+    pub fn statement_error(&self) -> Option<StatementError> {
+        let error = match self {
+            LocksmithError::Unresolvable { source, .. }
+            | LocksmithError::EmptySchema { source }
+            | LocksmithError::DependentObjects { source, .. }
+            | LocksmithError::Connection { source }
+            | LocksmithError::StatementFailed { source, .. }
+            | LocksmithError::Introspection { source } => source,
+            LocksmithError::UnobservableLock { .. }
+            | LocksmithError::IterationLimit { .. }
+            | LocksmithError::Timeout { .. } => return None,
+            LocksmithError::Other(e) => e,
+        };
+        error.chain().find_map(|e| {
+            if let Some(e) = e.downcast_ref::<tokio_postgres::Error>() {
+                return e.as_db_error().map(StatementError::from);
+            }
+            let e = e.downcast_ref::<sqlx::Error>()?.as_database_error()?;
+            Some(StatementError::from(
+                e.try_downcast_ref::<PgDatabaseError>()?,
+            ))
+        })
+    }
+
+    /// Convert the failure of a query that reads the system catalogs into a
+    /// [LocksmithError::Introspection], unless the connection was lost.
+    pub(crate) fn introspection(error: anyhow::Error) -> Self {
+        match LocksmithError::from(error) {
+            LocksmithError::StatementFailed { source, .. } | LocksmithError::Other(source) => {
+                LocksmithError::Introspection { source }
+            }
+            error => error,
+        }
+    }
+}
+
+/// How a failure of the database, found in the chain of an error, is reported.
+enum DatabaseFailure {
+    Connection,
+    Statement { sqlstate: String, message: String },
+    Introspection,
+}
+
+impl DatabaseFailure {
+    /// Classify the first error in the chain of `error` that came from the database or a
+    /// connection to it, if any.
+    fn of(error: &anyhow::Error) -> Option<Self> {
+        error.chain().find_map(|e| {
+            if let Some(e) = e.downcast_ref::<LocksmithError>() {
+                return match e {
+                    LocksmithError::Connection { .. } => Some(DatabaseFailure::Connection),
+                    LocksmithError::StatementFailed {
+                        sqlstate, message, ..
+                    } => Some(DatabaseFailure::statement(sqlstate, message)),
+                    LocksmithError::Introspection { .. } => Some(DatabaseFailure::Introspection),
+                    _ => None,
+                };
+            }
+            if let Some(e) = e.downcast_ref::<tokio_postgres::Error>() {
+                // Errors without a database error, such as IO errors, are classified by their
+                // source, which is next in the chain.
+                return match e.as_db_error() {
+                    Some(db) => Some(DatabaseFailure::statement(db.code().code(), db.message())),
+                    None if e.is_closed() => Some(DatabaseFailure::Connection),
+                    None => None,
+                };
+            }
+            if let Some(e) = e.downcast_ref::<sqlx::Error>() {
+                return match e {
+                    sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::Protocol(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+                    | sqlx::Error::WorkerCrashed => Some(DatabaseFailure::Connection),
+                    _ => e.as_database_error().map(|db| {
+                        DatabaseFailure::statement(&db.code().unwrap_or_default(), db.message())
+                    }),
+                };
+            }
+            e.is::<std::io::Error>()
+                .then_some(DatabaseFailure::Connection)
+        })
+    }
+
+    /// A statement that failed with `sqlstate`. Errors in the connection exception class, and
+    /// those sent when the server shuts down or terminates the backend, are connection failures.
+    fn statement(sqlstate: &str, message: &str) -> Self {
+        if sqlstate.starts_with("08") || matches!(sqlstate, "57P01" | "57P02" | "57P03") {
+            return DatabaseFailure::Connection;
+        }
+        DatabaseFailure::Statement {
+            sqlstate: sqlstate.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// An error returned by Postgres when executing a statement, found via
+/// [LocksmithError::statement_error].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StatementError {
+    /// The SQLSTATE code of the error, such as `42601` for a syntax error.
+    pub code: String,
+    pub message: String,
+    /// The position of the error in the statement, as a 1-based index of a character, if
+    /// Postgres reported one.
+    pub position: Option<usize>,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl StatementError {
+    /// Describe the error in the same way as `psql`, including the line of `statement` that the
+    /// error is at, with a caret pointing at the offending token.
+    pub fn describe(&self, statement: &str) -> String {
+        let mut description = format!("ERROR {}: {}", self.code, self.message);
+        if let Some(position) = self.position {
+            let before: String = statement.chars().take(position.saturating_sub(1)).collect();
+            let line_number = before.matches('\n').count() + 1;
+            let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+            let column = before[line_start..].chars().count();
+            let line = statement[line_start..].lines().next().unwrap_or_default();
+            let prefix = format!("LINE {line_number}: ");
+            description.push_str(&format!(
+                " at position {position}\n{prefix}{line}\n{}^",
+                " ".repeat(prefix.len() + column)
+            ));
+        }
+        if let Some(detail) = &self.detail {
+            description.push_str(&format!("\nDETAIL: {detail}"));
+        }
+        if let Some(hint) = &self.hint {
+            description.push_str(&format!("\nHINT: {hint}"));
+        }
+        description
+    }
+}
+
+impl From<&PgDatabaseError> for StatementError {
+    fn from(error: &PgDatabaseError) -> Self {
+        StatementError {
+            code: error.code().to_string(),
+            message: error.message().to_string(),
+            position: match error.position() {
+                Some(PgErrorPosition::Original(position)) => Some(position),
+                Some(PgErrorPosition::Internal { .. }) | None => None,
+            },
+            detail: error.detail().map(str::to_string),
+            hint: error.hint().map(str::to_string),
+        }
+    }
+}
+
+impl From<&DbError> for StatementError {
+    fn from(error: &DbError) -> Self {
+        StatementError {
+            code: error.code().code().to_string(),
+            message: error.message().to_string(),
+            // Errors within a function body are positioned within the function's own query.
+            position: match error.position() {
+                Some(ErrorPosition::Original(position)) => Some(*position as usize),
+                Some(ErrorPosition::Internal { .. }) | None => None,
+            },
+            detail: error.detail().map(str::to_string),
+            hint: error.hint().map(str::to_string),
+        }
+    }
+}
+
+impl Display for LocksmithError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocksmithError::Unresolvable { .. } => {
+                write!(f, "Unable to resolve all side effects of the statement")
+            }
+            LocksmithError::EmptySchema { .. } => write!(
+                f,
+                "The statement references a table that does not exist, and the database does not \
+                 contain any tables. Check that the schema was loaded."
+            ),
+            LocksmithError::DependentObjects { dependents, .. } => write!(
+                f,
+                "The statement drops objects that other objects depend on, so it fails without \
+                 CASCADE: {}",
+                dependents.join("; ")
+            ),
+            LocksmithError::UnobservableLock { detail, .. } => write!(
+                f,
+                "The statement is blocked by a lock that the locker does not hold: {detail}"
+            ),
+            LocksmithError::IterationLimit { iterations, .. } => write!(
+                f,
+                "The statement was still blocked after {iterations} iterations, the maximum set \
+                 by QueryOracleBuilder::max_iterations"
+            ),
+            LocksmithError::Timeout {
+                elapsed, timeout, ..
+            } => write!(
+                f,
+                "Inspecting the statement took {elapsed:?}, longer than the timeout of \
+                 {timeout:?} set by QueryOracleBuilder::timeout"
+            ),
+            LocksmithError::Connection { source }
+            | LocksmithError::StatementFailed { source, .. }
+            | LocksmithError::Introspection { source }
+            | LocksmithError::Other(source) => Display::fmt(source, f),
+        }
+    }
+}
+
+impl std::error::Error for LocksmithError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LocksmithError::Unresolvable { source, .. }
+            | LocksmithError::EmptySchema { source }
+            | LocksmithError::DependentObjects { source, .. } => Some(source.as_ref()),
+            LocksmithError::UnobservableLock { .. }
+            | LocksmithError::IterationLimit { .. }
+            | LocksmithError::Timeout { .. } => None,
+            LocksmithError::Connection { source }
+            | LocksmithError::StatementFailed { source, .. }
+            | LocksmithError::Introspection { source }
+            | LocksmithError::Other(source) => source.source(),
+        }
+    }
+}
+
+/// Errors that are already a [LocksmithError] are returned as they are, and other errors caused by
+/// the database are classified by the first such error in their chain. Any other error is a
+/// [LocksmithError::Other].
+impl From<anyhow::Error> for LocksmithError {
+    fn from(error: anyhow::Error) -> Self {
+        // Downcasting also finds an error that context was added to, which would lose the context.
+        if error
+            .chain()
+            .next()
+            .is_some_and(|e| e.is::<LocksmithError>())
+        {
+            return error.downcast().expect("the error is a LocksmithError");
+        }
+        match DatabaseFailure::of(&error) {
+            Some(DatabaseFailure::Connection) => LocksmithError::Connection { source: error },
+            Some(DatabaseFailure::Statement { sqlstate, message }) => {
+                LocksmithError::StatementFailed {
+                    sqlstate,
+                    message,
+                    source: error,
+                }
+            }
+            Some(DatabaseFailure::Introspection) => LocksmithError::Introspection { source: error },
+            None => LocksmithError::Other(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{LocksmithError, StatementError};
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_anyhow_error() {
+        let error = anyhow::Error::new(LocksmithError::Connection {
+            source: anyhow::anyhow!("Connection unexpectedly finished"),
+        });
+        let error = LocksmithError::from(error);
+        assert!(
+            matches!(error, LocksmithError::Connection { .. }),
+            "{error:?}"
+        );
+
+        // Errors are classified by the first error from the database in their chain.
+        let error = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+            .context("Reading message from server")
+            .unwrap_err();
+        let error = LocksmithError::from(error);
+        assert!(
+            matches!(error, LocksmithError::Connection { .. }),
+            "{error:?}"
+        );
+        let error = anyhow::Error::new(LocksmithError::Introspection {
+            source: anyhow::anyhow!("Query error while listing tables"),
+        })
+        .context("Listing initial objects");
+        let error = LocksmithError::from(error);
+        assert!(
+            matches!(error, LocksmithError::Introspection { .. }),
+            "{error:?}"
+        );
+        assert_eq!(
+            format!("{:#}", anyhow::Error::new(error)),
+            "Listing initial objects: Query error while listing tables"
+        );
+
+        let error = LocksmithError::from(anyhow::anyhow!("Parsing DSN"));
+        assert!(matches!(error, LocksmithError::Other(_)), "{error:?}");
+    }
+
+    #[test]
+    fn test_describe_statement_error() {
+        let error = StatementError {
+            code: "42703".to_string(),
+            message: "column \"nme\" does not exist".to_string(),
+            position: Some(22),
+            detail: None,
+            hint: Some("Perhaps you meant to reference the column \"customers.name\".".to_string()),
+        };
+        let statement = "select 1;\nselect id, nme from customers;";
+        assert_eq!(
+            error.describe(statement),
+            "ERROR 42703: column \"nme\" does not exist at position 22\n\
+             LINE 2: select id, nme from customers;\n\
+             \x20                  ^\n\
+             HINT: Perhaps you meant to reference the column \"customers.name\"."
+        );
+
+        let error = StatementError {
+            position: None,
+            hint: None,
+            ..error
+        };
+        assert_eq!(
+            error.describe(statement),
+            "ERROR 42703: column \"nme\" does not exist"
+        );
+    }
+}
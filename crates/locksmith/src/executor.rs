@@ -1,41 +1,156 @@
-use crate::ConnectionID;
-use anyhow::{Context, bail};
+use crate::clock::{Clock, SystemClock};
+use crate::statement::{modifies_rows, transaction_control_command};
+use crate::tls::{TlsConfig, TlsStream, make_tls};
+use crate::{ConnectionID, Lock, LocksmithError, TableObject};
+use anyhow::{Context, anyhow};
+use futures::TryStreamExt;
+use futures::future::{self, Either};
+use std::collections::HashSet;
+use std::future::{Future, poll_fn};
 use std::pin::pin;
-use tokio_postgres::tls::NoTlsStream;
-use tokio_postgres::{AsyncMessage, Client, Connection, NoTls, Socket};
-use tracing::{debug, trace};
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+use tokio_postgres::error::{DbError, Severity, SqlState};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{AsyncMessage, Client, Config, Connection, SimpleQueryMessage, Socket};
+use tracing::{debug, trace, warn};
+
+/// How long a statement must remain blocked after the server reports a lock wait before it is
+/// treated as blocked. Locks that are held briefly by other sessions, such as internal locks, can
+/// be granted shortly after `deadlock_timeout` elapses, and should not be reported as blocks.
+pub(crate) const BLOCK_GRACE_PERIOD: Duration = Duration::from_millis(50);
+
+/// The settings enabled by the setup statements run by [StatementExecutor::new], along with the
+/// value each must have. Lock waits are detected from the messages that these settings cause the
+/// server to send, so they must not be changed by [StatementExecutor::execute_setup_statements].
+/// `deadlock_timeout` must not be changed either, but its value can be set with
+/// [StatementExecutor::set_deadlock_timeout].
+const LOCK_WAIT_SETTINGS: [(&str, &str); 2] =
+    [("log_lock_waits", "on"), ("client_min_messages", "log")];
+
+/// TCP keepalive settings for executor connections. A connection that silently dies, such as when
+/// a NAT mapping expires or the server's host goes away, is otherwise only noticed once the
+/// operating system's default of two hours has passed, and the executor would wait for messages
+/// from the server until then. With these settings a dead connection is detected within a
+/// minute, and the executor's connection fails with an IO error.
+///
+/// Keepalive probes are sent once the connection has been idle for [KEEPALIVES_IDLE], then every
+/// [KEEPALIVES_INTERVAL] until [KEEPALIVES_RETRIES] have gone unanswered. [TCP_USER_TIMEOUT]
+/// bounds how long data that has been sent can go unacknowledged, which detects a dead connection
+/// whilst a statement is being sent. Shorter values given in the DSN are kept.
+const KEEPALIVES_IDLE: Duration = Duration::from_secs(15);
+const KEEPALIVES_INTERVAL: Duration = Duration::from_secs(5);
+const KEEPALIVES_RETRIES: u32 = 6;
+const TCP_USER_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Parse `dsn` into the configuration of an executor connection, with TCP keepalive enabled as
+/// described in [KEEPALIVES_IDLE] unless the DSN disables it with `keepalives=0`.
+fn connection_config(dsn: &str) -> anyhow::Result<Config> {
+    let mut config: Config = dsn.parse().context("Parsing DSN")?;
+    let interval = config
+        .get_keepalives_interval()
+        .map_or(KEEPALIVES_INTERVAL, |interval| {
+            interval.min(KEEPALIVES_INTERVAL)
+        });
+    let retries = config
+        .get_keepalives_retries()
+        .map_or(KEEPALIVES_RETRIES, |retries| {
+            retries.min(KEEPALIVES_RETRIES)
+        });
+    let tcp_user_timeout = config
+        .get_tcp_user_timeout()
+        .map_or(TCP_USER_TIMEOUT, |timeout| (*timeout).min(TCP_USER_TIMEOUT));
+    config
+        .keepalives_idle(config.get_keepalives_idle().min(KEEPALIVES_IDLE))
+        .keepalives_interval(interval)
+        .keepalives_retries(retries)
+        .tcp_user_timeout(tcp_user_timeout);
+    Ok(config)
+}
+
+/// Sum the rows in the command tags of the commands in `statement` that modify rows, given the
+/// rows of each command in order, see [StatementExecutor::affected_rows]. Returns `None` if no
+/// command modifies rows, or if the commands could not be matched to the command tags.
+fn affected_rows(statement: &str, rows: &[u64]) -> Option<u64> {
+    let modifies_rows = modifies_rows(statement);
+    if modifies_rows.len() != rows.len() || !modifies_rows.contains(&true) {
+        return None;
+    }
+    Some(
+        modifies_rows
+            .into_iter()
+            .zip(rows)
+            .filter_map(|(modifies_rows, rows)| modifies_rows.then_some(rows))
+            .sum(),
+    )
+}
 
 /// A [StatementExecutor] is a client for executing statements on a Postgres database.
-/// It provides a method, [StatementExecutor::check_statement_for_locks], which executes a statement
-/// and returns true if the statement was blocked by a lock.
+/// It provides methods, [StatementExecutor::execute_statement] and [StatementExecutor::commit],
+/// which execute a statement or commit the transaction and return true if they were blocked by
+/// a lock, and [StatementExecutor::execute_without_commit], which runs a statement to completion
+/// within the transaction.
+///
+/// Dropping a [StatementExecutor] whilst a statement is blocked, or whilst it is still executing
+/// because the future executing it was dropped, cancels the statement on a best effort basis, see
+/// [StatementExecutor::close].
 pub struct StatementExecutor {
     client: Client,
-    connection: Connection<Socket, NoTlsStream>,
+    connection: Connection<Socket, TlsStream>,
     connection_id: ConnectionID,
+    /// Set when a statement has been reported as blocked, and is therefore still waiting for a
+    /// lock on the server, or whilst a statement is executing.
+    blocked: bool,
+    /// The lock that the last statement was waiting for when it was cancelled by `lock_timeout`.
+    timed_out_wait: Option<RelationLockWait>,
+    /// The number of rows modified by the last statement, if it completed without being blocked.
+    affected_rows: Option<u64>,
+    clock: Arc<dyn Clock>,
+    /// The TLS configuration of cancel requests, which connect to the server separately.
+    tls: TlsConfig,
+    /// The value of `deadlock_timeout`, as shown by the server, which setup statements must not
+    /// change.
+    deadlock_timeout: String,
 }
 
 impl StatementExecutor {
     /// Create a new [StatementExecutor] with a connection to the Postgres database at `dsn`.
-    pub async fn new(dsn: &str) -> anyhow::Result<Self> {
+    ///
+    /// TLS is used according to the `sslmode` parameter of the DSN, provided that one of the
+    /// `tls-rustls` or `tls-native-tls` features is enabled. TCP keepalive is enabled so that a
+    /// connection that silently dies is detected within a minute, rather than leaving the
+    /// inspection waiting for the server indefinitely.
+    pub async fn new(dsn: &str) -> Result<Self, LocksmithError> {
+        Self::new_with_tls(dsn, &TlsConfig::default()).await
+    }
+
+    /// Create a new [StatementExecutor] as in [StatementExecutor::new], using TLS as configured
+    /// by `tls`.
+    pub async fn new_with_tls(dsn: &str, tls: &TlsConfig) -> Result<Self, LocksmithError> {
+        let mut config = connection_config(dsn)?;
+        tls.configure(&mut config);
         // We have to use [tokio-postgres](https://crates.io/crates/tokio-postgres) for this, because
         // sqlx does not give us the ability to receive NOTICE messages from the server.
-        let (client, mut connection) = tokio_postgres::connect(dsn, NoTls)
+        let (client, mut connection) = config
+            .connect(make_tls(tls)?)
             .await
-            .context("Creating connection")?;
+            .context("Creating connection")
+            .map_err(|source| LocksmithError::Connection { source })?;
 
+        crate::log_sql("executor", None, "SELECT pg_backend_pid()");
         // There are some peculiarities when using tokio-postgres compared to sqlx, namely that the
         // client and the connection are separate and need to be driven separately.
-        // We do this by using `tokio::select!` to drive them both in parallel.
-        // If the connection future ever finishes before the client future then we bail out.
-        let connection_id = tokio::select! {
-            row = client.query_one("SELECT pg_backend_pid()", &[]) => {
-                let row = row.context("Query error while retrieving connection ID")?;
-                ConnectionID(row.get(0))
-            },
-            _ = &mut connection => {
-                bail!("Connection unexpectedly finished: retrieving connection ID")
-            }
-        };
+        // We do this by using `drive` to poll them both in parallel, which bails out if the
+        // connection future ever finishes before the client future.
+        let row = drive(
+            &mut connection,
+            client.query_one("SELECT pg_backend_pid()", &[]),
+            "retrieving connection ID",
+        )
+        .await?
+        .context("Query error while retrieving connection ID")?;
+        let connection_id = ConnectionID(row.get(0));
 
         // These statements are necessary to enable logging of lock waits. See
         // `detect_if_statement_blocks` for the implementation details.
@@ -45,23 +160,253 @@ impl StatementExecutor {
             SET deadlock_timeout='1ms';
             SET client_min_messages='log';
         "#;
-        tokio::select! {
-            setup_result = client.batch_execute(SETUP_STATEMENTS) => {
-                setup_result.context("Query error while executing setup statement")?
-            },
-            _ = &mut connection => bail!("Connection unexpectedly finished: executing setup statement")
-        }
+        crate::log_sql("executor", Some(connection_id), SETUP_STATEMENTS);
+        drive(
+            &mut connection,
+            client.batch_execute(SETUP_STATEMENTS),
+            "executing setup statement",
+        )
+        .await?
+        .context("Query error while executing setup statement")?;
 
         Ok(Self {
             client,
             connection,
             connection_id,
+            blocked: false,
+            timed_out_wait: None,
+            affected_rows: None,
+            clock: Arc::new(SystemClock),
+            tls: tls.clone(),
+            deadlock_timeout: "1ms".to_string(),
         })
     }
 
-    /// Attempt to terminate the backend connection. This is best-effort.
-    pub async fn attempt_termination(&self) {
-        self.client.cancel_token().cancel_query(NoTls).await.ok();
+    /// Set the [Clock] used to confirm that a statement is blocked, see [BLOCK_GRACE_PERIOD].
+    /// Defaults to the system clock.
+    pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Close the connection, first cancelling any blocked statement.
+    ///
+    /// Postgres does not notice that a client has disconnected whilst it is waiting for a lock,
+    /// so the backend of a blocked executor lingers, holding its locks and waiting in the lock
+    /// queue, until the lock it is waiting for is released. Dropping the [StatementExecutor] sends
+    /// the cancel request from a background task if a Tokio runtime is available, which may not
+    /// run if the runtime is shutting down. This method waits for the cancel request to be sent.
+    pub async fn close(mut self) -> Result<(), LocksmithError> {
+        if self.blocked {
+            self.client
+                .cancel_token()
+                .cancel_query(make_tls(&self.tls)?)
+                .await
+                .context("Cancelling blocked statement")?;
+            self.blocked = false;
+        }
+        Ok(())
+    }
+
+    /// Abort the transaction by cancelling any blocked statement and rolling back.
+    ///
+    /// Simply closing the connection is not enough: Postgres does not notice that a client has
+    /// disconnected whilst it is waiting for a lock, so a blocked `COMMIT` would go on to commit
+    /// the transaction as soon as the lock is released. This must be called before releasing any
+    /// locks that block the executor.
+    pub async fn abort(&mut self) -> Result<(), LocksmithError> {
+        self.client
+            .cancel_token()
+            .cancel_query(make_tls(&self.tls)?)
+            .await
+            .context("Cancelling blocked statement")?;
+        self.blocked = false;
+        // The cancel request is handled asynchronously by the server, so it can arrive after the
+        // blocked statement has already stopped and cancel the `ROLLBACK` instead. In that case
+        // the rollback is retried.
+        let mut retried = false;
+        loop {
+            self.log_sql("ROLLBACK;");
+            let rollback_result = drive(
+                &mut self.connection,
+                self.client.batch_execute("ROLLBACK;"),
+                "rolling back transaction",
+            )
+            .await?;
+            match rollback_result {
+                Ok(()) => break,
+                Err(e) if !retried && e.code() == Some(&SqlState::QUERY_CANCELED) => {
+                    debug!("Rollback cancelled, retrying");
+                    retried = true;
+                }
+                Err(e) => {
+                    return Err(anyhow::Error::new(e)
+                        .context("Query error while rolling back transaction")
+                        .into());
+                }
+            }
+        }
+        debug!("Transaction aborted");
+        Ok(())
+    }
+
+    /// Set the `search_path` of the executor's connection, which is used to resolve any
+    /// unqualified names in the statements it executes.
+    pub async fn set_search_path(
+        &mut self,
+        schemas: &[impl AsRef<str>],
+    ) -> Result<(), LocksmithError> {
+        let statement = crate::search_path_statement(schemas);
+        self.log_sql(&statement);
+        drive(
+            &mut self.connection,
+            self.client.batch_execute(&statement),
+            "setting search path",
+        )
+        .await?
+        .context("Query error while setting search path")?;
+        Ok(())
+    }
+
+    /// Set the `deadlock_timeout` of the executor's connection, which is how long a statement
+    /// waits for a lock before the server reports the wait and the statement is then treated as
+    /// blocked after a short grace period. Defaults to 1ms, which reports every wait as soon as
+    /// possible.
+    ///
+    /// Postgres also checks for deadlocks once a lock has been waited for this long, so a
+    /// longer timeout avoids running the check for every wait on a busy server, at the cost of
+    /// taking longer to detect each lock. Waits that are shorter than the timeout are not
+    /// reported, so the timeout must be shorter than any `lock_timeout`.
+    pub async fn set_deadlock_timeout(&mut self, timeout: Duration) -> Result<(), LocksmithError> {
+        let statement = format!("SET deadlock_timeout = '{}ms';", timeout.as_millis().max(1));
+        self.log_sql(&statement);
+        drive(
+            &mut self.connection,
+            self.client.batch_execute(&statement),
+            "setting deadlock timeout",
+        )
+        .await?
+        .context("Query error while setting deadlock timeout")?;
+        let row = drive(
+            &mut self.connection,
+            self.client.query_one("SHOW deadlock_timeout", &[]),
+            "reading deadlock timeout",
+        )
+        .await?
+        .context("Query error while reading deadlock timeout")?;
+        self.deadlock_timeout = row.get(0);
+        Ok(())
+    }
+
+    /// Set the `application_name` of the executor's connection, which identifies it in
+    /// `pg_stat_activity` and the server's logs.
+    pub async fn set_application_name(&mut self, name: &str) -> Result<(), LocksmithError> {
+        let statement = crate::application_name_statement(name);
+        self.log_sql(&statement);
+        drive(
+            &mut self.connection,
+            self.client.batch_execute(&statement),
+            "setting application name",
+        )
+        .await?
+        .context("Query error while setting application name")?;
+        Ok(())
+    }
+
+    /// Assume `role` on the executor's connection with `SET ROLE`, so that the statements it
+    /// executes run with the privileges of that role. The settings that lock waits are detected
+    /// with are set before the role is assumed, so it does not need to be a superuser.
+    pub async fn set_role(&mut self, role: &str) -> Result<(), LocksmithError> {
+        let statement = crate::role_statement(role);
+        self.log_sql(&statement);
+        drive(
+            &mut self.connection,
+            self.client.batch_execute(&statement),
+            "setting role",
+        )
+        .await?
+        .with_context(|| format!("Query error while setting role {role}"))?;
+        Ok(())
+    }
+
+    /// Execute additional setup statements in the executor's transaction, such as `SET role` or
+    /// `SET lock_timeout`, before the statement being inspected is executed. Settings changed
+    /// with `SET` last until the transaction ends.
+    ///
+    /// Fails if `sql` contains a command that ends the transaction, or if it changes any of the
+    /// settings that lock waits are detected with, such as `log_lock_waits`.
+    pub async fn execute_setup_statements(&mut self, sql: &str) -> Result<(), LocksmithError> {
+        if let Some(command) = transaction_control_command(sql) {
+            return Err(anyhow!("Setup statements cannot contain a `{command}` command").into());
+        }
+        self.log_sql(sql);
+        drive(
+            &mut self.connection,
+            self.client.batch_execute(sql),
+            "executing setup statements",
+        )
+        .await?
+        .context("Query error while executing setup statements")?;
+
+        let settings_query = format!(
+            "SELECT {}",
+            LOCK_WAIT_SETTINGS
+                .iter()
+                .map(|(name, _)| name)
+                .chain([&"deadlock_timeout"])
+                .map(|name| format!("current_setting('{name}')"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        self.log_sql(&settings_query);
+        let row = drive(
+            &mut self.connection,
+            self.client.query_one(&settings_query, &[]),
+            "checking lock wait settings",
+        )
+        .await?
+        .context("Query error while checking lock wait settings")?;
+        let expected_settings = LOCK_WAIT_SETTINGS
+            .iter()
+            .copied()
+            .chain([("deadlock_timeout", self.deadlock_timeout.as_str())]);
+        for (index, (name, expected)) in expected_settings.enumerate() {
+            let value: String = row.get(index);
+            if value != expected {
+                return Err(anyhow!(
+                    "Setup statements cannot change `{name}`, which is required to detect lock \
+                     waits: expected {expected:?}, found {value:?}"
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Log a SQL statement the executor's connection is about to run, see
+    /// [SQL_LOG_TARGET](crate::SQL_LOG_TARGET).
+    fn log_sql(&self, sql: &str) {
+        crate::log_sql("executor", Some(self.connection_id), sql);
+    }
+
+    /// The lock on a relation that the last statement was waiting for when it was cancelled by
+    /// `lock_timeout`, if it was.
+    ///
+    /// A statement that is cancelled by `lock_timeout`, or that fails to take a lock with
+    /// `NOWAIT`, is reported as blocked, but is no longer waiting for the lock and so the lock
+    /// does not appear in `pg_locks`. The lock is instead taken from the server's lock wait
+    /// notice. The transaction is aborted, and must be rolled back with
+    /// [StatementExecutor::abort].
+    pub(crate) fn timed_out_wait(&self) -> Option<&RelationLockWait> {
+        self.timed_out_wait.as_ref()
+    }
+
+    /// The number of rows inserted, updated, deleted or merged by the last statement executed by
+    /// [StatementExecutor::execute_statement] or [StatementExecutor::execute_statement_with_params],
+    /// summed over each of its commands that modify rows and taken from their command tags, such
+    /// as `UPDATE 1234`. This is `None` if the statement was blocked or does not modify rows.
+    pub fn affected_rows(&self) -> Option<u64> {
+        self.affected_rows
     }
 
     /// Get the connection ID for this [StatementExecutor].
@@ -69,24 +414,92 @@ impl StatementExecutor {
         self.connection_id
     }
 
-    /// the statement was blocked by a lock.
-    ///
-    /// Postgres takes some locks when `COMMIT`ing a transaction, so this method will also attempt
-    /// to commit the transaction after executing the statement.
+    /// Execute a statement within the executor's transaction, returning true if the statement was
+    /// blocked by a lock.
     ///
     /// If this method returns `true`, the connection and transaction will still be open and
     /// blocked until the [StatementExecutor] is dropped. This allows the caller to inspect
     /// the locks taken by the statement via another connection.
     #[tracing::instrument(skip(self, statement))]
-    pub async fn check_statement_for_locks(&mut self, statement: &str) -> anyhow::Result<bool> {
-        for to_execute in [statement, "COMMIT;"] {
-            let is_blocked = self.detect_if_statement_blocks(to_execute).await?;
-            if is_blocked {
-                return Ok(true);
-            }
+    pub async fn execute_statement(&mut self, statement: &str) -> Result<bool, LocksmithError> {
+        self.detect_if_statement_blocks(statement).await
+    }
+
+    /// Execute a single statement with bind parameters within the executor's transaction,
+    /// returning true if the statement was blocked by a lock. This behaves in the same way as
+    /// [StatementExecutor::execute_statement], except that the statement is executed with the
+    /// extended query protocol so it cannot contain multiple statements.
+    #[tracing::instrument(skip(self, statement, params))]
+    pub async fn execute_statement_with_params(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<bool, LocksmithError> {
+        self.detect_if_blocks(statement, Some(params)).await
+    }
+
+    /// Execute a statement within the executor's transaction and wait for it to complete, leaving
+    /// the transaction open.
+    ///
+    /// Unlike [StatementExecutor::execute_statement] this does not detect lock waits, so it waits
+    /// for any conflicting locks to be released. Once it returns, the locks held by the statement
+    /// can be inspected from another connection, such as with
+    /// [Locker::list_connection_locks](crate::Locker::list_connection_locks) and
+    /// [StatementExecutor::connection_id], before either calling [StatementExecutor::commit] or
+    /// rolling back with [StatementExecutor::abort].
+    #[tracing::instrument(skip(self, statement))]
+    pub async fn execute_without_commit(&mut self, statement: &str) -> Result<(), LocksmithError> {
+        self.log_sql(statement);
+        drive(
+            &mut self.connection,
+            self.client.batch_execute(statement),
+            "executing statement",
+        )
+        .await?
+        .context("Failed to execute statement")?;
+        debug!("Statement executed without committing");
+        Ok(())
+    }
+
+    /// List the tables in the current schema that have been sequentially scanned within the
+    /// executor's open transaction, such as when validating a constraint or rewriting a table.
+    ///
+    /// This uses the [pg_stat_xact_user_tables](https://www.postgresql.org/docs/current/monitoring-stats.html#MONITORING-PG-STAT-ALL-TABLES-VIEW)
+    /// view, which only counts the activity of the current transaction. It must be called after
+    /// the statement has executed and before the transaction is committed.
+    #[tracing::instrument(skip(self))]
+    pub async fn list_scanned_tables(&mut self) -> Result<HashSet<TableObject>, LocksmithError> {
+        const QUERY: &str = "SELECT relname::text FROM pg_stat_xact_user_tables \
+                             WHERE schemaname = current_schema() AND seq_scan > 0";
+        self.log_sql(QUERY);
+        let rows = drive(
+            &mut self.connection,
+            self.client.query(QUERY, &[]),
+            "listing scanned tables",
+        )
+        .await?
+        .context("Query error while listing scanned tables")?;
+        Ok(rows
+            .iter()
+            .map(|row| TableObject::from(row.get::<_, String>(0)))
+            .collect())
+    }
+
+    /// Commit the executor's transaction, returning true if the commit was blocked by a lock.
+    ///
+    /// Postgres can take locks when `COMMIT`ing a transaction, for example to check deferred
+    /// foreign key constraints, so this must be treated like any other statement. If the commit
+    /// is blocked then the transaction is left open and blocked in the same way as
+    /// [StatementExecutor::execute_statement]. Nothing is committed until the blocking lock is
+    /// released, so dropping the [StatementExecutor] aborts the transaction and leaves the
+    /// database unchanged.
+    #[tracing::instrument(skip(self))]
+    pub async fn commit(&mut self) -> Result<bool, LocksmithError> {
+        let is_blocked = self.detect_if_statement_blocks("COMMIT;").await?;
+        if !is_blocked {
+            debug!("Statement committed");
         }
-        debug!("Statement succeeded");
-        Ok(false)
+        Ok(is_blocked)
     }
 
     /// Detect if a statement is blocked by a lock. This
@@ -98,48 +511,319 @@ impl StatementExecutor {
     /// Server messages are delivered asynchronously to the [Connection], so we poll both the
     /// [Connection] and the [Client] in parallel to drive the query and receive messages.
     ///
-    /// If the server delivers us a NOTICE message that the statement is blocked, we stop polling
-    /// and return `true`. In this state the connection is still open and the transaction is still
-    /// intact.
+    /// If the server delivers us a NOTICE message that the statement is blocked, we keep polling
+    /// for [BLOCK_GRACE_PERIOD] to confirm that the block persists. If the statement completes or
+    /// the server reports that the lock was acquired within that time, the lock was only held
+    /// briefly and the statement is not considered blocked. Otherwise, we stop polling and return
+    /// `true`. In this state the connection is still open and the transaction is still intact.
     ///
-    /// When the [StatementExecutor] is dropped the connection will be closed and the transaction
-    /// will be aborted.
+    /// When the [StatementExecutor] is dropped or [closed](StatementExecutor::close) the blocked
+    /// statement is cancelled, the connection is closed and the transaction is aborted.
     #[tracing::instrument(skip(self, statement))]
-    async fn detect_if_statement_blocks(&mut self, statement: &str) -> anyhow::Result<bool> {
-        let mut poll_message_future = std::future::poll_fn(|cx| self.connection.poll_message(cx));
-        let mut execute_future = pin!(self.client.batch_execute(statement));
+    async fn detect_if_statement_blocks(
+        &mut self,
+        statement: &str,
+    ) -> Result<bool, LocksmithError> {
+        self.detect_if_blocks(statement, None).await
+    }
 
-        // Drive both the query future and the message future in parallel.
-        loop {
-            tokio::select! {
-                res = &mut execute_future => {
-                    res.context("Failed to execute statement")?;
-                    debug!("Statement executed successfully");
-                    return Ok(false)
-                },
-                async_message = &mut poll_message_future => {
-                    match async_message {
-                        None => {
-                            bail!("Connection unexpectedly finished: executing statement")
+    /// Detect if a statement is blocked by a lock, as in
+    /// [StatementExecutor::detect_if_statement_blocks]. If `params` are given the statement is
+    /// executed with the extended query protocol and the parameters bound to it, otherwise it is
+    /// executed with the simple query protocol and can contain multiple statements.
+    async fn detect_if_blocks(
+        &mut self,
+        statement: &str,
+        params: Option<&[&(dyn ToSql + Sync)]>,
+    ) -> Result<bool, LocksmithError> {
+        self.log_sql(statement);
+        self.timed_out_wait = None;
+        self.affected_rows = None;
+        let client = &self.client;
+        // Resolves to the number of rows in the command tag of each command in the statement.
+        // The simple query protocol returns the rows of any queries in the statement too, which
+        // are not kept.
+        let mut execute_future = pin!(async {
+            match params {
+                Some(params) => client
+                    .execute(statement, params)
+                    .await
+                    .map(|rows| vec![rows]),
+                None => {
+                    let mut rows = vec![];
+                    let mut messages = pin!(client.simple_query_raw(statement).await?);
+                    while let Some(message) = messages.try_next().await? {
+                        if let SimpleQueryMessage::CommandComplete(count) = message {
+                            rows.push(count);
                         }
-                        Some(msg) => {
-                            let async_message = msg.context("Reading message from server")?;
-                            trace!(?async_message, "Received message");
-
-                            if let AsyncMessage::Notice(msg) = async_message {
-                                // Postgres doesn't provide a structured code for notice messages,
-                                // so the best we can do is check the message text.
-                                let message = msg.message();
-                                if message.contains("still waiting for") {
-                                    debug!("Statement blocked");
-                                    return Ok(true)
-                                }
-                            }
+                    }
+                    Ok(rows)
+                }
+            }
+        });
+
+        // The statement is cancelled if the executor is dropped before it completes, such as when
+        // the inspection times out, so that it does not go on running on the server.
+        self.blocked = true;
 
+        // Set once the server reports that the statement is waiting for a lock, and cleared if the
+        // lock is then acquired.
+        let mut blocked_deadline = None;
+        // The relation lock that the statement is waiting for, if the notice could be read.
+        let mut waiting_for = None;
+
+        // Drive both the query future and the message future in parallel, along with the grace
+        // period once the statement is waiting for a lock.
+        loop {
+            let mut grace_period =
+                blocked_deadline.map(|deadline| self.clock.sleep_until(deadline));
+            let event = poll_fn(|cx| {
+                if let Poll::Ready(result) = execute_future.as_mut().poll(cx) {
+                    return Poll::Ready(ExecutionEvent::Executed(result));
+                }
+                if let Poll::Ready(message) = self.connection.poll_message(cx) {
+                    return Poll::Ready(ExecutionEvent::Message(Box::new(message)));
+                }
+                match &mut grace_period {
+                    Some(grace_period) => grace_period
+                        .as_mut()
+                        .poll(cx)
+                        .map(|()| ExecutionEvent::Blocked),
+                    None => Poll::Pending,
+                }
+            })
+            .await;
+
+            match event {
+                ExecutionEvent::Blocked => {
+                    debug!("Statement blocked");
+                    self.blocked = true;
+                    return Ok(true);
+                }
+                ExecutionEvent::Executed(res) => {
+                    self.blocked = false;
+                    // A `lock_timeout` that is shorter than the grace period cancels the
+                    // statement before it is confirmed as blocked, which is still a block.
+                    if let Err(e) = &res
+                        && e.code() == Some(&SqlState::LOCK_NOT_AVAILABLE)
+                        && let Some(wait) = waiting_for
+                    {
+                        debug!(?wait, "Statement cancelled whilst waiting for lock");
+                        self.timed_out_wait = Some(wait);
+                        return Ok(true);
+                    }
+                    let rows = res.context("Failed to execute statement")?;
+                    self.affected_rows = affected_rows(statement, &rows);
+                    debug!(
+                        affected_rows = self.affected_rows,
+                        "Statement executed successfully"
+                    );
+                    return Ok(false);
+                }
+                ExecutionEvent::Message(message) => {
+                    let Some(msg) = *message else {
+                        self.blocked = false;
+                        return Err(LocksmithError::Connection {
+                            source: anyhow!(
+                                "Connection unexpectedly finished: executing statement"
+                            ),
+                        });
+                    };
+                    if msg.is_err() {
+                        self.blocked = false;
+                    }
+                    // A dead connection is reported here once the keepalive probes fail.
+                    let async_message = msg
+                        .context("Reading message from server, the connection may have been lost")
+                        .map_err(|source| LocksmithError::Connection { source })?;
+                    trace!(?async_message, "Received message");
+
+                    if let AsyncMessage::Notice(msg) = async_message {
+                        let notice = LockWaitNotice::from(&msg);
+                        let lock_wait = match notice.lock_wait() {
+                            // The server reports at most one wait at a time, so a message that
+                            // can't be read alternates between the two.
+                            Some(LockWait::Unrecognised) if blocked_deadline.is_none() => {
+                                Some(LockWait::Waiting)
+                            }
+                            Some(LockWait::Unrecognised) => Some(LockWait::Acquired),
+                            lock_wait => lock_wait,
+                        };
+                        match lock_wait {
+                            Some(LockWait::Waiting) => {
+                                debug!("Statement waiting for lock");
+                                blocked_deadline = Some(self.clock.now() + BLOCK_GRACE_PERIOD);
+                                waiting_for = notice.waiting_for();
+                            }
+                            Some(LockWait::Acquired) => {
+                                debug!("Statement acquired lock after waiting");
+                                blocked_deadline = None;
+                                waiting_for = None;
+                            }
+                            _ => {}
                         }
                     }
                 }
+            }
+        }
+    }
+}
+
+/// The events that [StatementExecutor::detect_if_blocks] waits for whilst a statement executes.
+enum ExecutionEvent<T> {
+    /// The statement has been waiting for a lock for longer than [BLOCK_GRACE_PERIOD].
+    Blocked,
+    /// The statement finished executing.
+    Executed(T),
+    /// The server sent a message to the connection, or the connection finished.
+    Message(Box<Option<Result<AsyncMessage, tokio_postgres::Error>>>),
+}
+
+/// Drive `future`, which uses the [Client] of `connection`, to completion. The [Connection] sends
+/// the client's requests and receives their responses, so it must be polled at the same time.
+/// Fails with a [LocksmithError::Connection] if the connection finishes first, such as when the
+/// server closes it, with `action` describing what was being done.
+///
+/// This only uses runtime agnostic combinators, see [crate::runtime].
+async fn drive<T>(
+    connection: &mut Connection<Socket, TlsStream>,
+    future: impl Future<Output = T>,
+    action: &str,
+) -> Result<T, LocksmithError> {
+    let source = match future::select(pin!(future), connection).await {
+        Either::Left((output, _)) => return Ok(output),
+        Either::Right((Ok(()), _)) => anyhow!("Connection unexpectedly finished: {action}"),
+        Either::Right((Err(e), _)) => {
+            anyhow::Error::new(e).context(format!("Connection unexpectedly finished: {action}"))
+        }
+    };
+    Err(LocksmithError::Connection { source })
+}
+
+/// A change in whether a statement is waiting for a lock, as reported by a `log_lock_waits`
+/// notice.
+#[derive(Debug, Eq, PartialEq)]
+enum LockWait {
+    /// The statement has been waiting for a lock for longer than `deadlock_timeout`.
+    Waiting,
+    /// The statement acquired a lock that it was waiting for.
+    Acquired,
+    /// The notice comes from the lock manager, but its message is not one we recognise. This
+    /// happens when the server's `lc_messages` is not English.
+    Unrecognised,
+}
+
+/// A lock on a relation that a statement is waiting for, read from a lock wait notice.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct RelationLockWait {
+    pub(crate) lock: Lock,
+    /// The OID of the relation.
+    pub(crate) relation: u32,
+}
+
+/// The fields of a notice that identify it as a lock wait.
+///
+/// Postgres doesn't provide a structured code for lock wait notices: they are sent with the
+/// `LOG` severity and the `00000` code, along with the location of the lock manager routine
+/// that emits them. The message text is only used to tell the different kinds of notice apart.
+struct LockWaitNotice<'a> {
+    /// The non-localized severity, which is only sent by Postgres 9.6 and later.
+    severity: Option<Severity>,
+    /// The severity, which is localized according to `lc_messages`.
+    localized_severity: &'a str,
+    code: &'a SqlState,
+    file: Option<&'a str>,
+    routine: Option<&'a str>,
+    message: &'a str,
+}
+
+impl<'a> From<&'a DbError> for LockWaitNotice<'a> {
+    fn from(notice: &'a DbError) -> Self {
+        LockWaitNotice {
+            severity: notice.parsed_severity(),
+            localized_severity: notice.severity(),
+            code: notice.code(),
+            file: notice.file(),
+            routine: notice.routine(),
+            message: notice.message(),
+        }
+    }
+}
+
+impl LockWaitNotice<'_> {
+    fn lock_wait(&self) -> Option<LockWait> {
+        let is_log = match self.severity {
+            Some(severity) => severity == Severity::Log,
+            None => self.localized_severity == "LOG",
+        };
+        if !is_log || *self.code != SqlState::SUCCESSFUL_COMPLETION {
+            return None;
+        }
+
+        // All lock wait messages are emitted by `ProcSleep` in `proc.c`, so a notice from
+        // anywhere else is never a lock wait, whatever its message. The location fields can be
+        // left out by some servers and proxies, in which case only an English message is
+        // recognised.
+        let from_lock_manager = match (self.routine, self.file) {
+            (Some(routine), _) => Some(routine == "ProcSleep"),
+            (None, Some(file)) => Some(file == "proc.c"),
+            (None, None) => None,
+        };
+        if from_lock_manager == Some(false) {
+            return None;
+        }
+
+        // A deadlock that was avoided by rearranging the wait queue leaves the statement waiting.
+        // A detected deadlock or a failure to acquire the lock is followed by an error, which
+        // ends the statement.
+        let message = self.message;
+        if message.contains(" still waiting for ") || message.contains(" avoided deadlock for ") {
+            Some(LockWait::Waiting)
+        } else if message.contains(" acquired ") {
+            Some(LockWait::Acquired)
+        } else if message.contains(" detected deadlock while waiting for ")
+            || message.contains(" failed to acquire ")
+        {
+            None
+        } else if from_lock_manager == Some(true) {
+            Some(LockWait::Unrecognised)
+        } else {
+            None
+        }
+    }
+
+    /// The relation lock that a [LockWait::Waiting] notice reports, such as
+    /// `process 1234 still waiting for AccessExclusiveLock on relation 16384 of database 16385
+    /// after 1.021 ms`. Only English messages for relation locks can be read.
+    fn waiting_for(&self) -> Option<RelationLockWait> {
+        let (_, rest) = self.message.split_once(" still waiting for ")?;
+        let (lock, rest) = rest.split_once(" on relation ")?;
+        let (relation, _) = rest.split_once(' ')?;
+        Some(RelationLockWait {
+            lock: Lock::from_pg_string(lock),
+            relation: relation.parse().ok()?,
+        })
+    }
+}
+
+impl Drop for StatementExecutor {
+    fn drop(&mut self) {
+        if !self.blocked {
+            return;
+        }
+        let cancel_token = self.client.cancel_token();
+        let tls = self.tls.clone();
+        let spawned = crate::runtime::spawn(async move {
+            let result = match make_tls(&tls) {
+                Ok(tls) => cancel_token.cancel_query(tls).await.map_err(Into::into),
+                Err(e) => Err(e),
             };
+            if let Err(e) = result {
+                warn!("Failed to cancel blocked statement: {e:#}");
+            }
+        });
+        if !spawned {
+            warn!("Unable to cancel blocked statement without a running async runtime");
         }
     }
 }
@@ -147,17 +831,89 @@ impl StatementExecutor {
 #[cfg(test)]
 mod tests {
 
-    use crate::executor::StatementExecutor;
-    use crate::tests::{lock_tables, start_test_postgres, table_exists};
+    use crate::executor::{
+        BLOCK_GRACE_PERIOD, KEEPALIVES_IDLE, KEEPALIVES_INTERVAL, KEEPALIVES_RETRIES, LockWait,
+        LockWaitNotice, RelationLockWait, StatementExecutor, TCP_USER_TIMEOUT, connection_config,
+    };
+    use crate::tests::{MockClock, lock_tables, start_test_postgres, table_exists};
+    use crate::{Lock, Locker, LocksmithError, TableLock};
+    use sqlx::{Connection, PgConnection};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_postgres::error::{Severity, SqlState};
     use tracing_test::traced_test;
 
     #[traced_test]
+    #[test]
+    fn test_connection_config_keepalives() {
+        let config = connection_config("postgresql://user@localhost/db").unwrap();
+        assert!(config.get_keepalives());
+        assert_eq!(config.get_keepalives_idle(), KEEPALIVES_IDLE);
+        assert_eq!(config.get_keepalives_interval(), Some(KEEPALIVES_INTERVAL));
+        assert_eq!(config.get_keepalives_retries(), Some(KEEPALIVES_RETRIES));
+        assert_eq!(config.get_tcp_user_timeout(), Some(&TCP_USER_TIMEOUT));
+
+        // Shorter values from the DSN are kept, and longer ones are capped.
+        let config = connection_config(
+            "postgresql://user@localhost/db?keepalives_idle=5&keepalives_interval=60&keepalives_retries=2",
+        )
+        .unwrap();
+        assert_eq!(config.get_keepalives_idle(), Duration::from_secs(5));
+        assert_eq!(config.get_keepalives_interval(), Some(KEEPALIVES_INTERVAL));
+        assert_eq!(config.get_keepalives_retries(), Some(2));
+
+        let config = connection_config("postgresql://user@localhost/db?keepalives=0").unwrap();
+        assert!(!config.get_keepalives());
+    }
+
     #[tokio::test]
     async fn test_get_connection_id() {
         let (_container, dsn) = start_test_postgres().await;
         let executor = StatementExecutor::new(&dsn).await.unwrap();
         assert!(executor.connection_id.0 > 0)
     }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_execute_setup_statements() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        executor
+            .execute_setup_statements("SET lock_timeout = '5s'; SET application_name = 'migrate';")
+            .await
+            .unwrap();
+        assert!(
+            !executor
+                .execute_statement(
+                    "create table settings as select current_setting('lock_timeout') as value \
+                     union all select current_setting('application_name');"
+                )
+                .await
+                .unwrap()
+        );
+        assert!(!executor.commit().await.unwrap());
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let values: Vec<String> = sqlx::query_scalar("select value from settings")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(values, ["5s", "migrate"]);
+
+        for (sql, error) in [
+            ("SET log_lock_waits = off;", "log_lock_waits"),
+            ("RESET client_min_messages;", "client_min_messages"),
+            (
+                "SELECT set_config('deadlock_timeout', '1s', false);",
+                "deadlock_timeout",
+            ),
+            ("COMMIT;", "COMMIT"),
+        ] {
+            let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+            let err = executor.execute_setup_statements(sql).await.unwrap_err();
+            assert!(err.to_string().contains(error), "{sql}: {err}");
+        }
+    }
+
     #[traced_test]
     #[tokio::test]
     async fn test_check_statement_does_not_commit_if_blocked() {
@@ -165,7 +921,7 @@ mod tests {
         let mut executor = StatementExecutor::new(&dsn).await.unwrap();
         let _locker = lock_tables(&dsn, ["orders"]).await;
         let is_blocked = executor
-            .check_statement_for_locks("drop table orders;")
+            .execute_statement("drop table orders;")
             .await
             .unwrap();
         assert!(is_blocked);
@@ -177,12 +933,126 @@ mod tests {
     async fn test_check_statement_commits() {
         let (_container, dsn) = start_test_postgres().await;
         let mut executor = StatementExecutor::new(&dsn).await.unwrap();
-        let is_blocked = executor
-            .check_statement_for_locks("drop table orders;")
+        assert!(
+            !executor
+                .execute_statement("drop table orders;")
+                .await
+                .unwrap()
+        );
+        assert!(!executor.commit().await.unwrap());
+        assert!(!table_exists(&dsn, "orders").await);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_commit_blocked_by_deferred_constraint() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut setup = StatementExecutor::new(&dsn).await.unwrap();
+        let setup_statement = "alter table orders alter constraint orders_customer_id_fkey deferrable initially deferred;
+            insert into customers (id, name) values (1, 'customer');";
+        assert!(!setup.execute_statement(setup_statement).await.unwrap());
+        assert!(!setup.commit().await.unwrap());
+
+        // The deferred foreign key is only checked when committing, which requires a lock on
+        // the customers table.
+        let insert = "insert into orders (id, customer_id, price) values (1, 1, 1);";
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        let locker = lock_tables(&dsn, ["customers"]).await;
+        assert!(!executor.execute_statement(insert).await.unwrap());
+        assert!(executor.commit().await.unwrap());
+
+        // Aborting the blocked commit must leave the database unchanged once the lock is released,
+        // so the same row can be inserted again.
+        executor.abort().await.unwrap();
+        drop(locker);
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(!executor.execute_statement(insert).await.unwrap());
+        assert!(!executor.commit().await.unwrap());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_execute_without_commit() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        executor
+            .execute_without_commit("drop table orders;")
             .await
             .unwrap();
-        assert!(!is_blocked);
-        assert!(!table_exists(&dsn, "orders").await);
+
+        // The transaction is still open, so its locks can be inspected before rolling back.
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let locks = locker
+            .list_connection_locks(executor.connection_id())
+            .await
+            .unwrap();
+        assert!(locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+        executor.abort().await.unwrap();
+        assert!(table_exists(&dsn, "orders").await);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_scanned_tables() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            !executor
+                .execute_statement("alter table orders add column notes text;")
+                .await
+                .unwrap()
+        );
+        assert!(executor.list_scanned_tables().await.unwrap().is_empty());
+
+        // Validating a constraint scans the whole table, even when it is empty.
+        assert!(
+            !executor
+                .execute_statement("alter table orders validate constraint orders_price_check;")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            executor.list_scanned_tables().await.unwrap(),
+            HashSet::from(["orders".into()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_affected_rows() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            !executor
+                .execute_statement(
+                    "insert into customers (name) select 'customer' from generate_series(1, 3); \
+                     alter table customers add column notes text; \
+                     select * from customers; \
+                     update customers set notes = 'note' where id < 3;",
+                )
+                .await
+                .unwrap()
+        );
+        assert_eq!(executor.affected_rows(), Some(5));
+
+        assert!(
+            !executor
+                .execute_statement_with_params("delete from customers where id = $1", &[&1])
+                .await
+                .unwrap()
+        );
+        assert_eq!(executor.affected_rows(), Some(1));
+
+        // Statements that do not modify rows have no count, whatever their command tag.
+        assert!(
+            !executor
+                .execute_statement("select * from customers;")
+                .await
+                .unwrap()
+        );
+        assert_eq!(executor.affected_rows(), None);
     }
 
     #[traced_test]
@@ -190,7 +1060,39 @@ mod tests {
     async fn test_check_statement_with_invalid_sql() {
         let (_container, dsn) = start_test_postgres().await;
         let mut executor = StatementExecutor::new(&dsn).await.unwrap();
-        assert!(executor.check_statement_for_locks("foobar").await.is_err());
+        let err = executor.execute_statement("foobar").await.unwrap_err();
+        let LocksmithError::StatementFailed {
+            sqlstate, message, ..
+        } = &err
+        else {
+            panic!("{err:?}");
+        };
+        assert_eq!(sqlstate, "42601");
+        assert!(message.contains("foobar"), "{message}");
+        assert_eq!(err.statement_error().unwrap().code, "42601");
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_connection_errors() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        sqlx::query("select pg_terminate_backend($1)")
+            .bind(executor.connection_id().0)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        let err = executor.execute_statement("select 1;").await.unwrap_err();
+        assert!(matches!(err, LocksmithError::Connection { .. }), "{err:?}");
+
+        // Nothing listens on port 1.
+        let result = StatementExecutor::new("postgresql://postgres@127.0.0.1:1/postgres").await;
+        assert!(
+            matches!(result, Err(LocksmithError::Connection { .. })),
+            "{:?}",
+            result.err()
+        );
     }
 
     #[traced_test]
@@ -219,4 +1121,239 @@ mod tests {
         );
         drop(_locker)
     }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_is_statement_blocked_with_lock_timeout() {
+        let (_container, dsn) = start_test_postgres().await;
+        let _locker = lock_tables(&dsn, ["orders"]).await;
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        // The statement is cancelled well before the grace period has passed.
+        executor
+            .execute_setup_statements("SET lock_timeout = '10ms';")
+            .await
+            .unwrap();
+        assert!(
+            executor
+                .execute_statement("select * from orders;")
+                .await
+                .unwrap()
+        );
+        let wait = executor.timed_out_wait().unwrap();
+        assert_eq!(wait.lock, Lock::AccessShareLock);
+        executor.abort().await.unwrap();
+
+        // The lock is cleared by the next statement.
+        assert!(!executor.execute_statement("select 1;").await.unwrap());
+        assert_eq!(executor.timed_out_wait(), None);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_close_cancels_blocked_statement() {
+        let (_container, dsn) = start_test_postgres().await;
+        let _locker = lock_tables(&dsn, ["orders"]).await;
+
+        // Blocked statements are cancelled both when explicitly closing and when dropping the
+        // executor, so neither backend keeps waiting for the lock.
+        let mut closed = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            closed
+                .execute_statement("drop table orders;")
+                .await
+                .unwrap()
+        );
+        closed.close().await.unwrap();
+        let mut dropped = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            dropped
+                .execute_statement("drop table orders;")
+                .await
+                .unwrap()
+        );
+        drop(dropped);
+
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        for _ in 0..100 {
+            let waiting: i64 =
+                sqlx::query_scalar("select count(*) from pg_locks where not granted")
+                    .fetch_one(&mut conn)
+                    .await
+                    .unwrap();
+            if waiting == 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Blocked statements were not cancelled");
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_is_statement_blocked_after_grace_period() {
+        let (_container, dsn) = start_test_postgres().await;
+        let _locker = lock_tables(&dsn, ["customers"]).await;
+
+        let clock = Arc::new(MockClock::new());
+        let mut executor = StatementExecutor::new(&dsn)
+            .await
+            .unwrap()
+            .with_clock(clock.clone());
+        assert!(
+            executor
+                .detect_if_statement_blocks("select * from customers")
+                .await
+                .unwrap()
+        );
+        assert_eq!(clock.elapsed(), BLOCK_GRACE_PERIOD);
+        executor.close().await.unwrap();
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_is_statement_blocked_by_brief_lock() {
+        use sqlx::{Connection, Executor, PgConnection};
+
+        let (_container, dsn) = start_test_postgres().await;
+        // The grace period never elapses on a frozen clock, so the lock is always released
+        // within it.
+        let mut executor = StatementExecutor::new(&dsn)
+            .await
+            .unwrap()
+            .with_clock(Arc::new(MockClock::frozen()));
+        let mut other = PgConnection::connect(&dsn).await.unwrap();
+        other
+            .execute("begin; lock table customers in access exclusive mode;")
+            .await
+            .unwrap();
+
+        // Release the lock once the executor is waiting for it.
+        let release = tokio::spawn(async move {
+            let mut conn = PgConnection::connect(&dsn).await.unwrap();
+            loop {
+                let waiting: i64 =
+                    sqlx::query_scalar("select count(*) from pg_locks where not granted")
+                        .fetch_one(&mut conn)
+                        .await
+                        .unwrap();
+                if waiting > 0 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            other.execute("rollback;").await.unwrap();
+        });
+        assert!(
+            !executor
+                .detect_if_statement_blocks("select * from customers")
+                .await
+                .unwrap()
+        );
+        release.await.unwrap();
+        assert!(logs_contain("Statement waiting for lock"));
+    }
+
+    fn lock_manager_notice(message: &str) -> LockWaitNotice<'_> {
+        LockWaitNotice {
+            severity: Some(Severity::Log),
+            localized_severity: "LOG",
+            code: &SqlState::SUCCESSFUL_COMPLETION,
+            file: Some("proc.c"),
+            routine: Some("ProcSleep"),
+            message,
+        }
+    }
+
+    #[test]
+    fn test_lock_wait_notice() {
+        let waiting = "process 1234 still waiting for AccessExclusiveLock on relation 16384 of database 16385 after 1.109 ms";
+        let acquired = "process 1234 acquired AccessExclusiveLock on relation 16384 of database 16385 after 2.042 ms";
+        let avoided = "process 1234 avoided deadlock for AccessExclusiveLock on relation 16384 of database 16385 by rearranging queue order after 1.021 ms";
+        let deadlock = "process 1234 detected deadlock while waiting for AccessExclusiveLock on relation 16384 of database 16385 after 1.021 ms";
+        let failed = "process 1234 failed to acquire AccessExclusiveLock on relation 16384 of database 16385 after 1.021 ms";
+        let localized = "Prozess 1234 wartet immer noch auf AccessExclusiveLock-Sperre auf Relation 16384 der Datenbank 16385 nach 1.109 ms";
+
+        for (message, expected) in [
+            (waiting, Some(LockWait::Waiting)),
+            (acquired, Some(LockWait::Acquired)),
+            (avoided, Some(LockWait::Waiting)),
+            (deadlock, None),
+            (failed, None),
+            (localized, Some(LockWait::Unrecognised)),
+        ] {
+            assert_eq!(
+                lock_manager_notice(message).lock_wait(),
+                expected,
+                "{message}"
+            );
+        }
+
+        // Servers before 9.6 don't send the non-localized severity
+        let notice = LockWaitNotice {
+            severity: None,
+            ..lock_manager_notice(waiting)
+        };
+        assert_eq!(notice.lock_wait(), Some(LockWait::Waiting));
+
+        // Without a location only English messages are recognised
+        let notice = LockWaitNotice {
+            file: None,
+            routine: None,
+            ..lock_manager_notice(waiting)
+        };
+        assert_eq!(notice.lock_wait(), Some(LockWait::Waiting));
+        let notice = LockWaitNotice {
+            file: None,
+            routine: None,
+            ..lock_manager_notice(localized)
+        };
+        assert_eq!(notice.lock_wait(), None);
+
+        // Other notices that happen to contain the same text are ignored
+        let notice = LockWaitNotice {
+            severity: Some(Severity::Notice),
+            localized_severity: "NOTICE",
+            file: Some("pl_exec.c"),
+            routine: Some("exec_stmt_raise"),
+            ..lock_manager_notice(waiting)
+        };
+        assert_eq!(notice.lock_wait(), None);
+        let notice = LockWaitNotice {
+            code: &SqlState::WARNING,
+            ..lock_manager_notice(waiting)
+        };
+        assert_eq!(notice.lock_wait(), None);
+
+        // Statements logged by `log_statement` are sent as LOG notices, and can contain the text
+        // of a lock wait message in a comment or a literal.
+        for message in [
+            "statement: select 'process 1 still waiting for ShareLock';",
+            "statement: select 1; -- the lock is acquired here",
+        ] {
+            let notice = LockWaitNotice {
+                file: Some("postgres.c"),
+                routine: Some("exec_simple_query"),
+                message,
+                ..lock_manager_notice(waiting)
+            };
+            assert_eq!(notice.lock_wait(), None, "{message}");
+        }
+
+        // The lock being waited for is read from English messages
+        assert_eq!(
+            lock_manager_notice(waiting).waiting_for(),
+            Some(RelationLockWait {
+                lock: Lock::AccessExclusiveLock,
+                relation: 16384,
+            })
+        );
+        let tuple = "process 1234 still waiting for ShareLock on transaction 745 after 1.012 ms";
+        for message in [acquired, tuple, localized] {
+            assert_eq!(
+                lock_manager_notice(message).waiting_for(),
+                None,
+                "{message}"
+            );
+        }
+    }
 }
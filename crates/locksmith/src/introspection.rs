@@ -1,126 +1,1153 @@
 use crate::objects::DBObject;
-use crate::{ColumnObject, IndexObject, TableObject};
+use crate::pool::{ConnectionPool, PgConn};
+use crate::snapshot::SchemaSnapshot;
+use crate::{
+    ColumnObject, ConstraintObject, DomainConstraintObject, DomainObject, ExtensionObject,
+    ForeignTableObject, GeneratedColumn, IndexInheritanceObject, IndexObject,
+    IndexStorageParametersObject, InheritanceObject, LocksmithError, MaterializedViewObject,
+    SequenceObject, StorageParametersObject, TableObject, TableSize, TlsConfig, TriggerObject,
+    UnloggedTableObject, ViewDependency,
+};
 use anyhow::Context;
-use sqlx::{Connection, PgConnection, query, query_as};
+use futures::future::BoxFuture;
+use sqlx::{Executor, query, query_as, query_scalar};
 use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+/// A source of the objects in a database, which the [QueryOracle](crate::QueryOracle) lists
+/// before and after each statement to detect the objects it adds and removes and the tables it
+/// rewrites. [Introspector] lists them from the database itself.
+///
+/// A different source can be given to
+/// [QueryOracleBuilder::schema_source](crate::QueryOracleBuilder::schema_source), such as a fake
+/// that returns fixed objects to test how they are compared, or one that supplies schema state
+/// that was computed ahead of time. A source that fails for reasons of its own, rather than
+/// because of the database, returns [LocksmithError::Other].
+pub trait SchemaSource: Send {
+    /// List all objects in the database, as in [Introspector::list_objects].
+    fn list_objects(&mut self) -> BoxFuture<'_, Result<HashSet<DBObject>, LocksmithError>>;
+
+    /// List the file nodes of all tables in the database, as in
+    /// [Introspector::list_object_file_nodes].
+    fn list_object_file_nodes(
+        &mut self,
+    ) -> BoxFuture<'_, Result<HashMap<DBObject, i32>, LocksmithError>>;
+}
 
 /// An [Introspector] provides various introspection functions for a given Postgres database.
 /// Namely, it provides methods to list objects via [Introspector::list_objects], and to list
-/// file nodes for objects via [Introspector::list_object_file_nodes].
+/// file nodes for objects via [Introspector::list_object_file_nodes], which are also its
+/// implementation of [SchemaSource].
 pub struct Introspector {
-    conn: PgConnection,
+    conn: PgConn,
+    include_extension_members: bool,
 }
 
 impl Introspector {
     /// Construct a new [Introspector] with a connection to the Postgres database at `dsn`.
-    pub async fn new(dsn: &str) -> anyhow::Result<Self> {
-        let mut conn = PgConnection::connect(dsn)
+    pub async fn new(dsn: &str) -> Result<Self, LocksmithError> {
+        Self::new_with_tls(dsn, &TlsConfig::default()).await
+    }
+
+    /// Construct a new [Introspector] as in [Introspector::new], using TLS as configured by `tls`.
+    pub async fn new_with_tls(dsn: &str, tls: &TlsConfig) -> Result<Self, LocksmithError> {
+        let conn = PgConn::connect(dsn, tls)
+            .await
+            .map_err(|source| LocksmithError::Connection { source })?;
+        Ok(Self::with_connection(conn))
+    }
+
+    /// Construct a new [Introspector] with a connection acquired from `pool`, which is returned
+    /// to the pool when the introspector is dropped.
+    pub async fn from_pool(pool: &ConnectionPool) -> Result<Self, LocksmithError> {
+        let conn = pool
+            .acquire()
             .await
-            .context("Creating connection")?;
-        conn.ping().await.context("Pinging postgres")?;
-        Ok(Self { conn })
+            .map_err(|source| LocksmithError::Connection { source })?;
+        Ok(Self::with_connection(conn))
+    }
+
+    fn with_connection(conn: PgConn) -> Self {
+        Self {
+            conn,
+            include_extension_members: true,
+        }
+    }
+
+    /// Set whether objects that belong to an extension, such as tables created by
+    /// `CREATE EXTENSION`, are listed. Defaults to `true`.
+    ///
+    /// Extensions themselves are always listed via [Introspector::list_extensions], so disabling
+    /// this results in an extension being reported as a single object rather than alongside all
+    /// of the objects it contains.
+    pub fn with_extension_members(mut self, include: bool) -> Self {
+        self.include_extension_members = include;
+        self
+    }
+
+    /// Set the `search_path` of the introspector's connection. All objects are listed from the
+    /// first existing schema in the search path, as returned by `current_schema()`.
+    pub async fn set_search_path(
+        &mut self,
+        schemas: &[impl AsRef<str>],
+    ) -> Result<(), LocksmithError> {
+        self.conn
+            .execute(crate::search_path_statement(schemas).as_str())
+            .await
+            .context("Query error while setting search path")?;
+        Ok(())
+    }
+
+    /// Set the `application_name` of the introspector's connection, which identifies it in
+    /// `pg_stat_activity` and the server's logs.
+    pub async fn set_application_name(&mut self, name: &str) -> Result<(), LocksmithError> {
+        self.conn
+            .execute(crate::application_name_statement(name).as_str())
+            .await
+            .context("Query error while setting application name")?;
+        Ok(())
+    }
+
+    /// Assume `role` on the introspector's connection with `SET ROLE`, so that objects are
+    /// listed with the privileges of that role.
+    pub async fn set_role(&mut self, role: &str) -> Result<(), LocksmithError> {
+        self.conn
+            .execute(crate::role_statement(role).as_str())
+            .await
+            .with_context(|| format!("Query error while setting role {role}"))?;
+        Ok(())
     }
 
     /// ## List all objects in the database
-    /// This returns the set of all tables, columns and indexes in the database.
-    pub async fn list_objects(&mut self) -> anyhow::Result<HashSet<DBObject>> {
+    /// This returns the set of all tables, foreign tables, columns, indexes, constraints, domains,
+    /// sequences, extensions and table inheritance relationships in the database.
+    pub async fn list_objects(&mut self) -> Result<HashSet<DBObject>, LocksmithError> {
         let tables = self.list_tables().await?.into_iter().map(DBObject::from);
+        let foreign_tables = self
+            .list_foreign_tables()
+            .await?
+            .into_iter()
+            .map(DBObject::from);
         let columns = self.list_columns().await?.into_iter().map(DBObject::from);
         let indexes = self.list_indexes().await?.into_iter().map(DBObject::from);
-        Ok(tables.chain(columns).chain(indexes).collect())
+        let constraints = self
+            .list_constraints()
+            .await?
+            .into_iter()
+            .map(DBObject::from);
+        let triggers = self.list_triggers().await?.into_iter().map(DBObject::from);
+        let domains = self.list_domains().await?.into_iter().map(DBObject::from);
+        let domain_constraints = self
+            .list_domain_constraints()
+            .await?
+            .into_iter()
+            .map(DBObject::from);
+        let objects = tables
+            .chain(foreign_tables)
+            .chain(columns)
+            .chain(indexes)
+            .chain(constraints)
+            .chain(triggers)
+            .chain(domains)
+            .chain(domain_constraints)
+            .chain(self.list_sequences().await?.into_iter().map(DBObject::from))
+            .chain(
+                self.list_extensions()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            )
+            .chain(
+                self.list_inheritance()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            )
+            .chain(
+                self.list_storage_parameters()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            )
+            .chain(
+                self.list_materialized_views()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            )
+            .chain(
+                self.list_unlogged_tables()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            )
+            .chain(
+                self.list_index_storage_parameters()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            )
+            .chain(
+                self.list_index_inheritance()
+                    .await?
+                    .into_iter()
+                    .map(DBObject::from),
+            );
+        #[cfg(feature = "comments")]
+        let objects = objects.chain(self.list_comments().await?.into_iter().map(DBObject::from));
+        Ok(objects.collect())
+    }
+
+    /// List all objects in the database as they would be after executing `statement`, returning
+    /// `None` if the statement fails. The statement is executed in a transaction that is rolled
+    /// back, so the database is left unchanged.
+    ///
+    /// This is used to check whether a statement that has already been applied can be applied
+    /// again, see
+    /// [QueryOracleBuilder::idempotency_check](crate::QueryOracleBuilder::idempotency_check).
+    pub async fn list_objects_after(
+        &mut self,
+        statement: &str,
+    ) -> Result<Option<HashSet<DBObject>>, LocksmithError> {
+        self.conn
+            .execute("BEGIN")
+            .await
+            .context("Query error while beginning transaction")?;
+        let objects = match self.conn.execute(sqlx::raw_sql(statement)).await {
+            Ok(_) => Some(self.list_objects().await),
+            Err(error) => {
+                debug!(%error, "Statement failed when executed again");
+                None
+            }
+        };
+        self.conn
+            .execute("ROLLBACK")
+            .await
+            .context("Query error while rolling back transaction")?;
+        objects.transpose()
     }
 
-    /// List the file nodes of all objects in the database.
+    /// Begin a read-only `REPEATABLE READ` transaction, so that every query run by the
+    /// introspector until [Introspector::commit] sees the same snapshot of the database, even if
+    /// other sessions change it in the meantime. Without a transaction, each query sees the
+    /// database as it is when that query starts.
+    ///
+    /// The snapshot covers everything read from the system catalogs, which is every object
+    /// listed by [Introspector::list_objects] and [Introspector::list_view_dependencies]. Functions
+    /// that read the storage of a table, such as the file nodes and table sizes, always return
+    /// its current state. Sizing a table takes an `ACCESS SHARE` lock on it that is held until
+    /// the transaction ends, so it must be committed before the tables are altered.
+    pub async fn begin_repeatable_read(&mut self) -> Result<(), LocksmithError> {
+        self.conn
+            .execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")
+            .await
+            .context("Query error while beginning transaction")?;
+        Ok(())
+    }
+
+    /// Commit the transaction begun by [Introspector::begin_repeatable_read].
+    pub async fn commit(&mut self) -> Result<(), LocksmithError> {
+        self.conn
+            .execute("COMMIT")
+            .await
+            .context("Query error while committing transaction")?;
+        Ok(())
+    }
+
+    /// Get the numeric server version, as given by the `server_version_num` setting.
+    pub async fn server_version_num(&mut self) -> Result<i32, LocksmithError> {
+        query_scalar!(r#"SELECT current_setting('server_version_num')::int as "version!""#)
+            .fetch_one(&mut *self.conn)
+            .await
+            .context("Query error while retrieving server version")
+            .map_err(LocksmithError::introspection)
+    }
+
+    /// Get the schema that objects are listed from, as given by `current_schema()`. This is
+    /// `None` if none of the schemas in the search path exist.
+    pub async fn current_schema(&mut self) -> Result<Option<String>, LocksmithError> {
+        query_scalar!("SELECT current_schema()")
+            .fetch_one(&mut *self.conn)
+            .await
+            .context("Query error while retrieving current schema")
+            .map_err(LocksmithError::introspection)
+    }
+
+    /// Take a [SchemaSnapshot] of the database, containing all objects, their file nodes and the
+    /// server version.
+    pub async fn snapshot(&mut self) -> Result<SchemaSnapshot, LocksmithError> {
+        let server_version_num = self.server_version_num().await?;
+        let objects = self.list_objects().await?;
+        let mut file_nodes: Vec<_> = self.list_object_file_nodes().await?.into_iter().collect();
+        file_nodes.sort();
+        Ok(SchemaSnapshot {
+            server_version_num,
+            objects,
+            file_nodes,
+        })
+    }
+
+    /// List the file nodes of all tables and indexes in the database.
     ///
     /// A file node is a unique identifier for a table's underlying storage file, which
-    /// is guaranteed to change if the table is rewritten (even if the table is empty).
+    /// is guaranteed to change if the table is rewritten (even if the table is empty). This
+    /// includes moving a table to another tablespace, which copies it to a new file node.
     /// This uses the [pg_relation_filenode](https://pgpedia.info/p/pg_relation_filenode.html)
-    /// function to get the file node for each table.
+    /// function to get the file node for each table. Views are not listed, and tables without any
+    /// storage, such as partitioned tables, are skipped.
     ///
-    /// **Note**: Currently this only lists table file nodes, and not indexes or other objects.
-    pub async fn list_object_file_nodes(&mut self) -> anyhow::Result<HashMap<DBObject, i32>> {
-        query!(
+    /// The file node of an index is kept when it is renamed, so comparing the file nodes of a
+    /// removed and an added index tells a renamed index apart from one that was dropped and
+    /// created again. Indexes without any storage, such as those on partitioned tables, are
+    /// skipped.
+    pub async fn list_object_file_nodes(
+        &mut self,
+    ) -> Result<HashMap<DBObject, i32>, LocksmithError> {
+        let tables = query!(
             r#"
-            SELECT table_name as "table!", pg_relation_filenode(table_name::text)::int as "file_node!"
+            SELECT table_name as "table!", pg_relation_filenode(format('%I.%I', table_schema, table_name)::regclass)::int as "file_node?"
             FROM information_schema.tables
             WHERE table_schema = "current_schema"()
               AND table_catalog = current_database()
+              AND table_type = 'BASE TABLE'
             order by table_name;"#
         )
-            .fetch_all(&mut self.conn)
-            .await.context("Query error while listing table file nodes")
-            .map(|r| {
-                r.into_iter().map(|r| {
-                    let table = DBObject::Table(TableObject {
-                        name: r.table,
-                    });
-                    (table, r.file_node)
-                }).collect()
+            .fetch_all(&mut *self.conn)
+            .await
+            .context("Query error while listing table file nodes")
+            .map_err(LocksmithError::introspection)?;
+        let index_file_nodes: HashMap<_, _> = query!(
+            r#"
+            SELECT c.relname::text as "index!", pg_relation_filenode(c.oid)::int as "file_node?"
+            FROM pg_class c
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind = 'i'
+            order by 1;"#
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing index file nodes")
+        .map_err(LocksmithError::introspection)?
+        .into_iter()
+        .filter_map(|r| Some((r.index, r.file_node?)))
+        .collect();
+        let indexes = self.list_indexes().await?.into_iter().filter_map(|index| {
+            let file_node = *index_file_nodes.get(&index.name)?;
+            Some((DBObject::Index(index), file_node))
+        });
+        Ok(tables
+            .into_iter()
+            .filter_map(|r| {
+                let table = DBObject::Table(TableObject::from(r.table));
+                Some((table, r.file_node?))
             })
+            .chain(indexes)
+            .collect())
+    }
+
+    /// List the size of every table in the current schema.
+    ///
+    /// This uses the [pg_total_relation_size](https://www.postgresql.org/docs/current/functions-admin.html#FUNCTIONS-ADMIN-DBSIZE)
+    /// function and the `reltuples` estimate from the
+    /// [pg_class](https://www.postgresql.org/docs/current/catalog-pg-class.html) catalog. Both
+    /// describe the database being inspected, which is usually much smaller than production.
+    ///
+    /// `pg_total_relation_size` waits for any `ACCESS EXCLUSIVE` lock on the table, so tables
+    /// locked by another session are instead sized from the `relpages` estimate in `pg_class`.
+    pub async fn list_table_sizes(&mut self) -> Result<Vec<TableSize>, LocksmithError> {
+        query!(
+            r#"
+            SELECT c.relname::text as "table!",
+                   CASE
+                       WHEN EXISTS (SELECT 1
+                                    FROM pg_locks l
+                                    WHERE l.relation = c.oid
+                                      AND l.database = (SELECT oid FROM pg_database WHERE datname = current_database())
+                                      AND l.mode = 'AccessExclusiveLock'
+                                      AND l.pid <> pg_backend_pid())
+                           THEN c.relpages::int8 * current_setting('block_size')::int8
+                       ELSE pg_total_relation_size(c.oid)
+                   END as "total_bytes!",
+                   CASE WHEN c.reltuples < 0 THEN NULL ELSE c.reltuples::int8 END as "estimated_rows?"
+            FROM pg_class c
+            JOIN pg_namespace n ON n.oid = c.relnamespace
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind IN ('r', 'p')
+            order by c.relname;"#
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing table sizes")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|r| TableSize {
+                    table: TableObject::from(r.table),
+                    total_bytes: r.total_bytes,
+                    estimated_rows: r.estimated_rows,
+                })
+                .collect()
+        })
     }
 
     /// ## List all tables in the database.
     /// This uses the [information_schema.tables](https://www.postgresql.org/docs/current/infoschema-tables.html)
-    /// view to retrieve tables in the current schema.
-    pub async fn list_tables(&mut self) -> anyhow::Result<Vec<TableObject>> {
+    /// view to retrieve tables in the current schema. Foreign tables are listed separately by
+    /// [Introspector::list_foreign_tables].
+    pub async fn list_tables(&mut self) -> Result<Vec<TableObject>, LocksmithError> {
         query_as!(
             TableObject,
             r#"
-            SELECT table_name as "name!"
+            SELECT table_name as "name!", NULL::text as "schema?"
             FROM information_schema.tables
             WHERE table_schema = "current_schema"()
               AND table_catalog = current_database()
-            order by table_name;"#
+              AND table_type <> 'FOREIGN'
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = format('%I.%I', table_schema, table_name)::regclass
+                  AND d.deptype = 'e'))
+            order by table_name;"#,
+            self.include_extension_members
         )
-        .fetch_all(&mut self.conn)
+        .fetch_all(&mut *self.conn)
         .await
         .context("Query error while listing tables")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List all foreign tables in the database.
+    /// This uses the [pg_foreign_table](https://www.postgresql.org/docs/current/catalog-pg-foreign-table.html)
+    /// catalog to retrieve foreign tables in the current schema, along with the name of the
+    /// foreign server they belong to.
+    pub async fn list_foreign_tables(&mut self) -> Result<Vec<ForeignTableObject>, LocksmithError> {
+        query_as!(
+            ForeignTableObject,
+            r#"
+            SELECT c.relname as "name!", s.srvname as "server!"
+            FROM pg_foreign_table ft
+            JOIN pg_class c ON ft.ftrelid = c.oid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            JOIN pg_foreign_server s ON ft.ftserver = s.oid
+            WHERE n.nspname = "current_schema"()
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e'))
+            order by 1;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing foreign tables")
+        .map_err(LocksmithError::introspection)
     }
 
     /// ## List columns in the database
     /// This uses the [information_schema.columns](https://www.postgresql.org/docs/current/infoschema-columns.html)
-    /// view to retrieve columns in the current schema.
-    pub async fn list_columns(&mut self) -> anyhow::Result<Vec<ColumnObject>> {
-        query_as!(
-            ColumnObject,
+    /// view to retrieve columns in the current schema, and the `attgenerated` column of the
+    /// [pg_attribute](https://www.postgresql.org/docs/current/catalog-pg-attribute.html) catalog
+    /// to tell stored generated columns from virtual ones.
+    pub async fn list_columns(&mut self) -> Result<Vec<ColumnObject>, LocksmithError> {
+        query!(
             r#"
-            SELECT table_name as "table!", column_name as "name!", data_type as "data_type!"
-            FROM information_schema.columns
-            WHERE table_schema = "current_schema"()
-              AND table_catalog = current_database()
-            order by table_name, column_name;
-            "#
+            SELECT col.table_name as "table!", col.column_name as "name!",
+                   col.data_type as "data_type!",
+                   col.generation_expression::text as "generation_expression?",
+                   a.attgenerated = 's' as "stored!"
+            FROM information_schema.columns col
+            JOIN pg_attribute a
+              ON a.attrelid = format('%I.%I', col.table_schema, col.table_name)::regclass
+             AND a.attname = col.column_name
+            WHERE col.table_schema = "current_schema"()
+              AND col.table_catalog = current_database()
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = a.attrelid
+                  AND d.deptype = 'e'))
+            order by col.table_name, col.column_name;
+            "#,
+            self.include_extension_members
         )
-        .fetch_all(&mut self.conn)
+        .fetch_all(&mut *self.conn)
         .await
         .context("Query error while listing columns")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| ColumnObject {
+                    table: TableObject::from(row.table),
+                    name: row.name,
+                    data_type: row.data_type,
+                    generated: row.generation_expression.map(|expression| GeneratedColumn {
+                        expression,
+                        stored: row.stored,
+                    }),
+                })
+                .collect()
+        })
     }
 
     /// ## List indexes in the database
     /// This uses the [pg_stat_all_indexes](https://pgpedia.info/p/pg_stat_all_indexes.html) view
-    /// to retrieve indexes in the current schema.
-    pub async fn list_indexes(&mut self) -> anyhow::Result<Vec<IndexObject>> {
+    /// to retrieve indexes in the current schema, and the
+    /// [pg_index](https://www.postgresql.org/docs/current/catalog-pg-index.html) catalog to
+    /// retrieve the ordered key columns of each index, and whether it is a primary key or unique
+    /// index.
+    ///
+    /// Each key column is given as its name, or for expression columns its definition from
+    /// [pg_get_indexdef](https://pgpedia.info/p/pg_get_indexdef.html), followed by its sort
+    /// order (`DESC`) and null ordering (`NULLS FIRST`/`NULLS LAST`) when they are not the
+    /// default. Note that `pg_get_indexdef` requires a lock on the table, so listing expression
+    /// indexes will wait for any conflicting locks held by other sessions.
+    pub async fn list_indexes(&mut self) -> Result<Vec<IndexObject>, LocksmithError> {
         query_as!(
             IndexObject,
             r#"
-            SELECT relname as "table!", indexrelname as "name!"
-            FROM pg_stat_all_indexes
+            SELECT relname as "table!", indexrelname as "name!",
+                   array(
+                       SELECT CASE
+                                  WHEN i.indkey[k - 1] = 0 THEN pg_get_indexdef(i.indexrelid, k, true)
+                                  ELSE (SELECT quote_ident(a.attname)
+                                        FROM pg_attribute a
+                                        WHERE a.attrelid = i.indrelid
+                                          AND a.attnum = i.indkey[k - 1])
+                              END
+                           || CASE WHEN i.indoption[k - 1] & 1 = 1 THEN ' DESC' ELSE '' END
+                           || CASE
+                                  WHEN i.indoption[k - 1] & 3 = 1 THEN ' NULLS LAST'
+                                  WHEN i.indoption[k - 1] & 3 = 2 THEN ' NULLS FIRST'
+                                  ELSE ''
+                              END
+                       FROM generate_series(1, i.indnkeyatts) k
+                       ORDER BY k
+                   ) as "columns!",
+                   i.indisprimary as "is_primary!", i.indisunique as "is_unique!"
+            FROM pg_stat_all_indexes s
+            JOIN pg_index i USING (indexrelid)
             WHERE schemaname = "current_schema"()
-            order by 1, 2;"#
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = s.relid
+                  AND d.deptype = 'e'))
+            order by 1, 2;"#,
+            self.include_extension_members
         )
-        .fetch_all(&mut self.conn)
+        .fetch_all(&mut *self.conn)
         .await
         .context("Query error while listing indexes")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List table constraints in the database
+    /// This uses the [pg_constraint](https://www.postgresql.org/docs/current/catalog-pg-constraint.html)
+    /// catalog to retrieve constraints on tables in the current schema, including whether they
+    /// have been validated and their definition.
+    ///
+    /// `pg_get_constraintdef` waits for any `ACCESS EXCLUSIVE` lock on the table to deparse the
+    /// expression of a check constraint, as does `pg_get_expr`, so the definition of check
+    /// constraints on tables locked by another session is left empty rather than given in another
+    /// form.
+    ///
+    /// `NOT NULL` constraints are excluded, as they are only present in `pg_constraint` from
+    /// Postgres 18 onwards and are already reflected in the column definition.
+    pub async fn list_constraints(&mut self) -> Result<Vec<ConstraintObject>, LocksmithError> {
+        query_as!(
+            ConstraintObject,
+            r#"
+            SELECT c.relname as "table!", con.conname as "name!", con.convalidated as "validated!",
+                   CASE
+                       WHEN con.contype = 'c' AND EXISTS (SELECT 1
+                                    FROM pg_locks l
+                                    WHERE l.relation = c.oid
+                                      AND l.database = (SELECT oid FROM pg_database WHERE datname = current_database())
+                                      AND l.mode = 'AccessExclusiveLock'
+                                      AND l.pid <> pg_backend_pid())
+                           THEN ''
+                       ELSE regexp_replace(pg_get_constraintdef(con.oid), ' NOT VALID$', '')
+                   END as "definition!"
+            FROM pg_constraint con
+            JOIN pg_class c ON con.conrelid = c.oid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND con.contype <> 'n'
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e'))
+            order by 1, 2;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing constraints")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List triggers in the database
+    /// This uses the [pg_trigger](https://www.postgresql.org/docs/current/catalog-pg-trigger.html)
+    /// catalog to retrieve the triggers on tables in the current schema and whether each is
+    /// enabled. Internal triggers are listed under the name of the constraint they enforce, once
+    /// for each table and state.
+    pub async fn list_triggers(&mut self) -> Result<Vec<TriggerObject>, LocksmithError> {
+        query!(
+            r#"
+            SELECT DISTINCT c.relname::text as "table!",
+                            CASE
+                                WHEN t.tgisinternal THEN coalesce(con.conname, t.tgname)
+                                ELSE t.tgname
+                            END::text as "name!",
+                            t.tgenabled::text as "enabled!",
+                            t.tgisinternal as "internal!"
+            FROM pg_trigger t
+            JOIN pg_class c ON t.tgrelid = c.oid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            LEFT JOIN pg_constraint con ON t.tgconstraint = con.oid
+            WHERE n.nspname = "current_schema"()
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e'))
+            order by 1, 2, 3;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing triggers")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| TriggerObject {
+                    table: TableObject::from(row.table),
+                    name: row.name,
+                    enabled: row.enabled.into(),
+                    internal: row.internal,
+                })
+                .collect()
+        })
+    }
+
+    /// ## List the storage parameters of tables in the database
+    /// This uses the `reloptions` column of the
+    /// [pg_class](https://www.postgresql.org/docs/current/catalog-pg-class.html) catalog for each
+    /// table and its TOAST table. Tables without any storage parameters are not listed.
+    pub async fn list_storage_parameters(
+        &mut self,
+    ) -> Result<Vec<StorageParametersObject>, LocksmithError> {
+        query!(
+            r#"
+            SELECT c.relname::text as "table!",
+                   array(SELECT option
+                         FROM (SELECT unnest(c.reloptions) as option
+                               UNION ALL
+                               SELECT 'toast.' || unnest(t.reloptions)) options
+                         ORDER BY option) as "parameters!"
+            FROM pg_class c
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            LEFT JOIN pg_class t ON c.reltoastrelid = t.oid
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind IN ('r', 'p')
+              AND (c.reloptions IS NOT NULL OR t.reloptions IS NOT NULL)
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e'))
+            order by 1;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing storage parameters")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|r| StorageParametersObject {
+                    table: TableObject::from(r.table),
+                    parameters: r.parameters,
+                })
+                .collect()
+        })
+    }
+
+    /// ## List the storage parameters of indexes in the database
+    /// This uses the `reloptions` column of the
+    /// [pg_class](https://www.postgresql.org/docs/current/catalog-pg-class.html) catalog for each
+    /// index in the current schema. Indexes without any storage parameters are not listed.
+    pub async fn list_index_storage_parameters(
+        &mut self,
+    ) -> Result<Vec<IndexStorageParametersObject>, LocksmithError> {
+        query!(
+            r#"
+            SELECT t.relname::text as "table!", c.relname::text as "index!",
+                   array(SELECT unnest(c.reloptions) ORDER BY 1) as "parameters!"
+            FROM pg_class c
+            JOIN pg_index i ON i.indexrelid = c.oid
+            JOIN pg_class t ON i.indrelid = t.oid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind IN ('i', 'I')
+              AND c.reloptions IS NOT NULL
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = t.oid
+                  AND d.deptype = 'e'))
+            order by 1, 2;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing index storage parameters")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|r| IndexStorageParametersObject {
+                    table: TableObject::from(r.table),
+                    index: r.index,
+                    parameters: r.parameters,
+                })
+                .collect()
+        })
+    }
+
+    /// ## List unlogged tables in the database
+    /// This uses the `relpersistence` column of the
+    /// [pg_class](https://www.postgresql.org/docs/current/catalog-pg-class.html) catalog to
+    /// retrieve the unlogged tables in the current schema.
+    pub async fn list_unlogged_tables(
+        &mut self,
+    ) -> Result<Vec<UnloggedTableObject>, LocksmithError> {
+        query_scalar!(
+            r#"
+            SELECT c.relname::text as "table!"
+            FROM pg_class c
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind IN ('r', 'p')
+              AND c.relpersistence = 'u'
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e'))
+            order by 1;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing unlogged tables")
+        .map_err(LocksmithError::introspection)
+        .map(|tables| {
+            tables
+                .into_iter()
+                .map(|table| UnloggedTableObject {
+                    table: TableObject::from(table),
+                })
+                .collect()
+        })
+    }
+
+    /// ## List sequences in the database
+    /// This uses the [pg_sequence](https://www.postgresql.org/docs/current/catalog-pg-sequence.html)
+    /// catalog to retrieve sequences in the current schema along with their attributes, and the
+    /// [pg_depend](https://www.postgresql.org/docs/current/catalog-pg-depend.html) catalog to
+    /// retrieve the column that owns each sequence.
+    pub async fn list_sequences(&mut self) -> Result<Vec<SequenceObject>, LocksmithError> {
+        query_as!(
+            SequenceObject,
+            r#"
+            SELECT c.relname as "name!",
+                   s.seqstart as "start!",
+                   s.seqincrement as "increment!",
+                   s.seqmin as "min!",
+                   s.seqmax as "max!",
+                   s.seqcycle as "cycle!",
+                   (SELECT format('%s.%s', t.relname, a.attname)
+                    FROM pg_depend d
+                    JOIN pg_class t ON d.refobjid = t.oid
+                    JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = d.refobjsubid
+                    WHERE d.classid = 'pg_class'::regclass
+                      AND d.objid = c.oid
+                      AND d.refclassid = 'pg_class'::regclass
+                      AND d.deptype IN ('a', 'i')) as "owned_by?"
+            FROM pg_sequence s
+            JOIN pg_class c ON s.seqrelid = c.oid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e'))
+            order by 1;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing sequences")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List materialized views in the database
+    /// This uses the [pg_matviews](https://www.postgresql.org/docs/current/view-pg-matviews.html)
+    /// view to retrieve materialized views in the current schema.
+    pub async fn list_materialized_views(
+        &mut self,
+    ) -> Result<Vec<MaterializedViewObject>, LocksmithError> {
+        query_as!(
+            MaterializedViewObject,
+            r#"
+            SELECT matviewname::text as "name!"
+            FROM pg_matviews
+            WHERE schemaname = "current_schema"()
+            order by 1;"#
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing materialized views")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List the dependencies of views in the database
+    /// This uses the [pg_depend](https://www.postgresql.org/docs/current/catalog-pg-depend.html)
+    /// catalog to retrieve the tables and columns that each view and materialized view in the
+    /// current schema depends on, via the [pg_rewrite](https://www.postgresql.org/docs/current/catalog-pg-rewrite.html)
+    /// rule that implements the view.
+    pub async fn list_view_dependencies(&mut self) -> Result<Vec<ViewDependency>, LocksmithError> {
+        query!(
+            r#"
+            SELECT DISTINCT v.relname::text as "view!",
+                            v.relkind = 'm' as "materialized!",
+                            t.relname::text as "table!",
+                            CASE WHEN t.relnamespace <> n.oid THEN tn.nspname::text END
+                                as "table_schema?",
+                            a.attname::text as "column?"
+            FROM pg_depend d
+            JOIN pg_rewrite r ON d.objid = r.oid
+            JOIN pg_class v ON r.ev_class = v.oid
+            JOIN pg_namespace n ON v.relnamespace = n.oid
+            JOIN pg_class t ON d.refobjid = t.oid
+            JOIN pg_namespace tn ON t.relnamespace = tn.oid
+            LEFT JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = d.refobjsubid
+            WHERE d.classid = 'pg_rewrite'::regclass
+              AND d.refclassid = 'pg_class'::regclass
+              AND n.nspname = "current_schema"()
+              AND v.relkind IN ('v', 'm')
+              AND t.oid <> v.oid
+            order by 1, 3, 4 NULLS FIRST, 5;"#
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing view dependencies")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| ViewDependency {
+                    view: if row.materialized {
+                        DBObject::MaterializedView(MaterializedViewObject { name: row.view })
+                    } else {
+                        DBObject::Table(TableObject::from(row.view))
+                    },
+                    table: TableObject {
+                        name: row.table,
+                        schema: row.table_schema,
+                    },
+                    column: row.column,
+                })
+                .collect()
+        })
+    }
+
+    /// ## List table inheritance in the database
+    /// This uses the [pg_inherits](https://www.postgresql.org/docs/current/catalog-pg-inherits.html)
+    /// catalog to retrieve the parent of every table in the current schema that inherits from
+    /// another table, including partitions. Inheritance between indexes is listed by
+    /// [Introspector::list_index_inheritance].
+    pub async fn list_inheritance(&mut self) -> Result<Vec<InheritanceObject>, LocksmithError> {
+        query!(
+            r#"
+            SELECT parent.relname::text as "parent!",
+                   CASE WHEN parent.relnamespace <> n.oid THEN pn.nspname::text END
+                       as "parent_schema?",
+                   child.relname::text as "child!"
+            FROM pg_inherits i
+            JOIN pg_class child ON i.inhrelid = child.oid
+            JOIN pg_class parent ON i.inhparent = parent.oid
+            JOIN pg_namespace n ON child.relnamespace = n.oid
+            JOIN pg_namespace pn ON parent.relnamespace = pn.oid
+            WHERE n.nspname = "current_schema"()
+              AND child.relkind IN ('r', 'p')
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = child.oid
+                  AND d.deptype = 'e'))
+            order by 1, 2 NULLS FIRST, 3;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing table inheritance")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| InheritanceObject {
+                    parent: TableObject {
+                        name: row.parent,
+                        schema: row.parent_schema,
+                    },
+                    child: row.child.into(),
+                })
+                .collect()
+        })
+    }
+
+    /// ## List index inheritance in the database
+    /// This uses the [pg_inherits](https://www.postgresql.org/docs/current/catalog-pg-inherits.html)
+    /// catalog to retrieve the parent of every index in the current schema that is attached to an
+    /// index of a partitioned table.
+    pub async fn list_index_inheritance(
+        &mut self,
+    ) -> Result<Vec<IndexInheritanceObject>, LocksmithError> {
+        query!(
+            r#"
+            SELECT t.relname::text as "table!", child.relname::text as "index!",
+                   CASE WHEN parent.relnamespace = n.oid THEN parent.relname::text
+                        ELSE format('%I.%I', pn.nspname, parent.relname) END as "parent!"
+            FROM pg_inherits inh
+            JOIN pg_class child ON inh.inhrelid = child.oid
+            JOIN pg_class parent ON inh.inhparent = parent.oid
+            JOIN pg_index i ON i.indexrelid = child.oid
+            JOIN pg_class t ON i.indrelid = t.oid
+            JOIN pg_namespace n ON child.relnamespace = n.oid
+            JOIN pg_namespace pn ON parent.relnamespace = pn.oid
+            WHERE n.nspname = "current_schema"()
+              AND child.relkind IN ('i', 'I')
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = t.oid
+                  AND d.deptype = 'e'))
+            order by 1, 2;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing index inheritance")
+        .map_err(LocksmithError::introspection)
+        .map(|rows| {
+            rows.into_iter()
+                .map(|r| IndexInheritanceObject {
+                    table: TableObject::from(r.table),
+                    index: r.index,
+                    parent: r.parent,
+                })
+                .collect()
+        })
+    }
+
+    /// ## List domains in the database
+    /// This uses the [pg_type](https://www.postgresql.org/docs/current/catalog-pg-type.html)
+    /// catalog to retrieve domain types in the current schema, along with their base type.
+    pub async fn list_domains(&mut self) -> Result<Vec<DomainObject>, LocksmithError> {
+        query_as!(
+            DomainObject,
+            r#"
+            SELECT t.typname as "name!", format_type(t.typbasetype, t.typtypmod) as "data_type!"
+            FROM pg_type t
+            JOIN pg_namespace n ON t.typnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND t.typtype = 'd'
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_type'::regclass
+                  AND d.objid = t.oid
+                  AND d.deptype = 'e'))
+            order by 1;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing domains")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List domain constraints in the database
+    /// This uses the [pg_constraint](https://www.postgresql.org/docs/current/catalog-pg-constraint.html)
+    /// catalog to retrieve constraints on domains in the current schema. As with
+    /// [Introspector::list_constraints], `NOT NULL` constraints are excluded.
+    pub async fn list_domain_constraints(
+        &mut self,
+    ) -> Result<Vec<DomainConstraintObject>, LocksmithError> {
+        query_as!(
+            DomainConstraintObject,
+            r#"
+            SELECT t.typname as "domain!", con.conname as "name!", con.convalidated as "validated!"
+            FROM pg_constraint con
+            JOIN pg_type t ON con.contypid = t.oid
+            JOIN pg_namespace n ON t.typnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+              AND con.contype <> 'n'
+              AND ($1 OR NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_type'::regclass
+                  AND d.objid = t.oid
+                  AND d.deptype = 'e'))
+            order by 1, 2;"#,
+            self.include_extension_members
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing domain constraints")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List extensions in the database
+    /// This uses the [pg_extension](https://www.postgresql.org/docs/current/catalog-pg-extension.html)
+    /// catalog to retrieve extensions installed into the current schema, along with their version.
+    pub async fn list_extensions(&mut self) -> Result<Vec<ExtensionObject>, LocksmithError> {
+        query_as!(
+            ExtensionObject,
+            r#"
+            SELECT e.extname as "name!", e.extversion as "version!"
+            FROM pg_extension e
+            JOIN pg_namespace n ON e.extnamespace = n.oid
+            WHERE n.nspname = "current_schema"()
+            order by 1;"#
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing extensions")
+        .map_err(LocksmithError::introspection)
+    }
+
+    /// ## List comments on tables and columns in the database
+    /// This uses the [pg_description](https://www.postgresql.org/docs/current/catalog-pg-description.html)
+    /// catalog to retrieve comments set via `COMMENT ON` for tables and columns in the current
+    /// schema.
+    ///
+    /// This is only available with the `comments` feature enabled.
+    #[cfg(feature = "comments")]
+    pub async fn list_comments(&mut self) -> Result<Vec<crate::CommentObject>, LocksmithError> {
+        query_as!(
+            crate::CommentObject,
+            r#"
+            SELECT c.relname as "table!", a.attname as "column?", d.description as "comment!"
+            FROM pg_description d
+            JOIN pg_class c ON d.objoid = c.oid AND d.classoid = 'pg_class'::regclass
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            LEFT JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = d.objsubid AND d.objsubid > 0
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind IN ('r', 'p')
+            order by 1, 2 nulls first;"#
+        )
+        .fetch_all(&mut *self.conn)
+        .await
+        .context("Query error while listing comments")
+        .map_err(LocksmithError::introspection)
+    }
+}
+
+impl SchemaSource for Introspector {
+    fn list_objects(&mut self) -> BoxFuture<'_, Result<HashSet<DBObject>, LocksmithError>> {
+        Box::pin(Introspector::list_objects(self))
+    }
+
+    fn list_object_file_nodes(
+        &mut self,
+    ) -> BoxFuture<'_, Result<HashMap<DBObject, i32>, LocksmithError>> {
+        Box::pin(Introspector::list_object_file_nodes(self))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tests::start_test_postgres;
+    use crate::TriggerEnabled;
+    use crate::tests::{lock_tables, start_test_postgres};
 
+    use sqlx::{Connection, PgConnection};
     use tracing_test::traced_test;
 
+    #[tokio::test]
+    async fn test_begin_repeatable_read() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+
+        // Tables created after the transaction's first query are not seen until it is committed.
+        target.begin_repeatable_read().await.unwrap();
+        let tables = target.list_tables().await.unwrap();
+        conn.execute("create table products (id integer);")
+            .await
+            .unwrap();
+        assert_eq!(target.list_tables().await.unwrap(), tables);
+        target.commit().await.unwrap();
+        assert!(
+            target
+                .list_tables()
+                .await
+                .unwrap()
+                .contains(&"products".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_introspection_errors() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+
+        // A statement that fails aborts the transaction, so every catalog query then fails.
+        target.begin_repeatable_read().await.unwrap();
+        let err = target.set_role("nobody").await.unwrap_err();
+        let LocksmithError::StatementFailed { sqlstate, .. } = &err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(sqlstate, "22023");
+        let err = target.list_objects().await.unwrap_err();
+        assert!(
+            matches!(err, LocksmithError::Introspection { .. }),
+            "{err:?}"
+        );
+        assert_eq!(err.statement_error().unwrap().code, "25P02");
+
+        // Nothing listens on port 1.
+        let result = Introspector::new("postgresql://postgres@127.0.0.1:1/postgres").await;
+        assert!(
+            matches!(result, Err(LocksmithError::Connection { .. })),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_current_schema() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        assert_eq!(
+            target.current_schema().await.unwrap().as_deref(),
+            Some("public")
+        );
+        target
+            .set_search_path(&["missing", "public"])
+            .await
+            .unwrap();
+        assert_eq!(
+            target.current_schema().await.unwrap().as_deref(),
+            Some("public")
+        );
+        target.set_search_path(&["missing"]).await.unwrap();
+        assert_eq!(target.current_schema().await.unwrap(), None);
+    }
+
     #[traced_test]
     #[tokio::test]
     async fn test_list_tables() {
@@ -141,33 +1168,69 @@ mod tests {
                 ColumnObject {
                     table: "customers".into(),
                     name: "id".to_string(),
-                    data_type: "integer".to_string()
+                    data_type: "integer".to_string(),
+                    generated: None
                 },
                 ColumnObject {
                     table: "customers".into(),
                     name: "name".to_string(),
-                    data_type: "text".to_string()
+                    data_type: "text".to_string(),
+                    generated: None
                 },
                 ColumnObject {
                     table: "orders".into(),
                     name: "customer_id".to_string(),
-                    data_type: "integer".to_string()
+                    data_type: "integer".to_string(),
+                    generated: None
                 },
                 ColumnObject {
                     table: "orders".into(),
                     name: "id".to_string(),
-                    data_type: "integer".to_string()
+                    data_type: "integer".to_string(),
+                    generated: None
                 },
                 ColumnObject {
                     table: "orders".into(),
                     name: "price".to_string(),
-                    data_type: "numeric".to_string()
+                    data_type: "numeric".to_string(),
+                    generated: None
                 }
             ]
         );
     }
     #[traced_test]
     #[tokio::test]
+    async fn test_list_generated_columns() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "ALTER TABLE orders ADD COLUMN customer_key integer
+                 GENERATED ALWAYS AS (customer_id * 2) STORED",
+            )
+            .await
+            .unwrap();
+
+        let columns = target.list_columns().await.unwrap();
+        let generated: Vec<_> = columns.iter().filter(|c| c.generated.is_some()).collect();
+        assert_eq!(
+            generated,
+            vec![&ColumnObject {
+                table: "orders".into(),
+                name: "customer_key".to_string(),
+                data_type: "integer".to_string(),
+                generated: Some(GeneratedColumn {
+                    expression: "(customer_id * 2)".to_string(),
+                    stored: true,
+                }),
+            }]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
     async fn test_list_indexes() {
         let (_container, dsn) = start_test_postgres().await;
         let mut target = Introspector::new(&dsn).await.unwrap();
@@ -178,17 +1241,642 @@ mod tests {
             vec![
                 IndexObject {
                     table: "customers".into(),
-                    name: "customers_pkey".to_string()
+                    name: "customers_pkey".to_string(),
+                    columns: vec!["id".to_string()],
+                    is_primary: true,
+                    is_unique: true,
                 },
                 IndexObject {
                     table: "orders".into(),
-                    name: "orders_pkey".to_string()
+                    name: "orders_pkey".to_string(),
+                    columns: vec!["id".to_string()],
+                    is_primary: true,
+                    is_unique: true,
                 },
                 IndexObject {
                     table: "orders".into(),
-                    name: "orders_price_idx".to_string()
+                    name: "orders_price_idx".to_string(),
+                    columns: vec!["price".to_string()],
+                    is_primary: false,
+                    is_unique: false,
                 }
             ]
         )
     }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_index_column_ordering() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE INDEX orders_ordering_idx ON orders \
+                 (price DESC, customer_id NULLS FIRST, lower(id::text) DESC NULLS LAST);",
+            )
+            .await
+            .unwrap();
+
+        let indexes = target.list_indexes().await.unwrap();
+        let index = indexes
+            .into_iter()
+            .find(|i| i.name == "orders_ordering_idx")
+            .unwrap();
+        assert_eq!(
+            index.columns,
+            vec![
+                "price DESC".to_string(),
+                "customer_id NULLS FIRST".to_string(),
+                "lower(id::text) DESC NULLS LAST".to_string(),
+            ]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_constraints() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+
+        let constraints = target.list_constraints().await.unwrap();
+        assert_eq!(
+            constraints,
+            vec![
+                ConstraintObject {
+                    table: "customers".into(),
+                    name: "customers_pkey".to_string(),
+                    validated: true,
+                    definition: "PRIMARY KEY (id)".to_string(),
+                },
+                ConstraintObject {
+                    table: "orders".into(),
+                    name: "orders_customer_id_fkey".to_string(),
+                    validated: true,
+                    definition: "FOREIGN KEY (customer_id) REFERENCES customers(id)".to_string(),
+                },
+                ConstraintObject {
+                    table: "orders".into(),
+                    name: "orders_pkey".to_string(),
+                    validated: true,
+                    definition: "PRIMARY KEY (id)".to_string(),
+                },
+                ConstraintObject {
+                    table: "orders".into(),
+                    name: "orders_price_check".to_string(),
+                    validated: false,
+                    definition: "CHECK (((price)::numeric >= (0)::numeric))".to_string(),
+                }
+            ]
+        );
+
+        // Check constraints on tables locked by another session are listed without waiting for
+        // the lock, and without a definition.
+        let _locker = lock_tables(&dsn, ["orders"]).await;
+        let locked_constraints = target.list_constraints().await.unwrap();
+        let definitions: Vec<_> = locked_constraints
+            .iter()
+            .map(|c| c.definition.as_str())
+            .collect();
+        assert_eq!(
+            definitions,
+            vec![
+                "PRIMARY KEY (id)",
+                "FOREIGN KEY (customer_id) REFERENCES customers(id)",
+                "PRIMARY KEY (id)",
+                "",
+            ]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_domains() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+
+        let domains = target.list_domains().await.unwrap();
+        assert_eq!(
+            domains,
+            vec![DomainObject {
+                name: "price".to_string(),
+                data_type: "numeric".to_string(),
+            }]
+        );
+        let constraints = target.list_domain_constraints().await.unwrap();
+        assert_eq!(
+            constraints,
+            vec![DomainConstraintObject {
+                domain: "price".to_string(),
+                name: "price_check".to_string(),
+                validated: true,
+            }]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_sequences() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute("CREATE SEQUENCE invoice_numbers START 1000 INCREMENT 10 CYCLE;")
+            .await
+            .unwrap();
+
+        let serial = |name: &str, owned_by: &str| SequenceObject {
+            name: name.to_string(),
+            start: 1,
+            increment: 1,
+            min: 1,
+            max: i32::MAX.into(),
+            cycle: false,
+            owned_by: Some(owned_by.to_string()),
+        };
+        let sequences = target.list_sequences().await.unwrap();
+        assert_eq!(
+            sequences,
+            vec![
+                serial("customers_id_seq", "customers.id"),
+                SequenceObject {
+                    name: "invoice_numbers".to_string(),
+                    start: 1000,
+                    increment: 10,
+                    min: 1,
+                    max: i64::MAX,
+                    cycle: true,
+                    owned_by: None,
+                },
+                serial("orders_id_seq", "orders.id"),
+            ]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_triggers() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE FUNCTION touch() RETURNS trigger LANGUAGE plpgsql AS \
+                 $$ BEGIN RETURN NEW; END $$; \
+                 CREATE TRIGGER orders_touch BEFORE UPDATE ON orders \
+                 FOR EACH ROW EXECUTE FUNCTION touch(); \
+                 ALTER TABLE orders ENABLE REPLICA TRIGGER orders_touch; \
+                 ALTER TABLE customers DISABLE TRIGGER ALL;",
+            )
+            .await
+            .unwrap();
+
+        let trigger = |table: &str, name: &str, enabled, internal| TriggerObject {
+            table: table.into(),
+            name: name.to_string(),
+            enabled,
+            internal,
+        };
+        // Each foreign key is enforced by internal triggers on both of its tables.
+        assert_eq!(
+            target.list_triggers().await.unwrap(),
+            vec![
+                trigger(
+                    "customers",
+                    "orders_customer_id_fkey",
+                    TriggerEnabled::Disabled,
+                    true
+                ),
+                trigger(
+                    "orders",
+                    "orders_customer_id_fkey",
+                    TriggerEnabled::Enabled,
+                    true
+                ),
+                trigger("orders", "orders_touch", TriggerEnabled::Replica, false),
+            ]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_storage_parameters() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        assert_eq!(target.list_storage_parameters().await.unwrap(), vec![]);
+
+        target
+            .conn
+            .execute(
+                "ALTER TABLE orders SET (fillfactor = 70, toast.autovacuum_enabled = false, \
+                 autovacuum_vacuum_scale_factor = 0.01);",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            target.list_storage_parameters().await.unwrap(),
+            vec![StorageParametersObject {
+                table: "orders".into(),
+                parameters: vec![
+                    "autovacuum_vacuum_scale_factor=0.01".to_string(),
+                    "fillfactor=70".to_string(),
+                    "toast.autovacuum_enabled=false".to_string(),
+                ],
+            }]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_index_storage_parameters() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        assert_eq!(
+            target.list_index_storage_parameters().await.unwrap(),
+            vec![]
+        );
+
+        target
+            .conn
+            .execute("ALTER INDEX orders_price_idx SET (fillfactor = 70, deduplicate_items = off);")
+            .await
+            .unwrap();
+        assert_eq!(
+            target.list_index_storage_parameters().await.unwrap(),
+            vec![IndexStorageParametersObject {
+                table: "orders".into(),
+                index: "orders_price_idx".to_string(),
+                parameters: vec![
+                    "deduplicate_items=off".to_string(),
+                    "fillfactor=70".to_string()
+                ],
+            }]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_index_inheritance() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE TABLE events (id int, created_at date) PARTITION BY RANGE (created_at);
+                 CREATE TABLE events_2024 PARTITION OF events
+                     FOR VALUES FROM ('2024-01-01') TO ('2025-01-01');
+                 CREATE INDEX events_id_idx ON events (id);",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            target.list_index_inheritance().await.unwrap(),
+            vec![IndexInheritanceObject {
+                table: "events_2024".into(),
+                index: "events_2024_id_idx".to_string(),
+                parent: "events_id_idx".to_string(),
+            }]
+        );
+        // The index on the partition has storage, and the index on the partitioned table does not.
+        let file_nodes = target.list_object_file_nodes().await.unwrap();
+        let indexes: Vec<_> = file_nodes
+            .keys()
+            .filter_map(|object| match object {
+                DBObject::Index(index) if index.name.starts_with("events") => {
+                    Some(index.name.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(indexes, vec!["events_2024_id_idx"]);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_unlogged_tables() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        assert_eq!(target.list_unlogged_tables().await.unwrap(), vec![]);
+
+        target
+            .conn
+            .execute("ALTER TABLE orders SET UNLOGGED; CREATE UNLOGGED TABLE events (id int);")
+            .await
+            .unwrap();
+        let unlogged = |table: &str| UnloggedTableObject {
+            table: table.into(),
+        };
+        assert_eq!(
+            target.list_unlogged_tables().await.unwrap(),
+            vec![unlogged("events"), unlogged("orders")]
+        );
+
+        target
+            .conn
+            .execute("ALTER TABLE orders SET LOGGED;")
+            .await
+            .unwrap();
+        assert_eq!(
+            target.list_unlogged_tables().await.unwrap(),
+            vec![unlogged("events")]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_view_dependencies() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE VIEW order_prices AS SELECT id, price FROM orders; \
+                 CREATE MATERIALIZED VIEW total_price AS SELECT sum(price) AS total FROM order_prices;",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            target.list_materialized_views().await.unwrap(),
+            vec![MaterializedViewObject {
+                name: "total_price".to_string()
+            }]
+        );
+        let dependency = |view: DBObject, table: &str, column: &str| ViewDependency {
+            view,
+            table: table.into(),
+            column: Some(column.to_string()),
+        };
+        let order_prices = DBObject::Table("order_prices".into());
+        let total_price = DBObject::MaterializedView(MaterializedViewObject {
+            name: "total_price".to_string(),
+        });
+        assert_eq!(
+            target.list_view_dependencies().await.unwrap(),
+            vec![
+                dependency(order_prices.clone(), "orders", "id"),
+                dependency(order_prices.clone(), "orders", "price"),
+                dependency(total_price, "order_prices", "price"),
+            ]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_table_sizes() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "INSERT INTO customers (name) SELECT 'customer' FROM generate_series(1, 1000); \
+                 ANALYZE customers;",
+            )
+            .await
+            .unwrap();
+
+        let sizes = target.list_table_sizes().await.unwrap();
+        let tables: Vec<_> = sizes.iter().map(|size| size.table.name.as_str()).collect();
+        assert_eq!(tables, vec!["customers", "orders"]);
+        assert_eq!(sizes[0].estimated_rows, Some(1000));
+        assert!(sizes[0].total_bytes > sizes[1].total_bytes);
+
+        // Tables locked by another session are sized without waiting for the lock.
+        let _locker = lock_tables(&dsn, ["customers"]).await;
+        let locked_sizes = target.list_table_sizes().await.unwrap();
+        assert_eq!(locked_sizes[0].table, sizes[0].table);
+        assert!(locked_sizes[0].total_bytes > 0);
+    }
+
+    #[tokio::test]
+    async fn test_list_object_file_nodes_with_view() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE VIEW order_prices AS SELECT id, price FROM orders;
+                 CREATE TABLE events (id int) PARTITION BY RANGE (id);",
+            )
+            .await
+            .unwrap();
+
+        let file_nodes = target.list_object_file_nodes().await.unwrap();
+        let mut objects: Vec<_> = file_nodes.keys().map(|object| object.to_string()).collect();
+        objects.sort();
+        assert_eq!(
+            objects,
+            vec![
+                "Index orders.orders_price_idx (price)",
+                "Primary key index customers.customers_pkey (id)",
+                "Primary key index orders.orders_pkey (id)",
+                "Table customers",
+                "Table orders"
+            ]
+        );
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_snapshot() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+
+        let snapshot = target.snapshot().await.unwrap();
+        assert!(snapshot.server_version_num > 0);
+        assert_eq!(snapshot.objects, target.list_objects().await.unwrap());
+        assert_eq!(
+            snapshot.file_nodes(),
+            target.list_object_file_nodes().await.unwrap()
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let serialized = serde_json::to_string(&snapshot).unwrap();
+            let deserialized: SchemaSnapshot = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(snapshot, deserialized);
+        }
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_extensions() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn)
+            .await
+            .unwrap()
+            .with_extension_members(false);
+        target
+            .conn
+            .execute("CREATE EXTENSION pg_trgm; ALTER EXTENSION pg_trgm ADD TABLE customers;")
+            .await
+            .unwrap();
+
+        let extensions = target.list_extensions().await.unwrap();
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0].name, "pg_trgm");
+
+        let tables = target.list_tables().await.unwrap();
+        assert_eq!(tables, vec!["orders".into()]);
+    }
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_inheritance() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE TABLE events (id int);
+                 CREATE TABLE events_2025 () INHERITS (events);
+                 CREATE TABLE measurements (id int) PARTITION BY RANGE (id);
+                 CREATE TABLE measurements_1 PARTITION OF measurements FOR VALUES FROM (0) TO (10);
+                 CREATE INDEX ON measurements (id);",
+            )
+            .await
+            .unwrap();
+
+        let inheritance = target.list_inheritance().await.unwrap();
+        assert_eq!(
+            inheritance,
+            vec![
+                InheritanceObject {
+                    parent: "events".into(),
+                    child: "events_2025".into(),
+                },
+                InheritanceObject {
+                    parent: "measurements".into(),
+                    child: "measurements_1".into(),
+                },
+            ]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_objects_referring_to_another_schema() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE SCHEMA archive;
+                 CREATE TABLE archive.orders (id int, note text);
+                 CREATE VIEW archived_notes AS SELECT id, note FROM archive.orders;
+                 CREATE TABLE archived_orders () INHERITS (archive.orders);",
+            )
+            .await
+            .unwrap();
+
+        // Tables in the archive schema are named with it, so they are distinct from public.orders.
+        assert_eq!(
+            target.list_view_dependencies().await.unwrap(),
+            ["id", "note"]
+                .map(|column| ViewDependency {
+                    view: DBObject::Table("archived_notes".into()),
+                    table: TableObject::in_schema("archive", "orders"),
+                    column: Some(column.to_string()),
+                })
+                .to_vec()
+        );
+        assert_eq!(
+            target.list_inheritance().await.unwrap(),
+            vec![InheritanceObject {
+                parent: TableObject::in_schema("archive", "orders"),
+                child: "archived_orders".into(),
+            }]
+        );
+        assert!(
+            !target
+                .list_tables()
+                .await
+                .unwrap()
+                .contains(&TableObject::in_schema("archive", "orders"))
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_foreign_tables() {
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "CREATE EXTENSION postgres_fdw;
+                 CREATE SERVER remote FOREIGN DATA WRAPPER postgres_fdw OPTIONS (dbname 'remote');
+                 CREATE FOREIGN TABLE remote_orders (id int) SERVER remote;",
+            )
+            .await
+            .unwrap();
+
+        let foreign_tables = target.list_foreign_tables().await.unwrap();
+        assert_eq!(
+            foreign_tables,
+            vec![ForeignTableObject {
+                name: "remote_orders".into(),
+                server: "remote".into(),
+            }]
+        );
+        let tables = target.list_tables().await.unwrap();
+        assert!(!tables.contains(&"remote_orders".into()));
+        let file_nodes = target.list_object_file_nodes().await.unwrap();
+        assert!(!file_nodes.contains_key(&DBObject::Table("remote_orders".into())));
+    }
+
+    #[cfg(feature = "comments")]
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_comments() {
+        use crate::CommentObject;
+        use sqlx::Executor;
+
+        let (_container, dsn) = start_test_postgres().await;
+        let mut target = Introspector::new(&dsn).await.unwrap();
+        target
+            .conn
+            .execute(
+                "COMMENT ON TABLE orders IS 'All orders'; \
+                 COMMENT ON COLUMN orders.price IS 'The order price';",
+            )
+            .await
+            .unwrap();
+
+        let comments = target.list_comments().await.unwrap();
+        assert_eq!(
+            comments,
+            vec![
+                CommentObject {
+                    table: "orders".into(),
+                    column: None,
+                    comment: "All orders".to_string(),
+                },
+                CommentObject {
+                    table: "orders".into(),
+                    column: Some("price".to_string()),
+                    comment: "The order price".to_string(),
+                }
+            ]
+        );
+    }
 }
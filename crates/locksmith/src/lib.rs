@@ -1,12 +1,101 @@
 #![doc = include_str!("../README.md")]
 
+mod analysis;
+mod cache;
+mod clock;
+mod error;
 mod executor;
 mod introspection;
+mod lint;
 mod locker;
 mod objects;
 mod oracle;
+mod pool;
+mod runtime;
+mod seed;
+mod snapshot;
+mod statement;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(test)]
 mod tests;
+mod tls;
 
+pub use analysis::StatementObservation;
+pub use cache::{CacheKey, InspectionCache, MemoryCache};
+pub use error::{LocksmithError, StatementError};
+pub use executor::StatementExecutor;
+pub use introspection::{Introspector, SchemaSource};
+pub use lint::{LintRule, LintWarning};
+pub use locker::Locker;
 pub use objects::*;
 pub use oracle::*;
+pub use pool::ConnectionPool;
+pub use snapshot::{ChangeReason, DBObjectDiff, ObjectChange, ObjectChanges, SchemaSnapshot};
+pub use tls::{ClientCertificate, TlsConfig};
+/// The trait for bind parameters given to
+/// [QueryOracle::inspect_statement_params](crate::QueryOracle::inspect_statement_params).
+pub use tokio_postgres::types::ToSql;
+
+/// The `tracing` target of the debug events emitted for every SQL statement run by a [Locker] or
+/// a [StatementExecutor], including the statement being inspected. Enabling it, for example with
+/// `RUST_LOG=locksmith::sql=debug`, gives the full sequence of commands issued to the database
+/// during an inspection.
+pub const SQL_LOG_TARGET: &str = "locksmith::sql";
+
+/// Emit a debug event with the [SQL_LOG_TARGET] target for `sql`, as it is about to be run by
+/// `connection` with the backend `connection_id`, if it is known.
+pub(crate) fn log_sql(connection: &str, connection_id: Option<ConnectionID>, sql: &str) {
+    tracing::debug!(
+        target: SQL_LOG_TARGET,
+        connection,
+        pid = connection_id.map(|id| id.0),
+        sql = sql.trim(),
+        "Executing SQL"
+    );
+}
+
+/// Serialize `set` as an array sorted by the [Ord] implementation of its items, rather than in the
+/// arbitrary order of the set, so that serialized results are stable across runs and can be used
+/// in golden tests.
+#[cfg(feature = "serde")]
+pub(crate) fn serialize_sorted<T, S>(
+    set: &std::collections::HashSet<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    T: serde::Serialize + Ord,
+    S: serde::Serializer,
+{
+    let mut items: Vec<_> = set.iter().collect();
+    items.sort();
+    serializer.collect_seq(items)
+}
+
+/// Build a `SET search_path` statement that sets the search path to `schemas`, in order.
+pub(crate) fn search_path_statement(schemas: &[impl AsRef<str>]) -> String {
+    if schemas.is_empty() {
+        return "SET search_path TO '';".to_string();
+    }
+    let schemas = schemas
+        .iter()
+        .map(|schema| quote_identifier(schema.as_ref()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("SET search_path TO {schemas};")
+}
+
+/// The `SET application_name` statement that identifies a connection as `name`.
+pub(crate) fn application_name_statement(name: &str) -> String {
+    format!("SET application_name TO '{}';", name.replace('\'', "''"))
+}
+
+/// The `SET ROLE` statement that makes a connection assume `role`.
+pub(crate) fn role_statement(role: &str) -> String {
+    format!("SET ROLE {};", quote_identifier(role))
+}
+
+/// Quote `name` for use as an identifier in a SQL statement.
+pub(crate) fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,670 @@
+use crate::{DBObject, IndexObject, InspectedStatement, Lock};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A rule checked by [InspectedStatement::lint].
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum LintRule {
+    /// An index is created on an existing table without `CONCURRENTLY`, which takes a
+    /// [Lock::ShareLock] that blocks all writes to the table until the index is built.
+    PreferCreateIndexConcurrently,
+    /// An index is dropped from a table without `CONCURRENTLY`, which takes a
+    /// [Lock::AccessExclusiveLock] that blocks all reads and writes to the table.
+    PreferDropIndexConcurrently,
+    /// A view or materialized view that depends on a removed or altered object is dropped along
+    /// with it by `CASCADE`, which can break queries that read from the view.
+    CascadingDrop,
+    /// A stored generated column is added to an existing table, or its expression is changed,
+    /// which rewrites the table under a [Lock::AccessExclusiveLock] to compute the value of every
+    /// row.
+    StoredGeneratedColumn,
+    /// A primary key is added to an existing table by building a new index, which holds a
+    /// [Lock::AccessExclusiveLock] that blocks all reads and writes to the table until the index
+    /// is built.
+    PreferPrimaryKeyUsingIndex,
+    /// An existing table is changed with `SET LOGGED` or `SET UNLOGGED`, which rewrites the table
+    /// under a [Lock::AccessExclusiveLock] although only its durability changes.
+    PersistenceChangeRewrite,
+    /// A validated `CHECK` or `FOREIGN KEY` constraint is added to an existing table, which scans
+    /// the table to validate every row while holding a lock that blocks writes to it.
+    PreferNotValidConstraint,
+}
+
+impl LintRule {
+    /// The name of the rule, such as `prefer-create-index-concurrently`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintRule::PreferCreateIndexConcurrently => "prefer-create-index-concurrently",
+            LintRule::PreferDropIndexConcurrently => "prefer-drop-index-concurrently",
+            LintRule::CascadingDrop => "cascading-drop",
+            LintRule::StoredGeneratedColumn => "stored-generated-column",
+            LintRule::PreferPrimaryKeyUsingIndex => "prefer-primary-key-using-index",
+            LintRule::PersistenceChangeRewrite => "persistence-change-rewrite",
+            LintRule::PreferNotValidConstraint => "prefer-not-valid-constraint",
+        }
+    }
+}
+
+impl Display for LintRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A problem with a statement found by [InspectedStatement::lint], such as taking a lock that
+/// blocks the application when a less disruptive alternative exists.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LintWarning {
+    pub rule: LintRule,
+    /// The object that the warning is about.
+    pub object: DBObject,
+    /// A description of the problem and how to fix it.
+    pub message: String,
+}
+
+impl InspectedStatement {
+    /// Check the side effects of the statement against every [LintRule], returning a
+    /// [LintWarning] for each problem found, sorted by rule and object.
+    ///
+    /// Rules are checked against the inspection result rather than the SQL of the statement, so
+    /// they apply however the statement is written, including to statements run by functions.
+    pub fn lint(&self) -> Vec<LintWarning> {
+        let mut warnings: Vec<_> = self
+            .create_index_warnings()
+            .chain(self.drop_index_warnings())
+            .chain(self.cascading_drop_warnings())
+            .chain(self.stored_generated_column_warnings())
+            .chain(self.primary_key_warnings())
+            .chain(self.persistence_change_warnings())
+            .chain(self.not_valid_constraint_warnings())
+            .collect();
+        warnings.sort();
+        warnings
+    }
+
+    /// [LintRule::PreferCreateIndexConcurrently]: indexes that were added to a table whose
+    /// strongest lock was a [Lock::ShareLock]. Tables created by the statement are never locked,
+    /// and indexes added by `ALTER TABLE`, such as for a new primary key, are built under a
+    /// stronger lock that `CONCURRENTLY` does not avoid, so neither are reported.
+    fn create_index_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        let strongest_locks = self.strongest_lock_per_table();
+        self.added_objects.iter().filter_map(move |object| {
+            let DBObject::Index(index) = object else {
+                return None;
+            };
+            if strongest_locks.get(&index.table) != Some(&Lock::ShareLock) {
+                return None;
+            }
+            Some(LintWarning {
+                rule: LintRule::PreferCreateIndexConcurrently,
+                object: object.clone(),
+                message: format!(
+                    "Creating index `{}` takes a SHARE lock on `{}`, which blocks all writes to \
+                     the table until the index is built. Use `CREATE INDEX CONCURRENTLY` instead, \
+                     outside of a transaction.",
+                    index.name, index.table
+                ),
+            })
+        })
+    }
+
+    /// [LintRule::PreferDropIndexConcurrently]: indexes that were removed from a table that still
+    /// exists, and whose columns and constraint were not removed with them. Indexes removed along
+    /// with their table, a column or the constraint they back cannot be dropped concurrently, and
+    /// indexes [attached to a new constraint](Self::attached_to_constraint) were not dropped.
+    fn drop_index_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        let strongest_locks = self.strongest_lock_per_table();
+        self.removed_objects.iter().filter_map(move |object| {
+            let DBObject::Index(index) = object else {
+                return None;
+            };
+            let removed_with_index = self.removed_objects.iter().any(|removed| match removed {
+                DBObject::Table(table) => *table == index.table,
+                DBObject::Column(column) => {
+                    column.table == index.table && index.columns.contains(&column.name)
+                }
+                DBObject::Constraint(constraint) => {
+                    constraint.table == index.table && constraint.name == index.name
+                }
+                _ => false,
+            });
+            if removed_with_index
+                || self.attached_to_constraint(index)
+                || strongest_locks.get(&index.table) != Some(&Lock::AccessExclusiveLock)
+            {
+                return None;
+            }
+            Some(LintWarning {
+                rule: LintRule::PreferDropIndexConcurrently,
+                object: object.clone(),
+                message: format!(
+                    "Dropping index `{}` takes an ACCESS EXCLUSIVE lock on `{}`, which blocks all \
+                     reads and writes to the table. Use `DROP INDEX CONCURRENTLY` instead, which \
+                     only takes a SHARE UPDATE EXCLUSIVE lock, outside of a transaction.",
+                    index.name, index.table
+                ),
+            })
+        })
+    }
+
+    /// Returns `true` if the removed `index` became the index of a constraint added by the
+    /// statement, as it does with `ADD CONSTRAINT ... USING INDEX`, which renames the index to the
+    /// name of the constraint.
+    fn attached_to_constraint(&self, index: &IndexObject) -> bool {
+        self.added_objects.iter().any(|added| {
+            let DBObject::Index(attached) = added else {
+                return false;
+            };
+            attached.table == index.table
+                && attached.columns == index.columns
+                && self.added_objects.iter().any(|constraint| {
+                    matches!(constraint, DBObject::Constraint(constraint)
+                        if constraint.table == attached.table && constraint.name == attached.name)
+                })
+        })
+    }
+
+    /// [LintRule::CascadingDrop]: [dependent views](InspectedStatement::dependents) that were
+    /// removed by the statement. Views that the statement drops and recreates unchanged are not
+    /// removed, so they are not reported.
+    fn cascading_drop_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        self.dependents
+            .iter()
+            .filter(|view| self.removed_objects.contains(view))
+            .map(|view| LintWarning {
+                rule: LintRule::CascadingDrop,
+                object: view.clone(),
+                message: format!(
+                    "{view} depends on a removed or altered object, and is dropped along with it \
+                     by `CASCADE`. Check that nothing reads from it, or recreate it in the same \
+                     migration."
+                ),
+            })
+    }
+
+    /// [LintRule::StoredGeneratedColumn]: stored generated columns that were added to a table
+    /// that the statement rewrote. Columns added to a table created by the statement do not cause
+    /// a rewrite, so they are not reported.
+    fn stored_generated_column_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        self.added_objects.iter().filter_map(move |object| {
+            let DBObject::Column(column) = object else {
+                return None;
+            };
+            let stored = column.generated.as_ref().is_some_and(|g| g.stored);
+            if !stored
+                || !self
+                    .rewrites
+                    .contains(&DBObject::Table(column.table.clone()))
+            {
+                return None;
+            }
+            Some(LintWarning {
+                rule: LintRule::StoredGeneratedColumn,
+                object: object.clone(),
+                message: format!(
+                    "Adding stored generated column `{}` to `{}`, or changing its expression, \
+                     rewrites the table to compute the value of every row while holding an \
+                     ACCESS EXCLUSIVE lock. Add a regular column that is backfilled in batches \
+                     and kept up to date by a trigger instead, or use a virtual generated column \
+                     on Postgres 18 or later.",
+                    column.name, column.table
+                ),
+            })
+        })
+    }
+
+    /// [LintRule::PreferPrimaryKeyUsingIndex]: primary key indexes that were added to a table
+    /// whose strongest lock was a [Lock::AccessExclusiveLock], unless an existing index on the
+    /// same columns was removed with it. `ADD CONSTRAINT ... USING INDEX` turns an existing
+    /// unique index into the primary key index, which replaces it rather than building a new one.
+    fn primary_key_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        let strongest_locks = self.strongest_lock_per_table();
+        self.added_objects.iter().filter_map(move |object| {
+            let DBObject::Index(index) = object else {
+                return None;
+            };
+            let uses_existing_index = self.removed_objects.iter().any(|removed| {
+                matches!(removed, DBObject::Index(existing)
+                    if existing.table == index.table && existing.columns == index.columns)
+            });
+            if !index.is_primary
+                || uses_existing_index
+                || strongest_locks.get(&index.table) != Some(&Lock::AccessExclusiveLock)
+            {
+                return None;
+            }
+            Some(LintWarning {
+                rule: LintRule::PreferPrimaryKeyUsingIndex,
+                object: object.clone(),
+                message: format!(
+                    "Adding primary key `{}` builds its index while holding an ACCESS EXCLUSIVE \
+                     lock on `{}`, which blocks all reads and writes to the table. Build a unique \
+                     index with `CREATE UNIQUE INDEX CONCURRENTLY` first, then add the primary \
+                     key with `ADD CONSTRAINT ... PRIMARY KEY USING INDEX`.",
+                    index.name, index.table
+                ),
+            })
+        })
+    }
+
+    /// [LintRule::PersistenceChangeRewrite]: tables that became unlogged or logged and were
+    /// rewritten by the statement. Tables created with `CREATE UNLOGGED TABLE` are not rewritten,
+    /// so they are not reported.
+    fn persistence_change_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        let set_unlogged = self
+            .added_objects
+            .iter()
+            .map(|object| (object, Persistence::Unlogged));
+        let set_logged = self
+            .removed_objects
+            .iter()
+            .map(|object| (object, Persistence::Logged));
+        set_unlogged
+            .chain(set_logged)
+            .filter_map(|(object, persistence)| {
+                let DBObject::UnloggedTable(unlogged) = object else {
+                    return None;
+                };
+                if !self
+                    .rewrites
+                    .contains(&DBObject::Table(unlogged.table.clone()))
+                {
+                    return None;
+                }
+                let wal = match persistence {
+                    Persistence::Logged => ", and writes every row to the WAL",
+                    Persistence::Unlogged => "",
+                };
+                Some(LintWarning {
+                    rule: LintRule::PersistenceChangeRewrite,
+                    object: object.clone(),
+                    message: format!(
+                        "Setting `{}` to {persistence} rewrites the table while holding an \
+                         ACCESS EXCLUSIVE lock, which blocks all reads and writes to the table \
+                         until it is copied{wal}. No data is changed, only the durability of the \
+                         table.",
+                        unlogged.table
+                    ),
+                })
+            })
+    }
+
+    /// [LintRule::PreferNotValidConstraint]: validated `CHECK` and `FOREIGN KEY` constraints that
+    /// were added to a table that existed before the statement. Constraints of tables created by
+    /// the statement have no rows to validate, and `VALIDATE CONSTRAINT`, which replaces a
+    /// constraint that is not valid with a validated one, is the recommended alternative, so
+    /// neither are reported.
+    fn not_valid_constraint_warnings(&self) -> impl Iterator<Item = LintWarning> + '_ {
+        self.added_objects.iter().filter_map(|object| {
+            let DBObject::Constraint(constraint) = object else {
+                return None;
+            };
+            let validates_rows = constraint.definition.starts_with("CHECK ")
+                || constraint.definition.starts_with("FOREIGN KEY ");
+            let table_created = self
+                .added_objects
+                .contains(&DBObject::Table(constraint.table.clone()));
+            let validates_existing = self.removed_objects.iter().any(|removed| {
+                matches!(removed, DBObject::Constraint(existing)
+                    if existing.table == constraint.table
+                        && existing.name == constraint.name
+                        && !existing.validated)
+            });
+            if !constraint.validated || !validates_rows || table_created || validates_existing {
+                return None;
+            }
+            Some(LintWarning {
+                rule: LintRule::PreferNotValidConstraint,
+                object: object.clone(),
+                message: format!(
+                    "Adding constraint `{}` to `{}` scans the table to validate every existing \
+                     row while holding a lock that blocks writes to the table. Add it with `NOT \
+                     VALID` first, then validate it with `ALTER TABLE ... VALIDATE CONSTRAINT` in \
+                     a separate transaction, which only takes a SHARE UPDATE EXCLUSIVE lock.",
+                    constraint.name, constraint.table
+                ),
+            })
+        })
+    }
+}
+
+/// The persistence that a table is changed to by `ALTER TABLE ... SET LOGGED` or `SET UNLOGGED`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Persistence {
+    Logged,
+    Unlogged,
+}
+
+impl Display for Persistence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Persistence::Logged => f.write_str("logged"),
+            Persistence::Unlogged => f.write_str("unlogged"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ColumnObject, ConstraintObject, DBObject, GeneratedColumn, IndexObject, InspectedStatement,
+        LintRule, LintWarning, Lock, TableLock, TableObject, UnloggedTableObject,
+    };
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_lint_create_index() {
+        let index = |table: &str, name: &str| {
+            DBObject::Index(IndexObject {
+                table: table.into(),
+                name: name.to_string(),
+                columns: vec!["id".to_string()],
+                is_primary: false,
+                is_unique: false,
+            })
+        };
+        let lock = |table: &str, lock: Lock| TableLock {
+            table: table.into(),
+            lock,
+        };
+
+        let create_index = InspectedStatement {
+            added_objects: HashSet::from([index("orders", "orders_id_idx")]),
+            locks: HashSet::from([
+                lock("orders", Lock::ShareLock),
+                lock("orders", Lock::AccessShareLock),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            create_index.lint(),
+            vec![LintWarning {
+                rule: LintRule::PreferCreateIndexConcurrently,
+                object: index("orders", "orders_id_idx"),
+                message: "Creating index `orders_id_idx` takes a SHARE lock on `orders`, which \
+                          blocks all writes to the table until the index is built. Use \
+                          `CREATE INDEX CONCURRENTLY` instead, outside of a transaction."
+                    .to_string(),
+            }]
+        );
+        assert_eq!(
+            LintRule::PreferCreateIndexConcurrently.to_string(),
+            "prefer-create-index-concurrently"
+        );
+
+        // Indexes built under a stronger lock, or on a new table, are not reported.
+        let add_primary_key = InspectedStatement {
+            added_objects: HashSet::from([index("orders", "orders_pkey")]),
+            locks: HashSet::from([
+                lock("orders", Lock::ShareLock),
+                lock("orders", Lock::AccessExclusiveLock),
+            ]),
+            ..Default::default()
+        };
+        assert!(add_primary_key.lint().is_empty());
+        let create_table = InspectedStatement {
+            added_objects: HashSet::from([
+                DBObject::Table(TableObject::from("products")),
+                index("products", "products_pkey"),
+            ]),
+            ..Default::default()
+        };
+        assert!(create_table.lint().is_empty());
+    }
+
+    #[test]
+    fn test_lint_cascading_drop() {
+        let view = DBObject::Table("order_prices".into());
+        let drop_cascade = InspectedStatement {
+            removed_objects: HashSet::from([DBObject::Table("orders".into()), view.clone()]),
+            dependents: HashSet::from([view.clone()]),
+            ..Default::default()
+        };
+        let warnings = drop_cascade.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].rule, LintRule::CascadingDrop);
+        assert_eq!(warnings[0].object, view);
+
+        // Views that are recreated by the statement are not removed, so they are not reported.
+        let recreated = InspectedStatement {
+            removed_objects: HashSet::from([DBObject::Column(ColumnObject {
+                table: "orders".into(),
+                name: "price".to_string(),
+                data_type: "integer".to_string(),
+                generated: None,
+            })]),
+            ..drop_cascade
+        };
+        assert!(recreated.lint().is_empty());
+    }
+
+    #[test]
+    fn test_lint_drop_index() {
+        let index = DBObject::Index(IndexObject {
+            table: "orders".into(),
+            name: "orders_price_idx".to_string(),
+            columns: vec!["price".to_string()],
+            is_primary: false,
+            is_unique: false,
+        });
+        let drop_index = InspectedStatement {
+            removed_objects: HashSet::from([index.clone()]),
+            locks: HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }]),
+            ..Default::default()
+        };
+        let warnings = drop_index.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].rule, LintRule::PreferDropIndexConcurrently);
+        assert_eq!(warnings[0].object, index);
+
+        // Indexes removed along with their table, a column or their constraint are not reported.
+        for removed in [
+            DBObject::Table("orders".into()),
+            DBObject::Column(ColumnObject {
+                table: "orders".into(),
+                name: "price".to_string(),
+                data_type: "price".to_string(),
+                generated: None,
+            }),
+            DBObject::Constraint(ConstraintObject {
+                table: "orders".into(),
+                name: "orders_price_idx".to_string(),
+                validated: true,
+                definition: "UNIQUE (price)".to_string(),
+            }),
+        ] {
+            let mut statement = drop_index.clone();
+            statement.removed_objects.insert(removed.clone());
+            assert!(statement.lint().is_empty(), "{removed}");
+        }
+    }
+
+    #[test]
+    fn test_lint_stored_generated_column() {
+        let column = |stored: bool| {
+            DBObject::Column(ColumnObject {
+                table: "orders".into(),
+                name: "double_price".to_string(),
+                data_type: "numeric".to_string(),
+                generated: Some(GeneratedColumn {
+                    expression: "(price * 2)".to_string(),
+                    stored,
+                }),
+            })
+        };
+        let add_column = InspectedStatement {
+            added_objects: HashSet::from([column(true)]),
+            rewrites: HashSet::from([DBObject::Table("orders".into())]),
+            ..Default::default()
+        };
+        let warnings = add_column.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].rule, LintRule::StoredGeneratedColumn);
+        assert_eq!(warnings[0].object, column(true));
+
+        // Virtual columns, and columns of tables that were not rewritten, are not reported.
+        let virtual_column = InspectedStatement {
+            added_objects: HashSet::from([column(false)]),
+            ..add_column.clone()
+        };
+        assert!(virtual_column.lint().is_empty());
+        let create_table = InspectedStatement {
+            rewrites: HashSet::new(),
+            ..add_column
+        };
+        assert!(create_table.lint().is_empty());
+    }
+
+    #[test]
+    fn test_lint_primary_key() {
+        let index = |name: &str, is_primary: bool| {
+            DBObject::Index(IndexObject {
+                table: "orders".into(),
+                name: name.to_string(),
+                columns: vec!["id".to_string()],
+                is_primary,
+                is_unique: true,
+            })
+        };
+        let add_primary_key = InspectedStatement {
+            added_objects: HashSet::from([index("orders_pkey", true)]),
+            locks: HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }]),
+            ..Default::default()
+        };
+        let warnings = add_primary_key.lint();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].rule, LintRule::PreferPrimaryKeyUsingIndex);
+        assert_eq!(warnings[0].object, index("orders_pkey", true));
+
+        // Primary keys that use an existing index, or are added to a new table, are not reported.
+        let mut using_index = InspectedStatement {
+            removed_objects: HashSet::from([index("orders_id_idx", false)]),
+            ..add_primary_key.clone()
+        };
+        using_index
+            .added_objects
+            .insert(DBObject::Constraint(ConstraintObject {
+                table: "orders".into(),
+                name: "orders_pkey".to_string(),
+                validated: true,
+                definition: "PRIMARY KEY (id)".to_string(),
+            }));
+        assert!(using_index.lint().is_empty());
+        let create_table = InspectedStatement {
+            locks: HashSet::new(),
+            ..add_primary_key
+        };
+        assert!(create_table.lint().is_empty());
+    }
+
+    #[test]
+    fn test_lint_persistence_change() {
+        let unlogged = DBObject::UnloggedTable(UnloggedTableObject {
+            table: "orders".into(),
+        });
+        let set_unlogged = InspectedStatement {
+            added_objects: HashSet::from([unlogged.clone()]),
+            rewrites: HashSet::from([DBObject::Table("orders".into())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            set_unlogged.lint(),
+            vec![LintWarning {
+                rule: LintRule::PersistenceChangeRewrite,
+                object: unlogged.clone(),
+                message: "Setting `orders` to unlogged rewrites the table while holding an \
+                          ACCESS EXCLUSIVE lock, which blocks all reads and writes to the table \
+                          until it is copied. No data is changed, only the durability of the \
+                          table."
+                    .to_string(),
+            }]
+        );
+        let set_logged = InspectedStatement {
+            added_objects: HashSet::new(),
+            removed_objects: HashSet::from([unlogged.clone()]),
+            ..set_unlogged.clone()
+        };
+        let warnings = set_logged.lint();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("writes every row to the WAL"));
+
+        // Tables created as unlogged are not rewritten, so they are not reported.
+        let create_table = InspectedStatement {
+            rewrites: HashSet::new(),
+            ..set_unlogged
+        };
+        assert!(create_table.lint().is_empty());
+    }
+
+    #[test]
+    fn test_lint_not_valid_constraint() {
+        let constraint = |validated: bool| {
+            DBObject::Constraint(ConstraintObject {
+                table: "orders".into(),
+                name: "orders_customer_fk".to_string(),
+                validated,
+                definition: "FOREIGN KEY (customer_id) REFERENCES customers(id)".to_string(),
+            })
+        };
+        let add_constraint = InspectedStatement {
+            added_objects: HashSet::from([constraint(true)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            add_constraint.lint(),
+            vec![LintWarning {
+                rule: LintRule::PreferNotValidConstraint,
+                object: constraint(true),
+                message: "Adding constraint `orders_customer_fk` to `orders` scans the table to \
+                          validate every existing row while holding a lock that blocks writes to \
+                          the table. Add it with `NOT VALID` first, then validate it with `ALTER \
+                          TABLE ... VALIDATE CONSTRAINT` in a separate transaction, which only \
+                          takes a SHARE UPDATE EXCLUSIVE lock."
+                    .to_string(),
+            }]
+        );
+
+        // Adding the constraint as not valid, or validating it afterwards, is what the rule
+        // recommends.
+        let not_valid = InspectedStatement {
+            added_objects: HashSet::from([constraint(false)]),
+            ..Default::default()
+        };
+        assert!(not_valid.lint().is_empty());
+        let validate = InspectedStatement {
+            removed_objects: HashSet::from([constraint(false)]),
+            ..add_constraint.clone()
+        };
+        assert!(validate.lint().is_empty());
+
+        // A new table has no rows to validate.
+        let create_table = InspectedStatement {
+            added_objects: HashSet::from([DBObject::Table("orders".into()), constraint(true)]),
+            ..Default::default()
+        };
+        assert!(create_table.lint().is_empty());
+
+        // Primary keys and unique constraints are validated by building their index.
+        let primary_key = DBObject::Constraint(ConstraintObject {
+            table: "orders".into(),
+            name: "orders_pkey".to_string(),
+            validated: true,
+            definition: "PRIMARY KEY (id)".to_string(),
+        });
+        let add_primary_key = InspectedStatement {
+            added_objects: HashSet::from([primary_key]),
+            ..Default::default()
+        };
+        assert!(add_primary_key.lint().is_empty());
+    }
+}
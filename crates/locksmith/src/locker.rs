@@ -1,82 +1,604 @@
-use crate::ConnectionID;
-use crate::objects::{TableLock, TableObject};
-use anyhow::Context;
-use sqlx::{Connection, Executor, PgConnection, query, query_as};
-use tracing::trace;
+use crate::objects::{
+    BlockedQuery, Lock, PredicateLock, PredicateLockGranularity, RawLock, TableLock, TableObject,
+};
+use crate::pool::{ConnectionPool, PgConn};
+use crate::{ConnectionID, LocksmithError, TlsConfig};
+use anyhow::{Context, bail};
+use sqlx::{Execute, Executor, query, query_as, query_scalar};
+use std::time::Duration;
+use tracing::{debug, trace};
+
+/// The SQLSTATE returned by Postgres when a `NOWAIT` lock cannot be acquired immediately.
+const LOCK_NOT_AVAILABLE: &str = "55P03";
 
 /// A [Locker] manages Postgres table locks in a transaction.
-/// It provides two methods: [Locker::lock_tables] and [Locker::list_connection_locks].
+/// It provides methods to lock tables, via [Locker::lock_tables] and [Locker::try_lock_tables],
+/// and to list the locks held by another connection via [Locker::list_connection_locks].
 pub struct Locker {
-    conn: PgConnection,
+    conn: PgConn,
+    connection_id: ConnectionID,
+    include_toast_locks: bool,
 }
 
 impl Locker {
     /// Construct a new [Locker] with a connection to the Postgres database at `dsn`.
-    pub async fn new(dsn: &str) -> anyhow::Result<Self> {
-        let mut conn = PgConnection::connect(dsn)
+    pub async fn new(dsn: &str) -> Result<Self, LocksmithError> {
+        Self::new_with_tls(dsn, &TlsConfig::default()).await
+    }
+
+    /// Construct a new [Locker] as in [Locker::new], using TLS as configured by `tls`.
+    pub async fn new_with_tls(dsn: &str, tls: &TlsConfig) -> Result<Self, LocksmithError> {
+        let conn = PgConn::connect(dsn, tls)
+            .await
+            .map_err(|source| LocksmithError::Connection { source })?;
+        Self::with_connection(conn).await
+    }
+
+    /// Construct a new [Locker] with a connection acquired from `pool`. The connection is rolled
+    /// back, releasing the locker's locks, and returned to the pool when the locker is closed or
+    /// dropped.
+    pub async fn from_pool(pool: &ConnectionPool) -> Result<Self, LocksmithError> {
+        let conn = pool
+            .acquire()
             .await
-            .context("Creating connection")?;
-        conn.ping().await.context("Pinging postgres")?;
-        query!("BEGIN;")
-            .execute(&mut conn)
+            .map_err(|source| LocksmithError::Connection { source })?;
+        Self::with_connection(conn).await
+    }
+
+    /// Start the locker's transaction on `conn`.
+    async fn with_connection(mut conn: PgConn) -> Result<Self, LocksmithError> {
+        let pid_query = query_scalar!(r#"SELECT pg_backend_pid() as "pid!""#);
+        crate::log_sql("locker", None, pid_query.sql());
+        let connection_id = ConnectionID(
+            pid_query
+                .fetch_one(&mut *conn)
+                .await
+                .context("Query error while retrieving connection ID")?,
+        );
+        let begin = query!("BEGIN;");
+        crate::log_sql("locker", Some(connection_id), begin.sql());
+        begin
+            .execute(&mut *conn)
             .await
             .context("Starting transaction")?;
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            connection_id,
+            include_toast_locks: false,
+        })
+    }
+
+    /// Log a SQL statement the locker's connection is about to run, see
+    /// [SQL_LOG_TARGET](crate::SQL_LOG_TARGET).
+    fn log_sql(&self, sql: &str) {
+        crate::log_sql("locker", Some(self.connection_id), sql);
+    }
+
+    /// Close the connection, releasing all of the locks held by the locker.
+    ///
+    /// Dropping a [Locker] also releases its locks once the server notices that the connection
+    /// has closed, but this can take a while if the runtime is shutting down. This waits for the
+    /// server to end the session.
+    pub async fn close(self) -> Result<(), LocksmithError> {
+        Ok(self.conn.close().await?)
+    }
+
+    /// Set whether [Locker::list_connection_locks] includes locks on TOAST tables. Defaults to
+    /// `false`.
+    ///
+    /// TOAST tables store large column values out-of-line, and some operations on tables with
+    /// large `text` or `bytea` columns also lock the associated TOAST table. These locks are
+    /// attributed to the table that owns the TOAST table.
+    pub fn with_toast_locks(mut self, include: bool) -> Self {
+        self.include_toast_locks = include;
+        self
+    }
+
+    /// Set the `search_path` of the locker's connection, which is used to resolve the tables that
+    /// are locked and to find the locks listed by [Locker::list_connection_locks].
+    pub async fn set_search_path(
+        &mut self,
+        schemas: &[impl AsRef<str>],
+    ) -> Result<(), LocksmithError> {
+        let statement = crate::search_path_statement(schemas);
+        self.log_sql(&statement);
+        self.conn
+            .execute(statement.as_str())
+            .await
+            .context("Query error while setting search path")?;
+        Ok(())
+    }
+
+    /// Set the `application_name` of the locker's connection, which identifies it in
+    /// `pg_stat_activity` and the server's logs.
+    pub async fn set_application_name(&mut self, name: &str) -> Result<(), LocksmithError> {
+        let statement = crate::application_name_statement(name);
+        self.log_sql(&statement);
+        self.conn
+            .execute(statement.as_str())
+            .await
+            .context("Query error while setting application name")?;
+        Ok(())
+    }
+
+    /// Assume `role` on the locker's connection with `SET ROLE`. Tables are then locked with the
+    /// privileges of that role, see [Locker::tables_without_lock_privilege].
+    pub async fn set_role(&mut self, role: &str) -> Result<(), LocksmithError> {
+        let statement = crate::role_statement(role);
+        self.log_sql(&statement);
+        self.conn
+            .execute(statement.as_str())
+            .await
+            .with_context(|| format!("Query error while setting role {role}"))?;
+        Ok(())
+    }
+
+    /// The tables in `tables` that the locker's current role does not have the privileges to
+    /// take `lock` on. `ACCESS SHARE` requires `SELECT`, `ROW EXCLUSIVE` requires `INSERT`,
+    /// `UPDATE`, `DELETE` or `TRUNCATE`, and every other mode requires `UPDATE`, `DELETE` or
+    /// `TRUNCATE`, which Postgres 17 extends with `MAINTAIN`. Owners and superusers have every
+    /// privilege.
+    pub async fn tables_without_lock_privilege(
+        &mut self,
+        tables: impl IntoIterator<Item = &TableObject>,
+        lock: &Lock,
+    ) -> Result<Vec<TableObject>, LocksmithError> {
+        let (names, schemas): (Vec<_>, Vec<_>) = tables
+            .into_iter()
+            .map(|table| (table.name.clone(), table.schema.clone()))
+            .unzip();
+        let privileges = match lock {
+            Lock::AccessShareLock => "SELECT",
+            Lock::RowExclusiveLock => "INSERT, UPDATE, DELETE, TRUNCATE",
+            _ => "UPDATE, DELETE, TRUNCATE",
+        };
+        let query = query!(
+            r#"
+            select name as "name!", schema
+            from unnest($1::text[], $3::text[]) as t(name, schema)
+            where not has_table_privilege(
+                format('%I.%I', coalesce(schema, current_schema()), name),
+                case
+                    when current_setting('server_version_num')::int >= 170000
+                        and $2 <> 'SELECT' then $2 || ', MAINTAIN'
+                    else $2
+                end
+            )
+            order by name, schema nulls first
+            "#,
+            &names,
+            privileges,
+            &schemas as &[Option<String>]
+        );
+        self.log_sql(query.sql());
+        let rows = query
+            .fetch_all(&mut *self.conn)
+            .await
+            .context("Query error while checking lock privileges")
+            .map_err(LocksmithError::introspection)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TableObject {
+                name: row.name,
+                schema: row.schema,
+            })
+            .collect())
     }
 
     /// Lock a set of tables, by name, in the database with `ACCESS EXCLUSIVE MODE`.
+    ///
+    /// All tables are locked with a single `LOCK TABLE` statement, which either acquires every
+    /// lock or fails as a whole, and avoids a round-trip per table on large schemas.
+    ///
+    /// Tables are locked with `ONLY`, as otherwise locking a parent table also locks all of the
+    /// tables that inherit from it, including ones that were deliberately left unlocked.
     pub async fn lock_tables(
         &mut self,
         tables: impl IntoIterator<Item = &TableObject>,
-    ) -> anyhow::Result<()> {
+    ) -> Result<(), LocksmithError> {
+        self.lock_tables_in_mode(tables, &Lock::AccessExclusiveLock)
+            .await
+    }
+
+    /// Lock a set of tables, by name, in the same way as [Locker::lock_tables] but with the mode
+    /// that takes `lock`, such as `SHARE MODE` for [Lock::ShareLock].
+    pub async fn lock_tables_in_mode(
+        &mut self,
+        tables: impl IntoIterator<Item = &TableObject>,
+        lock: &Lock,
+    ) -> Result<(), LocksmithError> {
+        let tables: Vec<_> = tables.into_iter().collect();
+        if tables.is_empty() {
+            return Ok(());
+        }
+        let mode = lock_mode(lock)?;
+        trace!(?tables, mode, "Locking tables");
+        let table_list = tables
+            .iter()
+            .map(|table| format!("ONLY {}", table.quoted_name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let lock_query = format!("LOCK TABLE {table_list} IN {mode} MODE;");
+        self.log_sql(&lock_query);
+        self.conn
+            .execute(lock_query.as_str())
+            .await
+            .with_context(|| format!("Query error while locking {tables:?}"))?;
+        Ok(())
+    }
+
+    /// Lock a set of tables, by name, in the database with `ACCESS EXCLUSIVE MODE NOWAIT`.
+    ///
+    /// Unlike [Locker::lock_tables], this does not wait for conflicting locks held by other
+    /// sessions to be released. Each lock is taken inside a savepoint, so a table that cannot be
+    /// locked immediately does not abort the transaction. The tables that could not be locked
+    /// are returned.
+    pub async fn try_lock_tables(
+        &mut self,
+        tables: impl IntoIterator<Item = &TableObject>,
+    ) -> Result<Vec<TableObject>, LocksmithError> {
+        self.try_lock_tables_in_mode(tables, &Lock::AccessExclusiveLock)
+            .await
+    }
+
+    /// Lock a set of tables, by name, in the same way as [Locker::try_lock_tables] but with the
+    /// mode that takes `lock`. The tables that could not be locked are returned.
+    pub async fn try_lock_tables_in_mode(
+        &mut self,
+        tables: impl IntoIterator<Item = &TableObject>,
+        lock: &Lock,
+    ) -> Result<Vec<TableObject>, LocksmithError> {
+        let mode = lock_mode(lock)?;
+        let mut unlockable = vec![];
         for table in tables {
-            trace!(?table, "Locking table");
-            let lock_query = format!("LOCK TABLE \"{}\" IN ACCESS EXCLUSIVE MODE;", table.name);
+            trace!(?table, "Locking table without waiting");
+            self.log_sql("SAVEPOINT locksmith_lock;");
             self.conn
-                .execute(lock_query.as_str())
+                .execute("SAVEPOINT locksmith_lock;")
                 .await
-                .with_context(|| format!("Query error while locking {table:?}"))?;
+                .context("Query error while creating savepoint")?;
+            let lock_query = format!(
+                "LOCK TABLE ONLY {} IN {mode} MODE NOWAIT;",
+                table.quoted_name()
+            );
+            self.log_sql(&lock_query);
+            match self.conn.execute(lock_query.as_str()).await {
+                Ok(_) => {
+                    self.log_sql("RELEASE SAVEPOINT locksmith_lock;");
+                    self.conn
+                        .execute("RELEASE SAVEPOINT locksmith_lock;")
+                        .await
+                        .context("Query error while releasing savepoint")?;
+                }
+                Err(sqlx::Error::Database(e))
+                    if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) =>
+                {
+                    debug!(?table, "Table is locked by another session");
+                    self.log_sql("ROLLBACK TO SAVEPOINT locksmith_lock;");
+                    self.conn
+                        .execute("ROLLBACK TO SAVEPOINT locksmith_lock;")
+                        .await
+                        .context("Query error while rolling back savepoint")?;
+                    unlockable.push(table.clone());
+                }
+                Err(e) => {
+                    return Err(anyhow::Error::new(e)
+                        .context(format!("Query error while locking {table:?}"))
+                        .into());
+                }
+            }
         }
-        Ok(())
+        Ok(unlockable)
     }
 
     /// List the locks held by a given connection ID. This returns a list of [TableLock]s, which
-    /// contain the table name and the lock mode.
+    /// contain the table and the lock mode. Tables outside the current schema have their schema
+    /// set, and the system catalogs are left out.
+    ///
+    /// Locks on foreign tables are included, as these are taken on the local foreign table
+    /// relation. If [Locker::with_toast_locks] is enabled, locks on TOAST tables are returned as
+    /// locks on the table that owns them.
     pub async fn list_connection_locks(
         &mut self,
         connection_id: ConnectionID,
-    ) -> anyhow::Result<Vec<TableLock>> {
-        query_as!(
-            TableLock,
+    ) -> Result<Vec<TableLock>, LocksmithError> {
+        let query = query!(
+            r#"
+            select coalesce(owner.relname, c.relname)::text as "table!",
+                   nullif(n.nspname, current_schema())::text as schema,
+                   mode as "lock!"
+            from pg_locks l
+            join pg_class c ON l.relation = c.oid
+            left join pg_class owner ON c.relkind = 't' AND owner.reltoastrelid = c.oid
+            join pg_namespace n ON coalesce(owner.relnamespace, c.relnamespace) = n.oid
+            WHERE l.pid = $1
+              AND n.nspname NOT LIKE 'pg\_%'
+              AND n.nspname <> 'information_schema'
+              AND (c.relkind IN ('r', 'p', 'f') OR ($2 AND c.relkind = 't'))
+              AND l.locktype = 'relation'
+              AND l.mode IS NOT NULL
+              AND database = (SELECT oid FROM pg_database WHERE datname = current_database());
+            "#,
+            connection_id.0,
+            self.include_toast_locks
+        );
+        self.log_sql(query.sql());
+        let rows = query
+            .fetch_all(&mut *self.conn)
+            .await
+            .with_context(|| {
+                format!("Query error while listing connection locks for {connection_id:?}")
+            })
+            .map_err(LocksmithError::introspection)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TableLock {
+                table: TableObject {
+                    name: row.table,
+                    schema: row.schema,
+                },
+                lock: row.lock.into(),
+            })
+            .collect())
+    }
+
+    /// List the predicate locks (`SIReadLock`) held by a given connection ID on tables, and on
+    /// their indexes, outside the system catalogs. These are only taken by transactions running
+    /// under `SERIALIZABLE` isolation.
+    pub async fn list_predicate_locks(
+        &mut self,
+        connection_id: ConnectionID,
+    ) -> Result<Vec<PredicateLock>, LocksmithError> {
+        let query = query!(
+            r#"
+            select t.relname::text as "table!",
+                   nullif(n.nspname, current_schema())::text as schema,
+                   l.locktype as "locktype!"
+            from pg_locks l
+            join pg_class c ON l.relation = c.oid
+            left join pg_index i ON i.indexrelid = c.oid
+            join pg_class t ON t.oid = coalesce(i.indrelid, c.oid)
+            join pg_namespace n ON t.relnamespace = n.oid
+            WHERE l.pid = $1
+              AND n.nspname NOT LIKE 'pg\_%'
+              AND n.nspname <> 'information_schema'
+              AND l.mode = 'SIReadLock'
+              AND l.locktype IN ('relation', 'page', 'tuple')
+              AND database = (SELECT oid FROM pg_database WHERE datname = current_database());
+            "#,
+            connection_id.0
+        );
+        self.log_sql(query.sql());
+        let rows = query
+            .fetch_all(&mut *self.conn)
+            .await
+            .with_context(|| {
+                format!("Query error while listing predicate locks for {connection_id:?}")
+            })
+            .map_err(LocksmithError::introspection)?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let granularity = match row.locktype.as_str() {
+                    "relation" => PredicateLockGranularity::Relation,
+                    "page" => PredicateLockGranularity::Page,
+                    "tuple" => PredicateLockGranularity::Tuple,
+                    _ => return None,
+                };
+                Some(PredicateLock {
+                    table: TableObject {
+                        name: row.table,
+                        schema: row.schema,
+                    },
+                    granularity,
+                })
+            })
+            .collect())
+    }
+
+    /// Get the lock that a given connection ID is waiting for, if it is waiting for a lock on a
+    /// table outside the system catalogs. Tables are attributed in the same way as
+    /// [Locker::list_connection_locks].
+    ///
+    /// A backend waits for at most one lock at a time, which appears in `pg_locks` as the only
+    /// lock of the connection that has not been granted.
+    pub async fn awaited_lock(
+        &mut self,
+        connection_id: ConnectionID,
+    ) -> Result<Option<TableLock>, LocksmithError> {
+        let query = query!(
             r#"
-            select relation::regclass::text as "table!", mode as "lock!"
+            select coalesce(owner.relname, c.relname)::text as "table!",
+                   nullif(n.nspname, current_schema())::text as schema,
+                   mode as "lock!"
             from pg_locks l
             join pg_class c ON l.relation = c.oid
-            join pg_namespace n ON c.relnamespace = n.oid
+            left join pg_class owner ON c.relkind = 't' AND owner.reltoastrelid = c.oid
+            join pg_namespace n ON coalesce(owner.relnamespace, c.relnamespace) = n.oid
             WHERE l.pid = $1
-              AND n.nspname = current_schema()
-              AND c.relkind IN ('r', 'p')
+              AND NOT l.granted
+              AND n.nspname NOT LIKE 'pg\_%'
+              AND n.nspname <> 'information_schema'
+              AND (c.relkind IN ('r', 'p', 'f') OR ($2 AND c.relkind = 't'))
               AND l.locktype = 'relation'
               AND l.mode IS NOT NULL
               AND database = (SELECT oid FROM pg_database WHERE datname = current_database());
             "#,
+            connection_id.0,
+            self.include_toast_locks
+        );
+        self.log_sql(query.sql());
+        let row = query
+            .fetch_optional(&mut *self.conn)
+            .await
+            .with_context(|| {
+                format!("Query error while retrieving the awaited lock of {connection_id:?}")
+            })
+            .map_err(LocksmithError::introspection)?;
+        Ok(row.map(|row| TableLock {
+            table: TableObject {
+                name: row.table,
+                schema: row.schema,
+            },
+            lock: row.lock.into(),
+        }))
+    }
+
+    /// Get the table with the OID `relation`, if it is a table outside the system catalogs,
+    /// attributing TOAST tables to the table that owns them in the same way as
+    /// [Locker::list_connection_locks].
+    pub(crate) async fn relation_table(
+        &mut self,
+        relation: u32,
+    ) -> Result<Option<TableObject>, LocksmithError> {
+        let query = query!(
+            r#"
+            select coalesce(owner.relname, c.relname)::text as "table!",
+                   nullif(n.nspname, current_schema())::text as schema
+            from pg_class c
+            left join pg_class owner ON c.relkind = 't' AND owner.reltoastrelid = c.oid
+            join pg_namespace n ON coalesce(owner.relnamespace, c.relnamespace) = n.oid
+            WHERE c.oid = $1::int8::oid
+              AND n.nspname NOT LIKE 'pg\_%'
+              AND n.nspname <> 'information_schema'
+              AND (c.relkind IN ('r', 'p', 'f') OR ($2 AND c.relkind = 't'));
+            "#,
+            i64::from(relation),
+            self.include_toast_locks
+        );
+        self.log_sql(query.sql());
+        let row = query
+            .fetch_optional(&mut *self.conn)
+            .await
+            .with_context(|| format!("Query error while retrieving relation {relation}"))
+            .map_err(LocksmithError::introspection)?;
+        Ok(row.map(|row| TableObject {
+            name: row.table,
+            schema: row.schema,
+        }))
+    }
+
+    /// List the queries in the current database that are blocked waiting for a lock, along with
+    /// the backends that are blocking them.
+    ///
+    /// Unlike the rest of the [Locker] this is intended for monitoring a live server rather than
+    /// inspecting a statement, and does not take any locks. It uses the
+    /// [pg_stat_activity](https://www.postgresql.org/docs/current/monitoring-stats.html#MONITORING-PG-STAT-ACTIVITY-VIEW)
+    /// view, the [pg_locks](https://www.postgresql.org/docs/current/view-pg-locks.html) view
+    /// and the `pg_blocking_pids` function.
+    pub async fn list_blocked_queries(&mut self) -> Result<Vec<BlockedQuery>, LocksmithError> {
+        // The locker's connection is always in a transaction, and pg_stat_activity is only read
+        // once per transaction unless the snapshot is cleared.
+        self.log_sql("SELECT pg_stat_clear_snapshot();");
+        self.conn
+            .execute("SELECT pg_stat_clear_snapshot();")
+            .await
+            .context("Query error while clearing statistics snapshot")
+            .map_err(LocksmithError::introspection)?;
+        // pg_locks.waitstart only exists from Postgres 14, so it is read via to_jsonb to remain
+        // compatible with earlier versions.
+        let query = query!(
+            r#"
+            SELECT a.pid as "waiting_pid!",
+                   blocking.pid as "blocking_pid!",
+                   (SELECT l.relation::regclass::text
+                    FROM pg_locks l
+                    WHERE l.pid = a.pid
+                      AND NOT l.granted
+                      AND l.relation IS NOT NULL
+                    LIMIT 1) as "relation?",
+                   a.query as "query!",
+                   extract(epoch FROM clock_timestamp() - coalesce(
+                       (SELECT (to_jsonb(l) ->> 'waitstart')::timestamptz
+                        FROM pg_locks l
+                        WHERE l.pid = a.pid AND NOT l.granted
+                        LIMIT 1),
+                       a.query_start))::float8 as "wait_seconds!"
+            FROM pg_stat_activity a
+            CROSS JOIN LATERAL unnest(pg_blocking_pids(a.pid)) AS blocking(pid)
+            WHERE a.datname = current_database()
+            order by a.pid, blocking.pid;
+            "#
+        );
+        self.log_sql(query.sql());
+        let rows = query
+            .fetch_all(&mut *self.conn)
+            .await
+            .context("Query error while listing blocked queries")
+            .map_err(LocksmithError::introspection)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| BlockedQuery {
+                waiting_pid: row.waiting_pid,
+                blocking_pid: row.blocking_pid,
+                relation: row.relation,
+                query: row.query,
+                wait_duration: Duration::from_secs_f64(row.wait_seconds.max(0.0)),
+            })
+            .collect())
+    }
+
+    /// List every row of `pg_locks` for a given connection ID, without any of the filtering or
+    /// attribution done by [Locker::list_connection_locks]. This is intended for diagnostics.
+    pub async fn list_raw_locks(
+        &mut self,
+        connection_id: ConnectionID,
+    ) -> Result<Vec<RawLock>, LocksmithError> {
+        let query = query_as!(
+            RawLock,
+            r#"
+            select locktype as "locktype!",
+                   database::int8 as database,
+                   relation::int8 as relation,
+                   page,
+                   tuple,
+                   virtualxid,
+                   transactionid::text::int8 as transactionid,
+                   classid::int8 as classid,
+                   objid::int8 as objid,
+                   objsubid,
+                   virtualtransaction,
+                   pid,
+                   mode,
+                   granted as "granted!",
+                   fastpath as "fastpath!"
+            from pg_locks
+            WHERE pid = $1
+            order by locktype, relation, mode;
+            "#,
             connection_id.0
-        )
-        .fetch_all(&mut self.conn)
-        .await
-        .with_context(|| {
-            format!("Query error while listing connection locks for {connection_id:?}")
-        })
+        );
+        self.log_sql(query.sql());
+        query
+            .fetch_all(&mut *self.conn)
+            .await
+            .with_context(|| format!("Query error while listing raw locks for {connection_id:?}"))
+            .map_err(LocksmithError::introspection)
     }
 }
 
+/// The `LOCK TABLE` mode that takes `lock`.
+fn lock_mode(lock: &Lock) -> anyhow::Result<&'static str> {
+    Ok(match lock {
+        Lock::AccessShareLock => "ACCESS SHARE",
+        Lock::RowShareLock => "ROW SHARE",
+        Lock::RowExclusiveLock => "ROW EXCLUSIVE",
+        Lock::ShareUpdateExclusiveLock => "SHARE UPDATE EXCLUSIVE",
+        Lock::ShareLock => "SHARE",
+        Lock::ShareRowExclusiveLock => "SHARE ROW EXCLUSIVE",
+        Lock::ExclusiveLock => "EXCLUSIVE",
+        Lock::AccessExclusiveLock => "ACCESS EXCLUSIVE",
+        Lock::Unknown(lock) => bail!("Unable to lock tables with unknown lock {lock}"),
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::tests::start_test_postgres;
+    use crate::tests::{lock_tables, start_test_postgres};
 
     use crate::locker::Locker;
-    use crate::{ConnectionID, Lock, TableLock};
-    use sqlx::query_scalar;
+    use crate::{ConnectionID, Lock, LocksmithError, StatementExecutor, TableLock, TableObject};
+    use sqlx::{Connection, Executor, PgConnection, query_scalar};
     use tracing_test::traced_test;
 
     #[traced_test]
@@ -85,7 +607,7 @@ mod tests {
         let (_container, dsn) = start_test_postgres().await;
         let mut locker = Locker::new(&dsn).await.unwrap();
         let connection_id = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
-            .fetch_one(&mut locker.conn)
+            .fetch_one(&mut *locker.conn)
             .await
             .map(ConnectionID)
             .unwrap();
@@ -101,4 +623,350 @@ mod tests {
             }]
         )
     }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_lock_tables_in_mode() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let connection_id = locker.connection_id;
+
+        let orders_table = "orders".into();
+        locker
+            .lock_tables_in_mode([&orders_table], &Lock::ShareLock)
+            .await
+            .unwrap();
+        let unlockable = locker
+            .try_lock_tables_in_mode([&"customers".into()], &Lock::RowExclusiveLock)
+            .await
+            .unwrap();
+        assert!(unlockable.is_empty());
+        let mut locks = locker.list_connection_locks(connection_id).await.unwrap();
+        locks.sort();
+        assert_eq!(
+            locks,
+            vec![
+                TableLock {
+                    table: "customers".into(),
+                    lock: Lock::RowExclusiveLock,
+                },
+                TableLock {
+                    table: orders_table,
+                    lock: Lock::ShareLock,
+                },
+            ]
+        );
+        let err = locker
+            .lock_tables_in_mode([&"customers".into()], &Lock::Unknown("Foo".into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LocksmithError::Other(_)), "{err:?}");
+        let err = locker
+            .lock_tables_in_mode([&"missing".into()], &Lock::ShareLock)
+            .await
+            .unwrap_err();
+        let LocksmithError::StatementFailed { sqlstate, .. } = &err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(sqlstate, "42P01");
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_lock_multiple_tables() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let connection_id = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
+            .fetch_one(&mut *locker.conn)
+            .await
+            .map(ConnectionID)
+            .unwrap();
+
+        locker.lock_tables([]).await.unwrap();
+        let tables: Vec<TableObject> = vec!["customers".into(), "orders".into()];
+        locker.lock_tables(&tables).await.unwrap();
+        let mut locks = locker.list_connection_locks(connection_id).await.unwrap();
+        locks.sort();
+        assert_eq!(
+            locks,
+            tables
+                .into_iter()
+                .map(|table| TableLock {
+                    table,
+                    lock: Lock::AccessExclusiveLock,
+                })
+                .collect::<Vec<_>>()
+        )
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_lock_tables_does_not_lock_children() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute(
+            "CREATE TABLE events (id int); CREATE TABLE events_2025 () INHERITS (events);",
+        )
+        .await
+        .unwrap();
+
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let connection_id = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
+            .fetch_one(&mut *locker.conn)
+            .await
+            .map(ConnectionID)
+            .unwrap();
+        locker.lock_tables([&"events".into()]).await.unwrap();
+        let locks = locker.list_connection_locks(connection_id).await.unwrap();
+        assert_eq!(
+            locks,
+            vec![TableLock {
+                table: "events".into(),
+                lock: Lock::AccessExclusiveLock,
+            }]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_lock_tables_in_another_schema() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute(
+            r#"CREATE SCHEMA archive; CREATE TABLE archive."Orders" (id int); CREATE ROLE archivist;
+               GRANT USAGE ON SCHEMA archive TO archivist; GRANT UPDATE ON orders TO archivist;"#,
+        )
+        .await
+        .unwrap();
+
+        // Each part of the name is quoted, so the schema is never read as part of the table name.
+        let table = TableObject::in_schema("archive", "Orders");
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        locker.lock_tables([&table]).await.unwrap();
+        let locked: bool = query_scalar(
+            r#"select exists (
+                select from pg_locks
+                where pid = pg_backend_pid()
+                  and relation = 'archive."Orders"'::regclass
+                  and mode = 'AccessExclusiveLock'
+            )"#,
+        )
+        .fetch_one(&mut *locker.conn)
+        .await
+        .unwrap();
+        assert!(locked);
+
+        // Locks on tables outside the current schema are listed with their schema.
+        let connection_id = locker.connection_id;
+        assert_eq!(
+            locker.list_connection_locks(connection_id).await.unwrap(),
+            vec![TableLock {
+                table: table.clone(),
+                lock: Lock::AccessExclusiveLock,
+            }]
+        );
+
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        locker.set_role("archivist").await.unwrap();
+        let orders = TableObject::from("orders");
+        assert_eq!(
+            locker
+                .tables_without_lock_privilege([&orders, &table], &Lock::AccessExclusiveLock)
+                .await
+                .unwrap(),
+            vec![table]
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_blocked_queries() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut blocking = lock_tables(&dsn, ["orders"]).await;
+        let blocking_pid = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
+            .fetch_one(&mut *blocking.conn)
+            .await
+            .unwrap();
+
+        let mut monitor = Locker::new(&dsn).await.unwrap();
+        assert_eq!(monitor.list_blocked_queries().await.unwrap(), vec![]);
+
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            executor
+                .execute_statement("select * from orders;")
+                .await
+                .unwrap()
+        );
+        let blocked = monitor.list_blocked_queries().await.unwrap();
+        assert_eq!(blocked.len(), 1, "{blocked:?}");
+        assert_eq!(blocked[0].waiting_pid, executor.connection_id().0);
+        assert_eq!(blocked[0].blocking_pid, blocking_pid);
+        assert_eq!(blocked[0].relation.as_deref(), Some("orders"));
+        assert_eq!(blocked[0].query, "select * from orders;");
+        executor.abort().await.unwrap();
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_awaited_lock() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        locker.lock_tables([&"orders".into()]).await.unwrap();
+
+        let mut monitor = Locker::new(&dsn).await.unwrap();
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            executor
+                .execute_statement("select * from customers; select * from orders;")
+                .await
+                .unwrap()
+        );
+        let connection_id = executor.connection_id();
+        assert_eq!(
+            monitor.awaited_lock(connection_id).await.unwrap(),
+            Some(TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessShareLock,
+            })
+        );
+        // Granted locks, such as the lock on `customers`, are not awaited.
+        assert_eq!(
+            monitor
+                .list_connection_locks(connection_id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        executor.abort().await.unwrap();
+        assert_eq!(monitor.awaited_lock(connection_id).await.unwrap(), None);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_raw_locks() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let connection_id = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
+            .fetch_one(&mut *locker.conn)
+            .await
+            .map(ConnectionID)
+            .unwrap();
+
+        locker.lock_tables([&"orders".into()]).await.unwrap();
+        let locks = locker.list_raw_locks(connection_id).await.unwrap();
+        assert!(locks.iter().all(|l| l.pid == Some(connection_id.0)));
+        // Every transaction holds a lock on its own virtual transaction ID
+        assert!(
+            locks
+                .iter()
+                .any(|l| l.locktype == "virtualxid" && l.granted)
+        );
+        assert!(locks.iter().any(|l| {
+            l.locktype == "relation"
+                && l.relation.is_some()
+                && l.mode.as_deref() == Some("AccessExclusiveLock")
+        }));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_predicate_locks() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let connection_id = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
+            .fetch_one(&mut conn)
+            .await
+            .map(ConnectionID)
+            .unwrap();
+
+        conn.execute("begin isolation level serializable; select * from orders where id = 1;")
+            .await
+            .unwrap();
+        let locks = locker.list_predicate_locks(connection_id).await.unwrap();
+        // The lookup reads a page of the primary key index, which is attributed to its table.
+        assert!(!locks.is_empty());
+        assert!(locks.iter().all(|lock| lock.table == "orders".into()));
+
+        conn.execute("rollback; select * from customers;")
+            .await
+            .unwrap();
+        assert!(
+            locker
+                .list_predicate_locks(connection_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_list_connection_toast_locks() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let connection_id = query_scalar!(r#"select pg_backend_pid() as "pid!""#)
+            .fetch_one(&mut *locker.conn)
+            .await
+            .map(ConnectionID)
+            .unwrap();
+
+        // Truncating a table also truncates its TOAST table
+        locker.conn.execute("TRUNCATE orders;").await.unwrap();
+        let table_locks = locker.list_connection_locks(connection_id).await.unwrap();
+        locker.include_toast_locks = true;
+        let all_locks = locker.list_connection_locks(connection_id).await.unwrap();
+
+        assert!(all_locks.len() > table_locks.len());
+        assert!(all_locks.iter().all(|l| l.table == "orders".into()));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_try_lock_tables() {
+        let (_container, dsn) = start_test_postgres().await;
+        let _other = lock_tables(&dsn, ["orders"]).await;
+
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        let unlockable = locker
+            .try_lock_tables(&["customers".into(), "orders".into()])
+            .await
+            .unwrap();
+        assert_eq!(unlockable, vec!["orders".into()]);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_statements_are_logged() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut locker = Locker::new(&dsn).await.unwrap();
+        locker
+            .lock_tables(&[TableObject::from("orders")])
+            .await
+            .unwrap();
+        let mut executor = StatementExecutor::new(&dsn).await.unwrap();
+        assert!(
+            executor
+                .execute_statement("select * from orders;")
+                .await
+                .unwrap()
+        );
+        executor.abort().await.unwrap();
+        locker.close().await.unwrap();
+
+        let pid = format!("pid={}", executor.connection_id().0);
+        assert!(logs_contain("locksmith::sql"));
+        assert!(logs_contain("connection=\"locker\""));
+        assert!(logs_contain("BEGIN;"));
+        assert!(logs_contain(
+            "LOCK TABLE ONLY \\\"orders\\\" IN ACCESS EXCLUSIVE MODE;"
+        ));
+        assert!(logs_contain("connection=\"executor\""));
+        assert!(logs_contain(&pid));
+        assert!(logs_contain("SET log_lock_waits=true;"));
+        assert!(logs_contain("select * from orders;"));
+        assert!(logs_contain("ROLLBACK;"));
+    }
 }
@@ -1,5 +1,8 @@
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
 
 /// A Postgres connection ID. Connections IDs can be retrieved via the
 /// [pg_backend_pid](https://pgpedia.info/p/pg_backend_pid.html) function.
@@ -7,7 +10,8 @@ use std::fmt::{Display, Formatter};
 pub struct ConnectionID(pub i32);
 
 /// A lock
-#[derive(Debug, Eq, PartialEq, Clone, Hash, Ord, PartialOrd, Serialize, Deserialize)]
+#[derive(Debug, Eq, PartialEq, Clone, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Lock {
     AccessShareLock,
     RowShareLock,
@@ -23,15 +27,137 @@ pub enum Lock {
     Unknown(String),
 }
 
+impl Lock {
+    /// The relative strength of the lock, from `1` for [Lock::AccessShareLock] to `8` for
+    /// [Lock::AccessExclusiveLock], following the order of the
+    /// [table-level lock modes](https://www.postgresql.org/docs/current/explicit-locking.html#LOCKING-TABLES).
+    /// A stronger lock conflicts with at least as many other lock modes as a weaker one.
+    ///
+    /// [Lock::Unknown] locks are treated as the strongest, as nothing is known about what they
+    /// conflict with.
+    pub fn strength(&self) -> u8 {
+        match self {
+            Self::AccessShareLock => 1,
+            Self::RowShareLock => 2,
+            Self::RowExclusiveLock => 3,
+            Self::ShareUpdateExclusiveLock => 4,
+            Self::ShareLock => 5,
+            Self::ShareRowExclusiveLock => 6,
+            Self::ExclusiveLock => 7,
+            Self::AccessExclusiveLock => 8,
+            Self::Unknown(_) => u8::MAX,
+        }
+    }
+
+    /// Returns `true` if this lock conflicts with `other`, following the
+    /// [table-level lock conflict matrix](https://www.postgresql.org/docs/current/explicit-locking.html#TABLE-LOCK-COMPATIBILITY).
+    /// Two transactions cannot hold conflicting locks on the same table at the same time, so one
+    /// waits for the other to release its lock.
+    ///
+    /// [Lock::Unknown] locks are treated as conflicting with every lock.
+    pub fn conflicts_with(&self, other: &Lock) -> bool {
+        use Lock::*;
+        let conflicts: &[Lock] = match self {
+            AccessShareLock => &[AccessExclusiveLock],
+            RowShareLock => &[ExclusiveLock, AccessExclusiveLock],
+            RowExclusiveLock => &[
+                ShareLock,
+                ShareRowExclusiveLock,
+                ExclusiveLock,
+                AccessExclusiveLock,
+            ],
+            ShareUpdateExclusiveLock => &[
+                ShareUpdateExclusiveLock,
+                ShareLock,
+                ShareRowExclusiveLock,
+                ExclusiveLock,
+                AccessExclusiveLock,
+            ],
+            ShareLock => &[
+                RowExclusiveLock,
+                ShareUpdateExclusiveLock,
+                ShareRowExclusiveLock,
+                ExclusiveLock,
+                AccessExclusiveLock,
+            ],
+            ShareRowExclusiveLock => &[
+                RowExclusiveLock,
+                ShareUpdateExclusiveLock,
+                ShareLock,
+                ShareRowExclusiveLock,
+                ExclusiveLock,
+                AccessExclusiveLock,
+            ],
+            ExclusiveLock => &[
+                RowShareLock,
+                RowExclusiveLock,
+                ShareUpdateExclusiveLock,
+                ShareLock,
+                ShareRowExclusiveLock,
+                ExclusiveLock,
+                AccessExclusiveLock,
+            ],
+            AccessExclusiveLock | Unknown(_) => return true,
+        };
+        matches!(other, Unknown(_)) || conflicts.contains(other)
+    }
+
+    /// Parse a lock mode as it appears in the `mode` column of `pg_locks`, such as
+    /// `AccessShareLock`, returning [Lock::Unknown] for modes that are not recognised instead of
+    /// failing. Use this for values read from the catalogs, which can contain modes that are not
+    /// table-level lock modes, such as the `SIReadLock` predicate locks taken by serializable
+    /// transactions. Use [str::parse] to reject unknown modes.
+    pub fn from_pg_string(mode: impl Into<String>) -> Self {
+        let mode = mode.into();
+        mode.parse().unwrap_or(Self::Unknown(mode))
+    }
+
+    /// Get the lock from the number that Postgres uses for its mode internally, such as the
+    /// `LOCKMODE` of a lock in a monitoring tool, from `1` for [Lock::AccessShareLock] to `8` for
+    /// [Lock::AccessExclusiveLock]. This is the same as [Lock::strength]. Returns `None` for any
+    /// other number.
+    pub fn from_lock_mode(mode: u8) -> Option<Self> {
+        Some(match mode {
+            1 => Self::AccessShareLock,
+            2 => Self::RowShareLock,
+            3 => Self::RowExclusiveLock,
+            4 => Self::ShareUpdateExclusiveLock,
+            5 => Self::ShareLock,
+            6 => Self::ShareRowExclusiveLock,
+            7 => Self::ExclusiveLock,
+            8 => Self::AccessExclusiveLock,
+            _ => return None,
+        })
+    }
+}
+
 impl Display for Lock {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{self:?}")
     }
 }
 
-impl From<String> for Lock {
-    fn from(value: String) -> Self {
-        match value.as_str() {
+/// The error returned when parsing a [Lock] from a string that is not the name of a table-level
+/// lock mode.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseLockError(pub String);
+
+impl Display for ParseLockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown lock mode `{}`", self.0)
+    }
+}
+
+impl std::error::Error for ParseLockError {}
+
+/// Parse the name of a table-level lock mode as it appears in `pg_locks`, such as
+/// `AccessShareLock`. Unknown modes are an error, see [Lock::from_pg_string] for a lossy
+/// conversion.
+impl FromStr for Lock {
+    type Err = ParseLockError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
             "AccessShareLock" => Self::AccessShareLock,
             "RowShareLock" => Self::RowShareLock,
             "RowExclusiveLock" => Self::RowExclusiveLock,
@@ -40,68 +166,674 @@ impl From<String> for Lock {
             "ShareRowExclusiveLock" => Self::ShareRowExclusiveLock,
             "ExclusiveLock" => Self::ExclusiveLock,
             "AccessExclusiveLock" => Self::AccessExclusiveLock,
-            _ => Self::Unknown(value),
-        }
+            _ => return Err(ParseLockError(value.to_string())),
+        })
     }
 }
 
-/// A database object, which can be a table, column, or index
-#[derive(
-    Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, derive_more::From, Serialize, Deserialize,
-)]
+/// Converts a lock mode read from `pg_locks` in the same way as [Lock::from_pg_string].
+impl From<String> for Lock {
+    fn from(value: String) -> Self {
+        Self::from_pg_string(value)
+    }
+}
+
+/// A database object, such as a table, foreign table, materialized view, column, index,
+/// constraint, trigger, domain, sequence, extension, an inheritance relationship between tables
+/// or indexes, the storage parameters of a table or index or whether a table is unlogged
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, derive_more::From)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DBObject {
     Table(TableObject),
+    ForeignTable(ForeignTableObject),
     Column(ColumnObject),
     Index(IndexObject),
+    Constraint(ConstraintObject),
+    Trigger(TriggerObject),
+    Domain(DomainObject),
+    DomainConstraint(DomainConstraintObject),
+    Sequence(SequenceObject),
+    Extension(ExtensionObject),
+    Inheritance(InheritanceObject),
+    StorageParameters(StorageParametersObject),
+    MaterializedView(MaterializedViewObject),
+    UnloggedTable(UnloggedTableObject),
+    IndexStorageParameters(IndexStorageParametersObject),
+    IndexInheritance(IndexInheritanceObject),
+    #[cfg(feature = "comments")]
+    Comment(CommentObject),
+}
+
+impl DBObject {
+    /// The table that the object belongs to, such as the table of a column, index or trigger, or
+    /// the child table of an inheritance relationship. Objects that are not part of a table, such
+    /// as domains and sequences, return `None`.
+    pub fn table(&self) -> Option<&TableObject> {
+        match self {
+            DBObject::Column(column) => Some(&column.table),
+            DBObject::Index(index) => Some(&index.table),
+            DBObject::Constraint(constraint) => Some(&constraint.table),
+            DBObject::Trigger(trigger) => Some(&trigger.table),
+            DBObject::Inheritance(inheritance) => Some(&inheritance.child),
+            DBObject::StorageParameters(parameters) => Some(&parameters.table),
+            DBObject::UnloggedTable(unlogged) => Some(&unlogged.table),
+            DBObject::IndexStorageParameters(parameters) => Some(&parameters.table),
+            DBObject::IndexInheritance(inheritance) => Some(&inheritance.table),
+            #[cfg(feature = "comments")]
+            DBObject::Comment(comment) => Some(&comment.table),
+            DBObject::Table(_)
+            | DBObject::ForeignTable(_)
+            | DBObject::Domain(_)
+            | DBObject::DomainConstraint(_)
+            | DBObject::Sequence(_)
+            | DBObject::Extension(_)
+            | DBObject::MaterializedView(_) => None,
+        }
+    }
 }
 
 impl Display for DBObject {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            DBObject::Table(table) => write!(f, "Table {}", table.name),
-            DBObject::Column(column) => write!(
+            DBObject::Table(table) => write!(f, "Table {table}"),
+            DBObject::ForeignTable(table) => {
+                write!(f, "Foreign table {} (server {})", table.name, table.server)
+            }
+            DBObject::Column(column) => {
+                write!(
+                    f,
+                    "Column {}.{} ({})",
+                    column.table, column.name, column.data_type
+                )?;
+                if let Some(generated) = &column.generated {
+                    write!(
+                        f,
+                        " generated always as {} {}",
+                        generated.expression,
+                        if generated.stored {
+                            "stored"
+                        } else {
+                            "virtual"
+                        }
+                    )?;
+                }
+                Ok(())
+            }
+            DBObject::Index(index) => write!(
+                f,
+                "{} {}.{} ({})",
+                match (index.is_primary, index.is_unique) {
+                    (true, _) => "Primary key index",
+                    (false, true) => "Unique index",
+                    (false, false) => "Index",
+                },
+                index.table,
+                index.name,
+                index.columns.join(", ")
+            ),
+            DBObject::Constraint(constraint) => {
+                write!(f, "Constraint {}.{}", constraint.table, constraint.name)?;
+                if !constraint.definition.is_empty() {
+                    write!(f, " {}", constraint.definition)?;
+                }
+                if !constraint.validated {
+                    f.write_str(" (not valid)")?;
+                }
+                Ok(())
+            }
+            DBObject::Trigger(trigger) => write!(
                 f,
-                "Column {}.{} ({})",
-                column.table.name, column.name, column.data_type
+                "{} {}.{} ({})",
+                if trigger.internal {
+                    "Internal trigger of constraint"
+                } else {
+                    "Trigger"
+                },
+                trigger.table,
+                trigger.name,
+                trigger.enabled
             ),
-            DBObject::Index(index) => write!(f, "Index {}.{}", index.table.name, index.name),
+            DBObject::Domain(domain) => write!(f, "Domain {} ({})", domain.name, domain.data_type),
+            DBObject::DomainConstraint(constraint) => {
+                write!(
+                    f,
+                    "Domain constraint {}.{}",
+                    constraint.domain, constraint.name
+                )?;
+                if !constraint.validated {
+                    f.write_str(" (not valid)")?;
+                }
+                Ok(())
+            }
+            DBObject::Sequence(sequence) => {
+                write!(
+                    f,
+                    "Sequence {} (start {}, increment {}, min {}, max {}",
+                    sequence.name, sequence.start, sequence.increment, sequence.min, sequence.max
+                )?;
+                if sequence.cycle {
+                    f.write_str(", cycle")?;
+                }
+                if let Some(owned_by) = &sequence.owned_by {
+                    write!(f, ", owned by {owned_by}")?;
+                }
+                f.write_str(")")
+            }
+            DBObject::Extension(extension) => {
+                write!(f, "Extension {} ({})", extension.name, extension.version)
+            }
+            DBObject::Inheritance(inheritance) => {
+                write!(
+                    f,
+                    "Table {} inherits from {}",
+                    inheritance.child, inheritance.parent
+                )
+            }
+            DBObject::MaterializedView(view) => write!(f, "Materialized view {}", view.name),
+            DBObject::UnloggedTable(unlogged) => write!(f, "Table {} is unlogged", unlogged.table),
+            DBObject::StorageParameters(parameters) => write!(
+                f,
+                "Storage parameters of {} ({})",
+                parameters.table,
+                parameters.parameters.join(", ")
+            ),
+            DBObject::IndexStorageParameters(parameters) => write!(
+                f,
+                "Storage parameters of index {}.{} ({})",
+                parameters.table,
+                parameters.index,
+                parameters.parameters.join(", ")
+            ),
+            DBObject::IndexInheritance(inheritance) => write!(
+                f,
+                "Index {}.{} is attached to {}",
+                inheritance.table, inheritance.index, inheritance.parent
+            ),
+            #[cfg(feature = "comments")]
+            DBObject::Comment(comment) => match &comment.column {
+                Some(column) => write!(f, "Comment on column {}.{}", comment.table, column),
+                None => write!(f, "Comment on table {}", comment.table),
+            },
         }
     }
 }
 
 /// A lock on a specific table
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TableLock {
     pub table: TableObject,
     pub lock: Lock,
 }
 
+/// A predicate lock (`SIReadLock`) taken on a table by a statement running under `SERIALIZABLE`
+/// isolation, see [InspectedStatement::predicate_locks](crate::InspectedStatement::predicate_locks).
+///
+/// Predicate locks do not block other sessions, but a transaction that writes to data covered by
+/// the predicate lock of a concurrent serializable transaction can cause either to fail with a
+/// serialization failure. Predicate locks on an index are attributed to the table it belongs to.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PredicateLock {
+    pub table: TableObject,
+    pub granularity: PredicateLockGranularity,
+}
+
+/// What a [PredicateLock] covers, given by the `locktype` of the lock in `pg_locks`. Postgres
+/// promotes many tuple locks on the same page to a page lock, and many page locks on the same
+/// relation to a relation lock.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PredicateLockGranularity {
+    /// The whole table or index, such as after a sequential scan.
+    Relation,
+    /// A page of the table or index.
+    Page,
+    /// A single row of the table.
+    Tuple,
+}
+
+/// A row from the [pg_locks](https://www.postgresql.org/docs/current/view-pg-locks.html) view,
+/// returned by [Locker::list_raw_locks](crate::Locker::list_raw_locks).
+///
+/// Unlike [TableLock] this includes every lock held or awaited by a connection, including locks
+/// on transaction IDs, tuples and other non-table objects. Object IDs are returned as integers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RawLock {
+    pub locktype: String,
+    pub database: Option<i64>,
+    pub relation: Option<i64>,
+    pub page: Option<i32>,
+    pub tuple: Option<i16>,
+    pub virtualxid: Option<String>,
+    pub transactionid: Option<i64>,
+    pub classid: Option<i64>,
+    pub objid: Option<i64>,
+    pub objsubid: Option<i16>,
+    pub virtualtransaction: Option<String>,
+    pub pid: Option<i32>,
+    pub mode: Option<String>,
+    pub granted: bool,
+    pub fastpath: bool,
+}
+
+/// A query on a live server that is waiting for a lock held by another backend, returned by
+/// [Locker::list_blocked_queries](crate::Locker::list_blocked_queries).
+///
+/// A query that is blocked by several backends is returned once for each blocking backend.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BlockedQuery {
+    /// The process ID of the backend that is waiting for the lock.
+    pub waiting_pid: i32,
+    /// The process ID of a backend that holds or is ahead in the queue for a conflicting lock.
+    pub blocking_pid: i32,
+    /// The relation that the waiting backend is waiting to lock, if it is waiting for a lock on
+    /// a relation rather than, for example, a transaction ID when updating a locked row.
+    pub relation: Option<String>,
+    /// The text of the waiting query.
+    pub query: String,
+    /// How long the backend has been waiting for the lock. Before Postgres 14 the time that a
+    /// lock wait started is not recorded, so this is the time since the query started instead.
+    pub wait_duration: Duration,
+}
+
+/// The size of a table, returned by
+/// [Introspector::list_table_sizes](crate::Introspector::list_table_sizes).
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableSize {
+    pub table: TableObject,
+    /// The total size of the table on disk in bytes, including its indexes and TOAST data.
+    pub total_bytes: i64,
+    /// The planner's estimate of the number of rows in the table, or `None` if the table has
+    /// never been vacuumed or analyzed.
+    pub estimated_rows: Option<i64>,
+}
+
 /// A table, identified by its name
-#[derive(
-    Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, derive_more::From, Serialize, Deserialize,
-)]
-#[from(String, &str)]
+///
+/// Only the tables in the current schema are inspected, and they have no `schema`. A table in
+/// another schema that an object refers to, such as the parent of an inherited table or a table
+/// that a view depends on, has its `schema` set, such as `archive` for `archive.orders`, so it is
+/// never mistaken for a table of the same name in the current schema.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TableObject {
     pub name: String,
+    /// The schema of a table outside the current schema, or `None` for a table in it.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub schema: Option<String>,
+}
+
+impl TableObject {
+    /// A table named `name` in `schema`, rather than in the current schema.
+    pub fn in_schema(schema: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            schema: Some(schema.into()),
+        }
+    }
+
+    /// The name of the table as it is written in a statement, with its schema if it has one and
+    /// each part quoted, such as `"archive"."orders"`.
+    pub(crate) fn quoted_name(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!(
+                "{}.{}",
+                crate::quote_identifier(schema),
+                crate::quote_identifier(&self.name)
+            ),
+            None => crate::quote_identifier(&self.name),
+        }
+    }
+}
+
+impl From<String> for TableObject {
+    fn from(name: String) -> Self {
+        Self { name, schema: None }
+    }
+}
+
+impl From<&str> for TableObject {
+    fn from(name: &str) -> Self {
+        name.to_string().into()
+    }
 }
 
 impl Display for TableObject {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(schema) = &self.schema {
+            write!(f, "{schema}.")?;
+        }
         f.write_str(&self.name)
     }
 }
 
+/// A foreign table created via `CREATE FOREIGN TABLE`, identified by its name, with the name of
+/// the foreign server it belongs to.
+///
+/// Only the local side of a foreign table is inspected: locks on the foreign table relation are
+/// detected, but any effect on the remote server is not.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ForeignTableObject {
+    pub name: String,
+    pub server: String,
+}
+
 /// A column in a given table, with a data type
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ColumnObject {
     pub table: TableObject,
     pub name: String,
     pub data_type: String,
+    /// How the column is computed, if it is a `GENERATED ALWAYS AS (...)` column. Changing the
+    /// expression results in the old column being removed and the new column being added.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub generated: Option<GeneratedColumn>,
 }
 
-/// An index on a table, identified by its name
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+/// The expression a generated column is computed from, found in [ColumnObject::generated].
+///
+/// Stored generated columns are computed when a row is written, so adding one to an existing
+/// table, or changing its expression, rewrites the table to compute the value for every row.
+/// Virtual generated columns, added in Postgres 18, are computed when they are read and never
+/// rewrite the table.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GeneratedColumn {
+    /// The expression as normalized by Postgres' `pg_get_expr`, such as `(price * 2)`.
+    pub expression: String,
+    pub stored: bool,
+}
+
+/// An index on a table, identified by its name, with its ordered key columns.
+///
+/// Each key column is a column name or expression, followed by its sort order and null
+/// ordering when they are not the default (e.g. `price DESC NULLS LAST`). Changing the columns
+/// of an index or their order results in the old index being removed and the new index being
+/// added.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct IndexObject {
     pub table: TableObject,
     pub name: String,
+    pub columns: Vec<String>,
+    /// Whether this index backs the primary key of the table
+    pub is_primary: bool,
+    /// Whether this is a unique index. This is also `true` for primary key indexes.
+    pub is_unique: bool,
+}
+
+/// A constraint on a table, identified by its name.
+///
+/// `validated` is `false` for constraints added with `NOT VALID`, which have not been checked
+/// against the existing rows in the table.
+///
+/// A constraint that is replaced by one with the same name but a different definition, such as a
+/// check constraint whose expression changes, is reported as removed and added again.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConstraintObject {
+    pub table: TableObject,
+    pub name: String,
+    pub validated: bool,
+    /// The definition of the constraint as normalized by Postgres' `pg_get_constraintdef`, such
+    /// as `CHECK ((price >= (0)::numeric))`, without the `NOT VALID` suffix of constraints that
+    /// have not been validated.
+    ///
+    /// This is empty for check constraints on tables that another session held an
+    /// `ACCESS EXCLUSIVE` lock on when they were listed, as their expression cannot be deparsed
+    /// without waiting for the lock.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub definition: String,
+}
+
+/// A trigger on a table, identified by its name, with whether it fires, as set by
+/// `ALTER TABLE ... {ENABLE | DISABLE} TRIGGER`.
+///
+/// The internal triggers that Postgres creates to enforce foreign keys are included, so
+/// `DISABLE TRIGGER ALL`, which also stops foreign keys from being checked, is reported even on
+/// tables without any triggers of their own. These are listed once for each constraint and
+/// table, named after the constraint rather than the triggers, whose names contain OIDs.
+///
+/// Enabling or disabling a trigger results in the old trigger being removed and the new trigger
+/// being added.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TriggerObject {
+    pub table: TableObject,
+    pub name: String,
+    pub enabled: TriggerEnabled,
+    /// Whether this is an internal trigger that enforces the constraint named `name`.
+    pub internal: bool,
+}
+
+/// When a [TriggerObject] fires, from the `tgenabled` column of
+/// [pg_trigger](https://www.postgresql.org/docs/current/catalog-pg-trigger.html), which depends on
+/// the [session_replication_role](https://www.postgresql.org/docs/current/runtime-config-client.html#GUC-SESSION-REPLICATION-ROLE)
+/// setting.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TriggerEnabled {
+    /// The trigger fires in the `origin` and `local` replication roles, which is the default.
+    Enabled,
+    /// The trigger never fires.
+    Disabled,
+    /// The trigger only fires in the `replica` replication role, set with
+    /// `ENABLE REPLICA TRIGGER`.
+    Replica,
+    /// The trigger fires in every replication role, set with `ENABLE ALWAYS TRIGGER`.
+    Always,
+    /// A `tgenabled` value that is not known.
+    Unknown(String),
+}
+
+impl From<String> for TriggerEnabled {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "O" => TriggerEnabled::Enabled,
+            "D" => TriggerEnabled::Disabled,
+            "R" => TriggerEnabled::Replica,
+            "A" => TriggerEnabled::Always,
+            _ => TriggerEnabled::Unknown(value),
+        }
+    }
+}
+
+impl Display for TriggerEnabled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TriggerEnabled::Enabled => f.write_str("enabled"),
+            TriggerEnabled::Disabled => f.write_str("disabled"),
+            TriggerEnabled::Replica => f.write_str("enabled on replicas only"),
+            TriggerEnabled::Always => f.write_str("always enabled"),
+            TriggerEnabled::Unknown(value) => write!(f, "tgenabled {value}"),
+        }
+    }
+}
+
+/// A domain type, identified by its name, with the data type it is based on.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DomainObject {
+    pub name: String,
+    pub data_type: String,
+}
+
+/// A constraint on a domain type, identified by its name.
+///
+/// Adding a constraint to a domain validates every column that uses the domain, so these can
+/// result in locks on tables that are not mentioned in the statement.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DomainConstraintObject {
+    pub domain: String,
+    pub name: String,
+    pub validated: bool,
+}
+
+/// A sequence, identified by its name, with its attributes and the column that owns it, if any,
+/// as `table.column`. Sequences are owned by `serial` and identity columns, or via
+/// `ALTER SEQUENCE ... OWNED BY`.
+///
+/// Changing any of the attributes of a sequence results in the old sequence being removed and the
+/// new sequence being added. The current value of a sequence is not included, as it changes
+/// whenever a row is inserted, so `ALTER SEQUENCE ... RESTART` is not reported.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SequenceObject {
+    pub name: String,
+    pub start: i64,
+    pub increment: i64,
+    pub min: i64,
+    pub max: i64,
+    pub cycle: bool,
+    pub owned_by: Option<String>,
+}
+
+/// The storage parameters of a table, such as `fillfactor` or `autovacuum_enabled`, as
+/// `name=value` pairs. Parameters of the table's TOAST table are prefixed with `toast.`, matching
+/// the syntax of `ALTER TABLE ... SET (toast.autovacuum_enabled = false)`.
+///
+/// Only tables with at least one storage parameter set are listed. Setting or resetting a
+/// parameter results in the old parameters being removed and the new parameters being added.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StorageParametersObject {
+    pub table: TableObject,
+    pub parameters: Vec<String>,
+}
+
+/// The storage parameters of an index, such as `fillfactor`, as `name=value` pairs.
+///
+/// Only indexes with at least one storage parameter set are listed. As with
+/// [StorageParametersObject], changing them with `ALTER INDEX ... SET` or `RESET` results in the
+/// old parameters being removed and the new parameters being added.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IndexStorageParametersObject {
+    /// The table of the index
+    pub table: TableObject,
+    pub index: String,
+    pub parameters: Vec<String>,
+}
+
+/// An index on a partition that is attached to an index of its partitioned table, either because
+/// it was created by creating the index on the partitioned table, or via
+/// `ALTER INDEX ... ATTACH PARTITION`.
+///
+/// `parent` is the name of the index on the partitioned table, which is qualified with its schema
+/// if it is outside the current schema.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IndexInheritanceObject {
+    /// The partition that the attached index is on
+    pub table: TableObject,
+    pub index: String,
+    pub parent: String,
+}
+
+/// A table created with `CREATE UNLOGGED TABLE` or changed with `ALTER TABLE ... SET UNLOGGED`,
+/// whose changes are not written to the WAL.
+///
+/// Only unlogged tables are listed, so `SET UNLOGGED` results in this object being added and
+/// `SET LOGGED` in it being removed. Both rewrite the table, and `SET LOGGED` writes the whole
+/// table to the WAL.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnloggedTableObject {
+    pub table: TableObject,
+}
+
+/// An extension installed via `CREATE EXTENSION`, identified by its name, with its version.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExtensionObject {
+    pub name: String,
+    pub version: String,
+}
+
+/// A table that inherits from a parent table, either via `INHERITS` or as a partition of a
+/// partitioned table.
+///
+/// Many statements on a parent table also apply to its children, such as adding a column, and
+/// so take locks on every child table.
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InheritanceObject {
+    pub parent: TableObject,
+    pub child: TableObject,
+}
+
+/// A materialized view, identified by its name.
+///
+/// Regular views are listed as [TableObject]s, as they are by
+/// [information_schema.tables](https://www.postgresql.org/docs/current/infoschema-tables.html).
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MaterializedViewObject {
+    pub name: String,
+}
+
+/// A view or materialized view that depends on a table or one of its columns, returned by
+/// [Introspector::list_view_dependencies](crate::Introspector::list_view_dependencies).
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ViewDependency {
+    /// The dependent view, which is a [DBObject::Table] for regular views and a
+    /// [DBObject::MaterializedView] for materialized views.
+    pub view: DBObject,
+    /// The table or view that is depended on.
+    pub table: TableObject,
+    /// The column that is depended on, or `None` if the view depends on the table as a whole.
+    pub column: Option<String>,
+}
+
+/// A comment on a table or column, set via `COMMENT ON`. Changing a comment results in the old
+/// comment being removed and the new comment being added.
+///
+/// This is only available with the `comments` feature enabled.
+#[cfg(feature = "comments")]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CommentObject {
+    pub table: TableObject,
+    pub column: Option<String>,
+    pub comment: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Lock, ParseLockError};
+
+    #[test]
+    fn test_parse_lock() {
+        assert_eq!("ShareLock".parse(), Ok(Lock::ShareLock));
+        assert_eq!(
+            "SIReadLock".parse::<Lock>(),
+            Err(ParseLockError("SIReadLock".to_string()))
+        );
+        assert_eq!(
+            Lock::from_pg_string("SIReadLock"),
+            Lock::Unknown("SIReadLock".to_string())
+        );
+        assert_eq!(
+            Lock::from("AccessExclusiveLock".to_string()),
+            Lock::AccessExclusiveLock
+        );
+        for mode in 1..=8 {
+            let lock = Lock::from_lock_mode(mode).unwrap();
+            assert_eq!(lock.strength(), mode);
+            assert_eq!(lock.to_string().parse(), Ok(lock));
+        }
+        assert_eq!(Lock::from_lock_mode(0), None);
+        assert_eq!(Lock::from_lock_mode(9), None);
+    }
 }
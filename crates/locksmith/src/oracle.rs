@@ -1,33 +1,911 @@
+use crate::analysis::{foreign_tables, lockable_tables};
+use crate::cache::{CacheKey, InspectionCache};
+use crate::clock::{Clock, SystemClock};
 use crate::executor::StatementExecutor;
-use crate::introspection::Introspector;
+use crate::introspection::{Introspector, SchemaSource};
 use crate::locker::Locker;
-use crate::{DBObject, TableLock};
-use anyhow::Context;
+use crate::pool::ConnectionPool;
+use crate::seed::Seeder;
+use crate::statement::{split_sql, transaction_control_command, uses_concurrently};
+use crate::{
+    ConnectionID, DBObject, DBObjectDiff, Lock, LocksmithError, PredicateLock, SchemaSnapshot,
+    StatementObservation, TableLock, TableObject, TlsConfig,
+};
+use anyhow::{Context, bail};
+use futures::future::{self, Either};
+use futures::{Stream, stream};
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use tracing::{debug, info};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, MutexGuard, OwnedSemaphorePermit};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tracing::{debug, info, warn};
 
 /// [InspectedStatement] is a struct that contains the side effects of inspecting a SQL statement.
 /// It includes the objects that were added, removed, locked, and rewritten by the statement.
-#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InspectedStatement {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
     pub added_objects: HashSet<DBObject>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
     pub removed_objects: HashSet<DBObject>,
+    /// The objects that were renamed by the statement, which are left out of `added_objects` and
+    /// `removed_objects`. Only renamed indexes are detected, see [RenamedObject].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub renamed: HashSet<RenamedObject>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
     pub locks: HashSet<TableLock>,
+    /// The subset of `locks` that are taken when committing the statement, rather than when
+    /// executing it, such as locks required to check deferred constraints.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub commit_locks: HashSet<TableLock>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
     pub rewrites: HashSet<DBObject>,
+    /// The estimated amount of data written by rewriting each table in `rewrites`, based on the
+    /// size of the table before the statement was executed.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub rewrite_estimates: HashSet<RewriteEstimate>,
+    /// The columns that were added or changed on each table in `rewrites`, which are the
+    /// candidates for the change that caused the rewrite. Rewritten tables without any added or
+    /// changed columns, such as those rewritten by `VACUUM FULL`, are left out.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub rewrite_columns: HashSet<RewriteColumns>,
+    /// The estimated cost of the locks taken on each locked table, based on the size of the
+    /// table before the statement was executed.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub lock_costs: HashSet<TableLockCost>,
+    /// Views and materialized views that depend on a table or column that is removed or altered
+    /// by the statement, including views that depend on those views in turn. Dependent views are
+    /// either dropped along with the object via `CASCADE`, or cause the statement to fail.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub dependents: HashSet<DBObject>,
+    /// Locks that the statement took on tables excluded via [QueryOracleBuilder::excluded_tables].
+    /// Excluded tables are never locked by the locker, so these are only the locks the executor
+    /// was seen holding, and are potential locks that have not been verified. They are not
+    /// included in `locks`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub unverified_locks: HashSet<TableLock>,
+    /// Whether executing the statement a second time succeeds without changing any objects, such
+    /// as when it only uses `CREATE TABLE IF NOT EXISTS` and `DROP ... IF EXISTS`. Only set when
+    /// inspecting with [QueryOracleBuilder::idempotency_check].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub idempotent: Option<bool>,
+    /// The number of rows inserted, updated, deleted or merged by the statement, taken from the
+    /// command tags of its commands that modify rows, or `None` if it has none. The schema is
+    /// usually loaded without any rows, so this is only meaningful against a database containing
+    /// data, or with [QueryOracleBuilder::seed_rows].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub affected_rows: Option<u64>,
+    /// The predicate locks (`SIReadLock`) held by the statement when it finished executing, which
+    /// are only taken under `SERIALIZABLE` isolation. Only set when inspecting with
+    /// [QueryOracleBuilder::predicate_locks].
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, serialize_with = "crate::serialize_sorted")
+    )]
+    pub predicate_locks: HashSet<PredicateLock>,
+}
+
+impl InspectedStatement {
+    /// The objects added and removed by the statement, see [DBObjectDiff::since] to compare them
+    /// against a [SchemaSnapshot](crate::SchemaSnapshot).
+    pub fn object_diff(&self) -> DBObjectDiff {
+        DBObjectDiff {
+            added_objects: self.added_objects.clone(),
+            removed_objects: self.removed_objects.clone(),
+        }
+    }
+
+    /// Compare the locks and rewrites of this statement against those of a previously approved
+    /// `baseline`, returning the locks and rewrites that are not present in the baseline.
+    pub fn compare_to_baseline(&self, baseline: &InspectedStatement) -> BaselineComparison {
+        BaselineComparison {
+            new_locks: self.locks.difference(&baseline.locks).cloned().collect(),
+            new_rewrites: self
+                .rewrites
+                .difference(&baseline.rewrites)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Collapse [InspectedStatement::locks] into the single strongest lock taken on each table,
+    /// as determined by [Lock::strength]. A statement can take several locks on the same table,
+    /// such as while executing and committing, but its impact is determined by the strongest.
+    pub fn strongest_lock_per_table(&self) -> BTreeMap<TableObject, Lock> {
+        let mut strongest: BTreeMap<TableObject, Lock> = BTreeMap::new();
+        for lock in &self.locks {
+            match strongest.get(&lock.table) {
+                Some(existing) if existing.strength() >= lock.lock.strength() => {}
+                _ => {
+                    strongest.insert(lock.table.clone(), lock.lock.clone());
+                }
+            }
+        }
+        strongest
+    }
+
+    /// Classify the operation the statement performs on each locked table, from the strongest
+    /// lock taken on it and the cost of its locks, sorted by table.
+    ///
+    /// This separates tables that are only locked briefly, such as to change the catalog, from
+    /// tables that are scanned or rewritten in full while the lock is held, whose impact scales
+    /// with the size of the table. Tables without a [lock cost](InspectedStatement::lock_costs)
+    /// are treated as [LockCost::Instant].
+    pub fn table_operations(&self) -> Vec<TableOperation> {
+        self.strongest_lock_per_table()
+            .into_iter()
+            .map(|(table, lock)| {
+                let cost = self
+                    .lock_costs
+                    .iter()
+                    .find(|cost| cost.table == table)
+                    .map_or(LockCost::Instant, |cost| cost.cost);
+                TableOperation { table, lock, cost }
+            })
+            .collect()
+    }
+
+    /// Return the locks in `application_locks`, such as the locks taken by an application's
+    /// typical queries, that would be blocked by this statement. A lock is blocked if the
+    /// statement takes a lock on the same table that [conflicts](Lock::conflicts_with) with it.
+    ///
+    /// For example, an `orders` [Lock::RowExclusiveLock] taken by inserts is blocked by a
+    /// statement that takes a [Lock::ShareLock] on `orders`. Blocked locks are returned in the
+    /// order they are given.
+    pub fn blocks(&self, application_locks: &[TableLock]) -> Vec<TableLock> {
+        application_locks
+            .iter()
+            .filter(|application_lock| {
+                self.locks.iter().any(|lock| {
+                    lock.table == application_lock.table
+                        && lock.lock.conflicts_with(&application_lock.lock)
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns `true` if this statement and `other` can run at the same time, from separate
+    /// transactions, without either blocking the other. This is the case unless both take
+    /// [conflicting](Lock::conflicts_with) locks on a shared table, in which case one waits for
+    /// the other to finish or the two deadlock.
+    ///
+    /// For example, two statements that each add an index with `CREATE INDEX` only take a
+    /// [Lock::ShareLock] and can run concurrently, whereas two `ALTER TABLE` statements on the
+    /// same table cannot. Locks in [InspectedStatement::unverified_locks] are included.
+    pub fn can_run_concurrently(&self, other: &InspectedStatement) -> bool {
+        let other_locks: Vec<_> = other.locks.union(&other.unverified_locks).collect();
+        !self.locks.union(&self.unverified_locks).any(|lock| {
+            other_locks.iter().any(|other_lock| {
+                lock.table == other_lock.table
+                    && (lock.lock.conflicts_with(&other_lock.lock)
+                        || other_lock.lock.conflicts_with(&lock.lock))
+            })
+        })
+    }
+}
+
+/// An [InspectedStatement] extended with the timeline of the locks detected while inspecting it,
+/// returned by [QueryOracle::inspect_statement_report].
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InspectionReport {
+    pub inspected: InspectedStatement,
+    /// Each lock in [InspectedStatement::locks] along with when it was detected, relative to the
+    /// first execution of the statement, in the order they were detected.
+    ///
+    /// Locks are detected one iteration of the statement at a time, so this gives the approximate
+    /// order in which the statement acquires its locks. Locks detected in the same iteration
+    /// share a timestamp, and the time between iterations includes the time taken to lock tables
+    /// and connect to the database as well as the time spent acquiring the lock.
+    pub lock_timeline: Vec<(TableLock, Duration)>,
+}
+
+/// How much work a statement does on a table while holding its locks, which determines roughly
+/// how long the locks are held for.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LockCost {
+    /// Only the catalog is changed, so the locks are held for a constant amount of time
+    /// regardless of the size of the table.
+    Instant,
+    /// The table is read in full, such as to validate a constraint, so the locks are held for
+    /// time proportional to the size of the table.
+    Scan,
+    /// The table and its indexes are rewritten, so the locks are held for time proportional to
+    /// the size of the table, and the table temporarily requires twice as much disk space.
+    Rewrite,
+}
+
+impl Display for LockCost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockCost::Instant => f.write_str("instant"),
+            LockCost::Scan => f.write_str("full table scan"),
+            LockCost::Rewrite => f.write_str("full table rewrite"),
+        }
+    }
+}
+
+/// The estimated cost of the locks a statement takes on a table, found in
+/// [InspectedStatement::lock_costs].
+///
+/// The table sizes describe the database being inspected, so they are only meaningful when
+/// inspecting a database with a realistic amount of data, such as a copy of production.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableLockCost {
+    pub table: TableObject,
+    pub cost: LockCost,
+    /// The total size of the table in bytes, see [TableSize::total_bytes](crate::TableSize::total_bytes).
+    pub total_bytes: i64,
+    /// The estimated number of rows in the table, see [TableSize::estimated_rows](crate::TableSize::estimated_rows).
+    pub estimated_rows: Option<i64>,
+}
+
+impl TableLockCost {
+    /// Roughly estimate how long the locks are held for if the server reads `bytes_per_second`.
+    ///
+    /// [LockCost::Instant] locks are estimated to be held for no time at all, and rewrites are
+    /// estimated to take twice as long as scans because the table is both read and written.
+    pub fn estimated_duration(&self, bytes_per_second: u64) -> Duration {
+        let bytes = self.total_bytes.max(0) as f64;
+        let passes = match self.cost {
+            LockCost::Instant => return Duration::ZERO,
+            LockCost::Scan => 1.0,
+            LockCost::Rewrite => 2.0,
+        };
+        Duration::from_secs_f64(passes * bytes / bytes_per_second.max(1) as f64)
+    }
+}
+
+/// The operation a statement performs on a locked table, returned by
+/// [InspectedStatement::table_operations].
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TableOperation {
+    pub table: TableObject,
+    /// The strongest lock taken on the table, see [InspectedStatement::strongest_lock_per_table].
+    pub lock: Lock,
+    pub cost: LockCost,
+}
+
+impl TableOperation {
+    /// Returns `true` if the lock blocks writes to the table, such as by `INSERT` or `UPDATE`.
+    pub fn blocks_writes(&self) -> bool {
+        self.lock.conflicts_with(&Lock::RowExclusiveLock)
+    }
+
+    /// Returns `true` if the lock blocks reads from the table, such as by `SELECT`.
+    pub fn blocks_reads(&self) -> bool {
+        self.lock.conflicts_with(&Lock::AccessShareLock)
+    }
+
+    /// Returns `true` if the lock is held while the table is scanned or rewritten in full, so
+    /// that it is held for time proportional to the size of the table.
+    pub fn scales_with_table_size(&self) -> bool {
+        self.cost != LockCost::Instant
+    }
+}
+
+impl Display for TableOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let blocks = match (self.blocks_reads(), self.blocks_writes()) {
+            (true, _) => "blocks reads and writes",
+            (false, true) => "blocks writes",
+            (false, false) => "does not block reads or writes",
+        };
+        match self.cost {
+            LockCost::Instant => write!(f, "`{}` held briefly, which {blocks}", self.lock),
+            cost => write!(
+                f,
+                "`{}` held for a {cost}, which {blocks} for time proportional to the size of the \
+                 table",
+                self.lock
+            ),
+        }
+    }
+}
+
+/// The estimated amount of data written when a table is rewritten, found in
+/// [InspectedStatement::rewrite_estimates].
+///
+/// A rewrite writes a new copy of the table and its indexes, all of which is also written to the
+/// WAL and sent to any replicas, so this gives a sense of the replication lag and disk space
+/// caused by the rewrite. Like [TableLockCost], it describes the database being inspected.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RewriteEstimate {
+    pub table: TableObject,
+    /// The total size of the table before it was rewritten, including its indexes and TOAST
+    /// data, see [TableSize::total_bytes](crate::TableSize::total_bytes).
+    pub rewrite_bytes_estimate: i64,
+}
+
+/// The columns that may have caused a table to be rewritten, found in
+/// [InspectedStatement::rewrite_columns].
+///
+/// A rewrite applies to the whole table, so the column that caused it cannot be observed
+/// directly. Instead, these are the columns of the table that the statement added or changed,
+/// such as by altering their type or adding them with a volatile default, which are the
+/// candidates for the cause of the rewrite. Changes that do not require a rewrite, such as
+/// increasing the length of a `varchar` column, are still included if another change to the
+/// same table rewrote it.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RewriteColumns {
+    pub table: TableObject,
+    /// The names of the added or changed columns, sorted by name.
+    pub columns: Vec<String>,
+}
+
+/// An object that was renamed, found in [InspectedStatement::renamed].
+///
+/// Renaming an object changes its identity, so without further information a rename cannot be
+/// told apart from dropping the object and creating an identical one with the new name. An index
+/// keeps its file node when it is renamed, so a removed and an added index that only differ by
+/// their name and have the same [file node](crate::Introspector::list_object_file_nodes) are
+/// reported as a rename, such as by `ALTER INDEX ... RENAME TO`. Other objects are reported as
+/// removed and added.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RenamedObject {
+    pub from: DBObject,
+    pub to: DBObject,
+}
+
+/// The result of [InspectedStatement::compare_to_baseline], containing the locks and rewrites
+/// that were not present in the baseline.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BaselineComparison {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
+    pub new_locks: HashSet<TableLock>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
+    pub new_rewrites: HashSet<DBObject>,
+}
+
+impl BaselineComparison {
+    /// Returns `true` if the statement did not introduce any locks or rewrites beyond the baseline.
+    pub fn is_empty(&self) -> bool {
+        self.new_locks.is_empty() && self.new_rewrites.is_empty()
+    }
+}
+
+/// Controls how the locker behaves when a table is already locked by another session, which can
+/// happen when inspecting statements against a database that is not completely isolated.
+#[derive(Debug, Copy, Clone, Default, Hash, Eq, PartialEq)]
+pub enum LockConflictPolicy {
+    /// Wait for conflicting locks to be released. This can block the inspection indefinitely.
+    #[default]
+    Wait,
+    /// Lock tables with `NOWAIT`, and return an error listing any tables that could not be locked.
+    Error,
+    /// Lock tables with `NOWAIT`, and skip any tables that could not be locked. Locks that the
+    /// statement requires on skipped tables may not be detected.
+    Skip,
+}
+
+/// Controls how the locks of a blocked statement are detected in each iteration of
+/// [QueryOracle::inspect_statement].
+#[derive(Debug, Copy, Clone, Default, Hash, Eq, PartialEq)]
+pub enum LockDetection {
+    /// List every lock held or awaited by the executor once the statement is blocked.
+    #[default]
+    ConnectionLocks,
+    /// Once the statement is blocked, poll `pg_locks` for the lock that the executor is waiting
+    /// for, see [Locker::awaited_lock], before listing every lock held by the executor.
+    ///
+    /// The awaited lock is the one the statement is blocked on, so knowing it precisely means
+    /// that a statement blocked by anything other than a lock held by the locker, such as a
+    /// table locked by another session or a row locked by another transaction, fails
+    /// immediately with a [LocksmithError::UnobservableLock] rather than after repeating the
+    /// iteration. Locks are otherwise detected in the same way, so the result is the same as
+    /// [LockDetection::ConnectionLocks].
+    AwaitedLock,
 }
 
 pub struct QueryOracle {
     dsn: String,
+    lock_conflict_policy: LockConflictPolicy,
+    lock_detection: LockDetection,
+    include_extension_members: bool,
+    include_toast_locks: bool,
+    include_predicate_locks: bool,
+    search_path: Option<Vec<String>>,
+    role: Option<String>,
+    setup_statements: Vec<String>,
+    excluded_tables: HashSet<TableObject>,
+    check_idempotency: bool,
+    repeatable_read_introspection: bool,
+    seed_rows: u64,
+    seeded: bool,
+    cache: Option<Arc<dyn InspectionCache>>,
+    pool: Option<ConnectionPool>,
+    schema_source: Option<Mutex<Box<dyn SchemaSource>>>,
+    clock: Arc<dyn Clock>,
+    tls: TlsConfig,
+    lock_mode: Lock,
+    deadlock_timeout: Option<Duration>,
+    application_name: Option<String>,
+    max_iterations: Option<u32>,
+    timeout: Option<Duration>,
 }
 
-impl QueryOracle {
+/// A builder of a [QueryOracle], created by [QueryOracle::builder], with a method for each of the
+/// oracle's settings. Every setting defaults to the behaviour of [QueryOracle::new].
+pub struct QueryOracleBuilder {
+    oracle: QueryOracle,
+}
+
+impl QueryOracleBuilder {
+    /// Create a builder of an oracle that inspects statements against the database at `dsn`.
     pub fn new(dsn: impl ToString) -> Self {
         Self {
-            dsn: dsn.to_string(),
+            oracle: QueryOracle {
+                dsn: dsn.to_string(),
+                lock_conflict_policy: LockConflictPolicy::default(),
+                lock_detection: LockDetection::default(),
+                include_extension_members: true,
+                include_toast_locks: false,
+                include_predicate_locks: false,
+                search_path: None,
+                role: None,
+                setup_statements: vec![],
+                excluded_tables: HashSet::new(),
+                check_idempotency: false,
+                repeatable_read_introspection: false,
+                seed_rows: 0,
+                seeded: false,
+                cache: None,
+                pool: None,
+                schema_source: None,
+                clock: Arc::new(SystemClock),
+                tls: TlsConfig::default(),
+                lock_mode: Lock::AccessExclusiveLock,
+                deadlock_timeout: None,
+                application_name: None,
+                max_iterations: None,
+                timeout: None,
+            },
         }
     }
 
+    /// Set the [LockConflictPolicy] used when the locker encounters a table that is already
+    /// locked by another session.
+    pub fn lock_conflict_policy(mut self, policy: LockConflictPolicy) -> Self {
+        self.oracle.lock_conflict_policy = policy;
+        self
+    }
+
+    /// Set the [LockDetection] used to detect the locks of a statement each time it is blocked.
+    /// Defaults to [LockDetection::ConnectionLocks].
+    pub fn lock_detection(mut self, detection: LockDetection) -> Self {
+        self.oracle.lock_detection = detection;
+        self
+    }
+
+    /// Set the lock that the locker takes on each table that the statement has not yet been
+    /// observed locking. Defaults to [Lock::AccessExclusiveLock], which conflicts with every lock.
+    ///
+    /// A statement is only blocked by the locker when it takes a lock that conflicts with this
+    /// one, so with a weaker lock the statement completes in fewer iterations, and the locks that
+    /// do not conflict are read from the locks it holds once it has completed. These are the
+    /// same locks, but without the order they are taken in: they are all reported in the
+    /// [lock timeline](InspectionReport::lock_timeline) at the time the statement completed.
+    /// The lock must be one that `LOCK TABLE` can take, and inspection fails otherwise.
+    pub fn lock_mode(mut self, lock: Lock) -> Self {
+        self.oracle.lock_mode = lock;
+        self
+    }
+
+    /// Set the `deadlock_timeout` of the executor's connection, which is how long the statement
+    /// waits for a lock before the wait is reported. Defaults to 1ms, see
+    /// [StatementExecutor::set_deadlock_timeout].
+    pub fn deadlock_timeout(mut self, timeout: Duration) -> Self {
+        self.oracle.deadlock_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the `application_name` of every connection the oracle opens, so that they can be told
+    /// apart from other sessions in `pg_stat_activity`. Defaults to the `application_name` given
+    /// in the DSN, if any.
+    pub fn application_name(mut self, name: &str) -> Self {
+        self.oracle.application_name = Some(name.to_string());
+        self
+    }
+
+    /// Fail with a [LocksmithError::IterationLimit] if a statement is still blocked after
+    /// `iterations` iterations of [QueryOracle::inspect_statement]. Defaults to no limit: each
+    /// iteration detects at least one new lock, so the number of iterations is already bounded
+    /// by the number of tables.
+    pub fn max_iterations(mut self, iterations: u32) -> Self {
+        self.oracle.max_iterations = Some(iterations);
+        self
+    }
+
+    /// Fail with a [LocksmithError::Timeout] if inspecting a statement takes longer than
+    /// `timeout`, including listing the objects before and after it. Defaults to no timeout.
+    ///
+    /// The inspection is stopped wherever it is when the timeout is reached, and the statement is
+    /// cancelled if it is still executing, so this also bounds statements that run for a long
+    /// time without being blocked. Each statement of a migration has its own timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.oracle.timeout = Some(timeout);
+        self
+    }
+
+    /// Set whether objects that belong to an extension are reported as added or removed objects,
+    /// in addition to the extension itself. See [Introspector::with_extension_members].
+    pub fn extension_members(mut self, include: bool) -> Self {
+        self.oracle.include_extension_members = include;
+        self
+    }
+
+    /// Set whether locks on TOAST tables are reported, attributed to the table that owns them.
+    /// See [Locker::with_toast_locks].
+    pub fn toast_locks(mut self, include: bool) -> Self {
+        self.oracle.include_toast_locks = include;
+        self
+    }
+
+    /// Set whether the [predicate locks](InspectedStatement::predicate_locks) held by the
+    /// statement are reported. Statements only take predicate locks when they run under
+    /// `SERIALIZABLE` isolation, which can be set with the setup statement
+    /// `SET TRANSACTION ISOLATION LEVEL SERIALIZABLE`, see
+    /// [QueryOracleBuilder::setup_statements].
+    pub fn predicate_locks(mut self, include: bool) -> Self {
+        self.oracle.include_predicate_locks = include;
+        self
+    }
+
+    /// Set the `search_path` used by every connection the oracle opens. Defaults to the search
+    /// path of the connecting role.
+    ///
+    /// The introspector, locker and executor each use their own connection, so setting this
+    /// ensures that unqualified names in the statement resolve to the same schema that objects
+    /// and locks are listed from.
+    pub fn search_path(mut self, schemas: &[&str]) -> Self {
+        self.oracle.search_path = Some(schemas.iter().map(|schema| schema.to_string()).collect());
+        self
+    }
+
+    /// Assume `role` with `SET ROLE` on the introspector, locker and executor connections, after
+    /// the settings that locksmith requires, such as to run the statement as the role that runs
+    /// migrations in production. Defaults to the connecting role.
+    ///
+    /// Privileges can change which locks a statement takes and whether it succeeds at all, so
+    /// inspecting as the same role gives the same result as the real migration. The locker must
+    /// still be able to lock every table with the role, which requires ownership or `UPDATE`,
+    /// `DELETE` or `TRUNCATE` privileges on each table, and inspection fails with the tables it
+    /// cannot lock if it is not. Tables are seeded by [QueryOracleBuilder::seed_rows] as the
+    /// connecting role.
+    pub fn role(mut self, role: &str) -> Self {
+        self.oracle.role = Some(role.to_string());
+        self
+    }
+
+    /// Execute `statements` in the executor's transaction before each execution of the statement
+    /// being inspected, such as `SET role` or `SET lock_timeout` to match the session settings the
+    /// statement is run with in production. They run after the settings that locksmith requires,
+    /// and inspection fails if they change those settings. See
+    /// [StatementExecutor::execute_setup_statements].
+    ///
+    /// Setup statements are only run by the executor, so they do not affect the objects that are
+    /// listed or the tables that are locked. Use [QueryOracleBuilder::role] to assume a role on
+    /// every connection.
+    ///
+    /// A `lock_timeout`, whether set here or on the connecting role, can cancel the statement
+    /// before it is confirmed as blocked. This is detected and treated as a block on the lock
+    /// the statement was waiting for, provided that the server's `lc_messages` is English.
+    pub fn setup_statements(mut self, statements: &[&str]) -> Self {
+        self.oracle.setup_statements = statements
+            .iter()
+            .map(|statement| statement.to_string())
+            .collect();
+        self
+    }
+
+    /// Never lock `tables` with the locker, such as a shared migrations bookkeeping table on a
+    /// database that is used by other sessions. Can be called multiple times to exclude more
+    /// tables.
+    ///
+    /// Locks on excluded tables cannot be detected by forcing a lock conflict. Instead, the locks
+    /// the executor is seen holding on them are reported in [InspectedStatement::unverified_locks].
+    pub fn excluded_tables(mut self, tables: &[TableObject]) -> Self {
+        self.oracle.excluded_tables.extend(tables.iter().cloned());
+        self
+    }
+
+    /// Set whether each statement is executed a second time once it has been inspected, to check
+    /// whether it can be re-run after a partial failure. Defaults to `false`.
+    ///
+    /// The second execution happens in a transaction that is rolled back, and the result is
+    /// reported in [InspectedStatement::idempotent]. Statements with bind parameters are not
+    /// checked.
+    pub fn idempotency_check(mut self, check: bool) -> Self {
+        self.oracle.check_idempotency = check;
+        self
+    }
+
+    /// Set whether the objects in the database are listed within a `REPEATABLE READ` transaction
+    /// before and after each statement is executed. Defaults to `false`.
+    ///
+    /// Listing the objects takes several queries, which can see inconsistent states of the
+    /// database if other sessions change it at the same time, such as when inspecting against a
+    /// shared development database. With this enabled, the objects listed before the statement
+    /// all come from one snapshot of the system catalogs, as do those listed after it, so an
+    /// object changed by another session is never half seen. See
+    /// [Introspector::begin_repeatable_read] for what the snapshot covers.
+    ///
+    /// This does not isolate the statement itself: changes that other sessions commit between the
+    /// two snapshots are still attributed to the statement.
+    pub fn repeatable_read_introspection(mut self, repeatable_read: bool) -> Self {
+        self.oracle.repeatable_read_introspection = repeatable_read;
+        self
+    }
+
+    /// Seed every table with `rows` dummy rows before the first statement is inspected. Defaults
+    /// to `0`, which leaves the tables as they are.
+    ///
+    /// Schemas are usually loaded without any rows, so statements that validate existing rows,
+    /// such as adding a `NOT NULL` column without a default, succeed when they would fail in
+    /// production. Seeding makes these failures, and the time that locks are held for, more
+    /// representative. Values are generated from the type of each column, so seeding is best
+    /// effort: tables that cannot be seeded, for example because of a check constraint, are
+    /// skipped with a warning.
+    pub fn seed_rows(mut self, rows: u64) -> Self {
+        self.oracle.seed_rows = rows;
+        self
+    }
+
+    /// Cache the results of [QueryOracle::inspect_statement] in `cache`, keyed by the statement,
+    /// the schema of the database, the server version and the oracle's settings. See
+    /// [InspectionCache] and [CacheKey].
+    ///
+    /// When a cached result is found the statement is still executed and committed, so that the
+    /// database is left in the same state as if it had been inspected, but its locks are not
+    /// detected. The schema is the same as when the result was cached, so the result is too.
+    pub fn cache(mut self, cache: Arc<dyn InspectionCache>) -> Self {
+        self.oracle.cache = Some(cache);
+        self
+    }
+
+    /// Draw the oracle's [Introspector] and [Locker] connections from `pool` rather than opening
+    /// new connections for every inspection, and wait for the pool to allow another inspection
+    /// before inspecting each statement. The pool's DSN replaces the one the oracle was created
+    /// with.
+    ///
+    /// Cloning a [ConnectionPool] shares it, so many oracles, such as those inspecting statements
+    /// in different tasks of a service, can share the same pool to bound the number of
+    /// inspections they run at once and the number of connections they open. See
+    /// [ConnectionPool] for how connections are reused.
+    pub fn pool(mut self, pool: ConnectionPool) -> Self {
+        self.oracle.dsn = pool.dsn().to_string();
+        self.oracle.pool = Some(pool);
+        self
+    }
+
+    /// Set how every connection the oracle opens uses TLS, such as to verify the certificate of
+    /// a managed database. Defaults to [TlsConfig::Unverified], which uses TLS as given by the
+    /// `sslmode` of the DSN. Connections drawn from a [ConnectionPool] use the configuration the
+    /// pool was created with, see [ConnectionPool::connect_with_tls].
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.oracle.tls = tls;
+        self
+    }
+
+    /// List the objects and file nodes before and after each statement from `source`, rather than
+    /// with an [Introspector] connected to the database. The statement is still executed against
+    /// the database, and the tables listed by `source` are the ones that are locked, so they must
+    /// exist in it. Table sizes, view dependencies and the result of
+    /// [QueryOracleBuilder::idempotency_check] are still read from the database.
+    pub fn schema_source(mut self, source: impl SchemaSource + 'static) -> Self {
+        self.oracle.schema_source = Some(Mutex::new(Box::new(source)));
+        self
+    }
+
+    /// Set the [Clock] used to confirm blocks and to time the
+    /// [lock timeline](InspectionReport::lock_timeline). Defaults to the system clock.
+    #[cfg(test)]
+    pub(crate) fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.oracle.clock = clock;
+        self
+    }
+
+    /// Create the [QueryOracle].
+    pub fn build(self) -> QueryOracle {
+        self.oracle
+    }
+}
+
+impl QueryOracle {
+    /// Create an oracle that inspects statements against the database at `dsn`, with the default
+    /// settings. Use [QueryOracle::builder] to configure it.
+    pub fn new(dsn: impl ToString) -> Self {
+        QueryOracleBuilder::new(dsn).build()
+    }
+
+    /// Create a [QueryOracleBuilder] of an oracle that inspects statements against the database
+    /// at `dsn`.
+    pub fn builder(dsn: impl ToString) -> QueryOracleBuilder {
+        QueryOracleBuilder::new(dsn)
+    }
+
+    /// A builder that starts from the oracle's current settings, for the deprecated `with_*`
+    /// methods.
+    fn into_builder(self) -> QueryOracleBuilder {
+        QueryOracleBuilder { oracle: self }
+    }
+
+    /// See [QueryOracleBuilder::lock_conflict_policy].
+    #[deprecated(note = "use `QueryOracleBuilder::lock_conflict_policy`")]
+    pub fn with_lock_conflict_policy(self, policy: LockConflictPolicy) -> Self {
+        self.into_builder().lock_conflict_policy(policy).build()
+    }
+
+    /// See [QueryOracleBuilder::lock_detection].
+    #[deprecated(note = "use `QueryOracleBuilder::lock_detection`")]
+    pub fn with_lock_detection(self, detection: LockDetection) -> Self {
+        self.into_builder().lock_detection(detection).build()
+    }
+
+    /// See [QueryOracleBuilder::extension_members].
+    #[deprecated(note = "use `QueryOracleBuilder::extension_members`")]
+    pub fn with_extension_members(self, include: bool) -> Self {
+        self.into_builder().extension_members(include).build()
+    }
+
+    /// See [QueryOracleBuilder::toast_locks].
+    #[deprecated(note = "use `QueryOracleBuilder::toast_locks`")]
+    pub fn with_toast_locks(self, include: bool) -> Self {
+        self.into_builder().toast_locks(include).build()
+    }
+
+    /// See [QueryOracleBuilder::predicate_locks].
+    #[deprecated(note = "use `QueryOracleBuilder::predicate_locks`")]
+    pub fn with_predicate_locks(self, include: bool) -> Self {
+        self.into_builder().predicate_locks(include).build()
+    }
+
+    /// See [QueryOracleBuilder::search_path].
+    #[deprecated(note = "use `QueryOracleBuilder::search_path`")]
+    pub fn with_search_path(self, schemas: &[&str]) -> Self {
+        self.into_builder().search_path(schemas).build()
+    }
+
+    /// See [QueryOracleBuilder::role].
+    #[deprecated(note = "use `QueryOracleBuilder::role`")]
+    pub fn with_role(self, role: &str) -> Self {
+        self.into_builder().role(role).build()
+    }
+
+    /// See [QueryOracleBuilder::setup_statements].
+    #[deprecated(note = "use `QueryOracleBuilder::setup_statements`")]
+    pub fn with_setup_statements(self, statements: &[&str]) -> Self {
+        self.into_builder().setup_statements(statements).build()
+    }
+
+    /// See [QueryOracleBuilder::excluded_tables].
+    #[deprecated(note = "use `QueryOracleBuilder::excluded_tables`")]
+    pub fn exclude_tables(self, tables: &[TableObject]) -> Self {
+        self.into_builder().excluded_tables(tables).build()
+    }
+
+    /// See [QueryOracleBuilder::idempotency_check].
+    #[deprecated(note = "use `QueryOracleBuilder::idempotency_check`")]
+    pub fn with_idempotency_check(self, check: bool) -> Self {
+        self.into_builder().idempotency_check(check).build()
+    }
+
+    /// See [QueryOracleBuilder::repeatable_read_introspection].
+    #[deprecated(note = "use `QueryOracleBuilder::repeatable_read_introspection`")]
+    pub fn with_repeatable_read_introspection(self, repeatable_read: bool) -> Self {
+        self.into_builder()
+            .repeatable_read_introspection(repeatable_read)
+            .build()
+    }
+
+    /// See [QueryOracleBuilder::seed_rows].
+    #[deprecated(note = "use `QueryOracleBuilder::seed_rows`")]
+    pub fn with_seed_rows(self, rows: u64) -> Self {
+        self.into_builder().seed_rows(rows).build()
+    }
+
+    /// See [QueryOracleBuilder::cache].
+    #[deprecated(note = "use `QueryOracleBuilder::cache`")]
+    pub fn with_cache(self, cache: Arc<dyn InspectionCache>) -> Self {
+        self.into_builder().cache(cache).build()
+    }
+
+    /// See [QueryOracleBuilder::pool].
+    #[deprecated(note = "use `QueryOracleBuilder::pool`")]
+    pub fn with_pool(self, pool: ConnectionPool) -> Self {
+        self.into_builder().pool(pool).build()
+    }
+
+    /// See [QueryOracleBuilder::tls].
+    #[deprecated(note = "use `QueryOracleBuilder::tls`")]
+    pub fn with_tls(self, tls: TlsConfig) -> Self {
+        self.into_builder().tls(tls).build()
+    }
+
+    /// See [QueryOracleBuilder::schema_source].
+    #[deprecated(note = "use `QueryOracleBuilder::schema_source`")]
+    pub fn with_schema_source(self, source: impl SchemaSource + 'static) -> Self {
+        self.into_builder().schema_source(source).build()
+    }
+
+    /// Inspect every `.sql` file in a directory, in sorted filename order, returning a [Stream]
+    /// that yields each file's path alongside the result of inspecting its contents.
+    ///
+    /// Each file is inspected as a single statement via [QueryOracle::inspect_statement]. As
+    /// statements that execute successfully are committed, each file is inspected against the
+    /// state left by the files before it, mirroring how a directory of migrations is applied.
+    /// A file that fails to be inspected yields an error, and does not stop the remaining files
+    /// from being inspected. Failing to read the directory or a file is a [LocksmithError::Other].
+    pub fn inspect_directory(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<
+        impl Stream<Item = (PathBuf, Result<InspectedStatement, LocksmithError>)> + '_,
+        LocksmithError,
+    > {
+        let path = path.as_ref();
+        let mut files = std::fs::read_dir(path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|e| e.path()))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .with_context(|| format!("Reading directory {path:?}"))
+            .map_err(LocksmithError::Other)?;
+        files.retain(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "sql"));
+        files.sort();
+
+        Ok(stream::unfold(
+            (self, files.into_iter()),
+            |(oracle, mut files)| async move {
+                let file = files.next()?;
+                let result = match std::fs::read_to_string(&file)
+                    .with_context(|| format!("Reading file {file:?}"))
+                {
+                    Ok(statement) => oracle.inspect_statement(&statement).await,
+                    Err(e) => Err(LocksmithError::Other(e)),
+                };
+                Some(((file, result), (oracle, files)))
+            },
+        ))
+    }
+
     /// Inspect a statement and return an [InspectedStatement], containing a summary of the
     /// side effects of the statement.
     ///
@@ -77,7 +955,7 @@ impl QueryOracle {
     /// 6. The "locker" connection then lists all locks that are blocking the executor's transaction,
     ///    which reveals the tables that the statement is trying to access. We now have an
     ///    initial set of tables that the statement is accessing.
-    /// 7. We close the "executor" connection _without_ committing the transaction, and close the
+    /// 7. We cancel the blocked statement and roll back the "executor" transaction, and close the
     ///    "locker" connection after. This releases the locks on the tables.
     /// 8. We repeat steps 1 to 7, but this time only locking the tables that have *not* been
     ///    locked in the previous iterations. This allows us to detect new locks that the statement
@@ -85,6 +963,52 @@ impl QueryOracle {
     /// 9. We repeat this process until the statement is no longer blocked by any locks and executes
     ///    successfully.
     ///
+    /// Once the statement executes without being blocked, the executor commits the transaction.
+    /// Committing can also take locks, for example when checking deferred foreign key constraints,
+    /// so a blocked commit is handled in the same way as a blocked statement. Any locks that were
+    /// not already held by the executor before committing are reported in
+    /// [InspectedStatement::commit_locks].
+    ///
+    /// Objects created by the statement itself, such as the new table in a
+    /// `CREATE TABLE ... AS SELECT`, do not exist when the locker runs and so can never block the
+    /// executor. This is correct, as no other session can be using them, so locks on them are not
+    /// reported although the objects appear in [InspectedStatement::added_objects].
+    ///
+    /// Foreign tables cannot be locked with `LOCK TABLE`, so they are never locked by the locker.
+    /// Locks on foreign tables are instead taken from the locks held by the executor once the
+    /// statement has executed. Only the local foreign table relation is inspected, not the
+    /// remote server.
+    ///
+    /// Partitioned tables and each of their partitions are locked individually, with
+    /// `LOCK TABLE ONLY`, so the locks a statement takes on the partitioned table are reported
+    /// separately from those on its partitions. For example, `ALTER TABLE events ...` reports a
+    /// lock on `events` and on every partition, whereas `ALTER TABLE ONLY events ...` only
+    /// reports a lock on `events`.
+    ///
+    /// Tables excluded with [QueryOracleBuilder::excluded_tables] are never locked by the locker
+    /// either. Locks on them are taken from the locks held by the executor, and reported
+    /// separately in [InspectedStatement::unverified_locks].
+    ///
+    /// The statement is executed within the executor's transaction, so it must not contain
+    /// commands that end the transaction, such as `COMMIT` or `ROLLBACK`, or that cannot run in
+    /// one, such as `CREATE INDEX CONCURRENTLY`, and inspection fails with a
+    /// [LocksmithError::Other] before anything is executed if it does. Savepoints can be
+    /// used instead: locks taken before a `ROLLBACK TO SAVEPOINT` are still reported, as the
+    /// statement has to acquire them, but objects are only reported if they exist once the
+    /// statement has been executed.
+    ///
+    /// Functions and procedures called by the statement, such as `SELECT migrate_step_1()`, run
+    /// on the executor's backend, so the locks taken by the statements in their body are detected
+    /// in the same way as if they were run directly. This includes `SECURITY DEFINER` functions
+    /// and locks taken in `BEGIN ... EXCEPTION` blocks, which are kept when the block completes.
+    /// A procedure that commits or rolls back cannot run within the executor's transaction, and
+    /// fails with a [LocksmithError::Unresolvable] that says so.
+    ///
+    /// If the executor's backend is terminated by another session, such as by an admin or a
+    /// monitoring tool running `pg_terminate_backend`, its transaction is rolled back and the loop
+    /// resumes with a new executor, keeping the locks detected so far. Inspection fails if the
+    /// executor is terminated more than a few times.
+    ///
     /// Once this process is completed, we have observed the complete set of locks that the statement
     /// requires in order to execute.
     ///
@@ -92,6 +1016,15 @@ impl QueryOracle {
     /// isolated instance of Postgres (i.e. not a production environment), and so it requires the
     /// schema to completely match the production schema in order to give accurate results.
     ///
+    /// The schema is usually loaded without any rows, which does not affect the locks that are
+    /// detected or whether a table is rewritten, but does affect whether the statement succeeds.
+    /// For example, `ALTER TABLE orders ADD COLUMN x int NOT NULL` without a default succeeds on
+    /// an empty table, but fails on a table containing rows. Similarly, adding a constraint or
+    /// changing a column's type can fail because of the existing data. In these cases the
+    /// statement is reported as if it succeeded, so results for statements that validate existing
+    /// rows should be checked against the data in production, or the tables seeded with rows via
+    /// [QueryOracleBuilder::seed_rows].
+    ///
     /// # Detecting other effects
     ///
     /// In addition to detecting locks, we also need to detect the other side effects of the
@@ -100,121 +1033,1401 @@ impl QueryOracle {
     ///
     /// The implementation of this is much simpler: we simply introspect relevant database objects
     /// *before* and *after* the statement is executed, and compare the two sets of objects.
+    ///
+    /// The [cost](InspectedStatement::lock_costs) of the locks on each table is derived from
+    /// whether the table was rewritten, or else whether the final execution of the statement
+    /// sequentially scanned it, alongside the size of the table before the statement executed.
+    /// The same size gives the [estimated amount of data](InspectedStatement::rewrite_estimates)
+    /// written by each rewrite.
+    ///
+    /// # Applying the statement
+    ///
+    /// Inspecting a statement also applies it: once its locks have been detected, a statement
+    /// that succeeds is committed to the target database, leaving it in the same state as
+    /// [QueryOracle::apply_statement] would. This is what allows [QueryOracle::inspect_migration]
+    /// to inspect each statement against the state left by the ones before it, and a disposable
+    /// database to be advanced by inspecting each migration in turn. There is no mode that rolls
+    /// the statement back instead, as the objects after the statement are listed from a separate
+    /// connection that only sees committed changes. Never inspect statements against a database
+    /// whose contents must not change.
+    ///
+    /// The executor and locker connections are closed as soon as the statement has been
+    /// committed, which releases every lock held by either of them before the objects after the
+    /// statement are listed.
+    ///
+    /// # Errors
+    ///
+    /// If inspection fails once the statement has started being executed, for example because
+    /// the statement itself fails or a connection is lost, a [LocksmithError::Unresolvable] is
+    /// returned. This carries an [InspectedStatement] containing the locks detected before the
+    /// failure, which is often enough to act on. Its source chain contains the
+    /// [LocksmithError::Connection] or [LocksmithError::StatementFailed] that caused it, if any.
+    /// Failures before the statement is executed, such as failing to connect or to list the
+    /// initial objects, are returned as a [LocksmithError::Connection] or a
+    /// [LocksmithError::Introspection].
+    ///
+    /// If the database does not contain any tables and the statement fails because it references
+    /// a table that does not exist, a [LocksmithError::EmptySchema] is returned instead, as the
+    /// schema has most likely not been loaded.
+    ///
+    /// If the statement is blocked by a lock that the locker does not hold, such as a table locked
+    /// by another session or a row locked by another transaction, an iteration detects no new
+    /// locks and every further iteration would be blocked in the same way. A
+    /// [LocksmithError::UnobservableLock] naming the table the statement is waiting for, if any,
+    /// is returned instead.
+    ///
+    /// If the statement drops an object that other objects, such as views, depend on without
+    /// `CASCADE`, a [LocksmithError::DependentObjects] listing the dependent objects is returned.
+    /// With `CASCADE`, the dependent objects are instead included in the
+    /// [removed objects](InspectedStatement::removed_objects), and dependent views are reported
+    /// by [LintRule::CascadingDrop](crate::LintRule::CascadingDrop).
+    ///
+    /// If the statement is still blocked after the iterations set by
+    /// [QueryOracleBuilder::max_iterations], a [LocksmithError::IterationLimit] is returned, and
+    /// if the whole inspection takes longer than [QueryOracleBuilder::timeout], a
+    /// [LocksmithError::Timeout] is returned.
+    ///
+    /// Whichever the error, [LocksmithError::statement_error] gives the SQLSTATE and message of
+    /// the error Postgres returned for the statement, if it was rejected by the server.
     pub async fn inspect_statement(
         &mut self,
         statement: &str,
-    ) -> anyhow::Result<InspectedStatement> {
-        let mut all_detected_locks: HashSet<TableLock> = HashSet::new();
-
-        // Create an inspector, and list the initial objects in the database.
-        let mut introspector = Introspector::new(&self.dsn)
-            .await
-            .context("Creating introspector")?;
-        let initial_objects = introspector
-            .list_objects()
-            .await
-            .context("Listing initial objects")?;
-        let initial_table_file_nodes = introspector
-            .list_object_file_nodes()
+    ) -> Result<InspectedStatement, LocksmithError> {
+        let _permit = self.inspection_permit().await?;
+        self.inspect_cached(statement, None)
             .await
-            .context("Listing object file nodes")?;
-
-        // Retrieve the set of initial tables
-        let all_tables: HashSet<_> = initial_objects
-            .iter()
-            .filter_map(|obj| match obj {
-                DBObject::Table(table) => Some(table),
-                _ => None,
-            })
-            .collect();
-
-        // This implements the main loop of the algorithm.
-        // Here we repeatedly lock tables and execute the statement until it is no longer blocked.
-        loop {
-            // Create a set of tables to lock that we have not yet observed requiring a lock.
-            let known_locked_table: HashSet<_> =
-                all_detected_locks.iter().map(|t| &t.table).collect();
-            let tables_to_lock = all_tables.difference(&known_locked_table);
-
-            // Create a new "locker" connection and lock those tables
-            let mut locker = Locker::new(&self.dsn).await.context("Creating locker")?;
-            locker
-                .lock_tables(tables_to_lock.into_iter().copied())
-                .await?;
-
-            // Create a statement executor and retrieve its connection ID
-            let mut executor = StatementExecutor::new(&self.dsn)
-                .await
-                .context("Creating executor")?;
-            let connection_id = executor.connection_id();
-            debug!("Executor created with connection ID {connection_id:?}");
+            .map(|(inspected, _)| inspected)
+    }
 
-            // Execute the statement, returning true if the statement has been blocked by
-            // a lock taken by the locker connection.
-            // If the statement successfully executed without any blocking, we can break the loop
-            let is_blocked = executor.check_statement_for_locks(statement).await?;
-            if !is_blocked {
-                info!("Statement executed successfully");
+    /// Inspect each statement of a migration in turn, returning the text of each statement
+    /// alongside the result of inspecting it via [QueryOracle::inspect_statement].
+    ///
+    /// Each statement is committed once it has been inspected, so every statement is inspected
+    /// against the state left by the statements before it, and the objects after one statement
+    /// are the objects before the next. A statement that uses an object renamed or created by an
+    /// earlier statement, such as `CREATE INDEX ON purchases (price)` after
+    /// `ALTER TABLE orders RENAME TO purchases`, therefore has its locks detected on the object
+    /// by its new name, and only its own changes are reported. Statements are split in the same
+    /// way as `psql` splits them, and must not contain commands that end the transaction.
+    ///
+    /// Inspection stops at the first statement that fails to be inspected, which is the last
+    /// result returned, as the statements after it rely on its changes. The statements that were
+    /// inspected before it have been committed.
+    ///
+    /// Inspecting the statements one at a time gives the locks each statement takes, but not the
+    /// locks that are held for the rest of the migration when it runs in a single transaction.
+    /// These are the union of the locks of every statement up to and including each one.
+    pub async fn inspect_migration(
+        &mut self,
+        migration: &str,
+    ) -> Vec<(String, Result<InspectedStatement, LocksmithError>)> {
+        let _permit = match self.inspection_permit().await {
+            Ok(permit) => permit,
+            Err(e) => return vec![(migration.to_string(), Err(e.into()))],
+        };
+        let mut results = vec![];
+        let mut objects = None;
+        for statement in split_sql(migration) {
+            let result = match self.inspect_cached(&statement, objects.take()).await {
+                Ok((inspected, after)) => {
+                    objects = after;
+                    Ok(inspected)
+                }
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            results.push((statement, result));
+            if failed {
                 break;
             }
-
-            // List all locks that are taken by the executor connection and add them to our
-            // set of seen locks.
-            let new_locks: Vec<_> = locker
-                .list_connection_locks(connection_id)
-                .await
-                .context("Listing connection locks")?;
-            debug!(?new_locks, "Detected {} new locks", new_locks.len());
-            all_detected_locks.extend(new_locks);
-
-            // Attempt to terminate the executor connection. Not required, but prevents some
-            // spurious issues with Postgres 13 and connection limits.
-            executor.attempt_termination().await;
         }
+        results
+    }
 
-        // Take a snapshot of the objects in the database after the statement has executed
-        let new_objects: HashSet<_> = introspector
-            .list_objects()
-            .await
-            .context("Listing new objects")?;
+    /// Inspect a statement from the objects `before` it, if they are known, using the cache if
+    /// the oracle has one. Returns the objects after the statement alongside the result, unless
+    /// the result was cached and so the objects were never listed.
+    async fn inspect_cached(
+        &mut self,
+        statement: &str,
+        before: Option<ObjectState>,
+    ) -> Result<(InspectedStatement, Option<ObjectState>), LocksmithError> {
+        let Some(cache) = self.cache.clone() else {
+            let (report, after) = self.inspect_from(statement, None, before).await?;
+            return Ok((report.inspected, Some(after)));
+        };
+        let key = self.cache_key(statement).await?;
+        if let Some(inspected) = cache.get(key) {
+            info!(%key, "Using cached inspection result");
+            self.apply(statement).await?;
+            return Ok((inspected, None));
+        }
+        let (report, after) = self.inspect_from(statement, None, before).await?;
+        cache.insert(key, &report.inspected);
+        Ok((report.inspected, Some(after)))
+    }
 
-        let added_objects: HashSet<_> = new_objects.difference(&initial_objects).cloned().collect();
-        let removed_objects: HashSet<_> =
-            initial_objects.difference(&new_objects).cloned().collect();
+    /// Inspect a statement in the same way as [QueryOracle::inspect_statement], returning an
+    /// [InspectionReport] that also contains the timeline of when each lock was detected.
+    pub async fn inspect_statement_report(
+        &mut self,
+        statement: &str,
+    ) -> Result<InspectionReport, LocksmithError> {
+        let _permit = self.inspection_permit().await?;
+        self.inspect(statement, None).await
+    }
 
-        // Detect any tables that have been rewritten. A rewritten table will always have a
-        // different file node than the original table.
-        let new_table_file_nodes = introspector
-            .list_object_file_nodes()
+    /// Inspect a statement with bind parameters, such as
+    /// `DELETE FROM orders WHERE created < $1`, and return an [InspectedStatement]. This behaves
+    /// in the same way as [QueryOracle::inspect_statement], except that `params` are bound to the
+    /// placeholders of the statement rather than having to be inlined into it.
+    ///
+    /// Statements with parameters are executed with the extended query protocol, which only
+    /// supports a single statement. If `statement` contains multiple statements then inspection
+    /// fails with a [LocksmithError::Unresolvable].
+    pub async fn inspect_statement_params(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<InspectedStatement, LocksmithError> {
+        let _permit = self.inspection_permit().await?;
+        self.inspect(statement, Some(params))
             .await
-            .context("Listing new table file nodes")?;
+            .map(|report| report.inspected)
+    }
 
-        let rewrites: HashSet<_> = new_table_file_nodes
-            .into_iter()
-            .filter_map(|(table, node)| match initial_table_file_nodes.get(&table) {
-                Some(initial_node) if initial_node != &node => Some(table),
+    /// Find the weakest lock that, when held by another session, blocks `statement` on each table
+    /// the statement locks. This precisely characterizes the strongest lock the statement takes
+    /// on the table: for example [Lock::AccessShareLock] only conflicts with
+    /// [Lock::AccessExclusiveLock], whereas a statement that only takes a
+    /// [Lock::RowExclusiveLock], such as an `UPDATE`, is first blocked by a [Lock::ShareLock].
+    ///
+    /// Each table is locked on its own by the locker, first with an `ACCESS EXCLUSIVE` lock to
+    /// check whether the statement locks the table at all, and then with each weaker lock in turn
+    /// from the weakest lock upwards, executing the statement each time to see whether it is
+    /// blocked. The statement is always rolled back, so unlike [QueryOracle::inspect_statement]
+    /// this leaves the database unchanged, but executes the statement several times for every
+    /// table in the database.
+    ///
+    /// Tables that are not locked by the statement are not included.
+    pub async fn classify_required_lock_strength(
+        &mut self,
+        statement: &str,
+    ) -> Result<BTreeMap<TableObject, Lock>, LocksmithError> {
+        check_transaction_control(statement)?;
+        let _permit = self.inspection_permit().await?;
+        self.seed().await?;
+
+        let mut introspector = self.create_introspector().await?;
+        let views: HashSet<_> = introspector
+            .list_view_dependencies()
+            .await
+            .context("Listing view dependencies")?
+            .into_iter()
+            .filter_map(|dependency| match dependency.view {
+                DBObject::Table(view) => Some(view),
                 _ => None,
             })
             .collect();
+        let tables: Vec<_> = introspector
+            .list_tables()
+            .await
+            .context("Listing tables")?
+            .into_iter()
+            .filter(|table| !views.contains(table) && !self.excluded_tables.contains(table))
+            .collect();
+
+        let mut strengths = BTreeMap::new();
+        for table in tables {
+            if !self
+                .is_blocked_by(statement, &table, &Lock::AccessExclusiveLock)
+                .await?
+            {
+                continue;
+            }
+            let mut weakest = Lock::AccessExclusiveLock;
+            for lock in PROBE_LOCKS {
+                if self.is_blocked_by(statement, &table, &lock).await? {
+                    weakest = lock;
+                    break;
+                }
+            }
+            debug!(?table, ?weakest, "Classified required lock strength");
+            strengths.insert(table, weakest);
+        }
+        Ok(strengths)
+    }
+
+    /// Execute `statement` whilst the locker holds `lock` on `table`, returning true if the
+    /// statement was blocked. The statement is always rolled back.
+    async fn is_blocked_by(
+        &self,
+        statement: &str,
+        table: &TableObject,
+        lock: &Lock,
+    ) -> anyhow::Result<bool> {
+        let mut locker = self.create_locker().await?;
+        self.lock_tables(&mut locker, [table], lock).await?;
+        let mut executor = self.create_executor().await?;
+        let is_blocked = executor.execute_statement(statement).await?;
+        executor
+            .abort()
+            .await
+            .context("Aborting executor transaction")?;
+        executor.close().await.context("Closing executor")?;
+        locker.close().await.context("Closing locker")?;
+        Ok(is_blocked)
+    }
+
+    /// The [CacheKey] of the result of inspecting `statement` against the database in its current
+    /// state, with the oracle's current settings.
+    async fn cache_key(&self, statement: &str) -> anyhow::Result<CacheKey> {
+        let mut introspector = self.create_introspector().await?;
+        let mut custom_source = self.lock_schema_source().await;
+        let snapshot = SchemaSnapshot {
+            server_version_num: introspector.server_version_num().await?,
+            objects: schema_source(&mut custom_source, &mut introspector)
+                .list_objects()
+                .await
+                .context("Listing objects")?,
+            file_nodes: vec![],
+        };
+        let mut excluded_tables: Vec<_> = self.excluded_tables.iter().collect();
+        excluded_tables.sort();
+        Ok(CacheKey::new(statement, &snapshot).with((
+            env!("CARGO_PKG_VERSION"),
+            self.lock_conflict_policy,
+            self.include_extension_members,
+            self.include_toast_locks,
+            self.include_predicate_locks,
+            &self.search_path,
+            &self.role,
+            &self.setup_statements,
+            excluded_tables,
+            self.check_idempotency,
+            self.seed_rows,
+            // Tuples only implement `Hash` up to twelve elements, so the settings that change how
+            // locks are detected are grouped.
+            (
+                &self.lock_mode,
+                self.lock_detection,
+                self.repeatable_read_introspection,
+                self.deadlock_timeout,
+            ),
+        )))
+    }
+
+    /// Execute and commit `statement` without inspecting it, leaving the database in the same
+    /// state as [QueryOracle::inspect_statement] would. This is much faster than inspecting it,
+    /// so it can be used for statements whose result is already known, such as migrations that
+    /// have already been inspected, to bring the database up to date before inspecting the next.
+    pub async fn apply_statement(&mut self, statement: &str) -> Result<(), LocksmithError> {
+        check_transaction_control(statement)?;
+        let _permit = self.inspection_permit().await?;
+        Ok(self.apply(statement).await?)
+    }
+
+    /// See [QueryOracle::apply_statement].
+    async fn apply(&mut self, statement: &str) -> anyhow::Result<()> {
+        self.seed().await?;
+        let mut executor = self.create_executor().await?;
+        executor
+            .execute_without_commit(statement)
+            .await
+            .context("Executing statement")?;
+        if executor.commit().await? {
+            bail!("Committing the statement was blocked by another session");
+        }
+        executor.close().await.context("Closing executor")
+    }
+
+    /// Wait for the oracle's [ConnectionPool], if it has one, to allow another inspection.
+    async fn inspection_permit(&self) -> anyhow::Result<Option<OwnedSemaphorePermit>> {
+        match &self.pool {
+            Some(pool) => Ok(Some(pool.inspection_permit().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Seed the tables with [QueryOracleBuilder::seed_rows] rows, if they have not been already.
+    async fn seed(&mut self) -> anyhow::Result<()> {
+        if self.seed_rows > 0 && !self.seeded {
+            let mut seeder = Seeder::new(&self.dsn, &self.tls)
+                .await
+                .context("Creating seeder")?;
+            if let Some(search_path) = &self.search_path {
+                seeder.set_search_path(search_path).await?;
+            }
+            if let Some(name) = &self.application_name {
+                seeder.set_application_name(name).await?;
+            }
+            seeder
+                .seed_tables(self.seed_rows)
+                .await
+                .context("Seeding tables")?;
+            self.seeded = true;
+        }
+        Ok(())
+    }
+
+    /// Create a new introspector connection, with the oracle's settings.
+    async fn create_introspector(&self) -> anyhow::Result<Introspector> {
+        let introspector = match &self.pool {
+            Some(pool) => Introspector::from_pool(pool).await,
+            None => Introspector::new_with_tls(&self.dsn, &self.tls).await,
+        };
+        let mut introspector = introspector
+            .context("Creating introspector")?
+            .with_extension_members(self.include_extension_members);
+        if let Some(search_path) = &self.search_path {
+            introspector.set_search_path(search_path).await?;
+        }
+        if let Some(name) = &self.application_name {
+            introspector.set_application_name(name).await?;
+        }
+        if let Some(role) = &self.role {
+            introspector.set_role(role).await?;
+        }
+        Ok(introspector)
+    }
+
+    /// Inspect a statement, binding `params` to it if they are given. See
+    /// [QueryOracle::inspect_statement] and [QueryOracle::inspect_statement_params].
+    async fn inspect(
+        &mut self,
+        statement: &str,
+        params: Option<&[&(dyn ToSql + Sync)]>,
+    ) -> Result<InspectionReport, LocksmithError> {
+        self.inspect_from(statement, params, None)
+            .await
+            .map(|(report, _)| report)
+    }
+
+    /// Inspect a statement in the same way as [QueryOracle::inspect], starting from the objects
+    /// `before` it rather than listing them if they are given, such as the objects left by the
+    /// previous statement of a migration. Returns the objects after the statement alongside the
+    /// report.
+    ///
+    /// The inspection fails with a [LocksmithError::Timeout] once the
+    /// [timeout](QueryOracleBuilder::timeout) has passed on the oracle's clock. It is stopped by
+    /// dropping it, which closes its connections and cancels the statement if it is executing.
+    async fn inspect_from(
+        &mut self,
+        statement: &str,
+        params: Option<&[&(dyn ToSql + Sync)]>,
+        before: Option<ObjectState>,
+    ) -> Result<(InspectionReport, ObjectState), LocksmithError> {
+        let mut detected = DetectedLocks::default();
+        let Some(timeout) = self.timeout else {
+            return self
+                .run_inspection(statement, params, before, &mut detected)
+                .await;
+        };
+        let clock = self.clock.clone();
+        let start = clock.now();
+        let inspection = Box::pin(self.run_inspection(statement, params, before, &mut detected));
+        let inspection = match future::select(inspection, clock.sleep_until(start + timeout)).await
+        {
+            Either::Left((result, _)) => return result,
+            Either::Right(((), inspection)) => inspection,
+        };
+        // The inspection borrows the detected locks until it is dropped.
+        drop(inspection);
+        Err(LocksmithError::Timeout {
+            partial: detected.partial(),
+            elapsed: clock.now() - start,
+            timeout,
+        })
+    }
+
+    /// Inspect a statement as described in [QueryOracle::inspect_from], adding the side effects
+    /// to `detected` as they are discovered.
+    async fn run_inspection(
+        &mut self,
+        statement: &str,
+        params: Option<&[&(dyn ToSql + Sync)]>,
+        before: Option<ObjectState>,
+        detected: &mut DetectedLocks,
+    ) -> Result<(InspectionReport, ObjectState), LocksmithError> {
+        check_transaction_control(statement)?;
+        self.seed().await?;
+
+        // Create an inspector, and list the initial objects in the database.
+        let mut introspector = self.create_introspector().await?;
+        let mut custom_source = self.lock_schema_source().await;
+        if self.repeatable_read_introspection {
+            introspector.begin_repeatable_read().await?;
+        }
+        let before = match before {
+            Some(before) => before,
+            None => ObjectState {
+                objects: schema_source(&mut custom_source, &mut introspector)
+                    .list_objects()
+                    .await
+                    .context("Listing initial objects")?,
+                file_nodes: schema_source(&mut custom_source, &mut introspector)
+                    .list_object_file_nodes()
+                    .await
+                    .context("Listing object file nodes")?,
+            },
+        };
+        let ObjectState {
+            objects: initial_objects,
+            file_nodes: initial_table_file_nodes,
+        } = before;
+        let table_sizes = introspector
+            .list_table_sizes()
+            .await
+            .context("Listing table sizes")?;
+        let view_dependencies = introspector
+            .list_view_dependencies()
+            .await
+            .context("Listing view dependencies")?;
+        if self.repeatable_read_introspection {
+            introspector.commit().await?;
+        }
+
+        // Retrieve the set of initial tables to lock.
+        let all_tables =
+            lockable_tables(&initial_objects, &view_dependencies, &self.excluded_tables);
+
+        if all_tables.is_empty() {
+            warn!("The database does not contain any tables. Check that the schema was loaded.");
+        }
+
+        // Locks on foreign tables are taken from the locks held by the executor once the
+        // statement has executed.
+        let foreign_tables = foreign_tables(&initial_objects);
+
+        // Detect the locks taken by the statement. From this point onwards the statement is being
+        // executed, so any error carries the locks that have been detected so far.
+        let unresolvable = |detected: &DetectedLocks, source| LocksmithError::Unresolvable {
+            partial: detected.partial(),
+            source,
+        };
+        if let Err(source) = self
+            .detect_locks(
+                Statement {
+                    sql: statement,
+                    params,
+                },
+                &all_tables,
+                &foreign_tables,
+                detected,
+            )
+            .await
+        {
+            if all_tables.is_empty() && is_undefined_table(&source) {
+                return Err(LocksmithError::EmptySchema { source });
+            }
+            if let Some(UnobservableLock { table, detail }) = source.downcast_ref() {
+                return Err(LocksmithError::UnobservableLock {
+                    partial: detected.partial(),
+                    table: table.clone(),
+                    detail: detail.clone(),
+                });
+            }
+            if let Some(&IterationLimit(iterations)) = source.downcast_ref() {
+                return Err(LocksmithError::IterationLimit {
+                    partial: detected.partial(),
+                    iterations,
+                });
+            }
+            if is_invalid_transaction_termination(&source) {
+                let source = source.context(
+                    "The statement called a procedure that commits or rolls back, which is not \
+                     supported because statements are inspected within a transaction",
+                );
+                return Err(unresolvable(detected, source));
+            }
+            if let Some(dependents) = dependent_objects(&source) {
+                return Err(LocksmithError::DependentObjects {
+                    partial: detected.partial(),
+                    dependents,
+                    source,
+                });
+            }
+            return Err(unresolvable(detected, source));
+        }
+
+        // Take a snapshot of the objects in the database after the statement has executed
+        if self.repeatable_read_introspection {
+            introspector
+                .begin_repeatable_read()
+                .await
+                .map_err(|e| unresolvable(detected, e.into()))?;
+        }
+        let new_objects: HashSet<_> = schema_source(&mut custom_source, &mut introspector)
+            .list_objects()
+            .await
+            .context("Listing new objects")
+            .map_err(|e| unresolvable(detected, e))?;
+
+        let new_table_file_nodes = schema_source(&mut custom_source, &mut introspector)
+            .list_object_file_nodes()
+            .await
+            .context("Listing new table file nodes")
+            .map_err(|e| unresolvable(detected, e))?;
+        if self.repeatable_read_introspection {
+            introspector
+                .commit()
+                .await
+                .map_err(|e| unresolvable(detected, e.into()))?;
+        }
+
+        // Execute the statement again against the state it left behind. It is idempotent if this
+        // succeeds and leaves the same objects as the first execution.
+        let idempotent = if self.check_idempotency && params.is_none() {
+            let objects = introspector
+                .list_objects_after(statement)
+                .await
+                .context("Executing statement again")
+                .map_err(|e| unresolvable(detected, e))?;
+            Some(objects.is_some_and(|objects| objects == new_objects))
+        } else {
+            None
+        };
+
+        let detected = std::mem::take(detected);
+        let after = ObjectState {
+            objects: new_objects.clone(),
+            file_nodes: new_table_file_nodes.clone(),
+        };
+        let observation = StatementObservation {
+            objects_before: initial_objects,
+            objects_after: new_objects,
+            file_nodes_before: initial_table_file_nodes,
+            file_nodes_after: new_table_file_nodes,
+            table_sizes,
+            view_dependencies,
+            locks: detected.locks,
+            commit_locks: detected.commit_locks,
+            unverified_locks: detected.unverified_locks,
+            scanned_tables: detected.scanned_tables,
+            affected_rows: detected.affected_rows,
+            predicate_locks: detected.predicate_locks,
+        };
+        let report = InspectionReport {
+            inspected: InspectedStatement {
+                idempotent,
+                ..observation.analyze()
+            },
+            lock_timeline: detected.timeline,
+        };
+        Ok((report, after))
+    }
+
+    /// Lock the source set via [QueryOracleBuilder::schema_source], if any, for the duration of an
+    /// inspection.
+    async fn lock_schema_source(&self) -> Option<MutexGuard<'_, Box<dyn SchemaSource>>> {
+        match &self.schema_source {
+            Some(source) => Some(source.lock().await),
+            None => None,
+        }
+    }
+
+    /// Create a new locker connection, with the oracle's settings.
+    async fn create_locker(&self) -> anyhow::Result<Locker> {
+        let locker = match &self.pool {
+            Some(pool) => Locker::from_pool(pool).await,
+            None => Locker::new_with_tls(&self.dsn, &self.tls).await,
+        };
+        let mut locker = locker
+            .context("Creating locker")?
+            .with_toast_locks(self.include_toast_locks);
+        if let Some(search_path) = &self.search_path {
+            locker.set_search_path(search_path).await?;
+        }
+        if let Some(name) = &self.application_name {
+            locker.set_application_name(name).await?;
+        }
+        if let Some(role) = &self.role {
+            locker.set_role(role).await?;
+        }
+        Ok(locker)
+    }
+
+    /// Create a new executor connection, with the oracle's settings.
+    async fn create_executor(&self) -> anyhow::Result<StatementExecutor> {
+        let mut executor = StatementExecutor::new_with_tls(&self.dsn, &self.tls)
+            .await
+            .context("Creating executor")?
+            .with_clock(self.clock.clone());
+        if let Some(search_path) = &self.search_path {
+            executor.set_search_path(search_path).await?;
+        }
+        if let Some(name) = &self.application_name {
+            executor.set_application_name(name).await?;
+        }
+        if let Some(timeout) = self.deadlock_timeout {
+            executor.set_deadlock_timeout(timeout).await?;
+        }
+        if let Some(role) = &self.role {
+            executor.set_role(role).await?;
+        }
+        for statement in &self.setup_statements {
+            executor.execute_setup_statements(statement).await?;
+        }
+        Ok(executor)
+    }
+
+    /// Lock `tables` with `locker`, using `lock` and following the [LockConflictPolicy]. Fails
+    /// with the tables that cannot be locked if the [QueryOracleBuilder::role] role does not have
+    /// the privileges to lock them.
+    async fn lock_tables<'a>(
+        &self,
+        locker: &mut Locker,
+        tables: impl IntoIterator<Item = &'a TableObject>,
+        lock: &Lock,
+    ) -> anyhow::Result<()> {
+        let tables: Vec<_> = tables.into_iter().collect();
+        if let Some(role) = &self.role {
+            let forbidden = locker
+                .tables_without_lock_privilege(tables.iter().copied(), lock)
+                .await?;
+            if !forbidden.is_empty() {
+                bail!(
+                    "Role {role} does not have the privileges to lock {}, which requires \
+                     ownership or UPDATE, DELETE or TRUNCATE privileges on each table",
+                    forbidden
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        match self.lock_conflict_policy {
+            LockConflictPolicy::Wait => Ok(locker.lock_tables_in_mode(tables, lock).await?),
+            policy => {
+                let unlockable = locker.try_lock_tables_in_mode(tables, lock).await?;
+                if !unlockable.is_empty() {
+                    if policy == LockConflictPolicy::Error {
+                        bail!("Tables are locked by another session: {unlockable:?}");
+                    }
+                    warn!(?unlockable, "Skipping tables locked by another session");
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Repeatedly lock tables and execute the statement until it is no longer blocked, adding the
+    /// side effects to `detected` as they are discovered. See [QueryOracle::inspect_statement]
+    /// for details of the algorithm.
+    async fn detect_locks(
+        &self,
+        statement: Statement<'_>,
+        all_tables: &HashSet<&TableObject>,
+        foreign_tables: &HashSet<TableObject>,
+        detected: &mut DetectedLocks,
+    ) -> anyhow::Result<()> {
+        // This implements the main loop of the algorithm.
+        // Here we repeatedly lock tables and execute the statement until it is no longer blocked.
+        let start = self.clock.now();
+        let mut iterations = 0;
+        let mut restarts = 0;
+        loop {
+            iterations += 1;
+            // Create a set of tables to lock that we have not yet observed requiring a lock.
+            let known_locked_table: HashSet<_> = detected.locks.iter().map(|t| &t.table).collect();
+            let tables_to_lock: HashSet<&TableObject> = all_tables
+                .iter()
+                .copied()
+                .filter(|table| !known_locked_table.contains(table))
+                .collect();
+
+            // Create a new "locker" connection and lock those tables
+            let mut locker = self.create_locker().await?;
+            self.lock_tables(&mut locker, tables_to_lock.iter().copied(), &self.lock_mode)
+                .await?;
+
+            // Create a statement executor and retrieve its connection ID
+            let mut executor = self.create_executor().await?;
+            let connection_id = executor.connection_id();
+            debug!("Executor created with connection ID {connection_id:?}");
+
+            // Execute the statement, returning true if the statement has been blocked by
+            // a lock taken by the locker connection.
+            // If the executor's backend was terminated by another session, such as by an admin
+            // running `pg_terminate_backend`, the statement is rolled back along with it. The
+            // loop restarts with a new executor, keeping the locks detected so far.
+            let is_blocked = match statement.execute(&mut executor).await {
+                Err(e) if self.should_restart_executor(&e, &mut restarts) => {
+                    locker.close().await.context("Closing locker")?;
+                    continue;
+                }
+                result => result?,
+            };
 
-        Ok(InspectedStatement {
-            added_objects,
-            removed_objects,
-            locks: all_detected_locks,
-            rewrites,
+            // If the statement executed without being blocked then commit it, which can take
+            // further locks such as when checking deferred constraints. The locks held before
+            // committing belong to the statement, so any others are attributed to the commit.
+            let mut statement_locks = HashSet::new();
+            let is_commit_blocked = if is_blocked {
+                false
+            } else {
+                statement_locks = locker
+                    .list_connection_locks(connection_id)
+                    .await
+                    .context("Listing statement locks")?
+                    .into_iter()
+                    .collect();
+                detected.scanned_tables = executor
+                    .list_scanned_tables()
+                    .await
+                    .context("Listing scanned tables")?;
+                detected.affected_rows = executor.affected_rows();
+                if self.include_predicate_locks {
+                    detected.predicate_locks = locker
+                        .list_predicate_locks(connection_id)
+                        .await
+                        .context("Listing predicate locks")?
+                        .into_iter()
+                        .collect();
+                }
+                executor.commit().await?
+            };
+
+            // If the statement and the commit both executed without any blocking, we can break
+            // the loop
+            if !is_blocked && !is_commit_blocked {
+                info!(iterations, "Statement executed successfully");
+                detected.unverified_locks.extend(
+                    statement_locks
+                        .iter()
+                        .filter(|lock| self.excluded_tables.contains(&lock.table))
+                        .cloned(),
+                );
+                // Locks on foreign tables, and locks on locked tables that do not conflict with a
+                // lock mode weaker than ACCESS EXCLUSIVE, are only seen once the statement has
+                // executed.
+                detected.extend(
+                    statement_locks.into_iter().filter(|lock| {
+                        foreign_tables.contains(&lock.table) || tables_to_lock.contains(&lock.table)
+                    }),
+                    self.clock.now() - start,
+                );
+                executor.close().await.context("Closing executor")?;
+                locker.close().await.context("Closing locker")?;
+                break;
+            }
+
+            // Read the lock that the statement is blocked on, unless it was cancelled and is no
+            // longer waiting for it.
+            let awaited_lock = if self.lock_detection == LockDetection::AwaitedLock
+                && executor.timed_out_wait().is_none()
+            {
+                Some(
+                    self.awaited_lock(&mut locker, connection_id, &tables_to_lock)
+                        .await?,
+                )
+            } else {
+                None
+            };
+
+            // List all locks that are taken by the executor connection and add them to our
+            // set of seen locks. Locks on excluded tables were not forced by the locker, so they
+            // are kept apart as unverified.
+            let mut connection_locks = locker
+                .list_connection_locks(connection_id)
+                .await
+                .context("Listing connection locks")?;
+            let mut blocked_on = awaited_lock.clone();
+            if let Some(awaited_lock) = awaited_lock
+                && !connection_locks.contains(&awaited_lock)
+            {
+                connection_locks.push(awaited_lock);
+            }
+            // A statement cancelled by `lock_timeout` is no longer waiting for the lock that
+            // blocked it, so that lock is taken from the executor instead.
+            if let Some(wait) = executor.timed_out_wait() {
+                info!(
+                    lock = %wait.lock,
+                    "Statement was cancelled by lock_timeout, treating it as blocked"
+                );
+                let Some(table) = locker
+                    .relation_table(wait.relation)
+                    .await
+                    .context("Retrieving the table the statement was waiting for")?
+                else {
+                    return Err(UnobservableLock {
+                        table: None,
+                        detail: format!(
+                            "Statement was cancelled by lock_timeout whilst waiting for relation \
+                             {}, which is not a table outside the system catalogs",
+                            wait.relation
+                        ),
+                    }
+                    .into());
+                };
+                let timed_out_lock = TableLock {
+                    table,
+                    lock: wait.lock.clone(),
+                };
+                blocked_on = Some(timed_out_lock.clone());
+                connection_locks.push(timed_out_lock);
+            }
+            let known_locks = detected.locks.len() + detected.unverified_locks.len();
+            let (unverified_locks, new_locks): (Vec<_>, Vec<_>) = connection_locks
+                .into_iter()
+                .partition(|lock| self.excluded_tables.contains(&lock.table));
+            detected.unverified_locks.extend(unverified_locks);
+            debug!(?new_locks, "Detected {} new locks", new_locks.len());
+            if is_commit_blocked {
+                detected.commit_locks.extend(
+                    new_locks
+                        .iter()
+                        .filter(|lock| !statement_locks.contains(*lock))
+                        .cloned(),
+                );
+            }
+            detected.extend(new_locks, self.clock.now() - start);
+
+            // A statement that is blocked without taking any lock that has not been detected
+            // before is not blocked by the locker, which only locks tables without a detected
+            // lock. Executing it again would block in the same way, so fail instead.
+            if detected.locks.len() + detected.unverified_locks.len() == known_locks {
+                let blocked_on = match blocked_on {
+                    Some(lock) => Some(lock),
+                    None => locker
+                        .awaited_lock(connection_id)
+                        .await
+                        .context("Retrieving the lock the statement is waiting for")?,
+                };
+                executor
+                    .abort()
+                    .await
+                    .context("Aborting executor transaction")?;
+                return Err(UnobservableLock::blocked_on(blocked_on).into());
+            }
+
+            // Abort the executor's transaction before the locker is dropped and its locks are
+            // released. Otherwise a blocked commit would complete once the lock is released.
+            // A terminated backend has already rolled back its transaction, so there is nothing
+            // left to abort.
+            match executor.abort().await {
+                Err(e) if self.should_restart_executor(&e, &mut restarts) => {}
+                result => result.context("Aborting executor transaction")?,
+            }
+            executor.close().await.context("Closing executor")?;
+            locker.close().await.context("Closing locker")?;
+
+            if let Some(max_iterations) = self.max_iterations
+                && iterations >= max_iterations
+            {
+                return Err(IterationLimit(iterations).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if `error` was caused by the executor's backend being terminated, and the
+    /// loop in [QueryOracle::detect_locks] has restarted fewer than [MAX_EXECUTOR_RESTARTS] times,
+    /// counting the restart in `restarts`.
+    fn should_restart_executor(&self, error: &LocksmithError, restarts: &mut u32) -> bool {
+        if *restarts >= MAX_EXECUTOR_RESTARTS || !is_terminated_connection(error) {
+            return false;
+        }
+        *restarts += 1;
+        warn!(
+            restarts,
+            "Executor connection was terminated, reconnecting and resuming: {error:#}"
+        );
+        true
+    }
+
+    /// Poll `pg_locks` until the blocked executor with `connection_id` is seen waiting for a lock,
+    /// returning the lock. See [LockDetection::AwaitedLock].
+    ///
+    /// Fails if the executor is not waiting for a lock on a table, or if it is waiting for a lock
+    /// on a table that is not in `locked_tables` and so is not held by the locker.
+    async fn awaited_lock(
+        &self,
+        locker: &mut Locker,
+        connection_id: ConnectionID,
+        locked_tables: &HashSet<&TableObject>,
+    ) -> anyhow::Result<TableLock> {
+        let mut polls = 0;
+        let awaited_lock = loop {
+            polls += 1;
+            let awaited_lock = locker
+                .awaited_lock(connection_id)
+                .await
+                .context("Retrieving the lock the statement is waiting for")?;
+            match awaited_lock {
+                Some(awaited_lock) => break awaited_lock,
+                None if polls < AWAITED_LOCK_POLLS => {
+                    self.clock
+                        .sleep_until(self.clock.now() + AWAITED_LOCK_POLL_INTERVAL)
+                        .await
+                }
+                None => return Err(UnobservableLock::blocked_on(None).into()),
+            }
+        };
+        debug!(?awaited_lock, polls, "Statement is waiting for lock");
+        // Tables skipped by the conflict policy are locked by another session, so statements are
+        // expected to be blocked on them.
+        if !locked_tables.contains(&awaited_lock.table)
+            && self.lock_conflict_policy != LockConflictPolicy::Skip
+        {
+            return Err(UnobservableLock::blocked_on(Some(awaited_lock)).into());
+        }
+        Ok(awaited_lock)
+    }
+}
+
+/// The number of times [LockDetection::AwaitedLock] reads `pg_locks` for the lock that a blocked
+/// statement is waiting for, [AWAITED_LOCK_POLL_INTERVAL] apart, before failing.
+const AWAITED_LOCK_POLLS: u32 = 10;
+const AWAITED_LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The error that [QueryOracle::detect_locks] fails with when the statement is blocked by a lock
+/// that the locker does not hold, which is returned as a [LocksmithError::UnobservableLock].
+#[derive(Debug)]
+struct UnobservableLock {
+    table: Option<TableObject>,
+    detail: String,
+}
+
+impl UnobservableLock {
+    /// The statement is blocked waiting for `awaited_lock`, or for a lock that is not on a table
+    /// if it is `None`.
+    fn blocked_on(awaited_lock: Option<TableLock>) -> Self {
+        match awaited_lock {
+            Some(lock) => UnobservableLock {
+                detail: format!(
+                    "Statement is waiting for {} on `{}`, which is not held by the locker. The \
+                     table is locked by another session.",
+                    lock.lock, lock.table
+                ),
+                table: Some(lock.table),
+            },
+            None => UnobservableLock {
+                table: None,
+                detail: "Statement is blocked, but is not waiting for a lock on a table. It may \
+                         be waiting for a row locked by another transaction, or for a lock on a \
+                         system catalog."
+                    .to_string(),
+            },
+        }
+    }
+}
+
+impl Display for UnobservableLock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+impl std::error::Error for UnobservableLock {}
+
+/// The error that [QueryOracle::detect_locks] fails with when the statement is still blocked
+/// after the [maximum number of iterations](QueryOracleBuilder::max_iterations), which is
+/// returned as a [LocksmithError::IterationLimit].
+#[derive(Debug)]
+struct IterationLimit(u32);
+
+impl Display for IterationLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Statement was still blocked after {} iterations", self.0)
+    }
+}
+
+impl std::error::Error for IterationLimit {}
+
+/// The number of times [QueryOracle::detect_locks] creates a new executor after its backend was
+/// terminated by another session, before failing.
+const MAX_EXECUTOR_RESTARTS: u32 = 3;
+
+/// The locks held by the locker in [QueryOracle::classify_required_lock_strength], from the
+/// weakest to the strongest, excluding [Lock::AccessExclusiveLock] which is always checked first.
+const PROBE_LOCKS: [Lock; 7] = [
+    Lock::AccessShareLock,
+    Lock::RowShareLock,
+    Lock::RowExclusiveLock,
+    Lock::ShareUpdateExclusiveLock,
+    Lock::ShareLock,
+    Lock::ShareRowExclusiveLock,
+    Lock::ExclusiveLock,
+];
+
+/// The objects in the database and the file nodes of its tables at a point in time, as listed by
+/// an [Introspector].
+struct ObjectState {
+    objects: HashSet<DBObject>,
+    file_nodes: HashMap<DBObject, i32>,
+}
+
+/// The side effects of a statement detected by [QueryOracle::detect_locks].
+#[derive(Default)]
+struct DetectedLocks {
+    locks: HashSet<TableLock>,
+    commit_locks: HashSet<TableLock>,
+    unverified_locks: HashSet<TableLock>,
+    /// The tables scanned by the final, unblocked execution of the statement.
+    scanned_tables: HashSet<TableObject>,
+    /// The rows modified by the final, unblocked execution of the statement.
+    affected_rows: Option<u64>,
+    /// The predicate locks held by the final, unblocked execution of the statement.
+    predicate_locks: HashSet<PredicateLock>,
+    /// The locks in `locks`, in the order they were detected.
+    timeline: Vec<(TableLock, Duration)>,
+}
+
+impl DetectedLocks {
+    /// The locks detected so far, as the partial result of an inspection that failed before all
+    /// of the statement's side effects were resolved.
+    fn partial(&self) -> Box<InspectedStatement> {
+        Box::new(InspectedStatement {
+            locks: self.locks.clone(),
+            commit_locks: self.commit_locks.clone(),
+            unverified_locks: self.unverified_locks.clone(),
+            ..Default::default()
         })
     }
+
+    /// Add `locks` to the detected locks, recording any that have not been detected before in the
+    /// timeline as being detected `elapsed` after the statement was first executed.
+    fn extend(&mut self, locks: impl IntoIterator<Item = TableLock>, elapsed: Duration) {
+        let mut new_locks: Vec<_> = locks
+            .into_iter()
+            .filter(|lock| self.locks.insert(lock.clone()))
+            .collect();
+        new_locks.sort();
+        self.timeline
+            .extend(new_locks.into_iter().map(|lock| (lock, elapsed)));
+    }
+}
+
+/// A statement being inspected, with the bind parameters to execute it with, if any.
+#[derive(Clone, Copy)]
+struct Statement<'a> {
+    sql: &'a str,
+    params: Option<&'a [&'a (dyn ToSql + Sync)]>,
+}
+
+impl Statement<'_> {
+    /// Execute the statement with `executor`, returning true if it was blocked by a lock.
+    async fn execute(&self, executor: &mut StatementExecutor) -> Result<bool, LocksmithError> {
+        match self.params {
+            Some(params) => {
+                executor
+                    .execute_statement_with_params(self.sql, params)
+                    .await
+            }
+            None => executor.execute_statement(self.sql).await,
+        }
+    }
+}
+
+/// The [SchemaSource] that objects are listed from: the source set via
+/// [QueryOracleBuilder::schema_source] if there is one, or else `introspector`.
+fn schema_source<'a>(
+    custom_source: &'a mut Option<MutexGuard<'_, Box<dyn SchemaSource>>>,
+    introspector: &'a mut Introspector,
+) -> &'a mut dyn SchemaSource {
+    match custom_source {
+        Some(source) => source.as_mut(),
+        None => introspector,
+    }
+}
+
+/// Fail if `statement` contains a command that ends the executor's transaction, such as `COMMIT`,
+/// or that cannot run inside a transaction, such as `DROP INDEX CONCURRENTLY`. The oracle relies
+/// on every execution of the statement being rolled back or committed as a whole, so these would
+/// leave the database partially changed and the detected locks incomplete.
+fn check_transaction_control(statement: &str) -> anyhow::Result<()> {
+    if let Some(command) = transaction_control_command(statement) {
+        bail!(
+            "The statement contains a `{command}` command, which is not supported because \
+             statements are inspected within a transaction. Remove it, or use savepoints instead."
+        );
+    }
+    if uses_concurrently(statement) {
+        bail!(
+            "The statement uses `CONCURRENTLY`, which cannot run inside a transaction and so cannot \
+             be inspected. Concurrent operations only take a ShareUpdateExclusiveLock on the \
+             table, which does not block reads or writes. Inspect the statement without \
+             `CONCURRENTLY` to see the objects it changes."
+        );
+    }
+    Ok(())
+}
+
+/// Returns `true` if `error` was caused by a statement referencing a table that does not exist.
+fn is_undefined_table(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<tokio_postgres::Error>())
+        .any(|e| e.code() == Some(&SqlState::UNDEFINED_TABLE))
+}
+
+/// Returns `true` if `error` was caused by a procedure committing or rolling back the
+/// transaction it was called in.
+fn is_invalid_transaction_termination(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<tokio_postgres::Error>())
+        .any(|e| e.code() == Some(&SqlState::INVALID_TRANSACTION_TERMINATION))
+}
+
+/// Returns true if `error` was caused by the connection's backend being terminated by another
+/// session, such as with `pg_terminate_backend`, or by the connection being closed.
+fn is_terminated_connection(error: &LocksmithError) -> bool {
+    let LocksmithError::Connection { source } = error else {
+        return false;
+    };
+    source
+        .chain()
+        .filter_map(|e| e.downcast_ref::<tokio_postgres::Error>())
+        .any(|e| e.code() == Some(&SqlState::ADMIN_SHUTDOWN) || e.is_closed())
+}
+
+/// If `error` was caused by dropping an object that other objects depend on without `CASCADE`,
+/// return the description of each dependent object given in the error's detail.
+fn dependent_objects(error: &anyhow::Error) -> Option<Vec<String>> {
+    let error = error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<tokio_postgres::Error>())
+        .find(|e| e.code() == Some(&SqlState::DEPENDENT_OBJECTS_STILL_EXIST))?;
+    let detail = error.as_db_error().and_then(|e| e.detail()).unwrap_or("");
+    Some(detail.lines().map(str::to_string).collect())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::tests::start_test_postgres;
+    use crate::executor::BLOCK_GRACE_PERIOD;
+    use crate::tests::{MockClock, lock_tables, start_test_postgres};
 
-    use crate::InspectedStatement;
-    use crate::oracle::QueryOracle;
+    use crate::SchemaSource;
+    use crate::introspection::Introspector;
+    use crate::oracle::{BaselineComparison, LockConflictPolicy, LockDetection, QueryOracle};
+    use crate::{
+        ColumnObject, DBObject, ForeignTableObject, IndexObject, InheritanceObject,
+        InspectedStatement, Lock, LockCost, MaterializedViewObject, PredicateLock,
+        PredicateLockGranularity, SequenceObject, TableLock, TableLockCost, TableObject,
+        TableOperation,
+    };
+    use crate::{LintRule, LocksmithError, MemoryCache};
+    use futures::StreamExt;
+    use futures::future::BoxFuture;
+    use sqlx::{Connection, Executor, PgConnection};
+    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::sync::Arc;
+    use std::time::Duration;
 
     use tracing_test::traced_test;
 
+    #[test]
+    fn test_compare_to_baseline() {
+        let lock = |table: &str, lock: Lock| TableLock {
+            table: table.into(),
+            lock,
+        };
+        let baseline = InspectedStatement {
+            locks: HashSet::from([lock("orders", Lock::AccessExclusiveLock)]),
+            ..Default::default()
+        };
+        let inspected = InspectedStatement {
+            locks: HashSet::from([
+                lock("orders", Lock::AccessExclusiveLock),
+                lock("customers", Lock::ShareLock),
+            ]),
+            rewrites: HashSet::from([DBObject::Table("orders".into())]),
+            ..Default::default()
+        };
+
+        assert!(inspected.compare_to_baseline(&inspected).is_empty());
+        assert_eq!(
+            inspected.compare_to_baseline(&baseline),
+            BaselineComparison {
+                new_locks: HashSet::from([lock("customers", Lock::ShareLock)]),
+                new_rewrites: HashSet::from([DBObject::Table("orders".into())]),
+            }
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_sorted() {
+        let tables: Vec<_> = (0..20).map(|i| format!("table_{i:02}")).collect();
+        let statement = |tables: &mut dyn Iterator<Item = &String>| InspectedStatement {
+            locks: tables
+                .map(|table| TableLock {
+                    table: table.as_str().into(),
+                    lock: Lock::AccessExclusiveLock,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let serialized = serde_json::to_string(&statement(&mut tables.iter())).unwrap();
+        let reversed = serde_json::to_string(&statement(&mut tables.iter().rev())).unwrap();
+        assert_eq!(serialized, reversed);
+
+        let value: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        let locked: Vec<_> = value["locks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|lock| lock["table"]["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(locked, tables);
+    }
+
+    #[test]
+    fn test_strongest_lock_per_table() {
+        let lock = |table: &str, lock: Lock| TableLock {
+            table: table.into(),
+            lock,
+        };
+        let inspected = InspectedStatement {
+            locks: HashSet::from([
+                lock("orders", Lock::RowExclusiveLock),
+                lock("orders", Lock::AccessExclusiveLock),
+                lock("orders", Lock::ShareLock),
+                lock("customers", Lock::RowShareLock),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            inspected.strongest_lock_per_table(),
+            BTreeMap::from([
+                ("customers".into(), Lock::RowShareLock),
+                ("orders".into(), Lock::AccessExclusiveLock),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let lock = |table: &str, lock: Lock| TableLock {
+            table: table.into(),
+            lock,
+        };
+        let inspected = InspectedStatement {
+            locks: HashSet::from([
+                lock("orders", Lock::ShareLock),
+                lock("customers", Lock::AccessShareLock),
+            ]),
+            ..Default::default()
+        };
+        let application_locks = [
+            lock("orders", Lock::AccessShareLock),
+            lock("orders", Lock::RowExclusiveLock),
+            lock("customers", Lock::RowExclusiveLock),
+            lock("customers", Lock::AccessExclusiveLock),
+            lock("products", Lock::AccessExclusiveLock),
+        ];
+        assert_eq!(
+            inspected.blocks(&application_locks),
+            vec![
+                lock("orders", Lock::RowExclusiveLock),
+                lock("customers", Lock::AccessExclusiveLock),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_can_run_concurrently() {
+        let lock = |table: &str, lock: Lock| TableLock {
+            table: table.into(),
+            lock,
+        };
+        let statement = |locks: &[TableLock]| InspectedStatement {
+            locks: locks.iter().cloned().collect(),
+            ..Default::default()
+        };
+        let create_index = statement(&[lock("orders", Lock::ShareLock)]);
+        let alter_orders = statement(&[lock("orders", Lock::AccessExclusiveLock)]);
+        let alter_customers = statement(&[lock("customers", Lock::AccessExclusiveLock)]);
+        let validate = statement(&[
+            lock("orders", Lock::ShareUpdateExclusiveLock),
+            lock("customers", Lock::RowShareLock),
+        ]);
+
+        assert!(create_index.can_run_concurrently(&create_index));
+        assert!(alter_orders.can_run_concurrently(&alter_customers));
+        assert!(!create_index.can_run_concurrently(&alter_orders));
+        assert!(!alter_orders.can_run_concurrently(&create_index));
+        assert!(!validate.can_run_concurrently(&validate));
+        assert!(!validate.can_run_concurrently(&alter_customers));
+        assert!(create_index.can_run_concurrently(&InspectedStatement::default()));
+
+        // Unverified and unknown locks are included.
+        let unverified = InspectedStatement {
+            unverified_locks: HashSet::from([lock("orders", Lock::Unknown("x".to_string()))]),
+            ..Default::default()
+        };
+        assert!(!create_index.can_run_concurrently(&unverified));
+        assert!(!unverified.can_run_concurrently(&create_index));
+        assert!(unverified.can_run_concurrently(&alter_customers));
+    }
+
+    #[test]
+    fn test_table_operations() {
+        let cost = |table: &str, cost: LockCost| TableLockCost {
+            table: table.into(),
+            cost,
+            total_bytes: 8192,
+            estimated_rows: None,
+        };
+        // Validating a constraint on orders, which scans orders and briefly locks customers.
+        let statement = InspectedStatement {
+            locks: HashSet::from([
+                TableLock {
+                    table: "orders".into(),
+                    lock: Lock::ShareUpdateExclusiveLock,
+                },
+                TableLock {
+                    table: "orders".into(),
+                    lock: Lock::AccessShareLock,
+                },
+                TableLock {
+                    table: "customers".into(),
+                    lock: Lock::AccessExclusiveLock,
+                },
+            ]),
+            lock_costs: HashSet::from([cost("orders", LockCost::Scan)]),
+            ..Default::default()
+        };
+        let operations = statement.table_operations();
+        assert_eq!(
+            operations,
+            vec![
+                TableOperation {
+                    table: "customers".into(),
+                    lock: Lock::AccessExclusiveLock,
+                    cost: LockCost::Instant,
+                },
+                TableOperation {
+                    table: "orders".into(),
+                    lock: Lock::ShareUpdateExclusiveLock,
+                    cost: LockCost::Scan,
+                },
+            ]
+        );
+        assert!(operations[0].blocks_reads() && !operations[0].scales_with_table_size());
+        assert!(!operations[1].blocks_writes() && operations[1].scales_with_table_size());
+        assert_eq!(
+            operations[0].to_string(),
+            "`AccessExclusiveLock` held briefly, which blocks reads and writes"
+        );
+
+        let scan_under_share_lock = TableOperation {
+            table: "orders".into(),
+            lock: Lock::ShareLock,
+            cost: LockCost::Scan,
+        };
+        assert!(scan_under_share_lock.blocks_writes() && !scan_under_share_lock.blocks_reads());
+        assert_eq!(
+            scan_under_share_lock.to_string(),
+            "`ShareLock` held for a full table scan, which blocks writes for time proportional to \
+             the size of the table"
+        );
+    }
+
     #[traced_test]
     #[tokio::test]
     async fn test_simple_inspect_statement() {
@@ -223,4 +2436,1756 @@ mod tests {
         let result = oracle.inspect_statement("select 1;").await.unwrap();
         assert_eq!(result, InspectedStatement::default())
     }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_returns_partial_result() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        // The lock on orders is detected in the first iteration, before the second query fails
+        // in the second iteration.
+        let err = oracle
+            .inspect_statement("select * from orders; select * from missing;")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LocksmithError::Unresolvable { .. }));
+        assert_eq!(
+            err.partial().unwrap().locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessShareLock,
+            }])
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_set_tablespace() {
+        let (_container, dsn) = start_test_postgres().await;
+        // Tablespaces need an existing directory owned by the server, and cannot be created
+        // within a transaction.
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        for statement in [
+            "copy (select 1) to program 'mkdir -p /tmp/locksmith_fast'",
+            "create tablespace fast location '/tmp/locksmith_fast'",
+        ] {
+            sqlx::raw_sql(statement).execute(&mut conn).await.unwrap();
+        }
+
+        // Moving a table to another tablespace copies it to a new file node.
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("alter table orders set tablespace fast;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.rewrites,
+            HashSet::from([DBObject::Table("orders".into())])
+        );
+        assert!(result.locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_inheritance() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        oracle
+            .inspect_statement(
+                "create table events (id int);
+                 create table events_2024 () inherits (events);
+                 create table events_2025 () inherits (events);",
+            )
+            .await
+            .unwrap();
+
+        // Adding a column to the parent also adds it to every child table.
+        let result = oracle
+            .inspect_statement("alter table events add column payload text;")
+            .await
+            .unwrap();
+        let locks: HashSet<_> = ["events", "events_2024", "events_2025"]
+            .into_iter()
+            .map(|table| TableLock {
+                table: table.into(),
+                lock: Lock::AccessExclusiveLock,
+            })
+            .collect();
+        assert_eq!(result.locks, locks);
+
+        let result = oracle
+            .inspect_statement("alter table events_2025 no inherit events;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.removed_objects,
+            HashSet::from([DBObject::Inheritance(InheritanceObject {
+                parent: "events".into(),
+                child: "events_2025".into(),
+            })])
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_foreign_table() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement(
+                "create extension postgres_fdw;
+                 create server remote foreign data wrapper postgres_fdw options (dbname 'remote');
+                 create foreign table remote_orders (id int) server remote;",
+            )
+            .await
+            .unwrap();
+        assert!(
+            result
+                .added_objects
+                .contains(&DBObject::ForeignTable(ForeignTableObject {
+                    name: "remote_orders".into(),
+                    server: "remote".into(),
+                }))
+        );
+
+        // Foreign tables cannot be locked by the locker, but the local lock taken by the
+        // executor is still detected.
+        let result = oracle
+            .inspect_statement("alter foreign table remote_orders add column total int;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "remote_orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_search_path() {
+        let (_container, dsn) = start_test_postgres().await;
+        QueryOracle::new(&dsn)
+            .inspect_statement("create schema app; create table app.orders (id int);")
+            .await
+            .unwrap();
+
+        let mut oracle = QueryOracle::builder(&dsn).search_path(&["app"]).build();
+        let result = oracle
+            .inspect_statement("alter table orders add column note text;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.added_objects,
+            HashSet::from([DBObject::Column(ColumnObject {
+                table: "orders".into(),
+                name: "note".into(),
+                data_type: "text".into(),
+                generated: None,
+            })])
+        );
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+
+        // The statement was applied to the table in the `app` schema, not `public`.
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let public_columns: i64 = sqlx::query_scalar(
+            "select count(*) from information_schema.columns
+             where table_schema = 'public' and table_name = 'orders' and column_name = 'note'",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(public_columns, 0);
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_with_same_named_table_in_another_schema() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute(
+            "create schema archive;
+             create table archive.orders (id int, note text);
+             create view archived_notes as select id, note from archive.orders;
+             create table archived_orders () inherits (archive.orders);",
+        )
+        .await
+        .unwrap();
+        let mut oracle = QueryOracle::new(&dsn);
+
+        // Altering public.orders is not confused with archive.orders, or the view that reads it.
+        let result = oracle
+            .inspect_statement("alter table orders alter column id type bigint;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+        assert!(result.dependents.is_empty());
+
+        // Altering archive.orders only affects the table in the current schema that inherits it,
+        // and locks archive.orders rather than public.orders.
+        let result = oracle
+            .inspect_statement("alter table archive.orders add column flagged bool;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.added_objects,
+            HashSet::from([DBObject::Column(ColumnObject {
+                table: "archived_orders".into(),
+                name: "flagged".into(),
+                data_type: "boolean".into(),
+                generated: None,
+            })])
+        );
+        assert!(result.removed_objects.is_empty());
+        assert_eq!(
+            result.locks,
+            HashSet::from([
+                TableLock {
+                    table: "archived_orders".into(),
+                    lock: Lock::AccessExclusiveLock,
+                },
+                TableLock {
+                    table: TableObject::in_schema("archive", "orders"),
+                    lock: Lock::AccessExclusiveLock,
+                },
+            ])
+        );
+        assert!(
+            !result
+                .locks
+                .iter()
+                .any(|lock| lock.table == "orders".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_calling_function() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute(
+            "create function migrate_step_1() returns void language plpgsql security definer as $$
+             begin
+                 alter table orders add column note text;
+             exception when duplicate_column then
+                 null;
+             end $$;
+             create procedure migrate_step_2() language plpgsql as $$
+             begin
+                 alter table orders add column total integer;
+                 commit;
+             end $$;",
+        )
+        .await
+        .unwrap();
+        let mut oracle = QueryOracle::new(&dsn);
+        let direct = oracle
+            .inspect_statement("alter table customers add column note text;")
+            .await
+            .unwrap();
+
+        // The locks taken inside the function are the same as running its body directly.
+        let result = oracle
+            .inspect_statement("select migrate_step_1();")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            direct
+                .locks
+                .iter()
+                .map(|lock| TableLock {
+                    table: "orders".into(),
+                    lock: lock.lock.clone(),
+                })
+                .collect()
+        );
+        assert_eq!(
+            result.added_objects,
+            HashSet::from([DBObject::Column(ColumnObject {
+                table: "orders".into(),
+                name: "note".into(),
+                data_type: "text".into(),
+                generated: None,
+            })])
+        );
+
+        // A procedure that commits cannot be inspected, rather than being under-reported.
+        let error = oracle
+            .inspect_statement("call migrate_step_2();")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, LocksmithError::Unresolvable { .. }));
+        assert!(format!("{:#}", anyhow::Error::from(error)).contains("procedure that commits"));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_applies_and_releases_locks() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        oracle
+            .inspect_statement("alter table orders add column note text;")
+            .await
+            .unwrap();
+
+        // The statement was committed, and no session holds a lock on any table once it returns.
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let columns: i64 = sqlx::query_scalar(
+            "select count(*) from information_schema.columns
+             where table_name = 'orders' and column_name = 'note'",
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(columns, 1);
+        let locks: Vec<String> = sqlx::query_scalar(
+            "select l.relation::regclass::text from pg_locks l
+             join pg_class c on l.relation = c.oid
+             where c.relkind = 'r' and c.relnamespace = 'public'::regnamespace
+               and l.pid <> pg_backend_pid()",
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+        assert!(locks.is_empty(), "{locks:?}");
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_with_role() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute(
+            "create role reader; grant select on all tables in schema public to reader;
+             create role migrator; grant update on all tables in schema public to migrator;
+             grant create on schema public to migrator;",
+        )
+        .await
+        .unwrap();
+
+        // The locker cannot lock any table with only SELECT privileges.
+        let error = QueryOracle::builder(&dsn)
+            .role("reader")
+            .build()
+            .inspect_statement("select 1;")
+            .await
+            .unwrap_err();
+        let LocksmithError::Unresolvable { source, .. } = error else {
+            panic!("{error:?}");
+        };
+        assert!(
+            format!("{source:#}").contains(
+                "Role reader does not have the privileges to lock customers, orders, which \
+                 requires ownership or UPDATE, DELETE or TRUNCATE privileges on each table"
+            ),
+            "{source:#}"
+        );
+
+        // The statement is executed as the role, which can lock the tables but does not own them.
+        let mut oracle = QueryOracle::builder(&dsn).role("migrator").build();
+        let error = oracle
+            .inspect_statement("alter table orders add column notes text;")
+            .await
+            .unwrap_err()
+            .statement_error()
+            .unwrap();
+        assert_eq!(error.code, "42501");
+
+        let result = oracle
+            .inspect_statement("create table audit (id int);")
+            .await
+            .unwrap();
+        assert!(
+            result
+                .added_objects
+                .contains(&DBObject::Table("audit".into()))
+        );
+        let owner: String =
+            sqlx::query_scalar("select tableowner::text from pg_tables where tablename = 'audit'")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+        assert_eq!(owner, "migrator");
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_alter_sequence() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("alter sequence orders_id_seq increment by 5;")
+            .await
+            .unwrap();
+        let sequence = SequenceObject {
+            name: "orders_id_seq".into(),
+            start: 1,
+            increment: 1,
+            min: 1,
+            max: i32::MAX.into(),
+            cycle: false,
+            owned_by: Some("orders.id".into()),
+        };
+        assert_eq!(
+            result.removed_objects,
+            HashSet::from([DBObject::Sequence(sequence.clone())])
+        );
+        assert_eq!(
+            result.added_objects,
+            HashSet::from([DBObject::Sequence(SequenceObject {
+                increment: 5,
+                ..sequence
+            })])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_affected_rows() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn).seed_rows(10).build();
+
+        // Only the final, unblocked execution of the statement is counted.
+        let result = oracle
+            .inspect_statement("update orders set price = price + 1 where id <= 4;")
+            .await
+            .unwrap();
+        assert_eq!(result.affected_rows, Some(4));
+        let result = oracle
+            .inspect_statement("alter table orders add column notes text;")
+            .await
+            .unwrap();
+        assert_eq!(result.affected_rows, None);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_seed_rows() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn).seed_rows(10).build();
+
+        // Adding a NOT NULL column without a default fails once the table contains rows.
+        let result = oracle
+            .inspect_statement("alter table orders add column total int not null;")
+            .await;
+        assert!(
+            matches!(result, Err(LocksmithError::Unresolvable { .. })),
+            "{result:?}"
+        );
+
+        // Rows are only seeded once, and orders are seeded after the customers they reference.
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        for table in ["customers", "orders"] {
+            let count: i64 = sqlx::query_scalar(&format!("select count(*) from {table}"))
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+            assert_eq!(count, 10, "{table}");
+        }
+        oracle.inspect_statement("select 1").await.unwrap();
+        let count: i64 = sqlx::query_scalar("select count(*) from orders")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 10);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_idempotency() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("create table products (id integer);")
+            .await
+            .unwrap();
+        assert_eq!(result.idempotent, None);
+
+        let mut oracle = QueryOracle::builder(&dsn).idempotency_check(true).build();
+        for (statement, idempotent) in [
+            ("create table invoices (id integer);", false),
+            ("create table if not exists shipments (id integer);", true),
+            ("alter table orders add column total int;", false),
+            (
+                "alter table orders add column if not exists discount int;",
+                true,
+            ),
+            ("drop table if exists invoices;", true),
+            ("alter table orders alter column total type bigint;", true),
+        ] {
+            let result = oracle.inspect_statement(statement).await.unwrap();
+            assert_eq!(result.idempotent, Some(idempotent), "{statement}");
+        }
+
+        // The second execution is rolled back
+        let mut introspector = Introspector::new(&dsn).await.unwrap();
+        let tables = introspector.list_tables().await.unwrap();
+        assert!(tables.contains(&"shipments".into()));
+        assert!(!tables.contains(&"invoices".into()));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_excluded_tables() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .excluded_tables(&["orders".into()])
+            .build();
+        let result = oracle
+            .inspect_statement("alter table customers alter column id type bigint;")
+            .await
+            .unwrap();
+        assert!(result.locks.contains(&TableLock {
+            table: "customers".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+        assert!(result.locks.iter().all(|lock| lock.table.name != "orders"));
+        assert!(result.unverified_locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+        assert!(
+            result
+                .unverified_locks
+                .iter()
+                .all(|lock| lock.table.name == "orders")
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_lock_costs() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        sqlx::raw_sql(
+            "insert into customers (name) select 'customer' from generate_series(1, 1000);
+             analyze customers;",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("alter table customers alter column id type bigint;")
+            .await
+            .unwrap();
+        let costs: BTreeMap<_, _> = result
+            .lock_costs
+            .iter()
+            .map(|cost| (cost.table.name.as_str(), cost))
+            .collect();
+        assert_eq!(costs["customers"].cost, LockCost::Rewrite);
+        assert_eq!(costs["customers"].estimated_rows, Some(1000));
+        assert_eq!(costs["orders"].cost, LockCost::Scan);
+
+        let customers = costs["customers"];
+        let bytes_per_second = customers.total_bytes as u64;
+        assert_eq!(
+            customers.estimated_duration(bytes_per_second),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            TableLockCost {
+                cost: LockCost::Instant,
+                ..customers.clone()
+            }
+            .estimated_duration(bytes_per_second),
+            Duration::ZERO
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_classify_required_lock_strength() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        for (statement, expected) in [
+            (
+                "select * from customers;",
+                BTreeMap::from([("customers".into(), Lock::AccessExclusiveLock)]),
+            ),
+            (
+                "update orders set price = 1;",
+                BTreeMap::from([("orders".into(), Lock::ShareLock)]),
+            ),
+            (
+                "create index on orders (customer_id);",
+                BTreeMap::from([("orders".into(), Lock::RowExclusiveLock)]),
+            ),
+            (
+                "alter table orders validate constraint orders_price_check;",
+                BTreeMap::from([("orders".into(), Lock::ShareUpdateExclusiveLock)]),
+            ),
+            (
+                "alter table orders add column notes text;",
+                BTreeMap::from([("orders".into(), Lock::AccessShareLock)]),
+            ),
+        ] {
+            let strengths = oracle
+                .classify_required_lock_strength(statement)
+                .await
+                .unwrap();
+            assert_eq!(strengths, expected, "{statement}");
+        }
+
+        // The statements are rolled back
+        let mut introspector = Introspector::new(&dsn).await.unwrap();
+        let objects = introspector.list_objects().await.unwrap();
+        assert!(!objects.iter().any(|object| matches!(
+            object,
+            DBObject::Column(column) if column.name == "notes"
+        )));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_partitioned_table() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        sqlx::raw_sql(
+            "create table events (id int, created date not null, kind text)
+                 partition by range (created);
+             create table events_2024 partition of events
+                 for values from ('2024-01-01') to ('2025-01-01');
+             create table events_2025 partition of events
+                 for values from ('2025-01-01') to ('2026-01-01');",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        let locks = |tables: &[&str], lock: Lock| -> HashSet<TableLock> {
+            tables
+                .iter()
+                .map(|table| TableLock {
+                    table: (*table).into(),
+                    lock: lock.clone(),
+                })
+                .collect()
+        };
+        let mut oracle = QueryOracle::new(&dsn);
+        for (statement, expected) in [
+            // Altering the partitioned table recurses to every partition
+            (
+                "alter table events alter column kind set default 'a';",
+                locks(
+                    &["events", "events_2024", "events_2025"],
+                    Lock::AccessExclusiveLock,
+                ),
+            ),
+            (
+                "create index on events (id);",
+                locks(&["events", "events_2024", "events_2025"], Lock::ShareLock),
+            ),
+            // With ONLY the partitions are left alone
+            (
+                "alter table only events alter column kind set default 'b';",
+                locks(&["events"], Lock::AccessExclusiveLock),
+            ),
+            (
+                "create index on only events (kind);",
+                locks(&["events"], Lock::ShareLock),
+            ),
+            // Altering a partition does not lock the partitioned table
+            (
+                "alter table events_2024 alter column kind set default 'c';",
+                locks(&["events_2024"], Lock::AccessExclusiveLock),
+            ),
+            // Attaching or detaching a partition locks the partitioned table
+            (
+                "create table events_2026 partition of events
+                     for values from ('2026-01-01') to ('2027-01-01');",
+                locks(&["events"], Lock::AccessExclusiveLock),
+            ),
+            (
+                "alter table events detach partition events_2025;",
+                locks(&["events", "events_2025"], Lock::AccessExclusiveLock),
+            ),
+        ] {
+            let result = oracle.inspect_statement(statement).await.unwrap();
+            assert_eq!(result.locks, expected, "{statement}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_drop_restrict_and_cascade() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        sqlx::raw_sql("create view order_prices as select id, price from orders;")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        // Without `CASCADE` the drop fails because of the dependent view, leaving it in place.
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle.inspect_statement("drop table orders;").await;
+        let Err(LocksmithError::DependentObjects {
+            partial,
+            dependents,
+            ..
+        }) = &result
+        else {
+            panic!("{result:?}");
+        };
+        assert_eq!(dependents, &["view order_prices depends on table orders"]);
+        assert!(partial.locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+
+        // With `CASCADE` the view is dropped along with the table.
+        let result = oracle
+            .inspect_statement("drop table orders cascade;")
+            .await
+            .unwrap();
+        let view = DBObject::Table("order_prices".into());
+        assert!(result.removed_objects.contains(&view));
+        assert_eq!(result.dependents, HashSet::from([view.clone()]));
+        let cascaded: Vec<_> = result
+            .lint()
+            .into_iter()
+            .filter(|warning| warning.rule == LintRule::CascadingDrop)
+            .map(|warning| warning.object)
+            .collect();
+        assert_eq!(cascaded, vec![view]);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_dependent_views() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        sqlx::raw_sql(
+            "create view order_prices as select id, price from orders;
+             create materialized view total_price as select sum(price) as total from order_prices;
+             create view customer_names as select name from customers;",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        // Dropping the column cascades to the view that uses it, and the materialized view that
+        // uses that view.
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("alter table orders drop column price cascade;")
+            .await
+            .unwrap();
+        let total_price = DBObject::MaterializedView(MaterializedViewObject {
+            name: "total_price".to_string(),
+        });
+        assert_eq!(
+            result.dependents,
+            HashSet::from([DBObject::Table("order_prices".into()), total_price.clone()])
+        );
+        assert!(result.removed_objects.contains(&total_price));
+
+        let result = oracle
+            .inspect_statement("alter table orders add column total int;")
+            .await
+            .unwrap();
+        assert!(result.dependents.is_empty());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_report() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let report = oracle
+            .inspect_statement_report("alter table customers alter column id type bigint;")
+            .await
+            .unwrap();
+
+        // Every lock appears in the timeline once, in the order it was detected. The statement
+        // locks customers before the orders table that references it.
+        let timeline_locks: HashSet<_> = report
+            .lock_timeline
+            .iter()
+            .map(|(lock, _)| lock.clone())
+            .collect();
+        assert_eq!(timeline_locks, report.inspected.locks);
+        assert_eq!(report.lock_timeline.len(), report.inspected.locks.len());
+        assert!(
+            report
+                .lock_timeline
+                .windows(2)
+                .all(|window| window[0].1 <= window[1].1)
+        );
+        assert_eq!(report.lock_timeline[0].0.table, "customers".into());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_report_timing() {
+        let (_container, dsn) = start_test_postgres().await;
+        let clock = Arc::new(MockClock::new());
+        let mut oracle = QueryOracle::builder(&dsn).clock(clock.clone()).build();
+        let report = oracle
+            .inspect_statement_report("alter table customers alter column id type bigint;")
+            .await
+            .unwrap();
+
+        // Only the grace period of each blocked iteration passes on the mock clock, so each lock
+        // is timed by the number of iterations it took to detect.
+        let iterations = clock.elapsed().as_millis() / BLOCK_GRACE_PERIOD.as_millis();
+        assert!(iterations >= 2);
+        assert_eq!(report.lock_timeline[0].1, BLOCK_GRACE_PERIOD);
+        assert_eq!(
+            report.lock_timeline.last().unwrap().1,
+            BLOCK_GRACE_PERIOD * iterations as u32
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_max_iterations() {
+        let (_container, dsn) = start_test_postgres().await;
+        // The statement locks several tables, so it is blocked in at least its first two
+        // iterations.
+        let mut oracle = QueryOracle::builder(&dsn).max_iterations(2).build();
+        let error = oracle
+            .inspect_statement("alter table customers alter column id type bigint;")
+            .await
+            .unwrap_err();
+        let LocksmithError::IterationLimit {
+            partial,
+            iterations: 2,
+        } = error
+        else {
+            panic!("Unexpected error: {error:?}");
+        };
+        assert!(!partial.locks.is_empty());
+
+        // A statement that locks a single table is blocked once, and completes in the second
+        // iteration.
+        let mut oracle = QueryOracle::builder(&dsn).max_iterations(2).build();
+        oracle
+            .inspect_statement("alter table orders add column notes text;")
+            .await
+            .unwrap();
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_timeout() {
+        let (_container, dsn) = start_test_postgres().await;
+        // The statement is blocked on orders in the first iteration, and then runs until the
+        // timeout is reached in the second.
+        let timeout = Duration::from_secs(3);
+        let mut oracle = QueryOracle::builder(&dsn).timeout(timeout).build();
+        let error = oracle
+            .inspect_statement("lock table orders in share mode; select pg_sleep(60);")
+            .await
+            .unwrap_err();
+        let LocksmithError::Timeout {
+            partial,
+            elapsed,
+            timeout: error_timeout,
+        } = error
+        else {
+            panic!("Unexpected error: {error:?}");
+        };
+        assert!(elapsed >= timeout && elapsed < Duration::from_secs(60));
+        assert_eq!(error_timeout, timeout);
+        assert_eq!(
+            partial.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::ShareLock,
+            }])
+        );
+
+        // The statement is cancelled rather than left running on the server.
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let mut running = true;
+        for _ in 0..50 {
+            running = sqlx::query_scalar(
+                "select exists (select from pg_stat_activity \
+                 where query like '%pg_sleep(60)%' and pid <> pg_backend_pid())",
+            )
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+            if !running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!running, "The statement is still running");
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_lock_mode() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .lock_mode(Lock::ShareLock)
+            .build();
+
+        // A lock that conflicts with the locker's is detected by blocking the statement.
+        let result = oracle
+            .inspect_statement("alter table orders add column notes text;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+
+        // A lock that does not is read once the statement has completed.
+        let result = oracle
+            .inspect_statement("select * from orders;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessShareLock,
+            }])
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_connection_settings() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .application_name("locksmith-test")
+            .deadlock_timeout(Duration::from_millis(200))
+            .build();
+        oracle
+            .inspect_statement(
+                "create table settings as select current_setting('application_name') as name, \
+                 current_setting('deadlock_timeout') as deadlock_timeout;",
+            )
+            .await
+            .unwrap();
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let settings: (String, String) = sqlx::query_as("select * from settings")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(settings, ("locksmith-test".into(), "200ms".into()));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_params() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement_params("delete from orders where id < $1;", &[&10i32])
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::RowExclusiveLock,
+            }])
+        );
+
+        // The extended query protocol does not support multiple statements.
+        let result = oracle
+            .inspect_statement_params("select $1::int; select 1;", &[&1i32])
+            .await;
+        assert!(
+            matches!(result, Err(LocksmithError::Unresolvable { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_empty_schema() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        oracle
+            .inspect_statement("drop table orders; drop table customers;")
+            .await
+            .unwrap();
+
+        let result = oracle.inspect_statement("select * from orders").await;
+        assert!(
+            matches!(result, Err(LocksmithError::EmptySchema { .. })),
+            "{result:?}"
+        );
+        assert!(logs_contain("The database does not contain any tables"));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_cache() {
+        let (_container, dsn) = start_test_postgres().await;
+        let cache = Arc::new(MemoryCache::new());
+        let mut oracle = QueryOracle::builder(&dsn).cache(cache.clone()).build();
+        let statement = "update orders set price = price + 1;";
+        let result = oracle.inspect_statement(statement).await.unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(!logs_contain("Using cached inspection result"));
+
+        // The same statement against the same schema uses the cached result.
+        assert_eq!(oracle.inspect_statement(statement).await.unwrap(), result);
+        assert_eq!(cache.len(), 1);
+        assert!(logs_contain("Using cached inspection result"));
+
+        // Cached statements are still applied, so the schema changes after the second execution
+        // of a statement that changes it and the third execution is inspected again.
+        let create_table = "create table if not exists products (id integer);";
+        let created = oracle.inspect_statement(create_table).await.unwrap();
+        assert!(!created.added_objects.is_empty());
+        assert_eq!(cache.len(), 2);
+        assert!(
+            oracle
+                .inspect_statement(create_table)
+                .await
+                .unwrap()
+                .added_objects
+                .is_empty()
+        );
+        assert_eq!(cache.len(), 3);
+
+        // Changing the oracle's settings changes the key.
+        let mut oracle = QueryOracle::builder(&dsn)
+            .cache(cache.clone())
+            .toast_locks(true)
+            .build();
+        oracle.inspect_statement(statement).await.unwrap();
+        assert_eq!(cache.len(), 4);
+        let mut oracle = QueryOracle::builder(&dsn)
+            .cache(cache.clone())
+            .lock_detection(LockDetection::AwaitedLock)
+            .build();
+        oracle.inspect_statement(statement).await.unwrap();
+        assert_eq!(cache.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_with_repeatable_read_introspection() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .repeatable_read_introspection(true)
+            .idempotency_check(true)
+            .build();
+        let result = oracle
+            .inspect_statement("alter table customers alter column id type bigint;")
+            .await
+            .unwrap();
+        assert!(
+            result
+                .rewrites
+                .contains(&DBObject::Table("customers".into()))
+        );
+        assert!(result.locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+        assert_eq!(result.idempotent, Some(true));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_awaited_lock_detection() {
+        let (_container, dsn) = start_test_postgres().await;
+        let (_awaited_container, awaited_dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let mut awaited_oracle = QueryOracle::builder(&awaited_dsn)
+            .lock_detection(LockDetection::AwaitedLock)
+            .build();
+        for statement in [
+            "alter table orders add column notes text;",
+            "select * from customers; alter table orders alter column price type bigint;",
+            "create index on orders (price);",
+            "alter table orders add constraint orders_price_positive check (price > 0) not valid; \
+             alter table orders validate constraint orders_price_positive;",
+            "alter table orders add column referrer_id integer references customers (id) \
+             deferrable initially deferred;",
+            "with customer as (insert into customers (name) values ('a') returning id) \
+             insert into orders (customer_id, referrer_id, price) select id, id, 1 from customer;",
+            "drop table orders, customers;",
+        ] {
+            let expected = oracle.inspect_statement(statement).await.unwrap();
+            let result = awaited_oracle.inspect_statement(statement).await.unwrap();
+            assert_eq!(result, expected, "{statement}");
+        }
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_awaited_lock_detection_blocked_by_another_session() {
+        let (_container, dsn) = start_test_postgres().await;
+        let _blocking = lock_tables(&dsn, ["orders"]).await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .lock_detection(LockDetection::AwaitedLock)
+            .excluded_tables(&["orders".into()])
+            .build();
+        let error = oracle
+            .inspect_statement("alter table orders add column notes text;")
+            .await
+            .unwrap_err();
+        let LocksmithError::UnobservableLock { table, detail, .. } = error else {
+            panic!("{error:?}");
+        };
+        assert_eq!(table, Some("orders".into()));
+        assert!(
+            detail.contains(
+                "Statement is waiting for AccessExclusiveLock on `orders`, which is not held by \
+                 the locker."
+            ),
+            "{detail}"
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_unobservable_lock() {
+        let (_container, dsn) = start_test_postgres().await;
+        // The statement blocks on the lock held by another session on an excluded table in every
+        // iteration, rather than on a lock held by the locker.
+        let _blocking = lock_tables(&dsn, ["orders"]).await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .excluded_tables(&["orders".into()])
+            .build();
+        let error = oracle
+            .inspect_statement("alter table orders add column notes text;")
+            .await
+            .unwrap_err();
+        let LocksmithError::UnobservableLock { partial, table, .. } = error else {
+            panic!("{error:?}");
+        };
+        assert_eq!(table, Some("orders".into()));
+        assert_eq!(
+            partial.unverified_locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+        drop(_blocking);
+
+        // A row locked by another transaction is not a lock on a table. The transaction also
+        // holds a lock on orders, which the locker skips.
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute(
+            "insert into customers (id, name) values (1, 'a'); \
+             insert into orders (id, customer_id, price) values (1, 1, 10);",
+        )
+        .await
+        .unwrap();
+        conn.execute("begin; select * from orders where id = 1 for update;")
+            .await
+            .unwrap();
+        let mut oracle = QueryOracle::builder(&dsn)
+            .lock_conflict_policy(LockConflictPolicy::Skip)
+            .build();
+        let error = oracle
+            .inspect_statement("update orders set price = 20 where id = 1;")
+            .await
+            .unwrap_err();
+        let LocksmithError::UnobservableLock { partial, table, .. } = error else {
+            panic!("{error:?}");
+        };
+        assert_eq!(table, None);
+        assert!(partial.locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::RowExclusiveLock,
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statement_with_invalid_sql() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let statement = "alter table orders add column total int;\nselect nme from customers;";
+        let error = oracle
+            .inspect_statement(statement)
+            .await
+            .unwrap_err()
+            .statement_error()
+            .unwrap();
+        assert_eq!(error.code, "42703");
+        assert_eq!(error.position, Some(49));
+        assert!(
+            error
+                .describe(statement)
+                .contains("LINE 2: select nme from customers;\n               ^\nHINT: "),
+            "{}",
+            error.describe(statement)
+        );
+
+        let error = oracle
+            .inspect_statement("foobar")
+            .await
+            .unwrap_err()
+            .statement_error()
+            .unwrap();
+        assert_eq!(error.code, "42601");
+        assert_eq!(error.position, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_apply_statement() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        oracle
+            .apply_statement("create table products (id integer);")
+            .await
+            .unwrap();
+        let result = oracle
+            .inspect_statement("drop table products;")
+            .await
+            .unwrap();
+        assert!(
+            result
+                .removed_objects
+                .contains(&DBObject::Table("products".into()))
+        );
+        assert!(oracle.apply_statement("commit;").await.is_err());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_concurrently() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("drop index concurrently orders_price_idx;")
+            .await;
+        let Err(LocksmithError::Other(err)) = result else {
+            panic!("{result:?}");
+        };
+        assert!(
+            err.to_string().contains("ShareUpdateExclusiveLock"),
+            "{err}"
+        );
+
+        let result = oracle
+            .inspect_statement("drop index orders_price_idx;")
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_predicate_locks() {
+        let (_container, dsn) = start_test_postgres().await;
+        let statement = "select count(*) from orders";
+        let mut oracle = QueryOracle::builder(&dsn)
+            .setup_statements(&["set transaction isolation level serializable"])
+            .predicate_locks(true)
+            .build();
+        let result = oracle.inspect_statement(statement).await.unwrap();
+        assert_eq!(
+            result.predicate_locks,
+            HashSet::from([PredicateLock {
+                table: "orders".into(),
+                granularity: PredicateLockGranularity::Relation,
+            }])
+        );
+
+        // Predicate locks are not taken under the default isolation level, and are not listed
+        // unless enabled.
+        let mut oracle = QueryOracle::builder(&dsn).predicate_locks(true).build();
+        let result = oracle.inspect_statement(statement).await.unwrap();
+        assert!(result.predicate_locks.is_empty());
+        let mut oracle = QueryOracle::builder(&dsn)
+            .setup_statements(&["set transaction isolation level serializable"])
+            .build();
+        let result = oracle.inspect_statement(statement).await.unwrap();
+        assert!(result.predicate_locks.is_empty());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_setup_statements() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .setup_statements(&[
+                "set lock_timeout = '5s'",
+                "set application_name = 'migrate'",
+            ])
+            .build();
+        let result = oracle
+            .inspect_statement(
+                "create table settings as select current_setting('lock_timeout') as value, \
+                 count(*) as orders from orders;",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessShareLock,
+            }])
+        );
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let values: Vec<String> = sqlx::query_scalar("select value from settings")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(values, ["5s"]);
+
+        let mut oracle = QueryOracle::builder(&dsn)
+            .setup_statements(&["set log_lock_waits = off"])
+            .build();
+        let result = oracle.inspect_statement("select * from orders").await;
+        let Err(LocksmithError::Unresolvable { source, .. }) = result else {
+            panic!("{result:?}");
+        };
+        assert!(
+            format!("{source:#}").contains("log_lock_waits"),
+            "{source:#}"
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_lock_timeout() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .setup_statements(&["set lock_timeout = '10ms'"])
+            .build();
+        let result = oracle
+            .inspect_statement(
+                "alter table orders add column total int; \
+                 alter table customers add column email text;",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([
+                TableLock {
+                    table: "orders".into(),
+                    lock: Lock::AccessExclusiveLock,
+                },
+                TableLock {
+                    table: "customers".into(),
+                    lock: Lock::AccessExclusiveLock,
+                },
+            ])
+        );
+        assert!(logs_contain("Statement was cancelled by lock_timeout"));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_terminated_executor() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute("create sequence terminations").await.unwrap();
+        let mut oracle = QueryOracle::new(&dsn);
+        // Sequences are not rolled back, so the executor's backend terminates itself the first
+        // time the statement runs past the lock on orders, which was detected in the iteration
+        // before. The loop resumes with a new executor without losing that lock.
+        let statement = "alter table orders add column total int; \
+                         select pg_terminate_backend(pg_backend_pid()) \
+                         where nextval('terminations') = 1;";
+        let result = oracle.inspect_statement(statement).await.unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([TableLock {
+                table: "orders".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+        assert!(logs_contain("Executor connection was terminated"));
+
+        // A backend that is terminated every time the statement runs fails the inspection once
+        // the executor has been restarted too many times.
+        let statement = "alter table customers add column email text; \
+                         select pg_terminate_backend(pg_backend_pid());";
+        let err = oracle.inspect_statement(statement).await.unwrap_err();
+        let LocksmithError::Unresolvable { partial, .. } = err else {
+            panic!("Expected an unresolvable error, got {err:?}");
+        };
+        assert_eq!(
+            partial.locks,
+            HashSet::from([TableLock {
+                table: "customers".into(),
+                lock: Lock::AccessExclusiveLock,
+            }])
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_savepoints() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        // The lock on customers is still detected, as it is taken before the savepoint is rolled
+        // back, but the column added to it is not.
+        let result = oracle
+            .inspect_statement(
+                "savepoint before_customers; alter table customers add column email text; \
+                 rollback to savepoint before_customers; alter table orders add column total int; \
+                 release savepoint before_customers;",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result.locks,
+            HashSet::from([
+                TableLock {
+                    table: "customers".into(),
+                    lock: Lock::AccessExclusiveLock,
+                },
+                TableLock {
+                    table: "orders".into(),
+                    lock: Lock::AccessExclusiveLock,
+                },
+            ])
+        );
+        assert_eq!(
+            result.added_objects,
+            HashSet::from([DBObject::Column(ColumnObject {
+                table: "orders".into(),
+                name: "total".to_string(),
+                data_type: "integer".to_string(),
+                generated: None,
+            })])
+        );
+        assert!(result.removed_objects.is_empty());
+
+        // Commands that end the transaction are rejected before anything is executed.
+        for statement in [
+            "alter table customers add column email text; commit;",
+            "rollback; alter table customers add column email text;",
+            "begin; alter table customers add column email text;",
+        ] {
+            let err = oracle.inspect_statement(statement).await.unwrap_err();
+            assert!(matches!(err, LocksmithError::Other(_)), "{err:?}");
+            assert!(err.to_string().contains("use savepoints instead"), "{err}");
+        }
+        let err = oracle
+            .classify_required_lock_strength("commit;")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LocksmithError::Other(_)), "{err:?}");
+        let mut introspector = Introspector::new(&dsn).await.unwrap();
+        let objects = introspector.list_objects().await.unwrap();
+        assert!(
+            !objects
+                .iter()
+                .any(|object| matches!(object, DBObject::Column(column) if column.name == "email"))
+        );
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_deferred_constraint() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        oracle
+            .inspect_statement(
+                "alter table orders alter constraint orders_customer_id_fkey deferrable initially deferred;
+                 insert into customers (id, name) values (1, 'customer');",
+            )
+            .await
+            .unwrap();
+
+        let result = oracle
+            .inspect_statement("insert into orders (customer_id, price) values (1, 1);")
+            .await
+            .unwrap();
+        let commit_lock = TableLock {
+            table: "customers".into(),
+            lock: Lock::RowShareLock,
+        };
+        assert_eq!(result.commit_locks, HashSet::from([commit_lock.clone()]));
+        assert!(result.locks.contains(&commit_lock));
+        assert!(result.locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::RowExclusiveLock,
+        }));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_lock_conflict_policy_error() {
+        let (_container, dsn) = start_test_postgres().await;
+        let _other = lock_tables(&dsn, ["orders"]).await;
+        let mut oracle = QueryOracle::builder(&dsn)
+            .lock_conflict_policy(LockConflictPolicy::Error)
+            .build();
+        let error = oracle
+            .inspect_statement("select * from customers;")
+            .await
+            .unwrap_err();
+        let error = anyhow::Error::from(error);
+        assert!(format!("{error:#}").contains("orders"), "{error:#}");
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_migration_rename_then_use() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let results = oracle
+            .inspect_migration(
+                "-- Rename orders, then index it by its new name.\n\
+                 alter table orders rename to purchases;\n\
+                 create index on purchases (price);",
+            )
+            .await;
+        let statements: Vec<_> = results.iter().map(|(sql, _)| sql.as_str()).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "-- Rename orders, then index it by its new name.\n\
+                 alter table orders rename to purchases;",
+                "create index on purchases (price);"
+            ]
+        );
+        let mut results = results.into_iter().map(|(_, result)| result.unwrap());
+
+        let rename = results.next().unwrap();
+        assert!(rename.locks.contains(&TableLock {
+            table: "orders".into(),
+            lock: Lock::AccessExclusiveLock,
+        }));
+        assert!(
+            rename
+                .removed_objects
+                .contains(&DBObject::Table("orders".into()))
+        );
+        assert!(
+            rename
+                .added_objects
+                .contains(&DBObject::Table("purchases".into()))
+        );
+
+        // The index is created on the renamed table, and is the only change since the rename.
+        let create_index = results.next().unwrap();
+        assert_eq!(
+            create_index
+                .lock_costs
+                .iter()
+                .map(|cost| cost.cost)
+                .collect::<Vec<_>>(),
+            vec![LockCost::Scan]
+        );
+        assert_eq!(
+            create_index,
+            InspectedStatement {
+                added_objects: HashSet::from([DBObject::Index(IndexObject {
+                    table: "purchases".into(),
+                    name: "purchases_price_idx".to_string(),
+                    columns: vec!["price".to_string()],
+                    is_primary: false,
+                    is_unique: false,
+                })]),
+                locks: HashSet::from([TableLock {
+                    table: "purchases".into(),
+                    lock: Lock::ShareLock,
+                }]),
+                lock_costs: create_index.lock_costs.clone(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inspect_migration_stops_at_failure() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let results = oracle
+            .inspect_migration(
+                "alter table orders add column notes text; select nme from orders; \
+                 alter table orders drop column notes;",
+            )
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].1.is_ok());
+        assert!(
+            results[1]
+                .1
+                .as_ref()
+                .unwrap_err()
+                .statement_error()
+                .is_some()
+        );
+
+        // The statement before the failure was committed.
+        let mut introspector = Introspector::new(&dsn).await.unwrap();
+        let objects = introspector.list_objects().await.unwrap();
+        assert!(objects.contains(&DBObject::Column(ColumnObject {
+            table: "orders".into(),
+            name: "notes".to_string(),
+            data_type: "text".to_string(),
+            generated: None,
+        })));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_directory() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/migrations");
+        let results: Vec<_> = oracle.inspect_directory(directory).unwrap().collect().await;
+        assert!(matches!(
+            oracle.inspect_directory(format!("{directory}/missing")),
+            Err(LocksmithError::Other(_))
+        ));
+
+        let names: Vec<_> = results
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "001_create_products.sql",
+                "002_add_product_name.sql",
+                "003_invalid.sql"
+            ]
+        );
+
+        let products = TableObject::from("products");
+        let mut results = results.into_iter().map(|(_, result)| result);
+        assert_eq!(
+            results.next().unwrap().unwrap(),
+            InspectedStatement {
+                added_objects: HashSet::from([
+                    DBObject::Table(products.clone()),
+                    DBObject::Column(ColumnObject {
+                        table: products.clone(),
+                        name: "id".to_string(),
+                        data_type: "integer".to_string(),
+                        generated: None,
+                    }),
+                ]),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            results.next().unwrap().unwrap(),
+            InspectedStatement {
+                added_objects: HashSet::from([DBObject::Column(ColumnObject {
+                    table: products.clone(),
+                    name: "name".to_string(),
+                    data_type: "text".to_string(),
+                    generated: None,
+                })]),
+                locks: HashSet::from([TableLock {
+                    table: products.clone(),
+                    lock: Lock::AccessExclusiveLock,
+                }]),
+                lock_costs: HashSet::from([TableLockCost {
+                    table: products,
+                    cost: LockCost::Instant,
+                    total_bytes: 0,
+                    estimated_rows: None,
+                }]),
+                ..Default::default()
+            }
+        );
+        assert!(results.next().unwrap().is_err());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_without_locks() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut oracle = QueryOracle::new(&dsn);
+        let result = oracle
+            .inspect_statement("create table products (id integer);")
+            .await
+            .unwrap();
+
+        let products = TableObject::from("products");
+        assert_eq!(
+            result,
+            InspectedStatement {
+                added_objects: HashSet::from([
+                    DBObject::Table(products.clone()),
+                    DBObject::Column(ColumnObject {
+                        table: products,
+                        name: "id".to_string(),
+                        data_type: "integer".to_string(),
+                        generated: None,
+                    }),
+                ]),
+                ..Default::default()
+            }
+        );
+        assert!(logs_contain("iterations=1"));
+    }
+
+    /// A [SchemaSource] that lists each of `objects` and `file_nodes` in turn.
+    struct FakeSchemaSource {
+        objects: Vec<HashSet<DBObject>>,
+        file_nodes: Vec<HashMap<DBObject, i32>>,
+    }
+
+    impl SchemaSource for FakeSchemaSource {
+        fn list_objects(&mut self) -> BoxFuture<'_, Result<HashSet<DBObject>, LocksmithError>> {
+            let objects = self.objects.remove(0);
+            Box::pin(async move { Ok(objects) })
+        }
+
+        fn list_object_file_nodes(
+            &mut self,
+        ) -> BoxFuture<'_, Result<HashMap<DBObject, i32>, LocksmithError>> {
+            let file_nodes = self.file_nodes.remove(0);
+            Box::pin(async move { Ok(file_nodes) })
+        }
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_inspect_statement_with_schema_source() {
+        let (_container, dsn) = start_test_postgres().await;
+        let orders = DBObject::Table("orders".into());
+        let total = DBObject::Column(ColumnObject {
+            table: "orders".into(),
+            name: "total".to_string(),
+            data_type: "integer".to_string(),
+            generated: None,
+        });
+        // The objects are compared as listed by the source, whatever the statement does.
+        let source = FakeSchemaSource {
+            objects: vec![
+                HashSet::from([orders.clone()]),
+                HashSet::from([orders.clone(), total.clone()]),
+            ],
+            file_nodes: vec![
+                HashMap::from([(orders.clone(), 1)]),
+                HashMap::from([(orders.clone(), 2)]),
+            ],
+        };
+        let mut oracle = QueryOracle::builder(&dsn).schema_source(source).build();
+        let result = oracle.inspect_statement("select 1").await.unwrap();
+        assert_eq!(result.added_objects, HashSet::from([total]));
+        assert!(result.removed_objects.is_empty());
+        assert_eq!(result.rewrites, HashSet::from([orders]));
+        assert!(result.locks.is_empty());
+    }
 }
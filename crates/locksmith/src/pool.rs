@@ -0,0 +1,259 @@
+//! A pool of connections shared by [QueryOracle](crate::QueryOracle)s, see [ConnectionPool].
+
+use crate::{LocksmithError, TlsConfig};
+use anyhow::Context;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{ConnectOptions, Connection, Executor, PgConnection, PgPool, Postgres};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A pool of connections to a Postgres database, shared by any number of
+/// [QueryOracle](crate::QueryOracle)s via
+/// [QueryOracleBuilder::pool](crate::QueryOracleBuilder::pool), which bounds the number of
+/// statements that are inspected at the same time.
+///
+/// Each inspection uses an [Introspector](crate::Introspector) and a [Locker](crate::Locker)
+/// connection at the same time, which are drawn from the pool and reused by later inspections.
+/// The [StatementExecutor](crate::StatementExecutor) reads lock waits from the notices the server
+/// sends it, which requires a tokio-postgres connection, and a blocked executor must close its
+/// connection to cancel the statement, so executor connections are opened for each execution of
+/// the statement and are never reused. With at most `max_inspections` inspections at once, the
+/// pool therefore opens at most three times as many connections.
+///
+/// Connections are rolled back and their settings reset with `RESET ALL` and `RESET ROLE`, which
+/// `RESET ALL` does not cover, when they are returned to the pool. This releases any locks held
+/// by the locker.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    dsn: String,
+    pool: PgPool,
+    inspections: Arc<Semaphore>,
+}
+
+impl ConnectionPool {
+    /// Create a pool of connections to the Postgres database at `dsn` that allows up to
+    /// `max_inspections` statements to be inspected at the same time. A connection is opened to
+    /// check that the database can be reached, and is kept in the pool.
+    ///
+    /// Fails with a [LocksmithError::Connection] if the database cannot be reached, or a
+    /// [LocksmithError::Other] if `max_inspections` is `0` or the DSN cannot be parsed.
+    pub async fn connect(dsn: &str, max_inspections: u32) -> Result<Self, LocksmithError> {
+        Self::connect_with_tls(dsn, max_inspections, &TlsConfig::default()).await
+    }
+
+    /// Create a pool as in [ConnectionPool::connect], using TLS as configured by `tls`. This
+    /// applies to the pooled connections only: the executor connections of an oracle are
+    /// configured by [QueryOracleBuilder::tls](crate::QueryOracleBuilder::tls).
+    pub async fn connect_with_tls(
+        dsn: &str,
+        max_inspections: u32,
+        tls: &TlsConfig,
+    ) -> Result<Self, LocksmithError> {
+        if max_inspections == 0 {
+            return Err(LocksmithError::Other(anyhow::anyhow!(
+                "A pool must allow at least one inspection"
+            )));
+        }
+        let options = tls.connect_options(dsn).map_err(LocksmithError::Other)?;
+        let pool = PgPoolOptions::new()
+            .max_connections(max_inspections * 2)
+            .after_release(|conn, _| {
+                Box::pin(async move {
+                    conn.execute("ROLLBACK; RESET ALL; RESET ROLE;").await?;
+                    Ok(true)
+                })
+            })
+            .connect_with(options)
+            .await
+            .context("Creating connection pool")
+            .map_err(|source| LocksmithError::Connection { source })?;
+        Ok(Self {
+            dsn: dsn.to_string(),
+            pool,
+            inspections: Arc::new(Semaphore::new(max_inspections as usize)),
+        })
+    }
+
+    /// The DSN of the database the pool connects to.
+    pub fn dsn(&self) -> &str {
+        &self.dsn
+    }
+
+    /// Wait until fewer than the maximum number of statements are being inspected, returning a
+    /// permit that allows an inspection until it is dropped.
+    pub(crate) async fn inspection_permit(&self) -> anyhow::Result<OwnedSemaphorePermit> {
+        self.inspections
+            .clone()
+            .acquire_owned()
+            .await
+            .context("Waiting for a pooled inspection")
+    }
+
+    /// Acquire a connection from the pool.
+    pub(crate) async fn acquire(&self) -> anyhow::Result<PgConn> {
+        let conn = self
+            .pool
+            .acquire()
+            .await
+            .context("Acquiring a pooled connection")?;
+        Ok(PgConn::Pooled(conn))
+    }
+}
+
+/// A connection used by the [Introspector](crate::Introspector) and [Locker](crate::Locker),
+/// which is either opened for their sole use or acquired from a [ConnectionPool].
+pub(crate) enum PgConn {
+    Direct(PgConnection),
+    Pooled(PoolConnection<Postgres>),
+}
+
+impl PgConn {
+    /// Open a new connection to the Postgres database at `dsn`, using TLS as configured by `tls`.
+    pub(crate) async fn connect(dsn: &str, tls: &TlsConfig) -> anyhow::Result<Self> {
+        let mut conn = tls
+            .connect_options(dsn)?
+            .connect()
+            .await
+            .context("Creating connection")?;
+        conn.ping().await.context("Pinging postgres")?;
+        Ok(PgConn::Direct(conn))
+    }
+
+    /// Close a connection that was opened directly, or roll back the transaction of a pooled
+    /// connection and return it to the pool.
+    pub(crate) async fn close(self) -> anyhow::Result<()> {
+        match self {
+            PgConn::Direct(conn) => conn.close().await.context("Closing connection"),
+            PgConn::Pooled(mut conn) => {
+                conn.execute("ROLLBACK;")
+                    .await
+                    .context("Rolling back pooled connection")?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Deref for PgConn {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            PgConn::Direct(conn) => conn,
+            PgConn::Pooled(conn) => conn,
+        }
+    }
+}
+
+impl DerefMut for PgConn {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            PgConn::Direct(conn) => conn,
+            PgConn::Pooled(conn) => conn,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::start_test_postgres;
+    use crate::{ConnectionPool, Introspector, Locker, LocksmithError, QueryOracle, TableLock};
+    use sqlx::{Connection, Executor, PgConnection, query_scalar};
+    use std::collections::HashSet;
+
+    #[tokio::test]
+    async fn test_connect_errors() {
+        assert!(matches!(
+            ConnectionPool::connect("postgres://user@127.0.0.1:1/postgres", 0).await,
+            Err(LocksmithError::Other(_))
+        ));
+        assert!(matches!(
+            ConnectionPool::connect("not a dsn", 1).await,
+            Err(LocksmithError::Other(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_inspect_statements_with_pool() {
+        let (_container, dsn) = start_test_postgres().await;
+        let pool = ConnectionPool::connect(&dsn, 1).await.unwrap();
+        let mut orders_oracle = QueryOracle::builder("").pool(pool.clone()).build();
+        let mut customers_oracle = QueryOracle::builder("").pool(pool.clone()).build();
+
+        // Each locker locks every table, so the inspections would block each other if they were
+        // not run one at a time.
+        let (orders, customers) = tokio::join!(
+            orders_oracle.inspect_statement("alter table orders add column notes text;"),
+            customers_oracle.inspect_statement("alter table customers add column notes text;"),
+        );
+        let locks_table = |locks: HashSet<TableLock>, table: &str| {
+            locks.into_iter().any(|lock| lock.table.name == table)
+        };
+        assert!(locks_table(orders.unwrap().locks, "orders"));
+        assert!(locks_table(customers.unwrap().locks, "customers"));
+        // Only the introspector and locker connections are pooled.
+        assert_eq!(pool.pool.size(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pooled_connections_are_reset() {
+        let (_container, dsn) = start_test_postgres().await;
+        let pool = ConnectionPool::connect(&dsn, 1).await.unwrap();
+        let mut locker = Locker::from_pool(&pool).await.unwrap();
+        let mut other = pool.acquire().await.unwrap();
+        locker
+            .set_search_path(&["missing", "public"])
+            .await
+            .unwrap();
+        locker.lock_tables([&"orders".into()]).await.unwrap();
+        let locker_pid = query_scalar(
+            "select pid from pg_locks where relation = 'orders'::regclass and granted",
+        );
+        let pid: i32 = locker_pid.fetch_one(&mut *other).await.unwrap();
+
+        // The pool is full, so the next connection is the locker's once it has been returned.
+        locker.close().await.unwrap();
+        let mut reused = pool.acquire().await.unwrap();
+        let reused_pid: i32 = query_scalar("select pg_backend_pid()")
+            .fetch_one(&mut *reused)
+            .await
+            .unwrap();
+        assert_eq!(reused_pid, pid);
+        let search_path: String = query_scalar("show search_path")
+            .fetch_one(&mut *reused)
+            .await
+            .unwrap();
+        assert_eq!(search_path, r#""$user", public"#);
+        let locks: i64 = query_scalar(
+            "select count(*) from pg_locks where pid = $1 and relation = 'orders'::regclass",
+        )
+        .bind(pid)
+        .fetch_one(&mut *other)
+        .await
+        .unwrap();
+        assert_eq!(locks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_pooled_connections_reset_role() {
+        let (_container, dsn) = start_test_postgres().await;
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        conn.execute("create role auditor;").await.unwrap();
+        let pool = ConnectionPool::connect(&dsn, 1).await.unwrap();
+
+        // The introspector does not run in a transaction, so its role outlives the rollback. The
+        // pool is full, so the next connection is the introspector's once it has been returned.
+        let _other = pool.acquire().await.unwrap();
+        let mut introspector = Introspector::from_pool(&pool).await.unwrap();
+        introspector.set_role("auditor").await.unwrap();
+        drop(introspector);
+        let mut reused = pool.acquire().await.unwrap();
+        let role: String = query_scalar("show role")
+            .fetch_one(&mut *reused)
+            .await
+            .unwrap();
+        assert_eq!(role, "none");
+    }
+}
@@ -0,0 +1,35 @@
+//! The parts of locksmith that depend on the async runtime, which is Tokio.
+//!
+//! The rest of the crate only uses runtime agnostic futures and combinators, such as those of the
+//! [futures] crate, so that supporting another runtime only requires changing this module. The
+//! connections are still opened with sqlx and tokio-postgres, which use Tokio's sockets, so a
+//! Tokio runtime must currently be running to inspect statements.
+//!
+//! [Semaphore](tokio::sync::Semaphore) and the other primitives of [tokio::sync] work with any
+//! runtime, so they are used directly.
+
+use std::future::Future;
+use std::time::Instant;
+
+/// The current instant, according to the runtime's timer.
+pub(crate) fn now() -> Instant {
+    tokio::time::Instant::now().into_std()
+}
+
+/// Wait until `deadline` has been reached.
+pub(crate) fn sleep_until(deadline: Instant) -> impl Future<Output = ()> + Send {
+    tokio::time::sleep_until(deadline.into())
+}
+
+/// Run `future` in the background on the current runtime, returning `false` if it could not be
+/// spawned because no runtime is running, such as when called from a destructor that runs after
+/// the runtime has shut down.
+pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> bool {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn(future);
+            true
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,196 @@
+use crate::TlsConfig;
+use anyhow::Context;
+use sqlx::{ConnectOptions, Connection, Executor, PgConnection, query, query_as};
+use tracing::{debug, info, warn};
+
+/// A [Seeder] populates the tables of a database with dummy rows, so that statements which depend
+/// on existing data behave as they would against a populated database.
+pub(crate) struct Seeder {
+    conn: PgConnection,
+}
+
+/// A column of a table that is being seeded.
+struct SeedColumn {
+    table: String,
+    name: String,
+    data_type: String,
+    /// The `typcategory` of the column's type, or of the base type for domains.
+    category: String,
+    /// Whether the column has a default, or is an identity or generated column, and so is
+    /// left to the database to fill in.
+    has_default: bool,
+}
+
+impl Seeder {
+    /// Construct a new [Seeder] with a connection to the Postgres database at `dsn`, using TLS
+    /// as configured by `tls`.
+    pub(crate) async fn new(dsn: &str, tls: &TlsConfig) -> anyhow::Result<Self> {
+        let mut conn = tls
+            .connect_options(dsn)?
+            .connect()
+            .await
+            .context("Creating connection")?;
+        conn.ping().await.context("Pinging postgres")?;
+        Ok(Self { conn })
+    }
+
+    /// Set the `search_path` of the seeder's connection. Tables are seeded in the first existing
+    /// schema in the search path.
+    pub(crate) async fn set_search_path(
+        &mut self,
+        schemas: &[impl AsRef<str>],
+    ) -> anyhow::Result<()> {
+        self.conn
+            .execute(crate::search_path_statement(schemas).as_str())
+            .await
+            .context("Query error while setting search path")?;
+        Ok(())
+    }
+
+    /// Set the `application_name` of the seeder's connection.
+    pub(crate) async fn set_application_name(&mut self, name: &str) -> anyhow::Result<()> {
+        self.conn
+            .execute(crate::application_name_statement(name).as_str())
+            .await
+            .context("Query error while setting application name")?;
+        Ok(())
+    }
+
+    /// Insert `rows` dummy rows into every table in the current schema, and commit them.
+    ///
+    /// Values are generated from a series from `1` to `rows` according to the type of each
+    /// column, and columns with defaults are filled in by the database. This means that foreign
+    /// keys referencing `serial` or identity columns of other seeded tables are satisfied, so
+    /// tables are retried until every table that references another has been seeded. Tables
+    /// that cannot be seeded, for example because of a check constraint, are skipped with a
+    /// warning. Partitions and tables that belong to extensions are not seeded.
+    pub(crate) async fn seed_tables(&mut self, rows: u64) -> anyhow::Result<()> {
+        let columns = self.list_columns().await?;
+        let mut pending: Vec<(String, String)> = columns
+            .chunk_by(|a, b| a.table == b.table)
+            .map(|columns| (columns[0].table.clone(), insert_statement(columns, rows)))
+            .collect();
+
+        let mut tx = self.conn.begin().await.context("Starting transaction")?;
+        loop {
+            let attempted = pending.len();
+            let mut failed = vec![];
+            for (table, statement) in std::mem::take(&mut pending) {
+                debug!(table, statement, "Seeding table");
+                query!("SAVEPOINT seed_table;")
+                    .execute(&mut *tx)
+                    .await
+                    .context("Query error while creating savepoint")?;
+                match tx.execute(statement.as_str()).await {
+                    Ok(_) => {
+                        query!("RELEASE SAVEPOINT seed_table;")
+                            .execute(&mut *tx)
+                            .await
+                            .context("Query error while releasing savepoint")?;
+                    }
+                    Err(error) => {
+                        query!("ROLLBACK TO SAVEPOINT seed_table;")
+                            .execute(&mut *tx)
+                            .await
+                            .context("Query error while rolling back savepoint")?;
+                        failed.push((table, statement, error));
+                    }
+                }
+            }
+            if failed.is_empty() {
+                break;
+            }
+            // A table can fail because a table it references has not been seeded yet, so keep
+            // retrying until a pass makes no progress.
+            if failed.len() == attempted {
+                for (table, _, error) in failed {
+                    warn!(table, %error, "Unable to seed table");
+                }
+                break;
+            }
+            pending = failed
+                .into_iter()
+                .map(|(table, statement, _)| (table, statement))
+                .collect();
+        }
+        tx.commit().await.context("Committing seeded rows")?;
+        info!(rows, "Seeded tables");
+        Ok(())
+    }
+
+    /// List the columns of every table that should be seeded, ordered by table and column
+    /// position.
+    async fn list_columns(&mut self) -> anyhow::Result<Vec<SeedColumn>> {
+        query_as!(
+            SeedColumn,
+            r#"
+            SELECT c.relname as "table!",
+                   a.attname as "name!",
+                   format_type(a.atttypid, a.atttypmod) as "data_type!",
+                   coalesce(base.typcategory, t.typcategory)::text as "category!",
+                   (a.atthasdef OR a.attidentity <> '' OR a.attgenerated <> '') as "has_default!"
+            FROM pg_attribute a
+            JOIN pg_class c ON a.attrelid = c.oid
+            JOIN pg_namespace n ON c.relnamespace = n.oid
+            JOIN pg_type t ON a.atttypid = t.oid
+            LEFT JOIN pg_type base ON t.typtype = 'd' AND base.oid = t.typbasetype
+            WHERE n.nspname = "current_schema"()
+              AND c.relkind = 'r'
+              AND NOT c.relispartition
+              AND a.attnum > 0
+              AND NOT a.attisdropped
+              AND NOT EXISTS (
+                SELECT FROM pg_depend d
+                WHERE d.classid = 'pg_class'::regclass
+                  AND d.objid = c.oid
+                  AND d.deptype = 'e')
+            order by c.relname, a.attnum;"#
+        )
+        .fetch_all(&mut self.conn)
+        .await
+        .context("Query error while listing columns to seed")
+    }
+}
+
+/// Build an `INSERT` statement that inserts `rows` rows into the table that `columns` belong to.
+fn insert_statement(columns: &[SeedColumn], rows: u64) -> String {
+    let table = crate::quote_identifier(&columns[0].table);
+    let (names, values): (Vec<_>, Vec<_>) = columns
+        .iter()
+        .filter(|column| !column.has_default)
+        .map(|column| (crate::quote_identifier(&column.name), seed_value(column)))
+        .unzip();
+    format!(
+        "INSERT INTO {table} ({}) SELECT {} FROM generate_series(1, {rows}) g;",
+        names.join(", "),
+        values.join(", ")
+    )
+}
+
+/// The expression used to generate the value of `column`, in terms of the series value `g`.
+fn seed_value(column: &SeedColumn) -> String {
+    let data_type = &column.data_type;
+    match column.category.as_str() {
+        // Numeric types
+        "N" => format!("g::{data_type}"),
+        // String types
+        "S" => format!("g::text::{data_type}"),
+        // Boolean types
+        "B" => "true".to_string(),
+        // Date and time types
+        "D" => format!("(timestamp '2000-01-01' + g * interval '1 second')::{data_type}"),
+        // Interval types
+        "T" => format!("(g * interval '1 second')::{data_type}"),
+        // Enum types
+        "E" => format!("(enum_range(NULL::{data_type}))[1]"),
+        // Array types
+        "A" => format!("'{{}}'::{data_type}"),
+        // Network address types
+        "I" => format!("('10.0.0.0'::inet + g)::{data_type}"),
+        // User-defined types, which includes `uuid` and `json`
+        "U" if data_type == "uuid" => "md5(g::text)::uuid".to_string(),
+        "U" if data_type.starts_with("json") => format!("'{{}}'::{data_type}"),
+        "U" => format!("g::text::{data_type}"),
+        _ => format!("NULL::{data_type}"),
+    }
+}
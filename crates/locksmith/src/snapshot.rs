@@ -0,0 +1,362 @@
+use crate::cache::StableHasher;
+use crate::{DBObject, TableObject};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// A [SchemaSnapshot] is a point-in-time capture of the objects in a database, created via
+/// [Introspector::snapshot](crate::Introspector::snapshot). Snapshots are serializable when the
+/// `serde` feature is enabled, which it is by default.
+///
+/// Snapshots allow the shape of a database that cannot be reached from CI (such as production)
+/// to be checked into a repository or stored as an artifact, and compared against the local
+/// database that statements are inspected against.
+///
+/// A snapshot does not contain enough information to recreate a database. To create a local
+/// database that matches a snapshot, restore a schema-only dump of the source database
+/// (`pg_dump --schema-only`) into a Postgres instance running the same major version as
+/// [SchemaSnapshot::server_version_num], and check that a snapshot of the restored database has
+/// the same [SchemaSnapshot::objects].
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SchemaSnapshot {
+    /// The numeric server version, as given by the `server_version_num` setting.
+    pub server_version_num: i32,
+    /// All objects in the database, see [Introspector::list_objects](crate::Introspector::list_objects).
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
+    pub objects: HashSet<DBObject>,
+    /// The file nodes of all tables in the database, see
+    /// [Introspector::list_object_file_nodes](crate::Introspector::list_object_file_nodes).
+    /// These are only meaningful for the database the snapshot was taken from.
+    pub file_nodes: Vec<(DBObject, i32)>,
+}
+
+impl SchemaSnapshot {
+    /// Get the file nodes of the snapshot as a map, as returned by
+    /// [Introspector::list_object_file_nodes](crate::Introspector::list_object_file_nodes).
+    pub fn file_nodes(&self) -> HashMap<DBObject, i32> {
+        self.file_nodes.iter().cloned().collect()
+    }
+
+    /// The major version of the server, such as `15` for 15.4. Before Postgres 10 the major
+    /// version had two parts, so 9.6.24 gives `906`.
+    pub fn major_version(&self) -> i32 {
+        if self.server_version_num >= 100000 {
+            self.server_version_num / 10000
+        } else {
+            self.server_version_num / 100
+        }
+    }
+
+    /// A hash of the [major version](SchemaSnapshot::major_version) and the objects in the
+    /// snapshot, which is the same for any two databases with the same schema and major version,
+    /// so a minor upgrade does not change it. File nodes are not included, as they differ between
+    /// databases with the same schema.
+    ///
+    /// The hash is stable across runs and platforms, so it can be stored and compared later, such
+    /// as in the [CacheKey](crate::CacheKey) of a persistent cache. Objects are hashed through
+    /// their [Hash] implementations, which include the encoding of strings chosen by the standard
+    /// library, so the hash may change between versions of Rust as well as of locksmith.
+    pub fn schema_hash(&self) -> u64 {
+        let mut objects: Vec<_> = self.objects.iter().collect();
+        objects.sort();
+        let mut hasher = StableHasher::default();
+        self.major_version().hash(&mut hasher);
+        objects.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// The objects added and removed by a statement, as returned by
+/// [InspectedStatement::object_diff](crate::InspectedStatement::object_diff).
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DBObjectDiff {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
+    pub added_objects: HashSet<DBObject>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize_sorted"))]
+    pub removed_objects: HashSet<DBObject>,
+}
+
+impl DBObjectDiff {
+    /// Reduce the diff to the changes that are new relative to `snapshot`, such as a snapshot
+    /// taken at the last release, rather than relative to the database the statement was
+    /// inspected against.
+    ///
+    /// The database can have changed between the snapshot and the statement, for example by
+    /// earlier migrations. An added object that is already in the snapshot only restores it, and
+    /// a removed object that is not in the snapshot was created since it was taken, so neither
+    /// changes the database relative to the snapshot. What remains is the statement's
+    /// contribution to the difference between the snapshot and the database after the statement.
+    pub fn since(&self, snapshot: &SchemaSnapshot) -> DBObjectDiff {
+        DBObjectDiff {
+            added_objects: self
+                .added_objects
+                .difference(&snapshot.objects)
+                .cloned()
+                .collect(),
+            removed_objects: self
+                .removed_objects
+                .intersection(&snapshot.objects)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if no objects were added or removed.
+    pub fn is_empty(&self) -> bool {
+        self.added_objects.is_empty() && self.removed_objects.is_empty()
+    }
+
+    /// The added and removed objects, sorted, each with the reason it was changed.
+    ///
+    /// Dropping a table also removes all of its columns, indexes, constraints and triggers, and
+    /// the sequences owned by its columns, so they appear in the diff alongside the table. These are given the reason
+    /// [ChangeReason::WithTable], which distinguishes a column removed by
+    /// `ALTER TABLE ... DROP COLUMN` from one removed by `DROP TABLE`, and allows a report to
+    /// fold them into the change to their table rather than listing them separately. The same
+    /// applies to the objects of a created table.
+    pub fn changes(&self) -> ObjectChanges {
+        fn with_reasons(objects: &HashSet<DBObject>) -> Vec<ObjectChange> {
+            let tables: HashSet<_> = objects
+                .iter()
+                .filter_map(|object| match object {
+                    DBObject::Table(table) => Some(table.clone()),
+                    DBObject::ForeignTable(table) => Some(TableObject::from(table.name.as_str())),
+                    _ => None,
+                })
+                .collect();
+            let mut changes: Vec<_> = objects
+                .iter()
+                .map(|object| {
+                    let table = match object {
+                        DBObject::Sequence(sequence) => sequence
+                            .owned_by
+                            .as_deref()
+                            .and_then(|column| column.rsplit_once('.'))
+                            .map(|(table, _)| TableObject::from(table)),
+                        _ => object.table().cloned(),
+                    };
+                    ObjectChange {
+                        object: object.clone(),
+                        reason: match table {
+                            Some(table) if tables.contains(&table) => {
+                                ChangeReason::WithTable(table)
+                            }
+                            _ => ChangeReason::Direct,
+                        },
+                    }
+                })
+                .collect();
+            changes.sort_by(|a, b| a.object.cmp(&b.object));
+            changes
+        }
+        ObjectChanges {
+            added: with_reasons(&self.added_objects),
+            removed: with_reasons(&self.removed_objects),
+        }
+    }
+}
+
+/// The objects added and removed by a statement with the reason for each change, as returned by
+/// [DBObjectDiff::changes].
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ObjectChanges {
+    pub added: Vec<ObjectChange>,
+    pub removed: Vec<ObjectChange>,
+}
+
+/// An object that was added or removed, and the reason it was changed.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ObjectChange {
+    pub object: DBObject,
+    pub reason: ChangeReason,
+}
+
+/// Why an object is in a [DBObjectDiff], see [DBObjectDiff::changes].
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChangeReason {
+    /// The object itself was added or removed, such as a column dropped with
+    /// `ALTER TABLE ... DROP COLUMN`.
+    Direct,
+    /// The object was added or removed along with its table, such as the columns, indexes and
+    /// owned sequences of a table dropped with `DROP TABLE`.
+    WithTable(TableObject),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ChangeReason, ColumnObject, DBObject, DBObjectDiff, IndexObject, ObjectChange,
+        SchemaSnapshot, SequenceObject, TableObject,
+    };
+    use std::collections::HashSet;
+
+    fn column(table: &str, name: &str) -> DBObject {
+        DBObject::Column(ColumnObject {
+            table: table.into(),
+            name: name.to_string(),
+            data_type: "integer".to_string(),
+            generated: None,
+        })
+    }
+
+    #[test]
+    fn test_diff_since_snapshot() {
+        // The snapshot has orders.price and orders.total. Since it was taken, orders.total was
+        // dropped and orders.notes was added, and the statement then restores orders.total,
+        // drops orders.notes and orders.price, and adds the products table.
+        let snapshot = SchemaSnapshot {
+            objects: HashSet::from([
+                DBObject::Table(TableObject::from("orders")),
+                column("orders", "price"),
+                column("orders", "total"),
+            ]),
+            ..Default::default()
+        };
+        let diff = DBObjectDiff {
+            added_objects: HashSet::from([
+                column("orders", "total"),
+                DBObject::Table(TableObject::from("products")),
+            ]),
+            removed_objects: HashSet::from([column("orders", "notes"), column("orders", "price")]),
+        };
+
+        assert_eq!(
+            diff.since(&snapshot),
+            DBObjectDiff {
+                added_objects: HashSet::from([DBObject::Table(TableObject::from("products"))]),
+                removed_objects: HashSet::from([column("orders", "price")]),
+            }
+        );
+        assert_eq!(
+            diff.since(&snapshot).since(&snapshot),
+            diff.since(&snapshot)
+        );
+        assert!(
+            DBObjectDiff::default()
+                .since(&SchemaSnapshot::default())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_major_version() {
+        let version = |server_version_num| SchemaSnapshot {
+            server_version_num,
+            ..Default::default()
+        };
+        assert_eq!(version(150004).major_version(), 15);
+        assert_eq!(version(180000).major_version(), 18);
+        assert_eq!(version(90624).major_version(), 906);
+    }
+
+    #[test]
+    fn test_schema_hash() {
+        let snapshot = SchemaSnapshot {
+            server_version_num: 150000,
+            objects: HashSet::from([
+                DBObject::Table(TableObject::from("orders")),
+                column("orders", "price"),
+                column("orders", "total"),
+            ]),
+            file_nodes: vec![(DBObject::Table(TableObject::from("orders")), 16384)],
+        };
+        // The hash must not change between runs, or persisted cache keys would never match.
+        assert_eq!(snapshot.schema_hash(), 0x2835bbc16e2d28fa);
+
+        let other_database = SchemaSnapshot {
+            file_nodes: vec![(DBObject::Table(TableObject::from("orders")), 24576)],
+            ..snapshot.clone()
+        };
+        assert_eq!(other_database.schema_hash(), snapshot.schema_hash());
+
+        let mut changed = snapshot.clone();
+        changed.objects.remove(&column("orders", "total"));
+        assert_ne!(changed.schema_hash(), snapshot.schema_hash());
+        let minor_upgrade = SchemaSnapshot {
+            server_version_num: 150005,
+            ..snapshot.clone()
+        };
+        assert_eq!(minor_upgrade.schema_hash(), snapshot.schema_hash());
+        let upgraded = SchemaSnapshot {
+            server_version_num: 160000,
+            ..snapshot.clone()
+        };
+        assert_ne!(upgraded.schema_hash(), snapshot.schema_hash());
+    }
+
+    #[test]
+    fn test_diff_changes() {
+        let index = DBObject::Index(IndexObject {
+            table: "orders".into(),
+            name: "orders_price_idx".to_string(),
+            columns: vec!["price".to_string()],
+            is_primary: false,
+            is_unique: false,
+        });
+        // An index of a table with the same name in another schema is not removed with it.
+        let archived_index = DBObject::Index(IndexObject {
+            table: TableObject::in_schema("archive", "orders"),
+            name: "orders_price_idx".to_string(),
+            columns: vec!["price".to_string()],
+            is_primary: false,
+            is_unique: false,
+        });
+        let sequence = DBObject::Sequence(SequenceObject {
+            name: "orders_id_seq".to_string(),
+            start: 1,
+            increment: 1,
+            min: 1,
+            max: i32::MAX as i64,
+            cycle: false,
+            owned_by: Some("orders.id".to_string()),
+        });
+        let diff = DBObjectDiff {
+            added_objects: HashSet::new(),
+            removed_objects: HashSet::from([
+                DBObject::Table("orders".into()),
+                column("orders", "price"),
+                index.clone(),
+                archived_index.clone(),
+                column("customers", "email"),
+                sequence.clone(),
+            ]),
+        };
+        let with_orders = ChangeReason::WithTable("orders".into());
+        assert_eq!(
+            diff.changes().removed,
+            vec![
+                ObjectChange {
+                    object: DBObject::Table("orders".into()),
+                    reason: ChangeReason::Direct,
+                },
+                ObjectChange {
+                    object: column("customers", "email"),
+                    reason: ChangeReason::Direct,
+                },
+                ObjectChange {
+                    object: column("orders", "price"),
+                    reason: with_orders.clone(),
+                },
+                ObjectChange {
+                    object: index,
+                    reason: with_orders.clone(),
+                },
+                ObjectChange {
+                    object: archived_index,
+                    reason: ChangeReason::Direct,
+                },
+                ObjectChange {
+                    object: sequence,
+                    reason: with_orders,
+                },
+            ]
+        );
+        assert!(diff.changes().added.is_empty());
+    }
+}
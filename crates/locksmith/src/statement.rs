@@ -0,0 +1,321 @@
+/// Find the first command in `sql` that ends or replaces the transaction it is executed in, such
+/// as `COMMIT`, `ROLLBACK` or `BEGIN`, returning its name.
+///
+/// Savepoints (`SAVEPOINT`, `RELEASE` and `ROLLBACK TO`) only affect a subtransaction within the
+/// current transaction, so they are not returned.
+///
+/// `sql` is split into statements in the same way as `psql` does, so semicolons within string
+/// literals, quoted identifiers, dollar-quoted bodies, comments and `BEGIN ATOMIC ... END`
+/// function bodies do not end a statement.
+pub(crate) fn transaction_control_command(sql: &str) -> Option<String> {
+    split_statements(sql)
+        .into_iter()
+        .find_map(|words| match words.as_slice() {
+            [first, ..]
+                if ["BEGIN", "START", "COMMIT", "END", "ABORT"].contains(&first.as_str()) =>
+            {
+                Some(first.clone())
+            }
+            [first, rest @ ..] if first == "ROLLBACK" => {
+                let is_savepoint = rest
+                    .iter()
+                    .find(|word| *word != "WORK" && *word != "TRANSACTION")
+                    .is_some_and(|word| word == "TO");
+                (!is_savepoint).then(|| first.clone())
+            }
+            [first, second, ..] if first == "PREPARE" && second == "TRANSACTION" => {
+                Some("PREPARE TRANSACTION".to_string())
+            }
+            _ => None,
+        })
+}
+
+/// Returns `true` if `sql` contains a concurrent index or partition operation, such as
+/// `CREATE INDEX CONCURRENTLY` or `ALTER TABLE ... DETACH PARTITION ... CONCURRENTLY`. These
+/// cannot run inside a transaction block.
+pub(crate) fn uses_concurrently(sql: &str) -> bool {
+    split_statements(sql).into_iter().any(|words| {
+        let is_concurrently = |word: &String| word == "CONCURRENTLY";
+        match words.first().map(String::as_str) {
+            // `CREATE [UNIQUE] INDEX CONCURRENTLY`, `DROP INDEX CONCURRENTLY` and
+            // `REINDEX {INDEX | TABLE} CONCURRENTLY`, with an optional parenthesized option list.
+            Some("CREATE" | "DROP" | "REINDEX") => words.iter().take(4).any(is_concurrently),
+            Some("ALTER") => words.last().is_some_and(is_concurrently),
+            _ => false,
+        }
+    })
+}
+
+/// Returns whether each statement in `sql`, in order, modifies rows: an `INSERT`, `UPDATE`,
+/// `DELETE` or `MERGE`, including one whose `WITH` clause contains any of these. The command tag
+/// of each of these statements counts the rows it modified.
+pub(crate) fn modifies_rows(sql: &str) -> Vec<bool> {
+    const DML_COMMANDS: [&str; 4] = ["INSERT", "UPDATE", "DELETE", "MERGE"];
+    split_statements(sql)
+        .into_iter()
+        .map(|words| match words.first().map(String::as_str) {
+            Some("WITH") => words
+                .iter()
+                .any(|word| DML_COMMANDS.contains(&word.as_str())),
+            Some(first) => DML_COMMANDS.contains(&first),
+            None => false,
+        })
+        .collect()
+}
+
+/// Split `sql` into the text of each of its statements, including the terminating semicolon and
+/// any comments before the statement, with surrounding whitespace trimmed. Statements without
+/// any words, such as a comment after the last statement, are omitted.
+pub(crate) fn split_sql(sql: &str) -> Vec<String> {
+    split(sql).into_iter().map(|(_, text)| text).collect()
+}
+
+/// Split `sql` into statements, returning the unquoted words of each statement in upper case.
+/// Statements without any words, such as those only containing a comment, are omitted.
+fn split_statements(sql: &str) -> Vec<Vec<String>> {
+    split(sql).into_iter().map(|(words, _)| words).collect()
+}
+
+/// Split `sql` into statements, returning the words of each statement, as in
+/// [split_statements], alongside its text, as in [split_sql].
+fn split(sql: &str) -> Vec<(Vec<String>, String)> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements = vec![];
+    let mut words: Vec<String> = vec![];
+    // The index of the first character of the current statement.
+    let mut start = 0;
+    // The number of `BEGIN` or `CASE` blocks opened, but not yet ended, in a `CREATE` statement.
+    let mut block_depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                // Block comments can be nested.
+                let mut depth = 0;
+                while i < chars.len() {
+                    if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            '\'' => i = skip_quoted(&chars, i, '\'', false),
+            '"' => {
+                // Quoted identifiers are kept as words, so that they are never mistaken for
+                // keywords.
+                let end = skip_quoted(&chars, i, '"', false);
+                words.push(chars[i..end].iter().collect());
+                i = end;
+            }
+            '$' => {
+                let tag_end = chars[i + 1..]
+                    .iter()
+                    .position(|c| !(c.is_alphanumeric() || *c == '_'))
+                    .map(|position| i + 1 + position);
+                match tag_end {
+                    Some(tag_end) if chars[tag_end] == '$' && !chars[i + 1].is_ascii_digit() => {
+                        let tag = &chars[i..=tag_end];
+                        i = tag_end + 1;
+                        while i < chars.len() && !chars[i..].starts_with(tag) {
+                            i += 1;
+                        }
+                        i = (i + tag.len()).min(chars.len());
+                    }
+                    // A positional parameter, such as `$1`.
+                    _ => i += 1,
+                }
+            }
+            ';' if block_depth == 0 => {
+                i += 1;
+                if !words.is_empty() {
+                    let text = chars[start..i].iter().collect::<String>();
+                    statements.push((std::mem::take(&mut words), text.trim().to_string()));
+                }
+                start = i;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$')
+                {
+                    i += 1;
+                }
+                let word = chars[start..i].iter().collect::<String>().to_uppercase();
+                // Escape string constants, such as `E'\''`, can contain escaped quotes.
+                if word == "E" && chars.get(i) == Some(&'\'') {
+                    i = skip_quoted(&chars, i, '\'', true);
+                    continue;
+                }
+                if words.first().is_some_and(|first| first == "CREATE") {
+                    match word.as_str() {
+                        "BEGIN" | "CASE" => block_depth += 1,
+                        "END" => block_depth = block_depth.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+                words.push(word);
+            }
+            _ => i += 1,
+        }
+    }
+    if !words.is_empty() {
+        let text = chars[start..].iter().collect::<String>();
+        statements.push((words, text.trim().to_string()));
+    }
+    statements
+}
+
+/// Return the index after the end of the literal or identifier quoted with `quote` that starts
+/// at `start`. A doubled quote is an escaped quote, as is a quote preceded by a backslash if
+/// `backslash_escapes` is set.
+fn skip_quoted(chars: &[char], start: usize, quote: char, backslash_escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if backslash_escapes && chars[i] == '\\' {
+            i += 2;
+        } else if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{modifies_rows, split_sql, transaction_control_command, uses_concurrently};
+
+    #[test]
+    fn test_transaction_control_command() {
+        let cases = [
+            ("alter table orders add column x int;", None),
+            ("commit;", Some("COMMIT")),
+            (
+                "alter table orders add column x int; COMMIT",
+                Some("COMMIT"),
+            ),
+            ("begin; alter table orders add column x int;", Some("BEGIN")),
+            ("start transaction;", Some("START")),
+            ("end", Some("END")),
+            ("abort;", Some("ABORT")),
+            ("rollback;", Some("ROLLBACK")),
+            ("rollback and chain;", Some("ROLLBACK")),
+            ("prepare transaction 'x';", Some("PREPARE TRANSACTION")),
+            ("prepare q as select 1; execute q;", None),
+            (
+                "savepoint a; alter table orders add column x int; rollback to savepoint a; \
+                 release a; rollback work to a;",
+                None,
+            ),
+            ("select 'commit; rollback';", None),
+            ("select E'\\'; commit; select 1';", None),
+            (
+                "select 1 as \"commit\"; -- commit;\n /* /* nested */ commit; */",
+                None,
+            ),
+            (
+                "do $body$ begin commit; end $body$; create function f() returns int as $$ \
+                 begin return 1; end $$ language plpgsql;",
+                None,
+            ),
+            (
+                "create function f() returns int language sql begin atomic \
+                 select case when true then 1 end; select 2; end; commit;",
+                Some("COMMIT"),
+            ),
+            ("select $1; commit;", Some("COMMIT")),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(
+                transaction_control_command(sql).as_deref(),
+                expected,
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_uses_concurrently() {
+        for (sql, expected) in [
+            ("drop index orders_price_idx;", false),
+            ("drop index concurrently orders_price_idx;", true),
+            ("create unique index concurrently on orders (price);", true),
+            (
+                "reindex (verbose) index concurrently orders_price_idx;",
+                true,
+            ),
+            (
+                "alter table events detach partition events_2024 concurrently;",
+                true,
+            ),
+            ("create index on orders (concurrently);", false),
+            ("select 'drop index concurrently x';", false),
+        ] {
+            assert_eq!(uses_concurrently(sql), expected, "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_modifies_rows() {
+        let cases = [
+            ("alter table orders add column x int;", vec![false]),
+            (
+                "update orders set price = 1; delete from orders; -- comment;\n select 1;",
+                vec![true, true, false],
+            ),
+            (
+                "insert into orders select * from orders; merge into orders using customers on true \
+                 when matched then do nothing;",
+                vec![true, true],
+            ),
+            (
+                "with moved as (delete from orders returning *) insert into orders select * from moved;",
+                vec![true],
+            ),
+            ("with ids as (select 1) select * from ids;", vec![false]),
+            (
+                "select 'update'; do $$ begin delete from orders; end $$;",
+                vec![false, false],
+            ),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(modifies_rows(sql), expected, "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_split_sql() {
+        let sql = "-- Rename orders\nalter table orders rename to purchases;\n\n\
+                   create function f() returns int language sql begin atomic select 1; end;\n\
+                   select ';' -- trailing;\n-- final comment\n";
+        assert_eq!(
+            split_sql(sql),
+            vec![
+                "-- Rename orders\nalter table orders rename to purchases;",
+                "create function f() returns int language sql begin atomic select 1; end;",
+                "select ';' -- trailing;\n-- final comment",
+            ]
+        );
+        assert_eq!(split_sql("select 1"), vec!["select 1"]);
+        assert!(split_sql("-- nothing to see here;").is_empty());
+    }
+}
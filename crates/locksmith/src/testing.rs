@@ -0,0 +1,214 @@
+//! Start Postgres containers to test schemas and statements against, enabled by the `testing`
+//! feature.
+//!
+//! A [PostgresTestHarness] is a Postgres container started with [testcontainers], configured
+//! through a [PostgresTestHarnessBuilder]. The container runs until the harness is dropped, so
+//! it must be kept alive for as long as its [DSN](PostgresTestHarness::dsn) is used:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use locksmith::QueryOracle;
+//! use locksmith::testing::PostgresTestHarnessBuilder;
+//!
+//! let harness = PostgresTestHarnessBuilder::new()
+//!     .with_init_sql("create table orders (id serial primary key, price integer);")
+//!     .with_setting("lock_timeout", "1s")
+//!     .start()
+//!     .await?;
+//! let mut oracle = QueryOracle::new(harness.dsn());
+//! let inspected = oracle
+//!     .inspect_statement("alter table orders add column notes text;")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [testcontainers]: testcontainers_modules::testcontainers
+
+use anyhow::Context;
+use std::path::PathBuf;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::core::CopyDataSource;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt};
+
+/// The image tag used when neither [PostgresTestHarnessBuilder::with_tag] nor the
+/// `TEST_POSTGRES_TAG` environment variable is set.
+pub const DEFAULT_POSTGRES_TAG: &str = "14-alpine";
+
+/// Configures and starts a [PostgresTestHarness].
+///
+/// By default this starts the `postgres` image with the tag in the `TEST_POSTGRES_TAG`
+/// environment variable, or [DEFAULT_POSTGRES_TAG] if it is not set, with an empty `postgres`
+/// database that the user `user` with the password `password` can connect to. Fsync is disabled,
+/// as the data is discarded with the container.
+#[derive(Debug, Clone)]
+pub struct PostgresTestHarnessBuilder {
+    image: Option<String>,
+    tag: String,
+    user: String,
+    password: String,
+    init_sql: Vec<CopyDataSource>,
+    settings: Vec<(String, String)>,
+}
+
+impl Default for PostgresTestHarnessBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostgresTestHarnessBuilder {
+    pub fn new() -> Self {
+        Self {
+            image: None,
+            tag: std::env::var("TEST_POSTGRES_TAG")
+                .unwrap_or_else(|_| DEFAULT_POSTGRES_TAG.to_string()),
+            user: "user".to_string(),
+            password: "password".to_string(),
+            init_sql: vec![],
+            settings: vec![],
+        }
+    }
+
+    /// Use the image `name` rather than `postgres`, such as an image with extensions installed.
+    /// The image must accept the same environment variables and init scripts as the official
+    /// image.
+    pub fn with_image(mut self, name: impl Into<String>) -> Self {
+        self.image = Some(name.into());
+        self
+    }
+
+    /// Use the image tag `tag`, such as `17-alpine`.
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = tag.into();
+        self
+    }
+
+    /// Connect as `user` with `password`, which is created as the superuser of the database.
+    /// Both are included in the [DSN](PostgresTestHarness::dsn) as they are, so they must not
+    /// contain characters that need to be percent-encoded in a URL.
+    pub fn with_credentials(
+        mut self,
+        user: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.user = user.into();
+        self.password = password.into();
+        self
+    }
+
+    /// Run `sql` when the database is first created, such as to create the schema under test.
+    /// Init scripts run in the order they are added.
+    pub fn with_init_sql(mut self, sql: impl Into<String>) -> Self {
+        self.init_sql
+            .push(CopyDataSource::Data(sql.into().into_bytes()));
+        self
+    }
+
+    /// Run the SQL file at `path` when the database is first created, in the same way as
+    /// [Self::with_init_sql]. The file is read when the container is started.
+    pub fn with_init_sql_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.init_sql.push(CopyDataSource::File(path.into()));
+        self
+    }
+
+    /// Start the server with the setting `name` set to `value`, as if it was given in
+    /// `postgresql.conf`, such as `max_locks_per_transaction`.
+    pub fn with_setting(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.settings.push((name.into(), value.into()));
+        self
+    }
+
+    /// The command the container is started with, which replaces the default of the image.
+    fn command(&self) -> Vec<String> {
+        let settings = self
+            .settings
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"));
+        let mut cmd = vec!["postgres".to_string()];
+        for setting in std::iter::once("fsync=off".to_string()).chain(settings) {
+            cmd.extend(["-c".to_string(), setting]);
+        }
+        cmd
+    }
+
+    /// Start the container and wait for the database to accept connections, after the init
+    /// scripts have run.
+    pub async fn start(self) -> anyhow::Result<PostgresTestHarness> {
+        let mut postgres = Postgres::default()
+            .with_user(&self.user)
+            .with_password(&self.password);
+        for sql in &self.init_sql {
+            postgres = postgres.with_init_sql(sql.clone());
+        }
+        let mut request = postgres.with_tag(&self.tag).with_cmd(self.command());
+        if let Some(image) = &self.image {
+            request = request.with_name(image);
+        }
+        let image = self.image.as_deref().unwrap_or("postgres");
+        let container = request
+            .start()
+            .await
+            .with_context(|| format!("Starting Postgres container {image}:{}", self.tag))?;
+        let id = container.id();
+        let host_ip = container
+            .get_host()
+            .await
+            .with_context(|| format!("Retrieving IP for container {id}"))?;
+        let host_port = container
+            .get_host_port_ipv4(5432)
+            .await
+            .with_context(|| format!("Retrieving mapped port 5432 for container {id}"))?;
+        let dsn = format!(
+            "postgresql://{}:{}@{host_ip}:{host_port}/postgres",
+            self.user, self.password
+        );
+        Ok(PostgresTestHarness { container, dsn })
+    }
+}
+
+/// A running Postgres container, started by [PostgresTestHarnessBuilder::start]. The container
+/// is stopped and removed when the harness is dropped.
+pub struct PostgresTestHarness {
+    container: ContainerAsync<Postgres>,
+    dsn: String,
+}
+
+impl PostgresTestHarness {
+    /// The DSN to connect to the `postgres` database of the container with.
+    pub fn dsn(&self) -> &str {
+        &self.dsn
+    }
+
+    /// The underlying container, such as to read its logs.
+    pub fn container(&self) -> &ContainerAsync<Postgres> {
+        &self.container
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostgresTestHarnessBuilder;
+
+    #[test]
+    fn test_command() {
+        let builder = PostgresTestHarnessBuilder::new();
+        assert_eq!(builder.command(), vec!["postgres", "-c", "fsync=off"]);
+        let builder = builder
+            .with_setting("max_locks_per_transaction", "128")
+            .with_setting("lock_timeout", "1s");
+        assert_eq!(
+            builder.command(),
+            vec![
+                "postgres",
+                "-c",
+                "fsync=off",
+                "-c",
+                "max_locks_per_transaction=128",
+                "-c",
+                "lock_timeout=1s"
+            ]
+        );
+    }
+}
@@ -1,31 +1,22 @@
 use crate::TableObject;
+use crate::clock::Clock;
 use crate::introspection::Introspector;
 use crate::locker::Locker;
-use anyhow::Context;
-use testcontainers_modules::postgres;
-use testcontainers_modules::postgres::Postgres;
-use testcontainers_modules::testcontainers::runners::AsyncRunner;
-use testcontainers_modules::testcontainers::{ContainerAsync, ImageExt};
+use crate::testing::{PostgresTestHarness, PostgresTestHarnessBuilder};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 #[must_use = "Postgres container must be used"]
-pub async fn start_test_postgres() -> (ContainerAsync<Postgres>, String) {
-    let postgres_tag = std::env::var("TEST_POSTGRES_TAG").unwrap_or("14-alpine".to_string());
-    let container = postgres::Postgres::default()
-        .with_init_sql(include_bytes!("../tests/test_schema.sql").to_vec())
-        .with_user("user")
-        .with_password("password")
-        .with_tag(&postgres_tag)
+pub async fn start_test_postgres() -> (PostgresTestHarness, String) {
+    let harness = PostgresTestHarnessBuilder::new()
+        .with_init_sql(include_str!("../tests/test_schema.sql"))
         .start()
         .await
-        .context("Starting Postgres container")
         .unwrap();
-    let host_ip = container.get_host().await.unwrap();
-    let host_port = container.get_host_port_ipv4(5432).await.unwrap();
-
-    (
-        container,
-        format!("postgresql://user:password@{host_ip}:{host_port}/postgres"),
-    )
+    let dsn = harness.dsn().to_string();
+    (harness, dsn)
 }
 
 /// Create a locker, and lock the given tables.
@@ -43,3 +34,53 @@ pub async fn table_exists(dsn: &str, table: impl Into<TableObject>) -> bool {
     let tables = introspector.list_tables().await.unwrap();
     tables.into_iter().any(|t| t == table)
 }
+
+/// A [Clock] whose time only moves when it is slept on, which jumps straight to the deadline.
+/// Timings measured with it are therefore exact sums of the sleeps taken, rather than depending
+/// on how long the database takes to respond.
+///
+/// A [MockClock::frozen] clock never moves, so sleeping on it never completes.
+pub struct MockClock {
+    start: Instant,
+    now: Mutex<Instant>,
+    frozen: bool,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            now: Mutex::new(start),
+            frozen: false,
+        }
+    }
+
+    pub fn frozen() -> Self {
+        Self {
+            frozen: true,
+            ..Self::new()
+        }
+    }
+
+    /// The time that has passed on the clock since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.now() - self.start
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        if self.frozen {
+            return Box::pin(std::future::pending());
+        }
+        Box::pin(async move {
+            let mut now = self.now.lock().unwrap();
+            *now = (*now).max(deadline);
+        })
+    }
+}
@@ -0,0 +1,337 @@
+//! The TLS implementation used by the [StatementExecutor](crate::executor::StatementExecutor),
+//! selected by the `tls-rustls` and `tls-native-tls` features, and the [TlsConfig] of all
+//! connections.
+//!
+//! The [Introspector](crate::Introspector) and [Locker](crate::Locker) use sqlx, which selects its
+//! TLS implementation through the same features. Whether TLS is used is controlled by the
+//! `sslmode` parameter of the DSN, which is interpreted by both drivers. If both features are
+//! enabled, rustls is used.
+//!
+//! tokio-postgres only supports the `disable`, `prefer` and `require` modes, which do not verify
+//! the server's certificate, so by default the executor's connector accepts any certificate. This
+//! matches how sqlx and libpq treat these modes. To verify the server's certificate, such as that
+//! of a managed database, use [TlsConfig::Verified], which requires TLS for all connections
+//! regardless of the `sslmode` of the DSN.
+
+use anyhow::Context;
+use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio_postgres::Socket;
+use tokio_postgres::config::SslMode;
+use tokio_postgres::tls::MakeTlsConnect;
+
+/// How connections to the database use TLS, given to
+/// [QueryOracleBuilder::tls](crate::QueryOracleBuilder::tls) and the `new_with_tls` constructors of
+/// the [StatementExecutor](crate::StatementExecutor), [Introspector](crate::Introspector),
+/// [Locker](crate::Locker) and [ConnectionPool](crate::ConnectionPool).
+///
+/// Certificates and keys are read from PEM files when each connection is opened. Verifying
+/// certificates requires the `tls-rustls` or `tls-native-tls` feature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TlsConfig {
+    /// Use TLS as given by the `sslmode` of the DSN, without verifying the server's certificate.
+    /// Connections only use TLS if one of the TLS features is enabled.
+    #[default]
+    Unverified,
+    /// Require TLS, and verify that the server's certificate is signed by the certificate
+    /// authority in `root_certificate` and matches the host of the DSN, as with
+    /// `sslmode=verify-full`. If `client_certificate` is set, it is presented to the server.
+    Verified {
+        root_certificate: PathBuf,
+        client_certificate: Option<ClientCertificate>,
+    },
+}
+
+/// A client certificate and its private key, presented to the server to authenticate with
+/// [TlsConfig::Verified].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The PEM file containing the certificate, followed by any intermediate certificates.
+    pub certificate: PathBuf,
+    /// The PEM file containing the certificate's private key, in PKCS #8 format.
+    pub key: PathBuf,
+}
+
+impl TlsConfig {
+    /// The sqlx options to connect to the database at `dsn` with, for the
+    /// [Introspector](crate::Introspector), [Locker](crate::Locker) and
+    /// [ConnectionPool](crate::ConnectionPool).
+    pub(crate) fn connect_options(&self, dsn: &str) -> anyhow::Result<PgConnectOptions> {
+        let options = PgConnectOptions::from_str(dsn).context("Parsing DSN")?;
+        Ok(match self {
+            TlsConfig::Unverified => options,
+            TlsConfig::Verified {
+                root_certificate,
+                client_certificate,
+            } => {
+                let options = options
+                    .ssl_mode(PgSslMode::VerifyFull)
+                    .ssl_root_cert(root_certificate);
+                match client_certificate {
+                    Some(client) => options
+                        .ssl_client_cert(&client.certificate)
+                        .ssl_client_key(&client.key),
+                    None => options,
+                }
+            }
+        })
+    }
+
+    /// Apply the configuration to the tokio-postgres `config` of an executor connection, which
+    /// must then be opened with the connector from [make_tls].
+    pub(crate) fn configure(&self, config: &mut tokio_postgres::Config) {
+        if let TlsConfig::Verified { .. } = self {
+            config.ssl_mode(SslMode::Require);
+        }
+    }
+}
+
+#[cfg(feature = "tls-rustls")]
+pub(crate) type MakeTls = tokio_postgres_rustls::MakeRustlsConnect;
+
+#[cfg(all(feature = "tls-native-tls", not(feature = "tls-rustls")))]
+pub(crate) type MakeTls = postgres_native_tls::MakeTlsConnector;
+
+#[cfg(not(any(feature = "tls-rustls", feature = "tls-native-tls")))]
+pub(crate) type MakeTls = tokio_postgres::NoTls;
+
+/// The stream type of a connection created with [MakeTls].
+pub(crate) type TlsStream = <MakeTls as MakeTlsConnect<Socket>>::Stream;
+
+/// Create the TLS connector for new executor connections.
+#[cfg(feature = "tls-rustls")]
+pub(crate) fn make_tls(tls: &TlsConfig) -> anyhow::Result<MakeTls> {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use std::sync::Arc;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("Creating rustls configuration")?;
+    let config = match tls {
+        TlsConfig::Unverified => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(rustls_verifier::AcceptAnyCertificate(
+                provider,
+            )))
+            .with_no_client_auth(),
+        TlsConfig::Verified {
+            root_certificate,
+            client_certificate,
+        } => {
+            let read_certificates = |path: &PathBuf| {
+                CertificateDer::pem_file_iter(path)
+                    .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+                    .with_context(|| format!("Reading certificates from {}", path.display()))
+            };
+            let mut roots = rustls::RootCertStore::empty();
+            for certificate in read_certificates(root_certificate)? {
+                roots.add(certificate).context("Adding root certificate")?;
+            }
+            let builder = builder.with_root_certificates(roots);
+            match client_certificate {
+                Some(client) => {
+                    let key = PrivateKeyDer::from_pem_file(&client.key).with_context(|| {
+                        format!("Reading private key from {}", client.key.display())
+                    })?;
+                    builder
+                        .with_client_auth_cert(read_certificates(&client.certificate)?, key)
+                        .context("Configuring client certificate")?
+                }
+                None => builder.with_no_client_auth(),
+            }
+        }
+    };
+    Ok(tokio_postgres_rustls::MakeRustlsConnect::new(config))
+}
+
+/// Create the TLS connector for new executor connections.
+#[cfg(all(feature = "tls-native-tls", not(feature = "tls-rustls")))]
+pub(crate) fn make_tls(tls: &TlsConfig) -> anyhow::Result<MakeTls> {
+    let read =
+        |path: &PathBuf| std::fs::read(path).with_context(|| format!("Reading {}", path.display()));
+    let mut builder = native_tls::TlsConnector::builder();
+    match tls {
+        TlsConfig::Unverified => {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        TlsConfig::Verified {
+            root_certificate,
+            client_certificate,
+        } => {
+            let root = native_tls::Certificate::from_pem(&read(root_certificate)?)
+                .context("Parsing root certificate")?;
+            builder
+                .disable_built_in_roots(true)
+                .add_root_certificate(root);
+            if let Some(client) = client_certificate {
+                let identity = native_tls::Identity::from_pkcs8(
+                    &read(&client.certificate)?,
+                    &read(&client.key)?,
+                )
+                .context("Parsing client certificate")?;
+                builder.identity(identity);
+            }
+        }
+    }
+    let connector = builder.build().context("Creating native TLS connector")?;
+    Ok(postgres_native_tls::MakeTlsConnector::new(connector))
+}
+
+/// Create the TLS connector for new executor connections. No TLS features are enabled, so
+/// connections that require TLS fail, and certificates cannot be verified.
+#[cfg(not(any(feature = "tls-rustls", feature = "tls-native-tls")))]
+pub(crate) fn make_tls(tls: &TlsConfig) -> anyhow::Result<MakeTls> {
+    anyhow::ensure!(
+        *tls == TlsConfig::Unverified,
+        "Verifying certificates requires the tls-rustls or tls-native-tls feature"
+    );
+    Ok(tokio_postgres::NoTls)
+}
+
+#[cfg(feature = "tls-rustls")]
+mod rustls_verifier {
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{DigitallySignedStruct, Error, SignatureScheme};
+    use std::sync::Arc;
+
+    /// A certificate verifier that accepts any server certificate, whilst still checking that
+    /// the handshake is signed by it.
+    #[derive(Debug)]
+    pub(super) struct AcceptAnyCertificate(pub(super) Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for AcceptAnyCertificate {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientCertificate, TlsConfig};
+    use sqlx::postgres::PgSslMode;
+    use tokio_postgres::config::SslMode;
+
+    fn verified() -> TlsConfig {
+        TlsConfig::Verified {
+            root_certificate: "root.crt".into(),
+            client_certificate: Some(ClientCertificate {
+                certificate: "client.crt".into(),
+                key: "client.key".into(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_connect_options() {
+        let dsn = "postgresql://user@localhost/db?sslmode=disable";
+        let options = TlsConfig::Unverified.connect_options(dsn).unwrap();
+        assert!(matches!(options.get_ssl_mode(), PgSslMode::Disable));
+        let options = verified().connect_options(dsn).unwrap();
+        assert!(matches!(options.get_ssl_mode(), PgSslMode::VerifyFull));
+    }
+
+    #[test]
+    fn test_configure() {
+        let dsn = "postgresql://user@localhost/db?sslmode=disable";
+        let mut config: tokio_postgres::Config = dsn.parse().unwrap();
+        TlsConfig::Unverified.configure(&mut config);
+        assert_eq!(config.get_ssl_mode(), SslMode::Disable);
+        verified().configure(&mut config);
+        assert_eq!(config.get_ssl_mode(), SslMode::Require);
+    }
+
+    #[cfg(any(feature = "tls-rustls", feature = "tls-native-tls"))]
+    #[test]
+    fn test_make_tls_missing_certificate() {
+        let error = super::make_tls(&verified()).err().unwrap();
+        assert!(format!("{error:#}").contains("root.crt"), "{error:#}");
+    }
+
+    /// Connect to a server that has TLS enabled with `sslmode=require`, which fails if any of the
+    /// oracle's connections cannot negotiate TLS.
+    #[cfg(any(feature = "tls-rustls", feature = "tls-native-tls"))]
+    #[tokio::test]
+    async fn test_connect_with_sslmode_require() {
+        use crate::QueryOracle;
+        use crate::testing::{DEFAULT_POSTGRES_TAG, PostgresTestHarnessBuilder};
+        use sqlx::{Connection, PgConnection};
+
+        // The Debian images, unlike the Alpine ones, include a self-signed "snakeoil" certificate.
+        let tag = std::env::var("TEST_POSTGRES_TAG")
+            .unwrap_or_else(|_| DEFAULT_POSTGRES_TAG.to_string())
+            .trim_end_matches("-alpine")
+            .to_string();
+        let harness = PostgresTestHarnessBuilder::new()
+            .with_tag(tag)
+            .with_init_sql("create table orders (id integer primary key);")
+            .with_setting("ssl", "on")
+            .with_setting("ssl_cert_file", "/etc/ssl/certs/ssl-cert-snakeoil.pem")
+            .with_setting("ssl_key_file", "/etc/ssl/private/ssl-cert-snakeoil.key")
+            .start()
+            .await
+            .unwrap();
+        let dsn = format!("{}?sslmode=require", harness.dsn());
+
+        let mut oracle = QueryOracle::new(&dsn);
+        let inspected = oracle
+            .inspect_statement("alter table orders add column price integer;")
+            .await
+            .unwrap();
+        assert!(!inspected.locks.is_empty());
+
+        let mut conn = PgConnection::connect(&dsn).await.unwrap();
+        let ssl: bool =
+            sqlx::query_scalar("SELECT ssl FROM pg_stat_ssl WHERE pid = pg_backend_pid()")
+                .fetch_one(&mut conn)
+                .await
+                .unwrap();
+        assert!(ssl);
+    }
+}
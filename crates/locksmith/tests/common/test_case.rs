@@ -1,4 +1,8 @@
-use locksmith::{DBObject, InspectedStatement, TableLock};
+use locksmith::{
+    DBObject, InspectedStatement, LintRule, LockCost, RenamedObject, RewriteColumns, TableLock,
+    TableObject,
+};
+use serde::Deserialize;
 use std::collections::HashSet;
 use std::str::FromStr;
 
@@ -15,23 +19,101 @@ use std::str::FromStr;
 /// alter table customers alter column id type bigint;
 /// ```
 ///
+/// Statements that do not lock any tables, such as those that only lock an index, are given a
+/// single `-- lock: none` line, so that a test case is never missing its lock expectations by
+/// mistake.
+///
+/// Locks that are taken when committing the statement are given as `-- commit lock:` lines, and
+/// are expected in both the `locks` and `commit_locks` of the result.
+///
+/// Views that depend on a removed or altered object are given as `-- dependent:` lines.
+///
+/// Renamed objects are given as `-- renamed:` lines, such as
+/// `{"from": {"Index": {...}}, "to": {"Index": {...}}}`.
+///
+/// The [lint rules](InspectedStatement::lint) expected to fire are given as `-- lint:` lines,
+/// such as `"prefer-create-index-concurrently"`. No warnings are expected if there are none.
+///
+/// The cost class of each locked table is given as `-- cost:` lines, such as
+/// `{"table": {"name": "customers"}, "cost": "Rewrite"}`. Table sizes depend on the Postgres
+/// version, so only the cost class is checked. For the same reason, only the tables of the
+/// [rewrite estimates](InspectedStatement::rewrite_estimates) are checked, against the rewrites.
+///
+/// The columns that may have caused each rewrite are given as `-- rewrite columns:` lines, such as
+/// `{"table": {"name": "customers"}, "columns": ["id"]}`.
+///
+/// Statements that are executed and committed before the statement is inspected, such as the
+/// first step of a change that is made in several migrations, are given as `-- setup:` lines.
+///
 /// See the `queries` directory for more examples.
 #[derive(Debug, Default)]
 pub struct TestCase {
+    pub setup: Vec<String>,
     pub statement: String,
     pub expected_locks: HashSet<TableLock>,
+    pub expect_no_locks: bool,
+    pub expected_commit_locks: HashSet<TableLock>,
     pub expected_removals: HashSet<DBObject>,
     pub expected_additions: HashSet<DBObject>,
+    pub expected_renames: HashSet<RenamedObject>,
     pub expected_rewrites: HashSet<DBObject>,
+    pub expected_rewrite_columns: HashSet<RewriteColumns>,
+    pub expected_costs: HashSet<ExpectedCost>,
+    pub expected_dependents: HashSet<DBObject>,
+    pub expected_lints: Vec<LintRule>,
+}
+
+/// The expected cost class of the locks taken on a table.
+#[derive(Debug, Hash, Eq, PartialEq, Deserialize)]
+pub struct ExpectedCost {
+    pub table: TableObject,
+    pub cost: LockCost,
 }
 
 impl TestCase {
     pub fn check_result(self, result: InspectedStatement) {
+        let lints: Vec<_> = result.lint().into_iter().map(|lint| lint.rule).collect();
+        assert_eq!(
+            self.expected_lints,
+            lints,
+            "Lint mismatch:\n{:#?}",
+            result.lint()
+        );
+        let costs: HashSet<_> = result
+            .lock_costs
+            .iter()
+            .map(|cost| ExpectedCost {
+                table: cost.table.clone(),
+                cost: cost.cost,
+            })
+            .collect();
+        assert_eq!(
+            self.expected_costs, costs,
+            "Cost mismatch:\n{:#?}\n\n{:#?}",
+            self.expected_costs, result.lock_costs
+        );
+        let estimated_rewrites: HashSet<_> = result
+            .rewrite_estimates
+            .iter()
+            .map(|estimate| DBObject::Table(estimate.table.clone()))
+            .collect();
+        assert_eq!(
+            self.expected_rewrites, estimated_rewrites,
+            "Rewrite estimate mismatch:\n{:#?}",
+            result.rewrite_estimates
+        );
         let expected = InspectedStatement {
             added_objects: self.expected_additions,
             removed_objects: self.expected_removals,
+            renamed: self.expected_renames,
             locks: self.expected_locks,
+            commit_locks: self.expected_commit_locks,
             rewrites: self.expected_rewrites,
+            rewrite_estimates: result.rewrite_estimates.clone(),
+            rewrite_columns: self.expected_rewrite_columns,
+            lock_costs: result.lock_costs.clone(),
+            dependents: self.expected_dependents,
+            ..Default::default()
         };
         assert_eq!(
             expected, result,
@@ -57,11 +139,18 @@ impl FromStr for TestCase {
             let (expectation, json) = line.split_once(": ").expect("Invalid line format");
             let expectation = expectation.trim_start_matches("-- ");
             match expectation {
+                "lock" if json.trim() == "none" => test_case.expect_no_locks = true,
                 "lock" => {
                     let obj: TableLock = serde_json::from_str(json)
                         .unwrap_or_else(|_| panic!("Invalid lock line: {}", line));
                     test_case.expected_locks.insert(obj);
                 }
+                "commit lock" => {
+                    let obj: TableLock = serde_json::from_str(json)
+                        .unwrap_or_else(|_| panic!("Invalid commit lock line: {}", line));
+                    test_case.expected_locks.insert(obj.clone());
+                    test_case.expected_commit_locks.insert(obj);
+                }
                 "removed" => {
                     let obj: DBObject = serde_json::from_str(json)
                         .unwrap_or_else(|_| panic!("Invalid removed line: {}", line));
@@ -72,18 +161,44 @@ impl FromStr for TestCase {
                         .unwrap_or_else(|_| panic!("Invalid addition line: {}", line));
                     test_case.expected_additions.insert(obj);
                 }
+                "renamed" => {
+                    let obj: RenamedObject = serde_json::from_str(json)
+                        .unwrap_or_else(|_| panic!("Invalid renamed line: {}", line));
+                    test_case.expected_renames.insert(obj);
+                }
                 "rewrite" => {
                     let obj: DBObject = serde_json::from_str(json)
                         .unwrap_or_else(|_| panic!("Invalid rewrite line: {}", line));
                     test_case.expected_rewrites.insert(obj);
                 }
+                "rewrite columns" => {
+                    let obj: RewriteColumns = serde_json::from_str(json)
+                        .unwrap_or_else(|_| panic!("Invalid rewrite columns line: {}", line));
+                    test_case.expected_rewrite_columns.insert(obj);
+                }
+                "dependent" => {
+                    let obj: DBObject = serde_json::from_str(json)
+                        .unwrap_or_else(|_| panic!("Invalid dependent line: {}", line));
+                    test_case.expected_dependents.insert(obj);
+                }
+                "setup" => test_case.setup.push(json.to_string()),
+                "lint" => {
+                    let rule: LintRule = serde_json::from_str(json)
+                        .unwrap_or_else(|_| panic!("Invalid lint line: {}", line));
+                    test_case.expected_lints.push(rule);
+                }
+                "cost" => {
+                    let obj: ExpectedCost = serde_json::from_str(json)
+                        .unwrap_or_else(|_| panic!("Invalid cost line: {}", line));
+                    test_case.expected_costs.insert(obj);
+                }
                 _ => continue,
             }
         }
 
         assert!(
-            !test_case.expected_locks.is_empty(),
-            "No lock expectations found in test case"
+            test_case.expected_locks.is_empty() == test_case.expect_no_locks,
+            "Expected either lock lines or a single `-- lock: none` line in test case"
         );
         Ok(test_case)
     }
@@ -0,0 +1 @@
+create table products (id integer);
\ No newline at end of file
@@ -0,0 +1 @@
+alter table products add column name text;
\ No newline at end of file
@@ -0,0 +1 @@
+alter table missing add column name text;
\ No newline at end of file
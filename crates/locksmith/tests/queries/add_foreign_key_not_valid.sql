@@ -0,0 +1,8 @@
+-- lock:  {"table": {"name": "orders"}, "lock": "ShareRowExclusiveLock"}
+-- lock:  {"table": {"name": "customers"}, "lock": "ShareRowExclusiveLock"}
+-- added: {"Constraint": {"table": {"name": "orders"}, "name": "orders_customer_fk", "validated": false, "definition": "FOREIGN KEY (customer_id) REFERENCES customers(id)"}}
+-- added: {"Trigger": {"table": {"name": "orders"}, "name": "orders_customer_fk", "enabled": "Enabled", "internal": true}}
+-- added: {"Trigger": {"table": {"name": "customers"}, "name": "orders_customer_fk", "enabled": "Enabled", "internal": true}}
+-- cost:  {"table": {"name": "orders"}, "cost": "Instant"}
+-- cost:  {"table": {"name": "customers"}, "cost": "Instant"}
+alter table orders add constraint orders_customer_fk foreign key (customer_id) references customers (id) not valid;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- lock:    {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
+-- added:   {"Column": {"table": {"name": "orders"}, "name": "customer_key", "data_type": "integer", "generated": {"expression": "(customer_id * 2)", "stored": true}}}
+-- rewrite: {"Table": {"name": "orders"}}
+-- rewrite columns: {"table": {"name": "orders"}, "columns": ["customer_key"]}
+-- cost:    {"table": {"name": "orders"}, "cost": "Rewrite"}
+-- lint:    "stored-generated-column"
+alter table orders add column customer_key integer generated always as (customer_id * 2) stored;
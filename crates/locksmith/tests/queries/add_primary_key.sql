@@ -0,0 +1,7 @@
+-- setup:   create table order_events (order_id integer not null, event text not null);
+-- lock:    {"table": {"name": "order_events"}, "lock": "AccessExclusiveLock"}
+-- added:   {"Index": {"table": {"name": "order_events"}, "name": "order_events_pkey", "columns": ["order_id"], "is_primary": true, "is_unique": true}}
+-- added:   {"Constraint": {"table": {"name": "order_events"}, "name": "order_events_pkey", "validated": true, "definition": "PRIMARY KEY (order_id)"}}
+-- cost:    {"table": {"name": "order_events"}, "cost": "Scan"}
+-- lint:    "prefer-primary-key-using-index"
+alter table order_events add primary key (order_id);
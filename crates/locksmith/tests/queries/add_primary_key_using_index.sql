@@ -0,0 +1,8 @@
+-- setup:   create table order_events (order_id integer not null, event text not null);
+-- setup:   create unique index order_events_order_id_idx on order_events (order_id);
+-- lock:    {"table": {"name": "order_events"}, "lock": "AccessExclusiveLock"}
+-- removed: {"Index": {"table": {"name": "order_events"}, "name": "order_events_order_id_idx", "columns": ["order_id"], "is_primary": false, "is_unique": true}}
+-- added:   {"Index": {"table": {"name": "order_events"}, "name": "order_events_pkey", "columns": ["order_id"], "is_primary": true, "is_unique": true}}
+-- added:   {"Constraint": {"table": {"name": "order_events"}, "name": "order_events_pkey", "validated": true, "definition": "PRIMARY KEY (order_id)"}}
+-- cost:    {"table": {"name": "order_events"}, "cost": "Instant"}
+alter table order_events add constraint order_events_pkey primary key using index order_events_order_id_idx;
@@ -0,0 +1,6 @@
+-- lock:    {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
+-- removed: {"Constraint": {"table": {"name": "orders"}, "name": "orders_price_check", "validated": false, "definition": "CHECK (((price)::numeric >= (0)::numeric))"}}
+-- added:   {"Constraint": {"table": {"name": "orders"}, "name": "orders_price_check", "validated": false, "definition": "CHECK (((price)::numeric > (0)::numeric))"}}
+-- cost:    {"table": {"name": "orders"}, "cost": "Instant"}
+alter table orders drop constraint orders_price_check,
+    add constraint orders_price_check check (price > 0) not valid;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- lock:  {"table": {"name": "orders"}, "lock": "ShareLock"}
+-- added: {"DomainConstraint": {"domain": "price", "name": "price_limit", "validated": true}}
+-- cost:  {"table": {"name": "orders"}, "cost": "Scan"}
+alter domain price add constraint price_limit check (value < 1000000);
\ No newline at end of file
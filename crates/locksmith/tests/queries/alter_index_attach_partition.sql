@@ -0,0 +1,10 @@
+-- setup:   create table order_events (order_id integer not null, created_at date not null) partition by range (created_at);
+-- setup:   create table order_events_2024 partition of order_events for values from ('2024-01-01') to ('2025-01-01');
+-- setup:   create index order_events_order_id_idx on only order_events (order_id);
+-- setup:   create index order_events_2024_order_id_idx on order_events_2024 (order_id);
+-- lock:    {"table": {"name": "order_events"}, "lock": "AccessShareLock"}
+-- lock:    {"table": {"name": "order_events_2024"}, "lock": "AccessShareLock"}
+-- added:   {"IndexInheritance": {"table": {"name": "order_events_2024"}, "index": "order_events_2024_order_id_idx", "parent": "order_events_order_id_idx"}}
+-- cost:    {"table": {"name": "order_events"}, "cost": "Instant"}
+-- cost:    {"table": {"name": "order_events_2024"}, "cost": "Instant"}
+alter index order_events_order_id_idx attach partition order_events_2024_order_id_idx;
@@ -0,0 +1,3 @@
+-- lock:    none
+-- renamed: {"from": {"Index": {"table": {"name": "orders"}, "name": "orders_price_idx", "columns": ["price"], "is_primary": false, "is_unique": false}}, "to": {"Index": {"table": {"name": "orders"}, "name": "orders_price_index", "columns": ["price"], "is_primary": false, "is_unique": false}}}
+alter index orders_price_idx rename to orders_price_index;
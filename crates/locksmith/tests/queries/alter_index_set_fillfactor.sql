@@ -0,0 +1,3 @@
+-- lock:    none
+-- added:   {"IndexStorageParameters": {"table": {"name": "orders"}, "index": "orders_price_idx", "parameters": ["fillfactor=70"]}}
+alter index orders_price_idx set (fillfactor = 70);
@@ -3,4 +3,7 @@
 -- removed: {"Column": {"table": {"name": "customers"}, "name": "id", "data_type": "integer"}}
 -- added:   {"Column": {"table": {"name": "customers"}, "name": "id", "data_type": "bigint"}}
 -- rewrite: {"Table": {"name": "customers"}}
+-- rewrite columns: {"table": {"name": "customers"}, "columns": ["id"]}
+-- cost:    {"table": {"name": "orders"}, "cost": "Scan"}
+-- cost:    {"table": {"name": "customers"}, "cost": "Rewrite"}
 alter table customers alter column id type bigint;
\ No newline at end of file
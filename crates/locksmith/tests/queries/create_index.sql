@@ -0,0 +1,5 @@
+-- lock: {"table": {"name": "orders"}, "lock": "ShareLock"}
+-- added: {"Index": {"table": {"name": "orders"}, "name": "orders_customer_id_idx", "columns": ["customer_id"], "is_primary": false, "is_unique": false}}
+-- cost: {"table": {"name": "orders"}, "cost": "Scan"}
+-- lint: "prefer-create-index-concurrently"
+create index orders_customer_id_idx on orders (customer_id);
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- lock: {"table": {"name": "orders"}, "lock": "AccessShareLock"}
+-- added: {"Table": {"name": "orders_archive"}}
+-- added: {"Column": {"table": {"name": "orders_archive"}, "name": "id", "data_type": "integer"}}
+-- added: {"Column": {"table": {"name": "orders_archive"}, "name": "customer_id", "data_type": "integer"}}
+-- added: {"Column": {"table": {"name": "orders_archive"}, "name": "price", "data_type": "numeric"}}
+-- cost: {"table": {"name": "orders"}, "cost": "Scan"}
+create table orders_archive as select * from orders;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- lock: {"table": {"name": "orders"}, "lock": "ShareUpdateExclusiveLock"}
+-- added: {"Table": {"name": "orders_child"}}
+-- added: {"Column": {"table": {"name": "orders_child"}, "name": "id", "data_type": "integer"}}
+-- added: {"Column": {"table": {"name": "orders_child"}, "name": "customer_id", "data_type": "integer"}}
+-- added: {"Column": {"table": {"name": "orders_child"}, "name": "price", "data_type": "numeric"}}
+-- added: {"Constraint": {"table": {"name": "orders_child"}, "name": "orders_price_check", "validated": true, "definition": "CHECK (((price)::numeric >= (0)::numeric))"}}
+-- added: {"Inheritance": {"parent": {"name": "orders"}, "child": {"name": "orders_child"}}}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
+create table orders_child () inherits (orders);
\ No newline at end of file
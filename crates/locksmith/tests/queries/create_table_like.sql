@@ -0,0 +1,11 @@
+-- lock: {"table": {"name": "orders"}, "lock": "AccessShareLock"}
+-- added: {"Table": {"name": "orders_copy"}}
+-- added: {"Column": {"table": {"name": "orders_copy"}, "name": "id", "data_type": "integer"}}
+-- added: {"Column": {"table": {"name": "orders_copy"}, "name": "customer_id", "data_type": "integer"}}
+-- added: {"Column": {"table": {"name": "orders_copy"}, "name": "price", "data_type": "numeric"}}
+-- added: {"Index": {"table": {"name": "orders_copy"}, "name": "orders_copy_pkey", "columns": ["id"], "is_primary": true, "is_unique": true}}
+-- added: {"Index": {"table": {"name": "orders_copy"}, "name": "orders_copy_price_idx", "columns": ["price"], "is_primary": false, "is_unique": false}}
+-- added: {"Constraint": {"table": {"name": "orders_copy"}, "name": "orders_copy_pkey", "validated": true, "definition": "PRIMARY KEY (id)"}}
+-- added: {"Constraint": {"table": {"name": "orders_copy"}, "name": "orders_price_check", "validated": true, "definition": "CHECK (((price)::numeric >= (0)::numeric))"}}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
+create table orders_copy (like orders including all);
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- lock:    none
+-- added: {"Table": {"name": "invoices"}}
+-- added: {"Column": {"table": {"name": "invoices"}, "name": "id", "data_type": "integer"}}
+-- added: {"Sequence": {"name": "invoices_id_seq", "start": 1, "increment": 1, "min": 1, "max": 2147483647, "cycle": false, "owned_by": "invoices.id"}}
+-- added: {"Index": {"table": {"name": "invoices"}, "name": "invoices_pkey", "columns": ["id"], "is_primary": true, "is_unique": true}}
+-- added: {"Constraint": {"table": {"name": "invoices"}, "name": "invoices_pkey", "validated": true, "definition": "PRIMARY KEY (id)"}}
+create table invoices (id serial primary key);
@@ -0,0 +1,5 @@
+-- lock:    {"table": {"name": "orders"}, "lock": "ShareRowExclusiveLock"}
+-- removed: {"Trigger": {"table": {"name": "orders"}, "name": "orders_customer_id_fkey", "enabled": "Enabled", "internal": true}}
+-- added:   {"Trigger": {"table": {"name": "orders"}, "name": "orders_customer_id_fkey", "enabled": "Disabled", "internal": true}}
+-- cost:    {"table": {"name": "orders"}, "cost": "Instant"}
+alter table orders disable trigger all;
\ No newline at end of file
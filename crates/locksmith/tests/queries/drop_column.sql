@@ -1,4 +1,9 @@
 -- lock: {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
 -- lock: {"table": {"name": "customers"}, "lock": "AccessExclusiveLock"}
 -- removed: {"Column": {"table": {"name": "orders"}, "name": "customer_id", "data_type": "integer"}}
+-- removed: {"Constraint": {"table": {"name": "orders"}, "name": "orders_customer_id_fkey", "validated": true, "definition": "FOREIGN KEY (customer_id) REFERENCES customers(id)"}}
+-- removed: {"Trigger": {"table": {"name": "orders"}, "name": "orders_customer_id_fkey", "enabled": "Enabled", "internal": true}}
+-- removed: {"Trigger": {"table": {"name": "customers"}, "name": "orders_customer_id_fkey", "enabled": "Enabled", "internal": true}}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
+-- cost: {"table": {"name": "customers"}, "cost": "Instant"}
 alter table orders drop column customer_id;
\ No newline at end of file
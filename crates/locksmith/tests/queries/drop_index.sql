@@ -1,3 +1,5 @@
 -- lock: {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
--- removed: {"Index": {"table": {"name": "orders"}, "name": "orders_price_idx"}}
+-- removed: {"Index": {"table": {"name": "orders"}, "name": "orders_price_idx", "columns": ["price"], "is_primary": false, "is_unique": false}}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
+-- lint: "prefer-drop-index-concurrently"
 drop index orders_price_idx;
\ No newline at end of file
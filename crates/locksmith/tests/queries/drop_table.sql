@@ -1,9 +1,17 @@
 -- lock: {"table": {"name": "customers"}, "lock": "AccessExclusiveLock"}
 -- lock: {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
 -- removed: {"Table": {"name": "orders"}}
--- removed: {"Index": {"table": {"name": "orders"}, "name": "orders_pkey"}}
--- removed: {"Index": {"table": {"name": "orders"}, "name": "orders_price_idx"}}
+-- removed: {"Sequence": {"name": "orders_id_seq", "start": 1, "increment": 1, "min": 1, "max": 2147483647, "cycle": false, "owned_by": "orders.id"}}
+-- removed: {"Index": {"table": {"name": "orders"}, "name": "orders_pkey", "columns": ["id"], "is_primary": true, "is_unique": true}}
+-- removed: {"Index": {"table": {"name": "orders"}, "name": "orders_price_idx", "columns": ["price"], "is_primary": false, "is_unique": false}}
 -- removed: {"Column": {"table": {"name": "orders"}, "name": "id", "data_type": "integer"}}
 -- removed: {"Column": {"table": {"name": "orders"}, "name": "price", "data_type": "numeric"}}
 -- removed: {"Column": {"table": {"name": "orders"}, "name": "customer_id", "data_type": "integer"}}
+-- removed: {"Constraint": {"table": {"name": "orders"}, "name": "orders_pkey", "validated": true, "definition": "PRIMARY KEY (id)"}}
+-- removed: {"Constraint": {"table": {"name": "orders"}, "name": "orders_customer_id_fkey", "validated": true, "definition": "FOREIGN KEY (customer_id) REFERENCES customers(id)"}}
+-- removed: {"Constraint": {"table": {"name": "orders"}, "name": "orders_price_check", "validated": false, "definition": "CHECK (((price)::numeric >= (0)::numeric))"}}
+-- removed: {"Trigger": {"table": {"name": "orders"}, "name": "orders_customer_id_fkey", "enabled": "Enabled", "internal": true}}
+-- removed: {"Trigger": {"table": {"name": "customers"}, "name": "orders_customer_id_fkey", "enabled": "Enabled", "internal": true}}
+-- cost: {"table": {"name": "customers"}, "cost": "Instant"}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
 drop table orders;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- setup:   alter table orders set unlogged;
+-- lock:    {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
+-- lock:    {"table": {"name": "customers"}, "lock": "AccessShareLock"}
+-- removed: {"UnloggedTable": {"table": {"name": "orders"}}}
+-- rewrite: {"Table": {"name": "orders"}}
+-- lint:    "persistence-change-rewrite"
+-- cost:    {"table": {"name": "orders"}, "cost": "Rewrite"}
+-- cost:    {"table": {"name": "customers"}, "cost": "Instant"}
+alter table orders set logged;
@@ -1,2 +1,3 @@
 -- lock: {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
 alter table orders alter column customer_id set not null;
\ No newline at end of file
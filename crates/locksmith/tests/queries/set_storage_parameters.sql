@@ -0,0 +1,4 @@
+-- lock: {"table": {"name": "orders"}, "lock": "ShareUpdateExclusiveLock"}
+-- added: {"StorageParameters": {"table": {"name": "orders"}, "parameters": ["fillfactor=70"]}}
+-- cost: {"table": {"name": "orders"}, "cost": "Instant"}
+alter table orders set (fillfactor = 70);
\ No newline at end of file
@@ -0,0 +1,6 @@
+-- lock:    {"table": {"name": "orders"}, "lock": "AccessExclusiveLock"}
+-- added:   {"UnloggedTable": {"table": {"name": "orders"}}}
+-- rewrite: {"Table": {"name": "orders"}}
+-- lint:    "persistence-change-rewrite"
+-- cost:    {"table": {"name": "orders"}, "cost": "Rewrite"}
+alter table orders set unlogged;
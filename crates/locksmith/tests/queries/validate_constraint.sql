@@ -0,0 +1,5 @@
+-- lock:    {"table": {"name": "orders"}, "lock": "ShareUpdateExclusiveLock"}
+-- removed: {"Constraint": {"table": {"name": "orders"}, "name": "orders_price_check", "validated": false, "definition": "CHECK (((price)::numeric >= (0)::numeric))"}}
+-- added:   {"Constraint": {"table": {"name": "orders"}, "name": "orders_price_check", "validated": true, "definition": "CHECK (((price)::numeric >= (0)::numeric))"}}
+-- cost:    {"table": {"name": "orders"}, "cost": "Scan"}
+alter table orders validate constraint orders_price_check;
\ No newline at end of file
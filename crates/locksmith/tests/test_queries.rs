@@ -18,6 +18,12 @@ macro_rules! test_suite_test {
                 TestCase::from_str(include_str!($file)).expect("Failed to parse test case");
             let (_container, dsn) = start_integration_test_postgres().await;
             let mut oracle = QueryOracle::new(&dsn);
+            for statement in &test_case.setup {
+                oracle
+                    .apply_statement(statement)
+                    .await
+                    .expect("Failed to apply setup statement");
+            }
             let result = oracle
                 .inspect_statement(&test_case.statement)
                 .await
@@ -32,5 +38,25 @@ test_suite! {
     alter_column_type="queries/alter_type.sql";
     drop_column="queries/drop_column.sql";
     drop_index="queries/drop_index.sql";
+    create_index="queries/create_index.sql";
     drop_table="queries/drop_table.sql";
+    add_foreign_key="queries/add_foreign_key.sql";
+    add_foreign_key_not_valid="queries/add_foreign_key_not_valid.sql";
+    validate_constraint="queries/validate_constraint.sql";
+    alter_check_constraint="queries/alter_check_constraint.sql";
+    alter_domain_add_constraint="queries/alter_domain_add_constraint.sql";
+    create_table_as="queries/create_table_as.sql";
+    create_table_like="queries/create_table_like.sql";
+    create_table_inherits="queries/create_table_inherits.sql";
+    create_table_serial="queries/create_table_serial.sql";
+    set_storage_parameters="queries/set_storage_parameters.sql";
+    disable_triggers="queries/disable_triggers.sql";
+    add_generated_column="queries/add_generated_column.sql";
+    add_primary_key="queries/add_primary_key.sql";
+    add_primary_key_using_index="queries/add_primary_key_using_index.sql";
+    set_unlogged="queries/set_unlogged.sql";
+    set_logged="queries/set_logged.sql";
+    alter_index_rename="queries/alter_index_rename.sql";
+    alter_index_set_fillfactor="queries/alter_index_set_fillfactor.sql";
+    alter_index_attach_partition="queries/alter_index_attach_partition.sql";
 }
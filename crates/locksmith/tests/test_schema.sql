@@ -1,3 +1,5 @@
+create domain price as numeric check (value >= 0);
+
 create table customers
 (
     id   serial primary key,
@@ -8,7 +10,10 @@ create table orders
 (
     id          serial primary key,
     customer_id integer not null references customers (id),
-    price       numeric not null
+    price       price   not null
 );
 
 create index orders_price_idx on orders (price);
+
+
+alter table orders add constraint orders_price_check check (price >= 0) not valid;
\ No newline at end of file